@@ -0,0 +1,80 @@
+//! Compares matching all pages against windowed matching on a large generated document.
+//!
+//! Needs pdfium like the integration tests, run it with
+//! `PDF_DIFF_GEN_TEST_PDFIUM=<dir> cargo bench --bench match_window`. The page count
+//! defaults to 200 and can be set with `PDF_DIFF_GEN_BENCH_PAGES`.
+
+#[path = "../tests/fixtures/mod.rs"]
+#[allow(dead_code)]
+mod fixtures;
+
+use {
+    fixtures::{document, pdfium, PageSpec, TestDir},
+    pdf_diff_gen::{Comparison, PDFComparison},
+    std::time::Instant,
+};
+
+const WINDOW: usize = 3;
+
+fn main() {
+    let pdfium = pdfium();
+    let pages = std::env::var("PDF_DIFF_GEN_BENCH_PAGES")
+        .ok()
+        .and_then(|pages| pages.parse().ok())
+        .unwrap_or(200);
+    let dir = TestDir::new("bench_match_window");
+    let previous = (0..pages)
+        .map(|page| PageSpec::text(page, 5))
+        .collect::<Vec<PageSpec>>();
+    // Every tenth page changes, the rest only moves by the page inserted at the front.
+    let mut current = vec![PageSpec::text(pages, 12)];
+    current.extend(previous.iter().enumerate().map(|(page, spec)| {
+        match page % 10 {
+            0 => spec
+                .clone()
+                .with_line(2, "A paragraph that was rewritten entirely."),
+            _ => spec.clone(),
+        }
+    }));
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &previous));
+
+    for prepass_width in [64, 0] {
+        let mut results = Vec::new();
+        for window in [None, Some(WINDOW)] {
+            let mut builder = PDFComparison::builder(pdfium.clone())
+                .structural_precheck(false)
+                .prepass_width(prepass_width);
+            if let Some(window) = window {
+                builder = builder.match_window(window);
+            }
+            let comparison = builder.build().unwrap();
+            let started = Instant::now();
+            let comparisons = comparison
+                .compare_pdfs(&a, &b, None)
+                .expect("Unable to compare the documents");
+            println!(
+                "{} pages, prepass width {}, {}: {:.2?}",
+                current.len(),
+                prepass_width,
+                match window {
+                    Some(window) => format!("window {}", window),
+                    None => String::from("all pages"),
+                },
+                started.elapsed()
+            );
+            results.push(comparisons);
+        }
+        let changed = |comparisons: &[Comparison]| {
+            comparisons
+                .iter()
+                .filter(|v| matches!(v, Comparison::Different(_)))
+                .count()
+        };
+        assert_eq!(
+            changed(&results[0]),
+            changed(&results[1]),
+            "Windowed matching found other changes"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Compares matching pages on thumbnails first against matching them at full resolution on
+//! a generated document of 100 pages.
+//!
+//! Needs pdfium like the integration tests, run it with
+//! `PDF_DIFF_GEN_TEST_PDFIUM=<dir> cargo bench --bench prepass`. The page count can be set
+//! with `PDF_DIFF_GEN_BENCH_PAGES`.
+
+#[path = "../tests/fixtures/mod.rs"]
+#[allow(dead_code)]
+mod fixtures;
+
+use {
+    fixtures::{document, pdfium, PageSpec, TestDir},
+    pdf_diff_gen::PDFComparison,
+    std::time::Instant,
+};
+
+const PREPASS_WIDTH: u32 = 64;
+
+fn main() {
+    let pdfium = pdfium();
+    let pages = std::env::var("PDF_DIFF_GEN_BENCH_PAGES")
+        .ok()
+        .and_then(|pages| pages.parse().ok())
+        .unwrap_or(100);
+    let dir = TestDir::new("bench_prepass");
+    let previous = (0..pages)
+        .map(|page| PageSpec::text(page, 5))
+        .collect::<Vec<PageSpec>>();
+    // Every page is matched against all previous ones, every fifth one changed and the
+    // order reversed, so the matches can't be found in place.
+    let current = previous
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(page, spec)| match page % 5 {
+            0 => spec
+                .clone()
+                .with_line(3, "A paragraph that was rewritten entirely."),
+            _ => spec.clone(),
+        })
+        .collect::<Vec<PageSpec>>();
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &previous));
+
+    let mut results = Vec::new();
+    for prepass_width in [PREPASS_WIDTH, 0] {
+        let comparison = PDFComparison::builder(pdfium.clone())
+            .structural_precheck(false)
+            .prepass_width(prepass_width)
+            .build()
+            .unwrap();
+        let started = Instant::now();
+        let comparisons = comparison
+            .compare_pdfs(&a, &b, None)
+            .expect("Unable to compare the documents");
+        println!(
+            "{} pages, {}: {:.2?}",
+            pages,
+            match prepass_width {
+                0 => String::from("full resolution"),
+                width => format!("prepass width {}", width),
+            },
+            started.elapsed()
+        );
+        results.push(comparisons);
+    }
+    assert_eq!(
+        results[0], results[1],
+        "The prepass matched other pages than the full resolution"
+    );
+}
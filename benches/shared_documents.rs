@@ -0,0 +1,83 @@
+//! Compares loading every document again for marking against marking the documents the
+//! comparison already loaded, on a large generated document.
+//!
+//! Needs pdfium like the integration tests, run it with
+//! `PDF_DIFF_GEN_TEST_PDFIUM=<dir> cargo bench --bench shared_documents`. The page count
+//! defaults to 500 and can be set with `PDF_DIFF_GEN_BENCH_PAGES`.
+
+#[path = "../tests/fixtures/mod.rs"]
+#[allow(dead_code)]
+mod fixtures;
+
+use {
+    fixtures::{document, pdfium, PageSpec, TestDir},
+    pdf_diff_gen::{PDFComparison, PDFEditor, PDFEditorConfig, TimingStage, Timings},
+    std::time::Instant,
+};
+
+fn main() {
+    let pdfium = pdfium();
+    let pages = std::env::var("PDF_DIFF_GEN_BENCH_PAGES")
+        .ok()
+        .and_then(|pages| pages.parse().ok())
+        .unwrap_or(500);
+    let dir = TestDir::new("bench_shared_documents");
+    let previous = (0..pages)
+        .map(|page| PageSpec::text(page, 12))
+        .collect::<Vec<PageSpec>>();
+    // Every twentieth page changes, so most of the document is removed again when marking.
+    let current = previous
+        .iter()
+        .enumerate()
+        .map(|(page, spec)| match page % 20 {
+            0 => spec
+                .clone()
+                .with_line(4, "A paragraph that was rewritten entirely."),
+            _ => spec.clone(),
+        })
+        .collect::<Vec<PageSpec>>();
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &previous));
+    let comparison = PDFComparison::new(pdfium.clone());
+    let editor = PDFEditor::new(
+        pdfium.clone(),
+        PDFEditorConfig {
+            summary_page: true,
+            ..Default::default()
+        },
+    );
+    let report = |name: &str, started: Instant, timings: Timings| {
+        println!(
+            "{} pages, {}: {:.2?}, loading {:.2?}",
+            pages,
+            name,
+            started.elapsed(),
+            timings.get(TimingStage::Loading)
+        );
+    };
+
+    let started = Instant::now();
+    let (result, mut timings) = comparison.compare_pdfs_timed(&a, &b, None, None, None);
+    let comparisons = result.expect("Unable to compare the documents").pages;
+    timings += editor
+        .mark_differences(&a, Some(&b), &comparisons, &dir.join("loaded.pdf"), None)
+        .expect("Unable to mark the differences")
+        .timings;
+    report("loading for marking", started, timings);
+
+    let started = Instant::now();
+    let (result, mut timings) = comparison.compare_pdfs_loaded(&a, &b, None, None, None);
+    let mut loaded = result.expect("Unable to compare the documents");
+    timings += editor
+        .mark_differences_in_document(
+            &mut loaded.current,
+            loaded.previous.as_ref(),
+            &loaded.comparison.pages,
+            &dir.join("shared.pdf"),
+            None,
+        )
+        .expect("Unable to mark the differences")
+        .timings;
+    report("sharing the documents", started, timings);
+    assert_eq!(loaded.comparison.pages, comparisons);
+}
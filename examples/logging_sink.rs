@@ -0,0 +1,78 @@
+//! Runs a single update cycle with a sink that only logs the changed pages.
+//!
+//! `cargo run --example logging_sink -- <current_path> <last_path> <diff_path> <pdfium_path>`
+
+use pdf_diff_gen::{
+    get_pdfium, Comparison, DiffSink, DiffSinkError, FileManager, FileManagerConfig, PDFComparison,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+struct LoggingSink {
+    changed_pages: usize,
+}
+
+impl DiffSink for LoggingSink {
+    fn begin_document(
+        &mut self,
+        path: &Path,
+        previous: Option<&Path>,
+    ) -> Result<(), DiffSinkError> {
+        log::info!(
+            "{} changed, previous version: {}",
+            path.to_string_lossy(),
+            previous.map_or(String::from("none"), |v| v.to_string_lossy().to_string())
+        );
+        self.changed_pages = 0;
+        Ok(())
+    }
+
+    fn page_changed(&mut self, index: usize, comparison: &Comparison) -> Result<(), DiffSinkError> {
+        if let Comparison::Different(seg) = comparison {
+            log::info!(
+                "  page {}: {:.1}% changed",
+                index + 1,
+                seg.changed_fraction() * 100.
+            );
+        }
+        self.changed_pages += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<PathBuf>, DiffSinkError> {
+        log::info!("  {} changed pages", self.changed_pages);
+        Ok(None)
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args = std::env::args()
+        .skip(1)
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    let [current_path, last_path, diff_path, pdfium_path] = args.as_slice() else {
+        eprintln!("usage: logging_sink <current_path> <last_path> <diff_path> <pdfium_path>");
+        std::process::exit(2);
+    };
+
+    let pdfium = Arc::new(get_pdfium(pdfium_path).expect("Unable to load PDFium"));
+    let mut file_manager = FileManager::new(
+        PDFComparison::new(pdfium),
+        Box::new(LoggingSink { changed_pages: 0 }),
+        current_path.clone(),
+        last_path.clone(),
+        diff_path.clone(),
+        FileManagerConfig {
+            write_report: false,
+            ..Default::default()
+        },
+    );
+    match file_manager.update(None).await {
+        Ok(report) => log::info!("{}", report),
+        Err(e) => log::error!("{}", e),
+    }
+}
@@ -0,0 +1,253 @@
+use {image::RgbImage, std::collections::HashMap};
+
+/// Largest number of row pairs aligned, beyond that rows are compared at their positions.
+const MAX_ALIGNMENT_CELLS: usize = 4_000_000;
+
+/// For every row of `a`, whether it has no counterpart in `b`.
+///
+/// Rows are matched like lines in a text diff, so content shifted down by an insertion
+/// doesn't count as changed, only the inserted rows do. Where rows of `b` were removed, the
+/// row of `a` following the removal is marked. Rows replaced at the same position still
+/// match if they are equal within `tolerance`.
+pub(crate) fn changed_rows(a: &RgbImage, b: &RgbImage, tolerance: u8) -> Vec<bool> {
+    let row_len = (a.width() as usize * 3).max(1);
+    let rows_a = a.as_raw().chunks_exact(row_len).collect::<Vec<&[u8]>>();
+    let rows_b = b.as_raw().chunks_exact(row_len).collect::<Vec<&[u8]>>();
+    let equal = |r_a: &[u8], r_b: &[u8]| {
+        r_a == r_b
+            || (tolerance > 0
+                && r_a
+                    .iter()
+                    .zip(r_b)
+                    .all(|(a, b)| a.abs_diff(*b) <= tolerance))
+    };
+    let mut changed = vec![false; rows_a.len()];
+
+    // Most pages only change in a few places, which keeps the part to align small.
+    let prefix = rows_a
+        .iter()
+        .zip(&rows_b)
+        .take_while(|(r_a, r_b)| equal(r_a, r_b))
+        .count();
+    let suffix = rows_a[prefix..]
+        .iter()
+        .rev()
+        .zip(rows_b[prefix..].iter().rev())
+        .take_while(|(r_a, r_b)| equal(r_a, r_b))
+        .count();
+    let middle_a = &rows_a[prefix..rows_a.len() - suffix];
+    let middle_b = &rows_b[prefix..rows_b.len() - suffix];
+
+    if middle_a.len() * middle_b.len() > MAX_ALIGNMENT_CELLS {
+        for (i, r_a) in middle_a.iter().enumerate() {
+            changed[prefix + i] = middle_b.get(i).is_none_or(|r_b| !equal(r_a, r_b));
+        }
+        return changed;
+    }
+
+    let mut inserted = Vec::new();
+    let mut removed = Vec::new();
+    let mut flush = |inserted: &mut Vec<usize>, removed: &mut Vec<usize>, next_a: usize| {
+        for (k, &i) in inserted.iter().enumerate() {
+            changed[prefix + i] = removed
+                .get(k)
+                .is_none_or(|&j| !equal(middle_a[i], middle_b[j]));
+        }
+        let last = changed.len().saturating_sub(1);
+        if inserted.is_empty() && !removed.is_empty() && !changed.is_empty() {
+            changed[(prefix + next_a).min(last)] = true;
+        }
+        inserted.clear();
+        removed.clear();
+    };
+    for operation in align(middle_a, middle_b) {
+        match operation {
+            RowOperation::Equal(i) => flush(&mut inserted, &mut removed, i),
+            RowOperation::Inserted(i) => inserted.push(i),
+            RowOperation::Removed(j) => removed.push(j),
+        }
+    }
+    flush(&mut inserted, &mut removed, middle_a.len());
+    changed
+}
+
+enum RowOperation {
+    /// Row of `a` with an identical row in `b`.
+    Equal(usize),
+    /// Row of `a` missing in `b`.
+    Inserted(usize),
+    /// Row of `b` missing in `a`.
+    Removed(usize),
+}
+
+/// Longest common subsequence of the rows of `a` and `b`, as operations in row order.
+fn align<'a>(a: &[&'a [u8]], b: &[&'a [u8]]) -> Vec<RowOperation> {
+    // Identical rows get the same id, so the table is filled comparing integers.
+    let mut ids: HashMap<&'a [u8], usize> = HashMap::new();
+    let mut id = |row: &'a [u8]| {
+        let next = ids.len();
+        *ids.entry(row).or_insert(next)
+    };
+    let ids_a = a.iter().map(|row| id(row)).collect::<Vec<usize>>();
+    let ids_b = b.iter().map(|row| id(row)).collect::<Vec<usize>>();
+
+    let (m, n) = (a.len(), b.len());
+    let width = n + 1;
+    // `lengths[i * width + j]` is the length of the subsequence of `a[i..]` and `b[j..]`.
+    let mut lengths = vec![0u32; (m + 1) * width];
+    for i in (0..m).rev() {
+        for j in (0..n).rev() {
+            lengths[i * width + j] = if ids_a[i] == ids_b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut operations = Vec::with_capacity(m.max(n));
+    let (mut i, mut j) = (0, 0);
+    while i < m && j < n {
+        if ids_a[i] == ids_b[j] {
+            operations.push(RowOperation::Equal(i));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            operations.push(RowOperation::Inserted(i));
+            i += 1;
+        } else {
+            operations.push(RowOperation::Removed(j));
+            j += 1;
+        }
+    }
+    operations.extend((i..m).map(RowOperation::Inserted));
+    operations.extend((j..n).map(RowOperation::Removed));
+    operations
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::{changed_rows, MAX_ALIGNMENT_CELLS},
+        image::RgbImage,
+    };
+
+    /// Xorshift, enough to generate images reproducibly.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: u64) -> u64 {
+            self.next() % bound
+        }
+    }
+
+    fn noise(random: &mut Random, width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |_, _| {
+            image::Rgb([0; 3].map(|_: u8| random.below(256) as u8))
+        })
+    }
+
+    /// Image with `rows` of one pixel, each a distinct gray.
+    fn column(rows: impl IntoIterator<Item = u8>) -> RgbImage {
+        let rows = rows.into_iter().collect::<Vec<u8>>();
+        RgbImage::from_fn(1, rows.len() as u32, |_, y| {
+            image::Rgb([rows[y as usize]; 3])
+        })
+    }
+
+    /// The rows of `a` differing from the row of `b` at the same position, pixel by pixel.
+    fn changed_in_place(a: &RgbImage, b: &RgbImage, tolerance: u8) -> Vec<bool> {
+        (0..a.height())
+            .map(|y| {
+                y >= b.height()
+                    || (0..a.width()).any(|x| {
+                        !crate::pdf::pixels_match(a.get_pixel(x, y), b.get_pixel(x, y), tolerance)
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rows_changed_in_place_match_the_pixel_comparison() {
+        let mut random = Random(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2000 {
+            let (width, height) = (1 + random.below(8) as u32, 1 + random.below(24) as u32);
+            let tolerance = random.below(9) as u8;
+            let previous = noise(&mut random, width, height);
+            let mut current = previous.clone();
+            for y in 0..height {
+                if random.below(3) != 0 {
+                    continue;
+                }
+                for _ in 0..1 + random.below(3) {
+                    let pixel = current.get_pixel_mut(random.below(width as u64) as u32, y);
+                    let channel = &mut pixel.0[random.below(3) as usize];
+                    let delta = random.below(2 * tolerance as u64 + 2) as u8;
+                    *channel = match random.below(2) {
+                        0 => channel.saturating_add(delta),
+                        _ => channel.saturating_sub(delta),
+                    };
+                }
+            }
+            assert_eq!(
+                changed_rows(&current, &previous, tolerance),
+                changed_in_place(&current, &previous, tolerance),
+                "{}x{} at tolerance {}",
+                width,
+                height,
+                tolerance
+            );
+        }
+    }
+
+    #[test]
+    fn inserted_rows_are_the_only_changed_ones() {
+        let previous = column(0..20);
+        let current = column([0, 1, 200, 201].into_iter().chain(2..20));
+        let mut expected = vec![false; 22];
+        expected[2] = true;
+        expected[3] = true;
+        assert_eq!(changed_rows(&current, &previous, 0), expected);
+    }
+
+    #[test]
+    fn removed_rows_mark_the_following_row() {
+        let previous = column(0..20);
+        let current = column((0..5).chain(7..20));
+        let mut expected = vec![false; 18];
+        expected[5] = true;
+        assert_eq!(changed_rows(&current, &previous, 0), expected);
+
+        // Removed at the end, the last row is marked.
+        let current = column(0..18);
+        let mut expected = vec![false; 18];
+        expected[17] = true;
+        assert_eq!(changed_rows(&current, &previous, 0), expected);
+    }
+
+    #[test]
+    fn large_changes_fall_back_to_comparing_rows_in_place() {
+        let mut random = Random(7);
+        let rows = (MAX_ALIGNMENT_CELLS as f64).sqrt() as u32 + 2;
+        let previous = noise(&mut random, 1, rows);
+        // A row inserted at the top and the last row changed leave nothing to trim.
+        let mut current = RgbImage::new(1, rows + 1);
+        for y in 0..rows {
+            current.put_pixel(0, y + 1, *previous.get_pixel(0, y));
+        }
+        current.put_pixel(0, 0, image::Rgb([1, 2, 3]));
+        let last = current.get_pixel_mut(0, rows);
+        last.0[0] = last.0[0].wrapping_add(128);
+        assert_eq!(
+            changed_rows(&current, &previous, 0),
+            changed_in_place(&current, &previous, 0)
+        );
+    }
+}
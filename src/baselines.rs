@@ -0,0 +1,91 @@
+use {
+    globset::{Glob, GlobMatcher},
+    serde::Deserialize,
+    std::path::{Path, PathBuf},
+};
+
+/// Compares the files matching `path` against the shared `baseline` instead of their copies
+/// in `last_path`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "OverrideConfig")]
+pub struct BaselineOverride {
+    path: GlobMatcher,
+    baseline: PathBuf,
+}
+
+/// An override as it is written in a mapping file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideConfig {
+    path: String,
+    baseline: PathBuf,
+}
+
+impl TryFrom<OverrideConfig> for BaselineOverride {
+    type Error = globset::Error;
+
+    fn try_from(value: OverrideConfig) -> Result<Self, Self::Error> {
+        Ok(BaselineOverride::new(
+            Glob::new(&value.path)?,
+            value.baseline,
+        ))
+    }
+}
+
+impl BaselineOverride {
+    /// Matches files matching `path`, relative to `current_path`.
+    pub fn new(path: Glob, baseline: PathBuf) -> Self {
+        BaselineOverride {
+            path: path.compile_matcher(),
+            baseline,
+        }
+    }
+
+    pub fn baseline(&self) -> &Path {
+        &self.baseline
+    }
+}
+
+/// Shared baselines some files are compared against, deserialized from a list of overrides
+/// with a `path` glob and a `baseline`, e.g. every `invoices/*.pdf` against
+/// `templates/invoice_master.pdf`. The first matching override applies.
+///
+/// The baselines are never written to: [`FileManager`](crate::FileManager) keeps the digests
+/// of the files and their baselines instead of copies, and compares a file again once
+/// either digest changes.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct BaselineOverrides {
+    overrides: Vec<BaselineOverride>,
+}
+
+impl BaselineOverrides {
+    pub fn new(overrides: Vec<BaselineOverride>) -> Self {
+        BaselineOverrides { overrides }
+    }
+
+    pub fn overrides(&self) -> &[BaselineOverride] {
+        &self.overrides
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.overrides.is_empty()
+    }
+
+    /// Resolves relative baselines against `dir`, e.g. the directory of the mapping file.
+    pub fn relative_to(mut self, dir: &Path) -> Self {
+        self.overrides
+            .iter_mut()
+            .filter(|v| v.baseline.is_relative())
+            .for_each(|v| v.baseline = dir.join(&v.baseline));
+        self
+    }
+
+    /// Baseline of the file at `path`, relative to `current_path`, if an override matches.
+    pub fn baseline_for(&self, path: &Path) -> Option<&Path> {
+        self.overrides
+            .iter()
+            .find(|v| v.path.is_match(path))
+            .map(|v| v.baseline.as_path())
+    }
+}
@@ -0,0 +1,48 @@
+use image::RgbImage;
+
+/// Blurs `image` in place with a box of `2 * radius + 1` pixels, as a horizontal and a
+/// vertical pass of running sums. Pixels beyond the edges repeat the edge pixels. `scratch`
+/// holds the result of the horizontal pass and is grown as needed, so blurring pages of the
+/// same size only allocates once.
+pub(crate) fn box_blur(image: &mut RgbImage, radius: u32, scratch: &mut Vec<u8>) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    if radius == 0 || width == 0 || height == 0 {
+        return;
+    }
+    scratch.resize(width * height * 3, 0);
+    let pixels: &mut [u8] = image;
+    for y in 0..height {
+        let row = y * width * 3;
+        blur_line(
+            &pixels[row..row + width * 3],
+            &mut scratch[row..row + width * 3],
+            width,
+            3,
+            radius as usize,
+        );
+    }
+    for x in 0..width {
+        blur_line(
+            &scratch[x * 3..],
+            &mut pixels[x * 3..],
+            height,
+            width * 3,
+            radius as usize,
+        );
+    }
+}
+
+/// Blurs the `len` pixels of `source` that are `stride` bytes apart into `target`.
+fn blur_line(source: &[u8], target: &mut [u8], len: usize, stride: usize, radius: usize) {
+    let pixel = |index: usize, channel: usize| source[index.min(len - 1) * stride + channel] as u32;
+    let window = (2 * radius + 1) as u32;
+    for channel in 0..3 {
+        let mut sum = pixel(0, channel) * radius as u32
+            + (0..=radius).map(|i| pixel(i, channel)).sum::<u32>();
+        for i in 0..len {
+            target[i * stride + channel] = ((sum + window / 2) / window) as u8;
+            sum += pixel(i + radius + 1, channel);
+            sum -= pixel(i.saturating_sub(radius), channel);
+        }
+    }
+}
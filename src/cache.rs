@@ -0,0 +1,83 @@
+use {
+    image::RgbImage,
+    pdfium_render::prelude::PdfPageIndex,
+    std::{collections::HashMap, sync::Arc},
+};
+
+use crate::{metrics::metrics, pdf::DocumentSide};
+
+/// Least recently used page renders of a single comparison, bounded by their size in bytes.
+/// With a size of zero nothing is kept and every page is rendered again.
+pub(crate) struct RenderCache {
+    max_bytes: usize,
+    used_bytes: usize,
+    /// Incremented on every access, the entry with the lowest value is evicted first.
+    clock: u64,
+    entries: HashMap<(DocumentSide, PdfPageIndex), (Arc<RgbImage>, u64)>,
+}
+
+impl RenderCache {
+    pub fn new(max_bytes: usize) -> Self {
+        RenderCache {
+            max_bytes,
+            used_bytes: 0,
+            clock: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, side: DocumentSide, page: PdfPageIndex) -> Option<Arc<RgbImage>> {
+        self.clock += 1;
+        match self.entries.get_mut(&(side, page)) {
+            Some((image, last_used)) => {
+                *last_used = self.clock;
+                metrics().render_cache_hits.inc();
+                Some(image.clone())
+            }
+            None => {
+                metrics().render_cache_misses.inc();
+                None
+            }
+        }
+    }
+
+    /// Keeps `image`, evicting the least recently used renders until it fits. Renders larger
+    /// than the whole cache aren't kept.
+    pub fn insert(&mut self, side: DocumentSide, page: PdfPageIndex, image: Arc<RgbImage>) {
+        let size = image.as_raw().len();
+        if size > self.max_bytes {
+            return;
+        }
+        self.evict_to(self.max_bytes - size);
+        self.clock += 1;
+        if let Some((replaced, _)) = self.entries.insert((side, page), (image, self.clock)) {
+            self.used_bytes -= replaced.as_raw().len();
+        }
+        self.used_bytes += size;
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    /// Changes the size of the cache, evicting the least recently used renders that no
+    /// longer fit.
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        self.evict_to(max_bytes);
+    }
+
+    fn evict_to(&mut self, max_bytes: usize) {
+        while self.used_bytes > max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| *key);
+            match oldest.and_then(|key| self.entries.remove(&key)) {
+                Some((evicted, _)) => self.used_bytes -= evicted.as_raw().len(),
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Asks a running comparison, edit or [`FileManager`](crate::FileManager) cycle to stop.
+///
+/// Clones share the same flag, so one clone can be handed to the work and another kept to
+/// cancel it, e.g. from a signal handler or when a client disconnects. The work checks the
+/// flag between pages and returns a `Cancelled` error once it's set.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the work of every clone of this token. Can't be undone.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
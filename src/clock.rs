@@ -0,0 +1,54 @@
+use {
+    chrono::{DateTime, Utc},
+    std::sync::Mutex,
+};
+
+/// Source of the current time used for output names and report timestamps. Replaceable so
+/// embedders and tests get reproducible names, see [`FixedClock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+impl FixedClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        FixedClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.time() = now;
+    }
+
+    pub fn advance(&self, duration: chrono::Duration) {
+        *self.time() += duration;
+    }
+
+    fn time(&self) -> std::sync::MutexGuard<'_, DateTime<Utc>> {
+        self.now
+            .lock()
+            .expect("The time is only locked to read or replace it")
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.time()
+    }
+}
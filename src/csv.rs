@@ -0,0 +1,74 @@
+use {
+    crate::files::{FileReport, RunReport},
+    std::{fs::OpenOptions, io::Write, path::Path},
+};
+
+/// First line of a csv report, naming the columns of [`csv_rows`].
+pub const CSV_HEADER: &str = concat!(
+    "timestamp,path,outcome,pages_total,pages_changed,pages_inserted,pages_removed,",
+    "max_change_fraction,output\n"
+);
+
+/// One line per file of `report`, in the columns of [`CSV_HEADER`]. Changed pages are the
+/// modified pages matched to a page of the previous version, inserted ones the pages
+/// without a match. Fields are quoted as needed, lines end with `\n`.
+pub fn csv_rows(report: &RunReport) -> String {
+    let timestamp = report.timestamp.rfc3339();
+    report
+        .files
+        .iter()
+        .map(|file| csv_row(&timestamp, file))
+        .collect()
+}
+
+fn csv_row(timestamp: &str, file: &FileReport) -> String {
+    let changed = |matched: bool| {
+        file.pages
+            .iter()
+            .filter(|page| page.changed() && page.matched_page.is_some() == matched)
+            .count()
+    };
+    let max_change = file
+        .pages
+        .iter()
+        .map(|page| page.changed_fraction)
+        .fold(0., f64::max);
+    let fields = [
+        timestamp.to_string(),
+        file.path.to_string_lossy().into_owned(),
+        file.result.name().to_string(),
+        file.pages.len().to_string(),
+        changed(true).to_string(),
+        changed(false).to_string(),
+        file.removed_pages.len().to_string(),
+        max_change.to_string(),
+        file.output
+            .as_ref()
+            .map(|output| output.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    ];
+    let mut row = fields.map(|field| escape(&field)).join(",");
+    row.push('\n');
+    row
+}
+
+/// Quotes `field` if it contains a separator, a quote or a line break, doubling quotes.
+fn escape(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+/// Appends the rows of `report` to the csv file at `path`, creating it with a header if
+/// it doesn't exist or is empty. All rows are appended with a single write, so runs
+/// appending to the same file at the same time don't interleave their rows.
+pub fn append_csv_report(path: &Path, report: &RunReport) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut content = match file.metadata()?.len() {
+        0 => String::from(CSV_HEADER),
+        _ => String::new(),
+    };
+    content.push_str(&csv_rows(report));
+    file.write_all(content.as_bytes())
+}
@@ -0,0 +1,495 @@
+#[cfg(feature = "serde")]
+use {
+    crate::{
+        memory::RenderMemory,
+        paper::PageSizeChange,
+        pdf::{Comparison, DocumentChanges, DocumentComparison, Thumbnails},
+    },
+    pdfium_render::prelude::PdfPageIndex,
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::{HashMap, HashSet},
+        io,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+    tokio::{fs::metadata, io::AsyncReadExt},
+};
+use {
+    sha2::{Digest, Sha256},
+    std::fmt::Write,
+};
+
+/// Name of the digest cache inside `diff_path`.
+#[cfg(feature = "serde")]
+pub(crate) const DIGEST_CACHE_FILE_NAME: &str = ".pdf_diff_gen.digests.json";
+
+/// Name of the [`BaselineState`] inside `diff_path`.
+#[cfg(feature = "serde")]
+pub(crate) const BASELINE_STATE_FILE_NAME: &str = ".pdf_diff_gen.baselines.json";
+
+/// Name of the [`ComparisonCache`] inside `diff_path`.
+#[cfg(feature = "serde")]
+pub(crate) const COMPARISON_CACHE_FILE_NAME: &str = ".pdf_diff_gen.comparisons.json";
+
+/// SHA-256 digest of `content`, the same [`file_digest`] computes for a file.
+pub(crate) fn content_digest(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+/// Lowercase hex encoding of `digest`.
+pub(crate) fn hex_digest(digest: &[u8]) -> String {
+    let mut hex = String::with_capacity(digest.len() * 2);
+    digest.iter().for_each(|byte| {
+        let _ = write!(hex, "{:02x}", byte);
+    });
+    hex
+}
+
+/// SHA-256 digest of the content of the file at `path`.
+#[cfg(feature = "serde")]
+pub(crate) async fn file_digest(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok(hasher.finalize().into());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// Like [`file_digest`], for callers that can't await.
+#[cfg(feature = "serde")]
+pub(crate) fn file_digest_blocking(path: &Path) -> io::Result<[u8; 32]> {
+    use std::io::Read;
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize().into());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedDigest {
+    len: u64,
+    modified: Option<SystemTime>,
+    digest: [u8; 32],
+}
+
+#[cfg(feature = "serde")]
+impl CachedDigest {
+    /// Digest of the file at `path`, taken from `cached` if size and modification time of the
+    /// file still match it.
+    async fn read(path: &Path, cached: Option<&CachedDigest>) -> io::Result<Self> {
+        let metadata = metadata(path).await?;
+        let (len, modified) = (metadata.len(), metadata.modified().ok());
+        if let Some(cached) = cached {
+            if cached.len == len && cached.modified.is_some() && cached.modified == modified {
+                return Ok(cached.clone());
+            }
+        }
+        Ok(CachedDigest {
+            len,
+            modified,
+            digest: file_digest(path).await?,
+        })
+    }
+}
+
+/// Content digests of files, kept in a file between cycles so unchanged files aren't read
+/// again. An entry is used as long as size and modification time of its file match.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub(crate) struct DigestCache {
+    path: PathBuf,
+    entries: HashMap<String, CachedDigest>,
+    /// Entries looked up since loading, only these are saved again.
+    used: HashSet<String>,
+    dirty: bool,
+}
+
+#[cfg(feature = "serde")]
+impl DigestCache {
+    /// Loads the cache stored at `path`. A missing or unreadable cache starts empty.
+    pub(crate) async fn load(path: PathBuf) -> Self {
+        DigestCache {
+            entries: load_entries(&path, "digest cache").await,
+            path,
+            used: HashSet::new(),
+            dirty: false,
+        }
+    }
+
+    /// Digest of the file at `file`, read from the cache if the file didn't change.
+    pub(crate) async fn digest(&mut self, file: &Path) -> io::Result<[u8; 32]> {
+        let key = entry_key(file);
+        self.used.insert(key.clone());
+        let cached = CachedDigest::read(file, self.entries.get(&key)).await?;
+        let digest = cached.digest;
+        if self
+            .entries
+            .insert(key, cached)
+            .is_none_or(|v| v.digest != digest)
+        {
+            self.dirty = true;
+        }
+        Ok(digest)
+    }
+
+    /// Writes the cache back, dropping the entries that weren't looked up.
+    pub(crate) async fn save(mut self) -> io::Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|key, _| self.used.contains(key));
+        if !self.dirty && self.entries.len() == before {
+            return Ok(());
+        }
+        save_entries(&self.path, &self.entries).await
+    }
+}
+
+/// Entries of the state file at `path`, `what` names it in warnings. A missing or unreadable
+/// file has no entries.
+#[cfg(feature = "serde")]
+async fn load_entries<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    what: &str,
+) -> HashMap<String, T> {
+    match tokio::fs::read(path).await {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            log::warn!(
+                "Discarding the {} {}. Error: {}",
+                what,
+                path.to_string_lossy(),
+                e
+            );
+            HashMap::new()
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+        Err(e) => {
+            log::warn!(
+                "Unable to read the {} {}. Error: {}",
+                what,
+                path.to_string_lossy(),
+                e
+            );
+            HashMap::new()
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+async fn save_entries<T: Serialize>(path: &Path, entries: &HashMap<String, T>) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let content = serde_json::to_vec(entries).map_err(io::Error::other)?;
+    tokio::fs::write(path, content).await
+}
+
+/// Digests of a file compared against a shared baseline and of the baseline, as they were
+/// when the file was last processed.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct BaselineEntry {
+    baseline: PathBuf,
+    file: CachedDigest,
+    baseline_file: CachedDigest,
+}
+
+#[cfg(feature = "serde")]
+impl BaselineEntry {
+    /// Whether both digests match the ones of `other`.
+    fn same_content(&self, other: &BaselineEntry) -> bool {
+        self.baseline == other.baseline
+            && self.file.digest == other.file.digest
+            && self.baseline_file.digest == other.baseline_file.digest
+    }
+}
+
+/// State of the files compared against shared baselines, kept in a file between cycles. It
+/// takes the place of the copies these files don't get: a file is compared again once its
+/// content or the content of its baseline differs from its entry.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub(crate) struct BaselineState {
+    path: PathBuf,
+    entries: HashMap<String, BaselineEntry>,
+    /// Entries looked up since loading, only these are saved again.
+    used: HashSet<String>,
+    dirty: bool,
+}
+
+#[cfg(feature = "serde")]
+impl BaselineState {
+    /// Loads the state stored at `path`. A missing or unreadable state starts empty, which
+    /// compares every file once.
+    pub(crate) async fn load(path: PathBuf) -> Self {
+        BaselineState {
+            entries: load_entries(&path, "baseline state").await,
+            path,
+            used: HashSet::new(),
+            dirty: false,
+        }
+    }
+
+    /// Current state of the file `key` at `file` and its `baseline`, and whether it is the
+    /// one the file was last processed in. Files are only read if their size or modification
+    /// time changed since.
+    pub(crate) async fn check(
+        &mut self,
+        key: &str,
+        file: &Path,
+        baseline: &Path,
+    ) -> io::Result<(BaselineEntry, bool)> {
+        self.used.insert(key.to_string());
+        let previous = self.entries.get(key);
+        let same_baseline = previous.filter(|v| v.baseline == baseline);
+        let entry = BaselineEntry {
+            baseline: baseline.to_path_buf(),
+            file: CachedDigest::read(file, previous.map(|v| &v.file)).await?,
+            baseline_file: CachedDigest::read(baseline, same_baseline.map(|v| &v.baseline_file))
+                .await?,
+        };
+        let current = same_baseline.is_some_and(|v| v.same_content(&entry));
+        if current && previous != Some(&entry) {
+            // Keeps the new modification times, so touched files aren't read again.
+            self.record(key.to_string(), entry.clone());
+        }
+        Ok((entry, current))
+    }
+
+    /// Records that the file `key` was processed in the state `entry`.
+    pub(crate) fn record(&mut self, key: String, entry: BaselineEntry) {
+        self.used.insert(key.clone());
+        self.entries.insert(key, entry);
+        self.dirty = true;
+    }
+
+    /// Writes the state back, dropping the entries that weren't looked up.
+    pub(crate) async fn save(mut self) -> io::Result<()> {
+        let before = self.entries.len();
+        self.entries.retain(|key, _| self.used.contains(key));
+        if !self.dirty && self.entries.len() == before {
+            return Ok(());
+        }
+        save_entries(&self.path, &self.entries).await
+    }
+}
+
+/// Outcome of comparing a file, as kept by the [`ComparisonCache`]. Thumbnails and renders
+/// aren't kept.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CachedComparison {
+    pages: Vec<Comparison>,
+    changes: DocumentChanges,
+    new_file: bool,
+    previous_pages: Option<PdfPageIndex>,
+    memory: RenderMemory,
+    blank_pages: Vec<PdfPageIndex>,
+    previous_blank_pages: Vec<PdfPageIndex>,
+    ink_coverage: Vec<Option<f64>>,
+    #[serde(default)]
+    size_changes: Vec<Option<PageSizeChange>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<&DocumentComparison> for CachedComparison {
+    fn from(comparison: &DocumentComparison) -> Self {
+        CachedComparison {
+            pages: comparison.pages.clone(),
+            changes: comparison.changes.clone(),
+            new_file: comparison.new_file,
+            previous_pages: comparison.thumbnails.previous_page_count,
+            memory: comparison.memory.clone(),
+            blank_pages: comparison.blank_pages.clone(),
+            previous_blank_pages: comparison.previous_blank_pages.clone(),
+            ink_coverage: comparison.ink_coverage.clone(),
+            size_changes: comparison.size_changes.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<CachedComparison> for DocumentComparison {
+    fn from(cached: CachedComparison) -> Self {
+        DocumentComparison {
+            pages: cached.pages,
+            thumbnails: Thumbnails {
+                pages: Vec::new(),
+                previous_page_count: cached.previous_pages,
+            },
+            changes: cached.changes,
+            new_file: cached.new_file,
+            renders: Vec::new(),
+            memory: cached.memory,
+            pages_rendered: 0,
+            blank_pages: cached.blank_pages,
+            previous_blank_pages: cached.previous_blank_pages,
+            ink_coverage: cached.ink_coverage,
+            size_changes: cached.size_changes,
+        }
+    }
+}
+
+/// What a comparison of a file depends on: the digests of the current version, of the
+/// version it is compared against, `None` if there is none, and of the comparison settings,
+/// along with the versions of this crate and of pdfium.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ComparisonKey {
+    pub(crate) current: [u8; 32],
+    pub(crate) previous: Option<[u8; 32]>,
+    pub(crate) settings: String,
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for ComparisonKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            hex_digest(&self.current),
+            self.previous
+                .map_or_else(|| String::from("none"), |v| hex_digest(&v)),
+            self.settings
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ComparisonEntry {
+    key: String,
+    comparison: CachedComparison,
+}
+
+/// The last comparison of every file, kept in a file between cycles. A file whose content,
+/// the content of the version it is compared against and the comparison settings match
+/// its entry isn't compared again, its diff is marked from the entry.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub(crate) struct ComparisonCache {
+    path: PathBuf,
+    entries: HashMap<String, ComparisonEntry>,
+    dirty: bool,
+}
+
+#[cfg(feature = "serde")]
+impl ComparisonCache {
+    /// Loads the cache stored at `path`. A missing or unreadable cache starts empty.
+    pub(crate) async fn load(path: PathBuf) -> Self {
+        ComparisonCache {
+            entries: load_entries(&path, "comparison cache").await,
+            path,
+            dirty: false,
+        }
+    }
+
+    /// The comparison of the file at `file` recorded under `key`.
+    pub(crate) fn get(&self, file: &Path, key: &ComparisonKey) -> Option<CachedComparison> {
+        let entry = self.entries.get(&entry_key(file))?;
+        (entry.key == key.to_string()).then(|| entry.comparison.clone())
+    }
+
+    /// Records `comparison` as the comparison of the file at `file` under `key`, replacing
+    /// the one recorded before.
+    pub(crate) fn insert(
+        &mut self,
+        file: &Path,
+        key: &ComparisonKey,
+        comparison: CachedComparison,
+    ) {
+        let key = key.to_string();
+        self.entries
+            .insert(entry_key(file), ComparisonEntry { key, comparison });
+        self.dirty = true;
+    }
+
+    /// Writes the cache back, dropping the entries of files that no longer exist.
+    pub(crate) async fn save(mut self) -> io::Result<()> {
+        let before = self.entries.len();
+        self.entries
+            .retain(|file, _| entry_path(file).is_some_and(|v| v.exists()));
+        if !self.dirty && self.entries.len() == before {
+            return Ok(());
+        }
+        save_entries(&self.path, &self.entries).await
+    }
+}
+
+/// Key of the entry of `file` in a state file: the path itself, or for paths that aren't
+/// valid UTF-8 the hex encoded bytes of the path behind a NUL, which no path contains.
+#[cfg(feature = "serde")]
+fn entry_key(file: &Path) -> String {
+    match file.to_str() {
+        Some(file) => file.to_string(),
+        None => format!("\0{}", hex_digest(file.as_os_str().as_encoded_bytes())),
+    }
+}
+
+/// Path of the entry `key`, see [`entry_key`]. `None` for the keys of paths that aren't valid
+/// UTF-8 outside Unix, where the bytes of a path can't be turned back into one.
+#[cfg(feature = "serde")]
+fn entry_path(key: &str) -> Option<PathBuf> {
+    match key.strip_prefix('\0') {
+        None => Some(PathBuf::from(key)),
+        #[cfg(unix)]
+        Some(hex) => {
+            use std::os::unix::ffi::OsStringExt;
+            let bytes = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+                .collect::<Option<Vec<u8>>>()?;
+            Some(std::ffi::OsString::from_vec(bytes).into())
+        }
+        #[cfg(not(unix))]
+        Some(_) => None,
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streamed_digests_match_the_digest_of_the_content() {
+        let path =
+            std::env::temp_dir().join(format!("pdf_diff_gen-digest-{}.pdf", std::process::id()));
+        // Longer than the buffer, with a partial last read.
+        let content = (0..200_000u32).map(|v| v as u8).collect::<Vec<u8>>();
+        std::fs::write(&path, &content).unwrap();
+        let expected = content_digest(&content);
+        assert_eq!(file_digest_blocking(&path).unwrap(), expected);
+        let streamed = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(file_digest(&path))
+            .unwrap();
+        assert_eq!(streamed, expected);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn entry_keys_of_paths_that_arent_utf8_are_distinct_and_reversible() {
+        use std::os::unix::ffi::OsStrExt;
+        let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"/srv/plans/caf\xe9.pdf"));
+        // The same name as `latin1` when decoded lossily.
+        let other = Path::new(std::ffi::OsStr::from_bytes(b"/srv/plans/caf\xff.pdf"));
+        let utf8 = Path::new("/srv/plans/caf\u{e9}.pdf");
+        assert_ne!(entry_key(latin1), entry_key(other));
+        assert_eq!(entry_key(utf8), "/srv/plans/caf\u{e9}.pdf");
+        for path in [latin1, other, utf8] {
+            assert_eq!(entry_path(&entry_key(path)).as_deref(), Some(path));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use {image::RgbImage, std::collections::HashMap};
+
+/// Difference of a color channel to the background up to which a pixel still counts as
+/// background, so paper tone and scanner noise aren't taken for ink.
+const NOISE_LEVEL: u8 = 63;
+
+/// Fraction of the pixels of `image` that differ from `background`, 0 for an empty image.
+pub(crate) fn ink_coverage(image: &RgbImage, background: [u8; 3]) -> f64 {
+    let pixels = image.width() as f64 * image.height() as f64;
+    if pixels == 0. {
+        return 0.;
+    }
+    let ink = image
+        .pixels()
+        .filter(|pixel| {
+            pixel
+                .0
+                .iter()
+                .zip(background)
+                .any(|(v, background)| v.abs_diff(background) > NOISE_LEVEL)
+        })
+        .count();
+    ink as f64 / pixels
+}
+
+/// What a page of the current version was aligned with by [`align_sheets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SheetMatch {
+    /// The page at the same position of the matched sheet of the previous version.
+    Pair(usize),
+    /// A blank page the matched sheet of the previous version lacks, which ends at the
+    /// contained page.
+    Tolerated(usize),
+    /// The page is part of an inserted sheet, or a page of a sheet the previous version has
+    /// fewer pages of.
+    Unmatched,
+}
+
+/// Aligns the pages of the current version `a` with the pages of the previous version `b`
+/// in sheets of `sheet_pages` pages, given which pages of either version are blank.
+///
+/// Either version may lack blank pages at the end of a sheet, e.g. the back of a duplex scan
+/// dropped by the scanner, which only shortens that sheet instead of shifting every later
+/// one. Sheets keep their order, a sheet that moved counts as removed and inserted.
+///
+/// `cost` is the fraction of differing pixels of a page pair, `None` if they can't be
+/// matched. Pairs of blank pages cost nothing, unmatched pages and blank pages paired with
+/// pages that aren't blank cost 1 each. The pairs are looked up once each.
+pub(crate) fn align_sheets<E>(
+    (blank_a, blank_b): (&[bool], &[bool]),
+    sheet_pages: usize,
+    mut cost: impl FnMut(usize, usize) -> Result<Option<f64>, E>,
+) -> Result<Vec<SheetMatch>, E> {
+    let (pages_a, pages_b) = (blank_a.len(), blank_b.len());
+    let n = sheet_pages.max(1);
+    let index = |a: usize, b: usize| a * (pages_b + 1) + b;
+    // Cost of the best alignment of the first pages of both versions, with the page counts
+    // of the last sheet pair of it.
+    let mut best: Vec<Option<(f64, usize, usize)>> = vec![None; (pages_a + 1) * (pages_b + 1)];
+    best[0] = Some((0., 0, 0));
+    let mut costs = HashMap::new();
+    for a in 0..=pages_a {
+        for b in 0..=pages_b {
+            let Some((base, ..)) = best[index(a, b)] else {
+                continue;
+            };
+            // Full sheets are tried first and win ties.
+            for sheet_a in (0..=n.min(pages_a - a)).rev() {
+                for sheet_b in (0..=n.min(pages_b - b)).rev() {
+                    let full_a = sheet_a == n || a + sheet_a == pages_a;
+                    let full_b = sheet_b == n || b + sheet_b == pages_b;
+                    let paired = sheet_a.min(sheet_b);
+                    let unpaired = match (sheet_a, sheet_b) {
+                        (0, 0) => continue,
+                        (0, _) if full_b => sheet_b as f64,
+                        (_, 0) if full_a => sheet_a as f64,
+                        (0, _) | (_, 0) => continue,
+                        _ => {
+                            let (extra, full, shorter_full) = match sheet_a >= sheet_b {
+                                true => (&blank_a[a + paired..a + sheet_a], full_a, full_b),
+                                false => (&blank_b[b + paired..b + sheet_b], full_b, full_a),
+                            };
+                            let content = extra.iter().filter(|v| !**v).count();
+                            // A sheet is only short by blank pages, or at the end.
+                            if !full || (!shorter_full && (content > 0 || extra.is_empty())) {
+                                continue;
+                            }
+                            content as f64
+                        }
+                    };
+                    let mut total = base + unpaired;
+                    for page in 0..paired {
+                        let (page_a, page_b) = (a + page, b + page);
+                        if blank_a[page_a] || blank_b[page_b] {
+                            // Blank pages only match blank pages.
+                            total += match blank_a[page_a] && blank_b[page_b] {
+                                true => 0.,
+                                false => 1.,
+                            };
+                            continue;
+                        }
+                        let pair_cost = match costs.get(&(page_a, page_b)) {
+                            Some(pair_cost) => *pair_cost,
+                            None => {
+                                let pair_cost = cost(page_a, page_b)?;
+                                costs.insert((page_a, page_b), pair_cost);
+                                pair_cost
+                            }
+                        };
+                        total += pair_cost.unwrap_or(1.);
+                    }
+                    let next = &mut best[index(a + sheet_a, b + sheet_b)];
+                    if next.is_none_or(|(cost, ..)| total < cost) {
+                        *next = Some((total, sheet_a, sheet_b));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut matches = vec![SheetMatch::Unmatched; pages_a];
+    let (mut a, mut b) = (pages_a, pages_b);
+    while let Some((_, sheet_a, sheet_b)) = best[index(a, b)].filter(|_| a + b > 0) {
+        (a, b) = (a - sheet_a, b - sheet_b);
+        for page in 0..sheet_a {
+            matches[a + page] = match page < sheet_b {
+                true => SheetMatch::Pair(b + page),
+                false if sheet_b > 0 && blank_a[a + page] => SheetMatch::Tolerated(b + sheet_b - 1),
+                false => SheetMatch::Unmatched,
+            };
+        }
+    }
+    Ok(matches)
+}
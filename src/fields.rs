@@ -0,0 +1,93 @@
+use {pdfium_render::prelude::*, std::collections::BTreeMap};
+
+/// How a value differs between the versions, serialized as `"added"`, `"removed"` or
+/// `"changed"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A form field whose value differs between the previous and the current version.
+///
+/// Field values aren't always visible on the rendered pages, e.g. for fields without an
+/// appearance or with rendering of form data disabled, so they are compared on their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FieldChange {
+    /// Fully qualified name of the field.
+    pub name: String,
+    pub kind: ChangeKind,
+    /// Value in the previous version, `None` for added fields and fields without a value.
+    pub previous: Option<String>,
+    /// Value in the current version, `None` for removed fields and fields without a value.
+    pub current: Option<String>,
+}
+
+impl std::fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: &Option<String>| v.clone().unwrap_or_default();
+        match self.kind {
+            ChangeKind::Added => write!(f, "{} added: {:?}", self.name, value(&self.current)),
+            ChangeKind::Removed => {
+                write!(f, "{} removed: {:?}", self.name, value(&self.previous))
+            }
+            ChangeKind::Changed => write!(
+                f,
+                "{} changed: {:?} -> {:?}",
+                self.name,
+                value(&self.previous),
+                value(&self.current)
+            ),
+        }
+    }
+}
+
+/// Names and values of all form fields of `document`, empty if it has no form.
+fn field_values(document: &PdfDocument) -> BTreeMap<String, Option<String>> {
+    match document.form() {
+        Some(form) => form.field_values(document.pages()).into_iter().collect(),
+        None => BTreeMap::new(),
+    }
+}
+
+/// Compares the values of `current` and `previous` by key, returning every key whose value
+/// differs, ordered by key, with the values of both sides if present.
+pub(crate) fn diff_values<V: PartialEq>(
+    current: BTreeMap<String, V>,
+    mut previous: BTreeMap<String, V>,
+) -> Vec<(String, ChangeKind, Option<V>, Option<V>)> {
+    let mut changes = Vec::new();
+    for (key, value) in current {
+        match previous.remove(&key) {
+            None => changes.push((key, ChangeKind::Added, None, Some(value))),
+            Some(previous) if previous != value => {
+                changes.push((key, ChangeKind::Changed, Some(previous), Some(value)))
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(
+        previous
+            .into_iter()
+            .map(|(key, value)| (key, ChangeKind::Removed, Some(value), None)),
+    );
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    changes
+}
+
+/// Every field of `a` and `b` whose value differs, ordered by name.
+pub(crate) fn field_changes(a: &PdfDocument, b: &PdfDocument) -> Vec<FieldChange> {
+    diff_values(field_values(a), field_values(b))
+        .into_iter()
+        .map(|(name, kind, previous, current)| FieldChange {
+            name,
+            kind,
+            previous: previous.flatten(),
+            current: current.flatten(),
+        })
+        .collect()
+}
@@ -1,265 +1,3477 @@
 use {
     futures::{future::BoxFuture, FutureExt},
-    pdfium_render::prelude::Pdfium,
+    globset::GlobSet,
+    image::{ImageFormat, RgbImage},
+    pdfium_render::prelude::PdfPageIndex,
+    serde::{Deserialize, Serialize},
     std::{
-        collections::HashMap,
-        ffi::OsStr,
+        collections::{BTreeMap, HashMap, HashSet},
         fs::{FileType, Metadata},
+        future::Future,
         io,
         path::{Path, PathBuf},
-        sync::Arc,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant, SystemTime},
     },
-    tokio::fs::{copy, create_dir_all, metadata, read_dir},
+    tokio::{
+        fs::{copy, create_dir_all, metadata, read_dir, remove_file, rename, write},
+        io::AsyncReadExt,
+    },
+};
+
+use crate::{
+    baselines::BaselineOverrides,
+    cancel::CancellationToken,
+    clock::{Clock, SystemClock},
+    digests::{
+        file_digest, file_digest_blocking, BaselineEntry, BaselineState, CachedComparison,
+        ComparisonCache, ComparisonKey, DigestCache, BASELINE_STATE_FILE_NAME,
+        COMPARISON_CACHE_FILE_NAME, DIGEST_CACHE_FILE_NAME,
+    },
+    flip::{encode_flip_gif, FlipGifConfig},
+    hook::{run_hooks, HookConfig, HookInvocation, HookOutcome},
+    isolation::{self, IsolationConfig, IsolationError},
+    memory::RenderMemory,
+    metrics::metrics,
+    notify::{notify, WebhookConfig},
+    paper::PageSizeChange,
+    pdf::{
+        ChangeScore, Comparison, DocumentChanges, DocumentComparison, DocumentSide, LoadedDocument,
+        LoadedDocuments, MarkOutcome, PDFComparison, PDFComparisonError, PDFEditorError,
+        PageChange, PageChangeKind, PageRenders, PageStatistics, RenderSettings, Thumbnails,
+    },
+    report::{render_html_report, HtmlDocument},
+    severity::{Severity, SeverityRules},
+    sheet::{render_contact_sheets, ContactSheetConfig},
+    sink::{CycleDocument, DiffSink, DiffSinkError},
+    status::{CycleCounts, CycleStatus, STATUS_FILE_NAME},
+    timestamp::{Timestamp, Timezone},
+    timings::Timings,
+    version::PdfiumVersion,
 };
 
-use crate::pdf::{Comparison, PDFComparison, PDFComparisonError, PDFEditor, PDFEditorError};
+/// Width of the page thumbnails in the html report in pixels.
+const HTML_THUMBNAIL_WIDTH: u32 = 160;
+
+/// File system step of [`FileManager`] an error occurred in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileOperation {
+    ScanningDirectory,
+    ReadingMetadata,
+    CreatingDirectory,
+    UpdatingCopy,
+    WritingReport,
+    WritingContactSheet,
+    WritingFlipGif,
+}
+
+/// Errors of [`FileManager`].
+#[derive(Debug)]
+pub enum FileManagerError {
+    Io {
+        path: PathBuf,
+        operation: FileOperation,
+        source: io::Error,
+    },
+    PDFComparisonError(PDFComparisonError),
+    DiffSinkError(DiffSinkError),
+    ChangedDuringProcessing(PathBuf),
+    /// The copy in `last_path` didn't match the file after copying and was discarded,
+    /// keeping the previous copy.
+    CopyMismatch(PathBuf),
+    /// The file is larger than `max_file_size` and wasn't compared.
+    TooLarge {
+        path: PathBuf,
+        size: u64,
+        limit: u64,
+    },
+    /// The directory given as `name` is `other` or inside it, see
+    /// [`FileManager::check_paths`]. `path` is the canonical path of `name`.
+    OverlappingPaths {
+        name: &'static str,
+        other: &'static str,
+        path: PathBuf,
+        nested: bool,
+    },
+    /// The directory is nested deeper than `max_depth` below `current_path`.
+    TooDeep {
+        path: PathBuf,
+        limit: usize,
+    },
+    /// Comparing the file or writing its diff took longer than `file_timeout` and was
+    /// abandoned.
+    Timeout {
+        path: PathBuf,
+        limit: Duration,
+    },
+    /// The cycle was cancelled before the file was done.
+    Cancelled(PathBuf),
+    /// The copy of the previous version exists but can't be read or loaded, e.g. because it
+    /// is damaged. It is kept as is instead of being replaced.
+    BrokenBaseline {
+        copy: PathBuf,
+        source: PDFComparisonError,
+    },
+    /// The shared baseline the file is compared against, see
+    /// [`FileManagerConfig::baselines`], doesn't exist.
+    MissingBaseline {
+        path: PathBuf,
+        baseline: PathBuf,
+    },
+    /// Comparing the file in a child process failed, see [`FileManagerConfig::isolation`].
+    Isolation {
+        path: PathBuf,
+        source: IsolationError,
+    },
+    /// The file crashed the child process comparing it or timed out before and wasn't
+    /// modified since, so it isn't compared again.
+    Quarantined(PathBuf),
+}
+
+impl FileManagerError {
+    fn io(operation: FileOperation, path: &Path) -> impl FnOnce(io::Error) -> FileManagerError {
+        let path = path.to_path_buf();
+        move |source| FileManagerError::Io {
+            path,
+            operation,
+            source,
+        }
+    }
+
+    /// Why the file the error occurred for counts as skipped rather than failed, if it does.
+    fn skip_reason(&self) -> Option<SkipReason> {
+        match self {
+            Self::ChangedDuringProcessing(_) => Some(SkipReason::ChangedDuringProcessing),
+            Self::TooLarge { size, limit, .. } => Some(SkipReason::TooLarge {
+                size: *size,
+                limit: *limit,
+            }),
+            Self::PDFComparisonError(PDFComparisonError::TooManyPages { pages, limit, .. }) => {
+                Some(SkipReason::TooManyPages {
+                    pages: *pages,
+                    limit: *limit,
+                })
+            }
+            Self::PDFComparisonError(PDFComparisonError::Encrypted { .. }) => {
+                Some(SkipReason::Encrypted)
+            }
+            Self::Cancelled(_) => Some(SkipReason::Cancelled),
+            Self::Quarantined(_) => Some(SkipReason::Quarantined),
+            Self::DiffSinkError(DiffSinkError::Flapping {
+                outputs, window, ..
+            }) => Some(SkipReason::Flapping {
+                outputs: *outputs,
+                window: *window,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for FileManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            // Both already describe the document and operation, so they are displayed as is
+            // and only their sources are part of the chain.
+            Self::PDFComparisonError(e) | Self::BrokenBaseline { source: e, .. } => e.source(),
+            Self::DiffSinkError(e) => e.source(),
+            Self::Isolation { source, .. } => Some(source),
+            Self::ChangedDuringProcessing(_)
+            | Self::CopyMismatch(_)
+            | Self::TooLarge { .. }
+            | Self::OverlappingPaths { .. }
+            | Self::TooDeep { .. }
+            | Self::Timeout { .. }
+            | Self::Cancelled(_)
+            | Self::MissingBaseline { .. }
+            | Self::Quarantined(_) => None,
+        }
+    }
+}
+
+impl std::fmt::Display for FileManagerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io {
+                path,
+                operation,
+                source,
+            } => {
+                let path = path.to_string_lossy();
+                match operation {
+                    FileOperation::ScanningDirectory => write!(f, "failed to scan {}", path),
+                    FileOperation::ReadingMetadata => {
+                        write!(f, "failed to read the metadata of {}", path)
+                    }
+                    FileOperation::CreatingDirectory => {
+                        write!(f, "failed to create the directory {}", path)
+                    }
+                    FileOperation::UpdatingCopy => write!(f, "failed to update the copy {}", path),
+                    FileOperation::WritingReport => {
+                        write!(f, "failed to write the report {}", path)
+                    }
+                    FileOperation::WritingContactSheet => {
+                        write!(f, "failed to write the contact sheet {}", path)
+                    }
+                    FileOperation::WritingFlipGif => {
+                        write!(f, "failed to write the flip gif {}", path)
+                    }
+                }?;
+                write!(f, ": {}", source)
+            }
+            Self::PDFComparisonError(e) => write!(f, "{}", e),
+            Self::DiffSinkError(e) => write!(f, "{}", e),
+            Self::ChangedDuringProcessing(path) => write!(
+                f,
+                "{} changed on disk between comparing and marking it",
+                path.to_string_lossy()
+            ),
+            Self::CopyMismatch(path) => write!(
+                f,
+                "the copy {} didn't match the source, the previous copy was kept",
+                path.to_string_lossy()
+            ),
+            Self::TooLarge { path, size, limit } => write!(
+                f,
+                "{} has {} bytes, more than the limit of {}",
+                path.to_string_lossy(),
+                size,
+                limit
+            ),
+            Self::OverlappingPaths {
+                name,
+                other,
+                path,
+                nested: true,
+            } => write!(f, "{} {} is inside {}", name, path.to_string_lossy(), other),
+            Self::OverlappingPaths {
+                name,
+                other,
+                path,
+                nested: false,
+            } => write!(
+                f,
+                "{} and {} are the same directory {}",
+                name,
+                other,
+                path.to_string_lossy()
+            ),
+            Self::TooDeep { path, limit } => write!(
+                f,
+                "{} is nested more than {} directories deep",
+                path.to_string_lossy(),
+                limit
+            ),
+            Self::Timeout { path, limit } => write!(
+                f,
+                "{} took longer than {} and was abandoned",
+                path.to_string_lossy(),
+                humantime::format_duration(*limit)
+            ),
+            Self::Cancelled(path) => write!(f, "{} was cancelled", path.to_string_lossy()),
+            Self::BrokenBaseline { copy, source } => write!(
+                f,
+                "the previous version {} can't be loaded and was kept: {}",
+                copy.to_string_lossy(),
+                source
+            ),
+            Self::MissingBaseline { path, baseline } => write!(
+                f,
+                "the baseline {} of {} doesn't exist",
+                baseline.to_string_lossy(),
+                path.to_string_lossy()
+            ),
+            Self::Isolation { path, source } => write!(
+                f,
+                "failed to compare {} in a child process: {}",
+                path.to_string_lossy(),
+                source
+            ),
+            Self::Quarantined(path) => write!(
+                f,
+                "{} crashed or timed out before and is quarantined until it is modified",
+                path.to_string_lossy()
+            ),
+        }
+    }
+}
+
+impl From<PDFComparisonError> for FileManagerError {
+    fn from(value: PDFComparisonError) -> Self {
+        Self::PDFComparisonError(value)
+    }
+}
+
+impl From<DiffSinkError> for FileManagerError {
+    fn from(value: DiffSinkError) -> Self {
+        Self::DiffSinkError(value)
+    }
+}
+
+enum FileTypeEnum {
+    Dir,
+    File,
+}
+
+impl From<FileType> for FileTypeEnum {
+    fn from(value: FileType) -> Self {
+        if value.is_dir() {
+            Self::Dir
+        } else {
+            Self::File
+        }
+    }
+}
+
+impl From<&Metadata> for FileTypeEnum {
+    fn from(value: &Metadata) -> Self {
+        if value.is_dir() {
+            Self::Dir
+        } else {
+            Self::File
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileResult {
+    Changed,
+    /// A diff was written for a file without a previous version.
+    New,
+    Identical,
+    Renamed,
+    Removed,
+    Skipped,
+    Error,
+}
+
+impl FileResult {
+    /// The name the result is serialized as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Changed => "changed",
+            Self::New => "new",
+            Self::Identical => "identical",
+            Self::Renamed => "renamed",
+            Self::Removed => "removed",
+            Self::Skipped => "skipped",
+            Self::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileReport {
+    /// Path of the file relative to `current_path`. Paths that aren't valid UTF-8 are
+    /// serialized lossily, as are the outputs.
+    #[serde(serialize_with = "serialize_lossy_path")]
+    pub path: PathBuf,
+    pub result: FileResult,
+    /// See [`FileManagerConfig::severity`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    /// How much of a changed or removed file changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<ChangeScore>,
+    /// Whether the file reached [`FileManagerConfig::alert_threshold`], only these are
+    /// posted to the webhook.
+    #[serde(default)]
+    pub alert: bool,
+    /// The shared baseline the file was compared against instead of its copy, see
+    /// [`FileManagerConfig::baselines`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_lossy_optional_path"
+    )]
+    pub baseline: Option<PathBuf>,
+    /// Whether the copy of an identical file was brought up to date, see
+    /// [`FileOutcome::Unchanged`].
+    #[serde(default)]
+    pub copy_updated: bool,
+    /// Whether the file was only processed because it was forced, see
+    /// [`FileManager::force_next_cycle`].
+    #[serde(default)]
+    pub forced: bool,
+    /// Whether the pages weren't compared again but taken from an earlier cycle that
+    /// compared the same versions with the same settings.
+    #[serde(default)]
+    pub cached: bool,
+    #[serde(serialize_with = "serialize_lossy_optional_path")]
+    pub output: Option<PathBuf>,
+    /// The diff marked onto the previous version, if one was written.
+    #[serde(default, serialize_with = "serialize_lossy_optional_path")]
+    pub previous_output: Option<PathBuf>,
+    /// Previous path of a renamed file, relative to `current_path`.
+    #[serde(serialize_with = "serialize_lossy_optional_path")]
+    pub renamed_from: Option<PathBuf>,
+    pub error: Option<String>,
+    pub pages: Vec<PageStatistics>,
+    /// Pages of the previous version no page of the current version was matched against.
+    #[serde(default)]
+    pub removed_pages: Vec<usize>,
+    /// Blank pages of the current version, only detected when pages are matched in sheets,
+    /// see [`ComparisonConfig::sheet_pages`](crate::ComparisonConfig::sheet_pages).
+    #[serde(default)]
+    pub blank_pages: Vec<PdfPageIndex>,
+    /// Blank pages of the previous version no page was matched against, which aren't part
+    /// of `removed_pages`.
+    #[serde(default)]
+    pub removed_blank_pages: Vec<usize>,
+    /// Changed pages the diff contains without their markers.
+    #[serde(default)]
+    pub failed_pages: Vec<FailedPage>,
+    /// Flip gifs written for the changed pages, see [`FileManagerConfig::flip_gifs`].
+    #[serde(default, serialize_with = "serialize_lossy_paths")]
+    pub flip_gifs: Vec<PathBuf>,
+    pub comparison_seconds: f64,
+    /// Outcome of the diff hook, if one ran for the file.
+    pub hook: Option<HookOutcome>,
+    /// Seconds spent in every stage of comparing and marking the file.
+    #[serde(skip_deserializing)]
+    pub timings: Timings,
+    #[serde(default)]
+    pub render_memory: RenderMemory,
+    /// Why the comparison is less reliable than configured, e.g. a reduced resolution.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Form fields, metadata and attachments that changed, listed as `fields`, `metadata`
+    /// and `attachments` even if the pages look identical.
+    #[serde(flatten)]
+    pub changes: DocumentChanges,
+}
+
+/// A changed page of the current version that couldn't be marked, see
+/// [`MarkOutcome::PartialSuccess`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedPage {
+    /// Zero based index of the page.
+    pub page: usize,
+    pub error: String,
+}
+
+impl FailedPage {
+    fn from_outcome(outcome: &MarkOutcome) -> Vec<Self> {
+        outcome
+            .failed_pages()
+            .iter()
+            .map(|(page, e)| FailedPage {
+                page: *page as usize,
+                error: e.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Serializes `path` as a string, replacing invalid UTF-8 instead of failing.
+pub(crate) fn serialize_lossy_path<S: serde::Serializer>(
+    path: &Path,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+pub(crate) fn serialize_lossy_optional_path<S: serde::Serializer, P: AsRef<Path>>(
+    path: &Option<P>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serializer.serialize_some(&path.as_ref().to_string_lossy()),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn serialize_lossy_paths<S: serde::Serializer>(
+    paths: &[PathBuf],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(paths.iter().map(|path| path.to_string_lossy()))
+}
+
+/// Machine readable summary of a single `FileManager::update` cycle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RunReport {
+    /// Start of the cycle, which the diffs written in it are named after as well.
+    pub timestamp: Timestamp,
+    /// Name of the tree, see [`FileManagerConfig::entry`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// Version of the pdfium build that rendered the pages, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdfium_version: Option<PdfiumVersion>,
+    pub duration_seconds: f64,
+    pub files: Vec<FileReport>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileManagerConfig {
+    /// Writes a `report-<timestamp>.json` into `diff_path` after every update.
+    pub write_report: bool,
+    /// Directory a `report-<timestamp>.html` with thumbnails of changed pages is written to.
+    pub html_report: Option<PathBuf>,
+    /// Csv file a row per processed file is appended to after every update, see
+    /// [`crate::csv`].
+    pub csv_report: Option<PathBuf>,
+    /// Writes contact sheets of the pages of every changed document next to its diff, as
+    /// `<diff>.sheet.png` followed by `<diff>.sheet-2.png` and so on.
+    pub contact_sheet: Option<ContactSheetConfig>,
+    /// Writes a gif flipping between the previous and the current version of every changed
+    /// page matched to a previous page, as `<dir>/<path of the pdf>.p<page>.gif`. Needs
+    /// [`crate::ComparisonConfig::keep_renders`], without it no gifs are written.
+    pub flip_gifs: Option<FlipGifConfig>,
+    /// Command run for every successfully processed file. Its failures are only reported.
+    pub on_diff: Option<HookConfig>,
+    /// Receives the changed files of every cycle. Its failures are only logged.
+    pub webhook: Option<WebhookConfig>,
+    /// Files larger than this many bytes are skipped without being loaded.
+    pub max_file_size: Option<u64>,
+    /// Extensions of the files treated as pdfs, matched case-insensitively.
+    pub extensions: Vec<String>,
+    /// Also treats files without an extension as pdfs if they start with `%PDF-`.
+    pub sniff_content: bool,
+    /// Also scans files and directories whose names start with a dot.
+    pub include_hidden: bool,
+    /// Files and directories matching these patterns, relative to `current_path`, aren't
+    /// scanned.
+    pub ignore: GlobSet,
+    /// Retries of transient errors while scanning and updating the copies.
+    pub io_retry: IoRetryPolicy,
+    /// Compares the content of every updated copy with its source, not only the size.
+    pub verify_copies: bool,
+    /// What happens to files without a copy in `last_path`.
+    pub new_files: NewFilePolicy,
+    /// Matches files without a copy against the copies of files that disappeared from
+    /// `current_path` by content, treating them as renamed instead of new.
+    pub detect_renames: bool,
+    /// Whether symbolic links in `current_path` are followed.
+    pub symlinks: SymlinkPolicy,
+    /// Deepest directory below `current_path` that is scanned, deeper ones fail the scan.
+    pub max_depth: usize,
+    /// Updates the copy of files whose pages are identical but whose metadata, attachments
+    /// or form fields changed, so the change isn't reported again in every cycle.
+    pub copy_document_changes: bool,
+    /// Copies encrypted documents to `last_path` even though they can't be compared, so they
+    /// are skipped until they are modified instead of in every cycle.
+    pub copy_encrypted: bool,
+    /// Name of the tree when several are watched by one process. Labels the metrics and the
+    /// run reports.
+    pub entry: Option<String>,
+    /// Version of the pdfium build the comparison uses, recorded in the run reports.
+    pub pdfium_version: Option<PdfiumVersion>,
+    /// Rewrites a [`CycleStatus`] file after every `FileManager::update`.
+    pub write_status: bool,
+    /// Where the status file is written, [`STATUS_FILE_NAME`] in `diff_path` if `None`.
+    pub status_path: Option<PathBuf>,
+    /// Longest time comparing a single file and, separately, writing its diff may take.
+    /// Files exceeding it are abandoned between two pages, fail with
+    /// [`FileManagerError::Timeout`] and are quarantined until they are modified, see
+    /// [`SkipReason::Quarantined`].
+    pub file_timeout: Option<Duration>,
+    /// Order the files of a cycle are processed and reported in.
+    pub order: ProcessingOrder,
+    /// Rules assigning a [`Severity`] to every changed and removed file, which is part of
+    /// the reports, the webhook payloads and [`UpdateReport::severity`].
+    pub severity: Option<SeverityRules>,
+    /// Files compared against a shared baseline instead of their copies in `last_path`.
+    pub baselines: BaselineOverrides,
+    /// Only changed and removed files whose [`ChangeScore::changed_fraction`] is at least
+    /// this alert: they run the diff hook, are posted to the webhook and count towards
+    /// [`UpdateReport::alerts`]. Diffs are written for every changed file regardless.
+    pub alert_threshold: Option<f64>,
+    /// Compares [`ChangeScore::substantive_fraction`] against `alert_threshold`, so pages
+    /// only changed by redactions don't alert, see
+    /// [`ComparisonConfig::detect_redactions`](crate::ComparisonConfig::detect_redactions).
+    pub alert_ignores_redactions: bool,
+    /// Compares every file in a child process started like this instead of in process, so
+    /// a malformed document crashing pdfium only fails its own file. A file crashing the
+    /// child is quarantined until it is modified, see [`SkipReason::Quarantined`]. Its diff
+    /// is still written in process. Comparisons needing thumbnails or renders, for the html
+    /// report, the contact sheets or `keep_renders`, aren't isolated. Off by default,
+    /// starting a process per file is slower.
+    pub isolation: Option<IsolationConfig>,
+    /// Zone the timestamps of the reports, the status file and the names of the artifacts
+    /// are written in.
+    pub timezone: Timezone,
+}
+
+/// Order [`FileManager`] processes the files of a cycle in. Reports and the outcomes of
+/// [`UpdateReport::processed`] list the files in the same order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ProcessingOrder {
+    /// By path, ascending.
+    #[default]
+    Path,
+    /// Least recently modified first, files modified at the same time by path.
+    Mtime,
+}
+
+/// Files [`FileManager::force_next_cycle`] processes although they weren't modified.
+#[derive(Debug, Clone)]
+pub enum ForceSelection {
+    All,
+    /// Files matching these patterns, relative to `current_path`.
+    Matching(GlobSet),
+}
+
+/// How `FileManager` treats symbolic links found while scanning `current_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SymlinkPolicy {
+    /// Follows links to files and directories. Files are copied as their targets and
+    /// directories reached a second time, e.g. through a link loop, are skipped.
+    Follow,
+    /// Leaves links out like ignored entries.
+    #[default]
+    Skip,
+}
+
+/// How [`FileManager`] handles files that have no copy in `last_path` yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewFilePolicy {
+    /// Compares them against an empty document, which marks every page as added.
+    #[default]
+    Diff,
+    /// Only copies them to `last_path` and reports them as skipped with
+    /// [`SkipReason::NoBaseline`]. Useful for the first run against an existing archive.
+    SeedBaseline,
+}
+
+impl Default for FileManagerConfig {
+    fn default() -> Self {
+        FileManagerConfig {
+            write_report: true,
+            html_report: None,
+            csv_report: None,
+            contact_sheet: None,
+            flip_gifs: None,
+            on_diff: None,
+            webhook: None,
+            max_file_size: None,
+            extensions: vec![String::from("pdf")],
+            sniff_content: false,
+            include_hidden: false,
+            ignore: GlobSet::empty(),
+            io_retry: IoRetryPolicy::default(),
+            verify_copies: false,
+            new_files: NewFilePolicy::Diff,
+            detect_renames: true,
+            symlinks: SymlinkPolicy::Skip,
+            max_depth: 64,
+            copy_document_changes: true,
+            copy_encrypted: false,
+            entry: None,
+            pdfium_version: None,
+            write_status: true,
+            status_path: None,
+            file_timeout: None,
+            order: ProcessingOrder::Path,
+            severity: None,
+            baselines: BaselineOverrides::default(),
+            alert_threshold: None,
+            alert_ignores_redactions: false,
+            timezone: Timezone::Utc,
+            isolation: None,
+        }
+    }
+}
+
+/// How often file system operations failing with a transient error, e.g. `ESTALE` on a
+/// network mount, are retried before the error is reported.
+#[derive(Debug, Clone)]
+pub struct IoRetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying.
+    pub retries: u32,
+    /// Delay before every retry.
+    pub delay: Duration,
+}
+
+impl Default for IoRetryPolicy {
+    fn default() -> Self {
+        IoRetryPolicy {
+            retries: 3,
+            delay: Duration::from_millis(500),
+        }
+    }
+}
+
+impl IoRetryPolicy {
+    async fn run<T, F: Future<Output = io::Result<T>>>(
+        &self,
+        operation: FileOperation,
+        path: &Path,
+        mut attempt: impl FnMut() -> F,
+    ) -> io::Result<T> {
+        let mut retries = 0;
+        loop {
+            match attempt().await {
+                Err(e) if retries < self.retries && IoRetryPolicy::is_transient(&e) => {
+                    retries += 1;
+                    log::warn!(
+                        "Transient error during {:?} of {}, retry {}/{}. Error: {}",
+                        operation,
+                        path.to_string_lossy(),
+                        retries,
+                        self.retries,
+                        e
+                    );
+                    tokio::time::sleep(self.delay).await;
+                }
+                result => {
+                    if retries > 0 {
+                        log::info!(
+                            "{:?} of {} finished after {} retries",
+                            operation,
+                            path.to_string_lossy(),
+                            retries
+                        );
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    fn is_transient(error: &io::Error) -> bool {
+        matches!(
+            error.kind(),
+            io::ErrorKind::WouldBlock
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::ResourceBusy
+                | io::ErrorKind::StaleNetworkFileHandle
+        )
+    }
+}
+
+/// What happened to a single modified file during an update cycle.
+#[derive(Debug)]
+pub enum FileOutcome {
+    /// Every page is identical to the previous version within the configured tolerance.
+    /// `changes` are what changed nonetheless, e.g. metadata. With `copy_document_changes`
+    /// the copy was updated if there are any. `copy_updated` is set if the copy was updated
+    /// or got the modification time of the file, so it isn't compared again until it is
+    /// modified.
+    Unchanged {
+        timings: Timings,
+        memory: RenderMemory,
+        changes: DocumentChanges,
+        copy_updated: bool,
+    },
+    /// The file has the content of the file at `from`, which disappeared. Its copy was moved
+    /// along and no diff was generated.
+    Renamed {
+        from: PathBuf,
+    },
+    /// The sink processed the file. `out` is the artifact it wrote, if any, `previous_out`
+    /// the one marking the changes on the previous version, see
+    /// [`DiffSink::take_previous_version_output`]. `new_file` is set if there was no
+    /// previous version and every page counts as inserted. `failed_pages` are part of `out`
+    /// without their markers. `score` is how much of the document changed.
+    DiffWritten {
+        new_file: bool,
+        out: Option<PathBuf>,
+        previous_out: Option<PathBuf>,
+        failed_pages: Vec<FailedPage>,
+        stats: Vec<PageStatistics>,
+        score: ChangeScore,
+        timings: Timings,
+        memory: RenderMemory,
+        changes: DocumentChanges,
+    },
+    /// The file only exists in `last_path`, only reported by `compare_trees`. The path is
+    /// where the file would be in `current_path`.
+    Removed,
+    /// The file wasn't processed. It is picked up again in the next cycle unless its copy
+    /// was updated anyway, see [`SkipReason::NoBaseline`] and [`SkipReason::Encrypted`].
+    Skipped {
+        reason: SkipReason,
+    },
+    Failed {
+        error: FileManagerError,
+    },
+}
+
+impl FileOutcome {
+    /// How much of the document changed, [`ChangeScore::ENTIRE`] for a removed file. Only
+    /// changed and removed files have a score.
+    pub fn score(&self) -> Option<ChangeScore> {
+        match self {
+            Self::DiffWritten { score, .. } => Some(*score),
+            Self::Removed => Some(ChangeScore::ENTIRE),
+            _ => None,
+        }
+    }
+}
+
+/// Why a modified file wasn't processed.
+#[derive(Debug, Clone)]
+pub enum SkipReason {
+    /// The file changed on disk between comparing and marking it.
+    ChangedDuringProcessing,
+    /// The file is larger than `max_file_size`.
+    TooLarge { size: u64, limit: u64 },
+    /// The document has more pages than `max_pages`.
+    TooManyPages { pages: usize, limit: usize },
+    /// The file had no copy in `last_path` and was copied without a diff, see
+    /// [`NewFilePolicy::SeedBaseline`].
+    NoBaseline,
+    /// The document is encrypted. With `copy_encrypted` it was copied to `last_path`, so it
+    /// isn't examined again until it is modified.
+    Encrypted,
+    /// The cycle was cancelled before the file was done.
+    Cancelled,
+    /// The sink already wrote `outputs` diffs of the file within `window`, see
+    /// [`OutputLimitAction::Skip`](crate::sink::OutputLimitAction::Skip). The copy isn't
+    /// updated.
+    Flapping { outputs: usize, window: Duration },
+    /// The file crashed the child process comparing it or exceeded the `file_timeout` in an
+    /// earlier cycle and wasn't modified since, see [`FileManagerConfig::isolation`] and
+    /// [`FileManagerConfig::file_timeout`].
+    Quarantined,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ChangedDuringProcessing => {
+                write!(f, "changed on disk between comparing and marking it")
+            }
+            Self::TooLarge { size, limit } => {
+                write!(f, "{} bytes exceed the limit of {} bytes", size, limit)
+            }
+            Self::TooManyPages { pages, limit } => {
+                write!(f, "{} pages exceed the limit of {} pages", pages, limit)
+            }
+            Self::NoBaseline => write!(f, "no previous version, copied as the baseline"),
+            Self::Encrypted => write!(f, "encrypted and requires a password"),
+            Self::Cancelled => write!(f, "the cycle was cancelled"),
+            Self::Quarantined => write!(f, "crashed or timed out before, quarantined"),
+            Self::Flapping { outputs, window } => write!(
+                f,
+                "already got {} diffs within {}",
+                outputs,
+                humantime::format_duration(*window)
+            ),
+        }
+    }
+}
+
+/// Result of a single `FileManager::update` cycle.
+#[derive(Debug)]
+pub struct UpdateReport {
+    /// Every modified file and what happened to it.
+    pub processed: Vec<(PathBuf, FileOutcome)>,
+    pub duration: Duration,
+    /// Number of files found in `current_path`.
+    pub scanned_files: usize,
+    /// Number of files that weren't modified since the previous cycle.
+    pub skipped: usize,
+    /// Number of files and directories left out as hidden or matching an ignore pattern.
+    pub ignored: usize,
+    /// The artifact the sink combined the documents of the cycle into, if any.
+    pub combined: Option<PathBuf>,
+    /// Highest severity of the files of the cycle, `None` without
+    /// [`FileManagerConfig::severity`] or changed files.
+    pub severity: Option<Severity>,
+    /// Number of changed and removed files reaching [`FileManagerConfig::alert_threshold`],
+    /// all of them without one.
+    pub alerts: usize,
+}
+
+impl UpdateReport {
+    pub(crate) fn count(&self, predicate: impl Fn(&FileOutcome) -> bool) -> usize {
+        self.processed.iter().filter(|(_, v)| predicate(v)).count()
+    }
+
+    pub fn changed(&self) -> usize {
+        self.count(|v| matches!(v, FileOutcome::DiffWritten { .. }))
+    }
+
+    pub fn removed(&self) -> usize {
+        self.count(|v| matches!(v, FileOutcome::Removed))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|v| matches!(v, FileOutcome::Failed { .. }))
+    }
+
+    /// Time spent in every stage, summed over all files.
+    pub fn timings(&self) -> Timings {
+        let mut total = Timings::default();
+        for (_, outcome) in &self.processed {
+            if let FileOutcome::Unchanged { timings, .. }
+            | FileOutcome::DiffWritten { timings, .. } = outcome
+            {
+                total += *timings;
+            }
+        }
+        total
+    }
+}
+
+/// A one line summary of the cycle.
+impl std::fmt::Display for UpdateReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cycle finished in {:.1}s: {} scanned, {} ignored, {} unmodified, {} changed, {} unchanged, {} renamed, {} removed, {} skipped, {} failed",
+            self.duration.as_secs_f64(),
+            self.scanned_files,
+            self.ignored,
+            self.skipped,
+            self.changed(),
+            self.count(|v| matches!(v, FileOutcome::Unchanged { .. })),
+            self.count(|v| matches!(v, FileOutcome::Renamed { .. })),
+            self.removed(),
+            self.count(|v| matches!(v, FileOutcome::Skipped { .. })),
+            self.failed()
+        )
+    }
+}
+
+/// Whether `FileManager::run` is a cycle of the watch loop or a one-off `compare_trees`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunMode {
+    Watch,
+    Batch,
+}
+
+/// Result of handing a file to the sink and updating its copy: the written artifact, if any.
+type FileResultOutput = Result<Option<PathBuf>, FileManagerError>;
+
+/// What the sink wrote for a file in `FileManager::write_diff`.
+struct WrittenDiff {
+    out: Option<PathBuf>,
+    previous_out: Option<PathBuf>,
+    timings: Timings,
+    failed_pages: Vec<FailedPage>,
+}
+
+/// Modified files found by `find_updated_files`.
+#[derive(Default)]
+struct Scan {
+    /// Modified files with the paths of their copies.
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Files of `files` and `overridden` that weren't modified but are forced.
+    forced: HashSet<PathBuf>,
+    /// Every file compared against a shared baseline with the path of the baseline, modified
+    /// or not.
+    overridden: Vec<(PathBuf, PathBuf)>,
+    scanned: usize,
+    ignored: usize,
+}
+
+impl Scan {
+    fn append(&mut self, mut other: Scan) {
+        self.files.append(&mut other.files);
+        self.overridden.append(&mut other.overridden);
+        self.forced.extend(other.forced);
+        self.scanned += other.scanned;
+        self.ignored += other.ignored;
+    }
+}
+
+/// Files compared against shared baselines that `FileManager::select_overridden` picked.
+#[derive(Default)]
+struct OverriddenFiles {
+    /// Files to compare with their baselines.
+    selected: HashMap<PathBuf, PathBuf>,
+    /// State to record for the selected files once they are processed.
+    entries: HashMap<PathBuf, BaselineEntry>,
+    /// Files that can't be compared because their baseline is missing.
+    failed: Vec<(PathBuf, FileOutcome)>,
+}
+
+struct FileComparison {
+    comparisons: Result<Vec<Comparison>, FileManagerError>,
+    new_file: bool,
+    duration: Duration,
+    timings: Timings,
+    memory: RenderMemory,
+    thumbnails: Option<Thumbnails>,
+    renders: Vec<PageRenders>,
+    /// Page count of the previous version, if there is one.
+    previous_pages: Option<PdfPageIndex>,
+    blank_pages: Vec<PdfPageIndex>,
+    previous_blank_pages: Vec<PdfPageIndex>,
+    ink_coverage: Vec<Option<f64>>,
+    size_changes: Vec<Option<PageSizeChange>>,
+    changes: DocumentChanges,
+    fingerprint: Option<FileFingerprint>,
+    /// What handing the file to the sink resulted in, `None` if it wasn't handed to it.
+    output: Option<FileResultOutput>,
+    previous_output: Option<PathBuf>,
+    failed_pages: Vec<FailedPage>,
+    /// Whether the comparison was taken from the [`ComparisonCache`].
+    cached: bool,
+}
+
+/// A comparison and, if a page changed, both documents it was made of.
+type WithDocuments<'a> = (DocumentComparison, Option<LoadedDocuments<'a>>);
+
+impl FileComparison {
+    /// A file that failed with `error` before it was compared.
+    fn failed(
+        error: FileManagerError,
+        duration: Duration,
+        fingerprint: Option<FileFingerprint>,
+    ) -> Self {
+        FileComparison {
+            comparisons: Err(error),
+            new_file: false,
+            duration,
+            timings: Timings::default(),
+            memory: RenderMemory::default(),
+            thumbnails: None,
+            renders: Vec::new(),
+            previous_pages: None,
+            blank_pages: Vec::new(),
+            previous_blank_pages: Vec::new(),
+            ink_coverage: Vec::new(),
+            size_changes: Vec::new(),
+            changes: DocumentChanges::default(),
+            fingerprint,
+            output: None,
+            previous_output: None,
+            failed_pages: Vec::new(),
+            cached: false,
+        }
+    }
+}
+
+/// Everything a cycle learns about a compared file, kept together from its comparison to
+/// its outcome and report.
+#[derive(Default)]
+struct ComparedFile {
+    new_file: bool,
+    cached: bool,
+    duration: Duration,
+    timings: Timings,
+    memory: RenderMemory,
+    changes: DocumentChanges,
+    fingerprint: Option<FileFingerprint>,
+    /// Empty if the comparison failed, as are the other page lists.
+    statistics: Vec<PageStatistics>,
+    score: Option<ChangeScore>,
+    removed_pages: Vec<usize>,
+    blank_pages: Vec<PdfPageIndex>,
+    removed_blank_pages: Vec<usize>,
+    thumbnails: Vec<RgbImage>,
+    renders: Vec<PageRenders>,
+    flip_gifs: Vec<PathBuf>,
+    previous_output: Option<PathBuf>,
+    failed_pages: Vec<FailedPage>,
+    /// What handing the file to the sink and updating its copy resulted in, `None` if the
+    /// file is unchanged.
+    result: Option<FileResultOutput>,
+}
+
+impl ComparedFile {
+    fn new(comparison: FileComparison) -> Self {
+        let pages = comparison.comparisons.as_ref().ok().map(|comparisons| {
+            let mut statistics = PageStatistics::from_comparisons(comparisons);
+            PageStatistics::set_blankness(
+                &mut statistics,
+                &comparison.ink_coverage,
+                &comparison.blank_pages,
+            );
+            PageStatistics::set_size_changes(&mut statistics, &comparison.size_changes);
+            let mut changes = PageChange::from_comparisons(comparisons, comparison.previous_pages);
+            PageChange::mark_blank(&mut changes, &comparison.previous_blank_pages);
+            (statistics, changes, comparisons.len())
+        });
+        let removed = |blank: bool| {
+            pages.as_ref().map_or(Vec::new(), |(_, changes, _)| {
+                changes
+                    .iter()
+                    .filter(|v| v.kind == PageChangeKind::Removed && v.blank == blank)
+                    .map(|v| v.page)
+                    .collect()
+            })
+        };
+        let removed_pages = removed(false);
+        let removed_blank_pages = removed(true);
+        let score = pages
+            .as_ref()
+            .map(|(_, changes, len)| ChangeScore::from_changes(changes, *len));
+        let thumbnails = match (&comparison.comparisons, comparison.thumbnails) {
+            (Ok(_), Some(thumbnails)) => thumbnails.pages,
+            _ => Vec::new(),
+        };
+        // Only changed files were handed to the sink, the unchanged ones are done.
+        let result = match comparison.comparisons {
+            Ok(_) => comparison.output,
+            Err(e) => Some(Err(e)),
+        };
+        ComparedFile {
+            new_file: comparison.new_file,
+            cached: comparison.cached,
+            duration: comparison.duration,
+            timings: comparison.timings,
+            memory: comparison.memory,
+            changes: comparison.changes,
+            fingerprint: comparison.fingerprint,
+            statistics: pages
+                .map(|(statistics, _, _)| statistics)
+                .unwrap_or_default(),
+            score,
+            removed_pages,
+            blank_pages: comparison.blank_pages,
+            removed_blank_pages,
+            thumbnails,
+            renders: comparison.renders,
+            flip_gifs: Vec::new(),
+            previous_output: comparison.previous_output,
+            failed_pages: comparison.failed_pages,
+            result,
+        }
+    }
+
+    /// The outcome of the file, moving out what only the outcome needs.
+    fn take_outcome(&mut self) -> FileOutcome {
+        match self.result.take() {
+            Some(Ok(out)) => FileOutcome::DiffWritten {
+                new_file: self.new_file,
+                out,
+                previous_out: self.previous_output.take(),
+                failed_pages: std::mem::take(&mut self.failed_pages),
+                stats: self.statistics.clone(),
+                score: self.score.unwrap_or_default(),
+                timings: self.timings,
+                memory: std::mem::take(&mut self.memory),
+                changes: std::mem::take(&mut self.changes),
+            },
+            Some(Err(error)) => match error.skip_reason() {
+                Some(reason) => FileOutcome::Skipped { reason },
+                None => FileOutcome::Failed { error },
+            },
+            None => FileOutcome::Unchanged {
+                timings: self.timings,
+                memory: std::mem::take(&mut self.memory),
+                changes: std::mem::take(&mut self.changes),
+                copy_updated: false,
+            },
+        }
+    }
+}
+
+/// Size and modification time of a file, used to notice files changing while processed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileFingerprint {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileFingerprint {
+    fn read(path: &Path) -> io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(FileFingerprint {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Gives the file at `path` the modification time and permissions of `source`.
+fn copy_file_metadata(source: &Metadata, path: &Path) -> io::Result<()> {
+    set_modified(path, source.modified()?)?;
+    std::fs::set_permissions(path, source.permissions())
+}
+
+/// Sets the modification time of `path`, which may already be read-only: copying a file
+/// gives it the permissions of its source.
+#[cfg(unix)]
+fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    // The owner may set the times through a handle that is only open for reading.
+    std::fs::File::open(path)?.set_modified(modified)
+}
+
+#[cfg(not(unix))]
+fn set_modified(path: &Path, modified: SystemTime) -> io::Result<()> {
+    // The time needs write access here, the permissions are restored by the caller.
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_modified(modified)
+}
+
+/// `path` with symlinks resolved. Trailing components that don't exist yet are appended as
+/// given.
+fn canonicalize_existing(path: &Path) -> io::Result<PathBuf> {
+    let mut missing = Vec::new();
+    let mut existing = path;
+    loop {
+        match std::fs::canonicalize(existing) {
+            Ok(canonical) => {
+                return Ok(missing
+                    .iter()
+                    .rev()
+                    .fold(canonical, |path, component| path.join(component)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        missing.push(name);
+                        existing = match parent.as_os_str().is_empty() {
+                            true => Path::new("."),
+                            false => parent,
+                        };
+                    }
+                    _ => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Settings of `find_updated_files`: which entries are scanned, which files are pdfs and
+/// how transient errors are retried.
+#[derive(Debug)]
+struct ScanSettings {
+    root: PathBuf,
+    extensions: Vec<String>,
+    sniff_content: bool,
+    include_hidden: bool,
+    ignore: GlobSet,
+    retry: IoRetryPolicy,
+    /// Returns every pdf, not only the ones modified since their copy was made.
+    all_files: bool,
+    /// Files returned if they have a copy, even if they weren't modified after it.
+    force: Option<ForceSelection>,
+    baselines: BaselineOverrides,
+    symlinks: SymlinkPolicy,
+    max_depth: usize,
+    /// Canonical paths of the directories scanned so far when following symlinks.
+    visited: Mutex<HashSet<PathBuf>>,
+}
+
+impl ScanSettings {
+    /// Whether the entry is hidden or matches an ignore pattern. Only looks at the path.
+    fn ignores(&self, path: &Path) -> bool {
+        let hidden = path
+            .file_name()
+            .is_some_and(|v| v.as_encoded_bytes().starts_with(b"."));
+        (hidden && !self.include_hidden)
+            || self
+                .ignore
+                .is_match(path.strip_prefix(&self.root).unwrap_or(path))
+    }
+
+    /// The shared baseline the file at `path` is compared against, if any.
+    fn baseline(&self, path: &Path) -> Option<PathBuf> {
+        self.baselines
+            .baseline_for(path.strip_prefix(&self.root).unwrap_or(path))
+            .map(Path::to_path_buf)
+    }
+
+    fn forces(&self, path: &Path) -> bool {
+        match &self.force {
+            Some(ForceSelection::All) => true,
+            Some(ForceSelection::Matching(globs)) => {
+                globs.is_match(path.strip_prefix(&self.root).unwrap_or(path))
+            }
+            None => false,
+        }
+    }
+
+    async fn is_pdf(&self, path: &Path) -> bool {
+        match path.extension().and_then(|v| v.to_str()) {
+            Some(extension) => self
+                .extensions
+                .iter()
+                .any(|v| v.eq_ignore_ascii_case(extension)),
+            None if self.sniff_content => {
+                let mut header = [0; 5];
+                match tokio::fs::File::open(path).await {
+                    Ok(mut file) => {
+                        file.read_exact(&mut header).await.is_ok() && &header == b"%PDF-"
+                    }
+                    Err(_) => false,
+                }
+            }
+            None => false,
+        }
+    }
+}
+
+/// Watches `current_path` for modified pdfs, hands their comparisons against the copies in
+/// `last_path` to a [`DiffSink`] and then updates the copies. Reports are written into
+/// `diff_path`.
+pub struct FileManager {
+    pub current_path: PathBuf,
+    pub last_path: PathBuf,
+    pub diff_path: PathBuf,
+    config: FileManagerConfig,
+    pdf_comparison: PDFComparison,
+    sink: Box<dyn DiffSink>,
+    clock: Arc<dyn Clock>,
+    /// Timestamp and counts of the last cycle that succeeded, for the status file.
+    last_success: Option<(Timestamp, CycleCounts)>,
+    /// Start of the current or last cycle, see [`Self::cycle_timestamp`].
+    cycle: Option<Timestamp>,
+    /// Files the next watching cycle processes regardless of their modification times.
+    force: Option<ForceSelection>,
+    /// Files that crashed the child process comparing them or timed out, as they were when
+    /// they did.
+    quarantined: HashMap<PathBuf, FileFingerprint>,
+}
+
+impl FileManager {
+    pub fn new(
+        pdf_comparison: PDFComparison,
+        mut sink: Box<dyn DiffSink>,
+        current_path: PathBuf,
+        last_path: PathBuf,
+        diff_path: PathBuf,
+        config: FileManagerConfig,
+    ) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        sink.set_clock(clock.clone());
+        FileManager {
+            diff_path,
+            current_path,
+            last_path,
+            config,
+            pdf_comparison,
+            sink,
+            clock,
+            last_success: None,
+            cycle: None,
+            force: None,
+            quarantined: HashMap::new(),
+        }
+    }
+
+    /// Uses `clock` instead of the system clock for report timestamps and, through
+    /// [`DiffSink::set_clock`], for the names of the diffs.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.sink.set_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Replaces the comparison settings, taking effect with the next cycle.
+    pub fn set_comparison(&mut self, pdf_comparison: PDFComparison) {
+        self.pdf_comparison = pdf_comparison;
+    }
+
+    /// Replaces the sink, taking effect with the next cycle. It gets the clock of this
+    /// manager.
+    pub fn set_sink(&mut self, mut sink: Box<dyn DiffSink>) {
+        sink.set_clock(self.clock.clone());
+        self.sink = sink;
+    }
+
+    /// Replaces the configuration, taking effect with the next cycle. The state kept between
+    /// cycles, e.g. for the status file, is kept.
+    pub fn set_config(&mut self, config: FileManagerConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &FileManagerConfig {
+        &self.config
+    }
+
+    /// Makes the next [`FileManager::update`] process the files of `selection` even if they
+    /// weren't modified since their copies were made, e.g. to regenerate their diffs with new
+    /// settings. Files without a copy in `last_path` are still treated as new files. Later
+    /// cycles only process modified files again.
+    pub fn force_next_cycle(&mut self, selection: ForceSelection) {
+        self.force = Some(selection);
+    }
+
+    /// Fails if two of the directories are the same, if `last_path` or `diff_path` is inside
+    /// `current_path`, where the copies or diffs would be scanned as modified files, or if
+    /// `diff_path` is inside `last_path`, where diffs would be taken for copies.
+    /// Symlinks are resolved, directories that don't exist yet are compared as given.
+    pub fn check_paths(
+        current_path: &Path,
+        last_path: &Path,
+        diff_path: &Path,
+    ) -> Result<(), FileManagerError> {
+        let canonical = |path: &Path| {
+            canonicalize_existing(path)
+                .map_err(FileManagerError::io(FileOperation::ReadingMetadata, path))
+        };
+        let current = ("current_path", canonical(current_path)?);
+        let last = ("last_path", canonical(last_path)?);
+        let diff = ("diff_path", canonical(diff_path)?);
+        for (a, b) in [(&last, &current), (&diff, &current), (&diff, &last)] {
+            if a.1.starts_with(&b.1) {
+                return Err(FileManagerError::OverlappingPaths {
+                    name: a.0,
+                    other: b.0,
+                    nested: a.1 != b.1,
+                    path: a.1.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a single cycle and reports what happened to every modified file. Once `cancel`
+    /// is cancelled, the file in progress is abandoned between two pages and the remaining
+    /// ones aren't started, all of them are reported as [`SkipReason::Cancelled`].
+    pub async fn update(
+        &mut self,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<UpdateReport, FileManagerError> {
+        if self.config.write_status {
+            self.check_render_settings().await;
+        }
+        let result = self.run(RunMode::Watch, cancel).await;
+        if self.config.write_status {
+            self.write_status(&result).await;
+        }
+        result
+    }
+
+    /// Path of the status file, see [`FileManagerConfig::status_path`].
+    pub fn status_path(&self) -> PathBuf {
+        self.config
+            .status_path
+            .clone()
+            .unwrap_or_else(|| self.diff_path.join(STATUS_FILE_NAME))
+    }
+
+    /// Warns if the status file records other render settings than the current ones. The
+    /// copies in `last_path` were compared with those, so pages rendered differently now
+    /// may be reported as changed.
+    async fn check_render_settings(&self) {
+        let Ok(status) = CycleStatus::read(&self.status_path()).await else {
+            return;
+        };
+        let current = RenderSettings::from(self.pdf_comparison.config());
+        match status.render_settings {
+            Some(recorded) if recorded != current => log::warn!(
+                "THE RENDER SETTINGS CHANGED since the last cycle, pages may be reported as \
+                 changed only because they are rendered differently. Last cycle: {}. This \
+                 cycle: {}",
+                recorded,
+                current
+            ),
+            _ => {}
+        }
+    }
+
+    /// Records the outcome of the cycle in the status file. Failing to write it is only
+    /// logged.
+    async fn write_status(&mut self, result: &Result<UpdateReport, FileManagerError>) {
+        let now = self.config.timezone.at(self.clock.now());
+        let last_error = match result {
+            Ok(report) => {
+                self.last_success = Some((self.cycle_timestamp(), CycleCounts::from(report)));
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        let status = CycleStatus {
+            updated: now,
+            entry: self.config.entry.clone(),
+            last_successful_cycle: self.last_success.as_ref().map(|(time, _)| *time),
+            counts: self.last_success.as_ref().map(|(_, counts)| counts.clone()),
+            last_error,
+            render_settings: Some(RenderSettings::from(self.pdf_comparison.config())),
+        };
+        let path = self.status_path();
+        if let Some(parent) = path.parent() {
+            let _ = create_dir_all(parent).await;
+        }
+        if let Err(e) = status.write(&path).await {
+            log::error!(
+                "Unable to write the status file {}. Error: {}",
+                path.to_string_lossy(),
+                e
+            );
+        }
+    }
+
+    /// Compares every pdf of `current_path` against the file at the same relative path in
+    /// `last_path` once, regardless of modification times, and writes the diffs and a single
+    /// report. Nothing in `last_path` is changed: renames aren't detected, no baselines are
+    /// seeded and no copies are updated. Files only found in `last_path` are reported as
+    /// [`FileOutcome::Removed`], files only found in `current_path` are diffed as new.
+    pub async fn compare_trees(&mut self) -> Result<UpdateReport, FileManagerError> {
+        self.run(RunMode::Batch, None).await
+    }
+
+    async fn run(
+        &mut self,
+        mode: RunMode,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<UpdateReport, FileManagerError> {
+        let started = Instant::now();
+        let cycle = self.config.timezone.at(self.clock.now());
+        self.cycle = Some(cycle);
+        self.sink.begin_cycle(cycle);
+        let batch = mode == RunMode::Batch;
+        let settings = Arc::new(ScanSettings {
+            root: self.current_path.clone(),
+            extensions: self.config.extensions.clone(),
+            sniff_content: self.config.sniff_content,
+            include_hidden: self.config.include_hidden,
+            ignore: self.config.ignore.clone(),
+            retry: self.config.io_retry.clone(),
+            all_files: batch,
+            force: match mode {
+                RunMode::Watch => self.force.take(),
+                RunMode::Batch => None,
+            },
+            baselines: self.config.baselines.clone(),
+            symlinks: self.config.symlinks,
+            max_depth: self.config.max_depth,
+            visited: Mutex::new(HashSet::from_iter(
+                std::fs::canonicalize(&self.current_path).ok(),
+            )),
+        });
+        let scan = FileManager::find_updated_files(
+            self.current_path.clone(),
+            self.last_path.clone(),
+            settings.clone(),
+            0,
+        )
+        .await?;
+        let scanned_files = scan.scanned;
+        let forced = scan
+            .forced
+            .iter()
+            .map(|path| self.relative_path(path))
+            .collect::<HashSet<PathBuf>>();
+        metrics()
+            .files_scanned
+            .with_label_values(&[self.metric_label()])
+            .inc_by(scanned_files as u64);
+        let (renamed, seeded, mut updated_files) = match mode {
+            RunMode::Watch => {
+                let (renamed, files) = self.detect_renames(scan.files).await;
+                let (seeded, updated_files) =
+                    FileManager::seed_baselines(&self.config, files).await;
+                (renamed, seeded, updated_files)
+            }
+            RunMode::Batch => (Vec::new(), Vec::new(), scan.files.into_iter().collect()),
+        };
+        let mut baseline_state = match mode {
+            RunMode::Watch if !self.config.baselines.is_empty() => {
+                Some(BaselineState::load(self.diff_path.join(BASELINE_STATE_FILE_NAME)).await)
+            }
+            _ => None,
+        };
+        let mut overridden = self
+            .select_overridden(scan.overridden, &scan.forced, baseline_state.as_mut())
+            .await;
+        // The files compared against shared baselines have no copies to update.
+        let copies = updated_files.clone();
+        updated_files.extend(std::mem::take(&mut overridden.selected));
+        let mut comparison_cache =
+            ComparisonCache::load(self.diff_path.join(COMPARISON_CACHE_FILE_NAME)).await;
+        let mut compared = self
+            .generate_comparisons(&updated_files, &mut comparison_cache, cancel)
+            .into_iter()
+            .map(|(path, comparison)| (path, ComparedFile::new(comparison)))
+            .collect::<BTreeMap<_, _>>();
+        if let Err(e) = comparison_cache.save().await {
+            log::warn!("Unable to save the comparison cache. Error: {}", e);
+        }
+        metrics()
+            .files_compared
+            .with_label_values(&[self.metric_label()])
+            .inc_by(compared.len() as u64);
+        for file in compared.values() {
+            metrics()
+                .comparison_duration
+                .with_label_values(&[self.metric_label()])
+                .observe(file.duration.as_secs_f64());
+            for (stage, duration) in file.timings.stages() {
+                metrics()
+                    .stage_duration
+                    .with_label_values(&[self.metric_label(), stage.name()])
+                    .observe(duration.as_secs_f64());
+            }
+        }
+        if mode == RunMode::Watch {
+            self.update_changed_pdfs(&mut compared, &copies).await;
+        }
+        if let Some(config) = &self.config.contact_sheet {
+            for file in compared.values() {
+                let Some(Ok(Some(output))) = &file.result else {
+                    continue;
+                };
+                let sheets = render_contact_sheets(&file.thumbnails, &file.statistics, config);
+                if let Err(e) = self.write_contact_sheets(output, &sheets).await {
+                    log::error!("Unable to write the contact sheet. Error: {}", e);
+                }
+            }
+        }
+        if let Some(config) = &self.config.flip_gifs {
+            for (path, file) in compared.iter_mut() {
+                if !matches!(file.result, Some(Ok(Some(_)))) || file.renders.is_empty() {
+                    continue;
+                }
+                match self.write_flip_gifs(config, path, &file.renders).await {
+                    Ok(paths) => file.flip_gifs = paths,
+                    Err(e) => log::error!("Unable to write the flip gifs. Error: {}", e),
+                }
+            }
+        }
+        let combined = self.combine_outputs(&mut compared);
+        let errored = compared
+            .values()
+            .filter(|v| matches!(&v.result, Some(Err(e)) if e.skip_reason().is_none()))
+            .count() as u64;
+        let changed = compared
+            .values()
+            .filter(|v| matches!(v.result, Some(Ok(_))))
+            .count() as u64;
+        metrics()
+            .files_errored
+            .with_label_values(&[self.metric_label()])
+            .inc_by(errored);
+        metrics()
+            .files_changed
+            .with_label_values(&[self.metric_label()])
+            .inc_by(changed);
+
+        if let (Some(dir), false) = (&self.config.html_report, updated_files.is_empty()) {
+            let documents = compared
+                .iter_mut()
+                .filter_map(|(path, file)| {
+                    let Some(Ok(output)) = &file.result else {
+                        return None;
+                    };
+                    Some(HtmlDocument {
+                        path: self.relative_path(path),
+                        output: output.clone(),
+                        pages: file.statistics.clone(),
+                        thumbnails: std::mem::take(&mut file.thumbnails),
+                        removed_pages: file.removed_pages.clone(),
+                    })
+                })
+                .collect::<Vec<HtmlDocument>>();
+            if let Err(e) = self.write_html_report(dir, &documents).await {
+                log::error!("Unable to write the html report. Error: {}", e);
+            }
+        }
+
+        // The copies in `last_path` are already updated at this point, so a failing hook
+        // neither fails the file nor causes it to be processed again.
+        let hook_outcomes = match &self.config.on_diff {
+            Some(hook) => {
+                let invocations = compared
+                    .iter()
+                    .filter(|(_, file)| self.reaches_alert_threshold(file.score))
+                    .filter_map(|(path, file)| {
+                        let Some(Ok(output)) = &file.result else {
+                            return None;
+                        };
+                        Some(HookInvocation::new(
+                            path,
+                            output.as_deref(),
+                            &file.statistics,
+                        ))
+                    })
+                    .collect();
+                run_hooks(hook, invocations).await.into_iter().collect()
+            }
+            None => HashMap::new(),
+        };
+
+        let mut processed = compared
+            .iter_mut()
+            .map(|(path, file)| (path.to_path_buf(), file.take_outcome()))
+            .collect::<Vec<(PathBuf, FileOutcome)>>();
+        processed.extend(renamed);
+        processed.extend(seeded);
+        processed.append(&mut overridden.failed);
+        let unmodified = scanned_files - processed.len();
+        match mode {
+            RunMode::Watch => {
+                self.update_unmarked_copies(&mut processed, &copies, &compared)
+                    .await;
+                if let Some(state) = baseline_state {
+                    self.record_overridden(state, &processed, overridden.entries)
+                        .await;
+                }
+            }
+            RunMode::Batch => {
+                for copy in self.orphaned_copies().await {
+                    if settings.is_pdf(&copy).await && !settings.ignores(&copy) {
+                        let path = self
+                            .current_path
+                            .join(copy.strip_prefix(&self.last_path).unwrap_or(&copy));
+                        processed.push((path, FileOutcome::Removed));
+                    }
+                }
+            }
+        }
+        self.sort_files(&mut processed, |(path, _)| path);
+        let severity = processed
+            .iter()
+            .filter_map(|(path, outcome)| self.severity(path, outcome))
+            .max();
+        let alerts = processed
+            .iter()
+            .filter(|(_, outcome)| self.reaches_alert_threshold(outcome.score()))
+            .count();
+
+        // Cycles without any modified files don't get a report, they would only add clutter.
+        // A batch run always gets one, it is its result.
+        let wants_report = self.config.write_report
+            || self.config.csv_report.is_some()
+            || self.config.webhook.is_some();
+        if wants_report && (batch || !processed.is_empty()) {
+            let mut report =
+                self.build_report(started.elapsed(), &processed, compared, hook_outcomes);
+            for file in &mut report.files {
+                file.forced = forced.contains(&file.path);
+            }
+            if self.config.write_report {
+                if let Err(e) = self.write_report(&report).await {
+                    log::error!("Unable to write the run report. Error: {}", e);
+                }
+            }
+            if let Some(path) = &self.config.csv_report {
+                if let Err(e) = crate::csv::append_csv_report(path, &report)
+                    .map_err(FileManagerError::io(FileOperation::WritingReport, path))
+                {
+                    log::error!("Unable to append to the csv report. Error: {}", e);
+                }
+            }
+            if let Some(webhook) = &self.config.webhook {
+                notify(webhook, &report).await;
+            }
+        }
+
+        metrics()
+            .last_successful_cycle
+            .with_label_values(&[self.metric_label()])
+            .set(self.cycle_timestamp().utc().timestamp());
+        Ok(UpdateReport {
+            skipped: unmodified,
+            processed,
+            duration: started.elapsed(),
+            scanned_files,
+            ignored: scan.ignored,
+            combined,
+            severity,
+            alerts,
+        })
+    }
+
+    /// Hands every document of the cycle to `DiffSink::end_cycle`. If the combined artifact
+    /// replaces the artifacts of the single documents, their outputs are pointed at it.
+    /// Failing to combine them doesn't fail the cycle, the single artifacts are still there.
+    fn combine_outputs(&mut self, compared: &mut BTreeMap<&Path, ComparedFile>) -> Option<PathBuf> {
+        let mut documents = compared
+            .iter()
+            .filter_map(|(path, file)| match file.result.as_ref()? {
+                Ok(None) => None,
+                Ok(Some(out)) => Some(CycleDocument {
+                    path: self.relative_path(path),
+                    output: Some(out.clone()),
+                    error: None,
+                }),
+                Err(e) if e.skip_reason().is_some() => None,
+                Err(e) => Some(CycleDocument {
+                    path: self.relative_path(path),
+                    output: None,
+                    error: Some(e.to_string()),
+                }),
+            })
+            .collect::<Vec<CycleDocument>>();
+        if documents.is_empty() {
+            return None;
+        }
+        documents.sort_by(|a, b| a.path.cmp(&b.path));
+        let combined = match self.sink.end_cycle(&documents) {
+            Ok(combined) => combined?,
+            Err(e) => {
+                log::error!("Unable to combine the diffs of the cycle. Error: {}", e);
+                return None;
+            }
+        };
+        log::info!(
+            "Combined the diffs into {}",
+            combined.path.to_string_lossy()
+        );
+        if combined.replaces_documents {
+            compared
+                .values_mut()
+                .filter_map(|file| file.result.as_mut()?.as_mut().ok()?.as_mut())
+                .for_each(|out| *out = combined.path.clone());
+        }
+        Some(combined.path)
+    }
+
+    /// With `detect_renames`, finds the files without a copy whose content matches the copy
+    /// of a file that is gone from `current_path`. Their copies are moved to the new path
+    /// and the remaining files are returned for comparing. Digests of copies are cached in
+    /// `diff_path` between cycles.
+    async fn detect_renames(
+        &self,
+        files: Vec<(PathBuf, PathBuf)>,
+    ) -> (Vec<(PathBuf, FileOutcome)>, Vec<(PathBuf, PathBuf)>) {
+        let mut new_files = Vec::new();
+        let mut remaining = Vec::new();
+        for (path, copy) in files {
+            if self.config.detect_renames && !tokio::fs::try_exists(&copy).await.unwrap_or(true) {
+                new_files.push((path, copy));
+            } else {
+                remaining.push((path, copy));
+            }
+        }
+        if new_files.is_empty() {
+            return (Vec::new(), remaining);
+        }
+        let orphans = self.orphaned_copies().await;
+        if orphans.is_empty() {
+            remaining.append(&mut new_files);
+            return (Vec::new(), remaining);
+        }
+
+        let mut cache = DigestCache::load(self.diff_path.join(DIGEST_CACHE_FILE_NAME)).await;
+        let mut orphans_by_digest = HashMap::new();
+        for orphan in orphans {
+            match cache.digest(&orphan).await {
+                Ok(digest) => {
+                    orphans_by_digest.entry(digest).or_insert(orphan);
+                }
+                Err(e) => log::warn!(
+                    "Unable to hash the copy {}. Error: {}",
+                    orphan.to_string_lossy(),
+                    e
+                ),
+            }
+        }
+        if let Err(e) = cache.save().await {
+            log::warn!("Unable to save the digest cache. Error: {}", e);
+        }
+
+        let mut renamed = Vec::new();
+        for (path, copy) in new_files {
+            let previous_copy = match file_digest(&path).await {
+                Ok(digest) => orphans_by_digest.remove(&digest),
+                Err(e) => {
+                    log::warn!("Unable to hash {}. Error: {}", path.to_string_lossy(), e);
+                    None
+                }
+            };
+            let Some(previous_copy) = previous_copy else {
+                remaining.push((path, copy));
+                continue;
+            };
+            let from = self.current_path.join(
+                previous_copy
+                    .strip_prefix(&self.last_path)
+                    .unwrap_or(&previous_copy),
+            );
+            log::info!(
+                "{} was renamed from {}",
+                path.to_string_lossy(),
+                from.to_string_lossy()
+            );
+            let outcome = match self.move_copy(&path, &previous_copy, &copy).await {
+                Ok(()) => FileOutcome::Renamed { from },
+                Err(error) => {
+                    metrics()
+                        .files_errored
+                        .with_label_values(&[self.metric_label()])
+                        .inc();
+                    FileOutcome::Failed { error }
+                }
+            };
+            renamed.push((path, outcome));
+        }
+        (renamed, remaining)
+    }
+
+    /// Of the files compared against shared baselines, see [`FileManagerConfig::baselines`],
+    /// picks the ones that need comparing: in a batch run every file, otherwise the forced
+    /// ones and the ones whose content or whose baseline's content differs from `state`.
+    async fn select_overridden(
+        &self,
+        files: Vec<(PathBuf, PathBuf)>,
+        forced: &HashSet<PathBuf>,
+        mut state: Option<&mut BaselineState>,
+    ) -> OverriddenFiles {
+        let mut overridden = OverriddenFiles::default();
+        for (path, baseline) in files {
+            if !tokio::fs::try_exists(&baseline).await.unwrap_or(true) {
+                metrics()
+                    .files_errored
+                    .with_label_values(&[self.metric_label()])
+                    .inc();
+                let error = FileManagerError::MissingBaseline {
+                    path: path.clone(),
+                    baseline,
+                };
+                overridden
+                    .failed
+                    .push((path, FileOutcome::Failed { error }));
+                continue;
+            }
+            if let Some(state) = state.as_deref_mut() {
+                let key = self.relative_path(&path).to_string_lossy().into_owned();
+                match state.check(&key, &path, &baseline).await {
+                    Ok((_, true)) if !forced.contains(&path) => continue,
+                    Ok((entry, _)) => {
+                        overridden.entries.insert(path.clone(), entry);
+                    }
+                    Err(e) => log::warn!(
+                        "Unable to hash {} or its baseline {}, it is compared. Error: {}",
+                        path.to_string_lossy(),
+                        baseline.to_string_lossy(),
+                        e
+                    ),
+                }
+            }
+            overridden.selected.insert(path, baseline);
+        }
+        overridden
+    }
+
+    /// Records the `entries` of the files compared against shared baselines that were
+    /// processed, so they aren't compared again until they or their baselines change. Files
+    /// that were skipped or failed are compared again in the next cycle.
+    async fn record_overridden(
+        &self,
+        mut state: BaselineState,
+        processed: &[(PathBuf, FileOutcome)],
+        mut entries: HashMap<PathBuf, BaselineEntry>,
+    ) {
+        for (path, outcome) in processed {
+            let Some(entry) = entries.remove(path) else {
+                continue;
+            };
+            if matches!(
+                outcome,
+                FileOutcome::DiffWritten { .. } | FileOutcome::Unchanged { .. }
+            ) {
+                let key = self.relative_path(path).to_string_lossy().into_owned();
+                state.record(key, entry);
+            }
+        }
+        if let Err(e) = state.save().await {
+            log::warn!("Unable to save the baseline state. Error: {}", e);
+        }
+    }
+
+    /// Copies in `last_path` whose file doesn't exist in `current_path` anymore. Entries
+    /// that can't be read are left out, as are shared baselines kept in `last_path`.
+    async fn orphaned_copies(&self) -> Vec<PathBuf> {
+        let baselines = self
+            .config
+            .baselines
+            .overrides()
+            .iter()
+            .filter_map(|v| std::fs::canonicalize(v.baseline()).ok())
+            .collect::<HashSet<PathBuf>>();
+        let mut orphans = Vec::new();
+        let mut directories = vec![self.last_path.clone()];
+        while let Some(directory) = directories.pop() {
+            let mut entries = match read_dir(&directory).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    log::warn!(
+                        "Unable to scan {} for copies without a source. Error: {}",
+                        directory.to_string_lossy(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                // Partial copies and other hidden files aren't copies.
+                if entry.file_name().as_encoded_bytes().starts_with(b".") {
+                    continue;
+                }
+                match entry.file_type().await {
+                    Ok(file_type) if file_type.is_dir() => directories.push(path),
+                    Ok(_) => {
+                        let source = self
+                            .current_path
+                            .join(path.strip_prefix(&self.last_path).unwrap_or(&path));
+                        let baseline = !baselines.is_empty()
+                            && std::fs::canonicalize(&path).is_ok_and(|v| baselines.contains(&v));
+                        if !baseline && !tokio::fs::try_exists(&source).await.unwrap_or(true) {
+                            orphans.push(path);
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        orphans
+    }
 
-#[derive(Debug)]
-pub enum FileManagerError {
-    Io(io::Error),
-    PDFComparisonError(PDFComparisonError),
-    PDFEditorError(PDFEditorError),
-}
+    /// Replaces the copy `previous_copy` of a renamed file with the copy `copy` of `source`.
+    async fn move_copy(
+        &self,
+        source: &Path,
+        previous_copy: &Path,
+        copy: &Path,
+    ) -> Result<(), FileManagerError> {
+        // Copying instead of renaming gives the copy the modification time of the file, so it
+        // isn't seen as modified in the next cycle.
+        FileManager::update_copy(&self.config, source, copy).await?;
+        self.config
+            .io_retry
+            .run(FileOperation::UpdatingCopy, previous_copy, || {
+                remove_file(previous_copy)
+            })
+            .await
+            .map_err(FileManagerError::io(
+                FileOperation::UpdatingCopy,
+                previous_copy,
+            ))
+    }
 
-impl std::error::Error for FileManagerError {}
+    /// With [`NewFilePolicy::SeedBaseline`], copies the files without a copy to `last_path`
+    /// and returns their outcomes together with the files still to compare.
+    async fn seed_baselines(
+        config: &FileManagerConfig,
+        files: Vec<(PathBuf, PathBuf)>,
+    ) -> (Vec<(PathBuf, FileOutcome)>, HashMap<PathBuf, PathBuf>) {
+        let mut seeded = Vec::new();
+        let mut remaining = HashMap::new();
+        for (path, copy) in files {
+            let seed = config.new_files == NewFilePolicy::SeedBaseline
+                && !tokio::fs::try_exists(&copy).await.unwrap_or(true);
+            if !seed {
+                remaining.insert(path, copy);
+                continue;
+            }
+            let outcome = match FileManager::update_copy(config, &path, &copy).await {
+                Ok(()) => {
+                    log::info!("Seeded the baseline of {}", path.to_string_lossy());
+                    FileOutcome::Skipped {
+                        reason: SkipReason::NoBaseline,
+                    }
+                }
+                Err(error) => {
+                    metrics()
+                        .files_errored
+                        .with_label_values(&[config.entry.as_deref().unwrap_or_default()])
+                        .inc();
+                    FileOutcome::Failed { error }
+                }
+            };
+            seeded.push((path, outcome));
+        }
+        (seeded, remaining)
+    }
 
-impl std::fmt::Display for FileManagerError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Io(e) => write!(f, "IO Error: {}", e),
-            Self::PDFComparisonError(e) => write!(f, "PDFComparison Error: {}", e),
-            Self::PDFEditorError(e) => write!(f, "PDFEditor Error: {}", e),
+    /// Updates the copies of files that got no diff but shouldn't be examined again: with
+    /// `copy_document_changes` files that are unchanged apart from their [`DocumentChanges`],
+    /// with `copy_encrypted` encrypted files. Files whose copy can't be updated fail. The
+    /// copies of the other unchanged files only get their modification time, see
+    /// [`FileManager::mark_examined`].
+    async fn update_unmarked_copies(
+        &self,
+        processed: &mut [(PathBuf, FileOutcome)],
+        associations: &HashMap<PathBuf, PathBuf>,
+        compared: &BTreeMap<&Path, ComparedFile>,
+    ) {
+        for (path, outcome) in processed.iter_mut() {
+            let copy = match outcome {
+                FileOutcome::Unchanged {
+                    changes,
+                    copy_updated,
+                    ..
+                } => {
+                    if self.config.copy_document_changes && !changes.is_empty() {
+                        *copy_updated = true;
+                        true
+                    } else {
+                        if let Some(copy) = associations.get(path) {
+                            let fingerprint = compared
+                                .get(path.as_path())
+                                .and_then(|v| v.fingerprint.as_ref());
+                            *copy_updated = self.mark_examined(path, copy, fingerprint);
+                        }
+                        false
+                    }
+                }
+                FileOutcome::Skipped {
+                    reason: SkipReason::Encrypted,
+                } => self.config.copy_encrypted,
+                _ => false,
+            };
+            let Some(copy) = associations.get(path).filter(|_| copy) else {
+                continue;
+            };
+            match FileManager::update_copy(&self.config, path, copy).await {
+                Ok(()) => log::info!(
+                    "Updated the copy of {} without a diff: {}",
+                    path.to_string_lossy(),
+                    match outcome {
+                        FileOutcome::Skipped { .. } => "the document is encrypted",
+                        _ => "only its metadata, attachments or form fields changed",
+                    }
+                ),
+                Err(error) => {
+                    metrics()
+                        .files_errored
+                        .with_label_values(&[self.metric_label()])
+                        .inc();
+                    *outcome = FileOutcome::Failed { error };
+                }
+            }
         }
     }
-}
 
-impl From<io::Error> for FileManagerError {
-    fn from(value: io::Error) -> Self {
-        Self::Io(value)
+    /// Gives `copy` the modification time of the unchanged `path`, so it isn't compared
+    /// again until it is modified. Its content is kept, otherwise changes within the
+    /// tolerance would add up unnoticed. Skipped if `path` was modified since it was compared
+    /// as `fingerprint`, returns whether the time was set.
+    fn mark_examined(
+        &self,
+        path: &Path,
+        copy: &Path,
+        fingerprint: Option<&FileFingerprint>,
+    ) -> bool {
+        let result = std::fs::metadata(path).and_then(|source| {
+            let current = FileFingerprint {
+                len: source.len(),
+                modified: source.modified().ok(),
+            };
+            if fingerprint != Some(&current) {
+                log::info!(
+                    "{} was modified after it was compared, it is compared again",
+                    path.to_string_lossy()
+                );
+                return Ok(false);
+            }
+            copy_file_metadata(&source, copy).map(|()| true)
+        });
+        result.unwrap_or_else(|e| {
+            log::warn!(
+                "Unable to update the modification time of {}, {} is compared again in the \
+                 next cycle. Error: {}",
+                copy.to_string_lossy(),
+                path.to_string_lossy(),
+                e
+            );
+            false
+        })
     }
-}
 
-impl From<PDFComparisonError> for FileManagerError {
-    fn from(value: PDFComparisonError) -> Self {
-        Self::PDFComparisonError(value)
+    fn build_report(
+        &self,
+        duration: Duration,
+        processed: &[(PathBuf, FileOutcome)],
+        mut compared: BTreeMap<&Path, ComparedFile>,
+        mut hook_outcomes: HashMap<PathBuf, HookOutcome>,
+    ) -> RunReport {
+        let files = processed
+            .iter()
+            .map(|(path, outcome)| {
+                let file = compared.remove(path.as_path()).unwrap_or_default();
+                let renamed_from = match outcome {
+                    FileOutcome::Renamed { from } => Some(self.relative_path(from)),
+                    _ => None,
+                };
+                let baseline = match outcome {
+                    FileOutcome::Renamed { .. } | FileOutcome::Removed => None,
+                    _ => self
+                        .config
+                        .baselines
+                        .baseline_for(&self.relative_path(path))
+                        .map(Path::to_path_buf),
+                };
+                let copy_updated = matches!(
+                    outcome,
+                    FileOutcome::Unchanged {
+                        copy_updated: true,
+                        ..
+                    }
+                );
+                let (result, output, error) = match outcome {
+                    FileOutcome::DiffWritten {
+                        new_file: true,
+                        out,
+                        ..
+                    } => (FileResult::New, out.clone(), None),
+                    FileOutcome::DiffWritten { out, .. } => {
+                        (FileResult::Changed, out.clone(), None)
+                    }
+                    FileOutcome::Unchanged { .. } => (FileResult::Identical, None, None),
+                    FileOutcome::Renamed { .. } => (FileResult::Renamed, None, None),
+                    FileOutcome::Removed => (FileResult::Removed, None, None),
+                    FileOutcome::Skipped { reason } => {
+                        (FileResult::Skipped, None, Some(reason.to_string()))
+                    }
+                    FileOutcome::Failed { error } => {
+                        (FileResult::Error, None, Some(error.to_string()))
+                    }
+                };
+                let (previous_output, failed_pages) = match outcome {
+                    FileOutcome::DiffWritten {
+                        previous_out,
+                        failed_pages,
+                        ..
+                    } => (previous_out.clone(), failed_pages.clone()),
+                    _ => (None, Vec::new()),
+                };
+                let render_memory = match outcome {
+                    FileOutcome::Unchanged { memory, .. }
+                    | FileOutcome::DiffWritten { memory, .. } => memory.clone(),
+                    _ => RenderMemory::default(),
+                };
+                FileReport {
+                    path: self.relative_path(path),
+                    result,
+                    severity: self.severity(path, outcome),
+                    score: outcome.score(),
+                    alert: self.reaches_alert_threshold(outcome.score()),
+                    baseline,
+                    copy_updated,
+                    forced: false,
+                    cached: file.cached,
+                    output,
+                    previous_output,
+                    renamed_from,
+                    error,
+                    pages: file.statistics,
+                    removed_pages: file.removed_pages,
+                    blank_pages: file.blank_pages,
+                    removed_blank_pages: file.removed_blank_pages,
+                    failed_pages,
+                    flip_gifs: file.flip_gifs,
+                    comparison_seconds: file.duration.as_secs_f64(),
+                    hook: hook_outcomes.remove(path),
+                    timings: match outcome {
+                        FileOutcome::Unchanged { timings, .. }
+                        | FileOutcome::DiffWritten { timings, .. } => *timings,
+                        _ => Timings::default(),
+                    },
+                    render_memory: render_memory.clone(),
+                    warnings: render_memory.warning().into_iter().collect(),
+                    changes: match outcome {
+                        FileOutcome::Unchanged { changes, .. }
+                        | FileOutcome::DiffWritten { changes, .. } => changes.clone(),
+                        _ => DocumentChanges::default(),
+                    },
+                }
+            })
+            .collect();
+        RunReport {
+            timestamp: self.cycle_timestamp(),
+            entry: self.config.entry.clone(),
+            pdfium_version: self.config.pdfium_version,
+            duration_seconds: duration.as_secs_f64(),
+            files,
+        }
     }
-}
 
-impl From<PDFEditorError> for FileManagerError {
-    fn from(value: PDFEditorError) -> Self {
-        Self::PDFEditorError(value)
+    /// Severity of `outcome` of the file at `path` under the configured rules.
+    fn severity(&self, path: &Path, outcome: &FileOutcome) -> Option<Severity> {
+        self.config
+            .severity
+            .as_ref()
+            .and_then(|rules| rules.evaluate(&self.relative_path(path), outcome))
     }
-}
 
-enum FileTypeEnum {
-    Dir,
-    File,
-}
+    /// Whether a file of `score` alerts, see [`FileManagerConfig::alert_threshold`]. Files
+    /// without a score never do, nor do files only changed by redactions if
+    /// [`FileManagerConfig::alert_ignores_redactions`] is set.
+    fn reaches_alert_threshold(&self, score: Option<ChangeScore>) -> bool {
+        score.is_some_and(|score| {
+            let fraction = match self.config.alert_ignores_redactions {
+                true => score.substantive_fraction(),
+                false => score.changed_fraction,
+            };
+            match self.config.alert_threshold {
+                Some(threshold) => fraction >= threshold,
+                None => {
+                    !self.config.alert_ignores_redactions
+                        || score.redacted_fraction == 0.
+                        || fraction > 0.
+                }
+            }
+        })
+    }
 
-impl From<FileType> for FileTypeEnum {
-    fn from(value: FileType) -> Self {
-        if value.is_dir() {
-            Self::Dir
-        } else {
-            Self::File
+    /// Start of the current cycle in the configured zone, which every report, status file
+    /// and artifact of the cycle is stamped with, or the current time outside of cycles.
+    fn cycle_timestamp(&self) -> Timestamp {
+        self.cycle
+            .unwrap_or_else(|| self.config.timezone.at(self.clock.now()))
+    }
+
+    /// Value of the `entry` label of the metrics.
+    fn metric_label(&self) -> &str {
+        self.config.entry.as_deref().unwrap_or_default()
+    }
+
+    /// Sorts `files` in the configured [`ProcessingOrder`] by the path `path` returns.
+    /// Files whose modification time can't be read come first.
+    fn sort_files<T>(&self, files: &mut [T], path: impl Fn(&T) -> &Path) {
+        match self.config.order {
+            ProcessingOrder::Path => files.sort_by(|a, b| path(a).cmp(path(b))),
+            ProcessingOrder::Mtime => files.sort_by_cached_key(|file| {
+                let path = path(file);
+                let modified = std::fs::metadata(path).and_then(|v| v.modified()).ok();
+                (modified, path.to_path_buf())
+            }),
         }
     }
-}
 
-impl From<&Metadata> for FileTypeEnum {
-    fn from(value: &Metadata) -> Self {
-        if value.is_dir() {
-            Self::Dir
-        } else {
-            Self::File
+    fn timed_out(config: &FileManagerConfig, path: &Path) -> FileManagerError {
+        let limit = config.file_timeout.unwrap_or_default();
+        log::warn!(
+            "Abandoned {} after {}",
+            path.to_string_lossy(),
+            humantime::format_duration(limit)
+        );
+        FileManagerError::Timeout {
+            path: path.to_path_buf(),
+            limit,
         }
     }
-}
 
-pub struct FileManager {
-    pub current_path: PathBuf,
-    pub last_path: PathBuf,
-    pub diff_path: PathBuf,
-    pdf_comparison: PDFComparison,
-    pdf_editor: PDFEditor,
-}
+    fn relative_path(&self, path: &Path) -> PathBuf {
+        path.strip_prefix(&self.current_path)
+            .unwrap_or(path)
+            .to_path_buf()
+    }
 
-impl FileManager {
-    pub fn new(
-        pdfium: Arc<Pdfium>,
-        current_path: PathBuf,
-        last_path: PathBuf,
-        diff_path: PathBuf,
-    ) -> Self {
-        FileManager {
-            diff_path,
-            current_path,
-            last_path,
-            pdf_comparison: PDFComparison::new(pdfium.clone()),
-            pdf_editor: PDFEditor::new(pdfium),
+    async fn write_html_report(
+        &self,
+        dir: &Path,
+        documents: &[HtmlDocument],
+    ) -> Result<PathBuf, FileManagerError> {
+        let now = self.cycle_timestamp();
+        let path = dir.join(format!("report-{}.html", now.file_stamp()));
+        let html = render_html_report(&format!("pdf_diff_gen report {}", now), documents);
+        create_dir_all(dir)
+            .await
+            .map_err(FileManagerError::io(FileOperation::CreatingDirectory, dir))?;
+        write(&path, html)
+            .await
+            .map_err(FileManagerError::io(FileOperation::WritingReport, &path))?;
+        Ok(path)
+    }
+
+    /// Whether the comparisons need to keep thumbnails of the pages.
+    fn wants_thumbnails(&self) -> bool {
+        self.config.html_report.is_some() || self.config.contact_sheet.is_some()
+    }
+
+    /// Writes `sheets` next to the diff `output`, see [`FileManagerConfig::contact_sheet`].
+    async fn write_contact_sheets(
+        &self,
+        output: &Path,
+        sheets: &[RgbImage],
+    ) -> Result<Vec<PathBuf>, FileManagerError> {
+        let mut paths = Vec::new();
+        for (index, sheet) in sheets.iter().enumerate() {
+            let path = match index {
+                0 => output.with_extension("sheet.png"),
+                _ => output.with_extension(format!("sheet-{}.png", index + 1)),
+            };
+            let writing = FileManagerError::io(FileOperation::WritingContactSheet, &path);
+            let mut png = io::Cursor::new(Vec::new());
+            sheet
+                .write_to(&mut png, ImageFormat::Png)
+                .map_err(|e| writing(io::Error::other(e)))?;
+            write(&path, png.into_inner())
+                .await
+                .map_err(FileManagerError::io(
+                    FileOperation::WritingContactSheet,
+                    &path,
+                ))?;
+            paths.push(path);
         }
+        Ok(paths)
     }
 
-    pub async fn update(
+    /// Writes a flip gif of every page of `renders`, see [`FileManagerConfig::flip_gifs`].
+    async fn write_flip_gifs(
         &self,
-    ) -> Result<HashMap<PathBuf, Result<PathBuf, FileManagerError>>, FileManagerError> {
-        let updated_files =
-            FileManager::find_updated_files(self.current_path.clone(), self.last_path.clone())
-                .await?
-                .into_iter()
-                .collect::<HashMap<_, _>>();
-        let comparsions = self.generate_comparisons(&updated_files);
-        let updated_pdfs = self.generate_updated_pdfs(comparsions);
-        let post_update_status = self.update_changed_pdfs(updated_pdfs, &updated_files).await;
-        Ok(post_update_status
-            .into_iter()
-            .map(|(associated_current_path, result)| {
-                (associated_current_path.to_path_buf(), result)
-            })
-            .collect())
+        config: &FlipGifConfig,
+        path: &Path,
+        renders: &[PageRenders],
+    ) -> Result<Vec<PathBuf>, FileManagerError> {
+        let base = config.dir.join(self.relative_path(path));
+        if let Some(parent) = base.parent() {
+            create_dir_all(parent).await.map_err(FileManagerError::io(
+                FileOperation::CreatingDirectory,
+                parent,
+            ))?;
+        }
+        let mut paths = Vec::new();
+        for page in renders {
+            let gif_path = base.with_extension(format!("p{}.gif", page.page + 1));
+            let writing = FileManagerError::io(FileOperation::WritingFlipGif, &gif_path);
+            let gif = encode_flip_gif(&page.previous, &page.current, config.delay)
+                .map_err(|e| writing(io::Error::other(e)))?;
+            write(&gif_path, gif).await.map_err(FileManagerError::io(
+                FileOperation::WritingFlipGif,
+                &gif_path,
+            ))?;
+            paths.push(gif_path);
+        }
+        Ok(paths)
+    }
+
+    async fn write_report(&self, report: &RunReport) -> Result<PathBuf, FileManagerError> {
+        let path = self
+            .diff_path
+            .join(format!("report-{}.json", report.timestamp.file_stamp()));
+        let content = serde_json::to_vec_pretty(report).map_err(|e| {
+            FileManagerError::io(FileOperation::WritingReport, &path)(io::Error::other(e))
+        })?;
+        create_dir_all(&self.diff_path)
+            .await
+            .map_err(FileManagerError::io(
+                FileOperation::CreatingDirectory,
+                &self.diff_path,
+            ))?;
+        write(&path, content)
+            .await
+            .map_err(FileManagerError::io(FileOperation::WritingReport, &path))?;
+        Ok(path)
     }
 
-    async fn update_changed_pdfs<'a>(
+    /// Updates the copies of the files that got a diff, a file whose copy can't be updated
+    /// fails.
+    async fn update_changed_pdfs(
         &self,
-        updated_pdfs: HashMap<&'a Path, Result<PathBuf, FileManagerError>>,
-        associations: &'a HashMap<PathBuf, PathBuf>,
-    ) -> HashMap<&'a Path, Result<PathBuf, FileManagerError>> {
-        let mut res = HashMap::new();
-        for (path, result) in updated_pdfs.into_iter() {
-            let cres = match result {
-                Ok(diff_path) => {
-                    let target_path = associations.get(path).unwrap();
-                    let res = match target_path.parent() {
-                        Some(parent) => Some(create_dir_all(parent).await),
-                        None => None,
-                    };
-                    match (res, copy(path, target_path).await) {
-                        (Some(Err(e)), _) => (path, Err(FileManagerError::Io(e))),
-                        (_, Ok(_)) => (path, Ok(diff_path)),
-                        (_, Err(e)) => (path, Err(FileManagerError::Io(e))),
+        compared: &mut BTreeMap<&Path, ComparedFile>,
+        associations: &HashMap<PathBuf, PathBuf>,
+    ) {
+        for (path, file) in compared.iter_mut() {
+            // Files compared against a shared baseline have no copy.
+            let (Some(Ok(_)), Some(target_path)) = (&file.result, associations.get(*path)) else {
+                continue;
+            };
+            if let Err(e) = FileManager::update_copy(&self.config, path, target_path).await {
+                file.result = Some(Err(e));
+            }
+        }
+    }
+
+    /// Replaces `target` with a copy of `source`. The copy is written next to `target` and
+    /// only renamed over it once it is verified, so a failed copy leaves the previous version
+    /// intact. It gets the modification time and permissions `source` had before copying.
+    async fn update_copy(
+        config: &FileManagerConfig,
+        source: &Path,
+        target: &Path,
+    ) -> Result<(), FileManagerError> {
+        let retry = &config.io_retry;
+        if let Some(parent) = target.parent() {
+            retry
+                .run(FileOperation::CreatingDirectory, parent, || {
+                    create_dir_all(parent)
+                })
+                .await
+                .map_err(FileManagerError::io(
+                    FileOperation::CreatingDirectory,
+                    parent,
+                ))?;
+        }
+        let updating = || FileManagerError::io(FileOperation::UpdatingCopy, target);
+        let partial = target.with_file_name(format!(
+            ".{}.partial",
+            target.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        // Read before copying, so a later modification of the source is newer than the copy.
+        let source_metadata = metadata(source).await;
+        let verified = match retry
+            .run(FileOperation::UpdatingCopy, target, || {
+                copy(source, &partial)
+            })
+            .await
+        {
+            Ok(copied) => FileManager::verify_copy(config, source, &partial, copied).await,
+            Err(e) => Err(e),
+        };
+        match verified {
+            Ok(true) => {
+                match &source_metadata {
+                    Ok(source_metadata) => {
+                        if let Err(e) = copy_file_metadata(source_metadata, &partial) {
+                            log::warn!(
+                                "Unable to set the modification time and permissions of {}. Error: {}",
+                                target.to_string_lossy(),
+                                e
+                            );
+                        }
                     }
+                    Err(e) => log::warn!(
+                        "Unable to read the metadata of {}. Error: {}",
+                        source.to_string_lossy(),
+                        e
+                    ),
                 }
-                Err(e) => (path, Err(e)),
-            };
-            res.insert(cres.0, cres.1);
+                retry
+                    .run(FileOperation::UpdatingCopy, target, || {
+                        rename(&partial, target)
+                    })
+                    .await
+                    .map_err(updating())
+            }
+            Ok(false) => {
+                let _ = remove_file(&partial).await;
+                Err(FileManagerError::CopyMismatch(target.to_path_buf()))
+            }
+            Err(e) => {
+                let _ = remove_file(&partial).await;
+                Err(updating()(e))
+            }
         }
-        res
     }
 
-    fn generate_updated_pdfs<'a>(
-        &self,
-        tasks: HashMap<&'a Path, Result<Vec<Comparison>, FileManagerError>>,
-    ) -> HashMap<&'a Path, Result<PathBuf, FileManagerError>> {
-        tasks
+    /// Whether `copy` has the size of `source` and the `copied` bytes, and with
+    /// `verify_copies` the same content.
+    async fn verify_copy(
+        config: &FileManagerConfig,
+        source: &Path,
+        copy: &Path,
+        copied: u64,
+    ) -> io::Result<bool> {
+        let len = metadata(copy).await?.len();
+        if len != copied || len != metadata(source).await?.len() {
+            log::warn!(
+                "The copy {} has {} bytes, expected {}",
+                copy.to_string_lossy(),
+                len,
+                copied
+            );
+            return Ok(false);
+        }
+        if config.verify_copies {
+            return Ok(file_digest(source).await? == file_digest(copy).await?);
+        }
+        Ok(true)
+    }
+
+    /// Hands `comparisons` of `path` to `sink` along with the documents the comparison
+    /// loaded, returning the written artifacts, the time the sink spent on them and the
+    /// pages it couldn't mark.
+    fn write_diff(
+        sink: &mut dyn DiffSink,
+        config: &FileManagerConfig,
+        (path, previous_path): (&Path, Option<&Path>),
+        comparisons: &[Comparison],
+        fingerprint: Option<&FileFingerprint>,
+        (current, previous): (&mut LoadedDocument, Option<&LoadedDocument>),
+        cancel: Option<&CancellationToken>,
+    ) -> Result<WrittenDiff, FileManagerError> {
+        if cancel.is_some_and(|v| v.is_cancelled()) {
+            return Err(FileManagerError::Cancelled(path.to_path_buf()));
+        }
+        // Sinks may load the file again, which must still be the version the comparisons
+        // were computed for.
+        if let Some(fingerprint) = fingerprint {
+            if FileFingerprint::read(path).ok().as_ref() != Some(fingerprint) {
+                return Err(FileManagerError::ChangedDuringProcessing(
+                    path.to_path_buf(),
+                ));
+            }
+        }
+        sink.set_deadline(config.file_timeout.map(|v| Instant::now() + v));
+        sink.begin_document(path, previous_path)?;
+        comparisons
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, comparison)| match comparison {
+                Comparison::Identical(matched_page) => sink.page_identical(index, *matched_page),
+                Comparison::Different(_) => sink.page_changed(index, comparison),
+            })?;
+        let out = sink.finish_loaded(current, previous).map_err(|e| match e {
+            DiffSinkError::PDFEditorError(PDFEditorError::TimedOut { .. }) => {
+                FileManager::timed_out(config, path)
+            }
+            DiffSinkError::PDFEditorError(PDFEditorError::Cancelled { .. }) => {
+                FileManagerError::Cancelled(path.to_path_buf())
+            }
+            e => e.into(),
+        })?;
+        Ok(WrittenDiff {
+            out,
+            previous_out: sink.take_previous_version_output(),
+            timings: sink.take_timings(),
+            failed_pages: FailedPage::from_outcome(&sink.take_mark_outcome()),
+        })
+    }
+
+    /// Compares every file of `files` against its copy in the configured order. Changed
+    /// files are handed to the sink right away with the documents loaded for the comparison,
+    /// so they are neither read nor parsed a second time and only one file is held in memory
+    /// at a time. Comparisons found in `cache` are reused, new ones are recorded in it.
+    fn generate_comparisons<'a>(
+        &mut self,
+        files: &'a HashMap<PathBuf, PathBuf>,
+        cache: &mut ComparisonCache,
+        cancel: Option<&CancellationToken>,
+    ) -> BTreeMap<&'a Path, FileComparison> {
+        self.sink.set_cancellation(cancel.cloned());
+        let mut files = files.iter().collect::<Vec<(&PathBuf, &PathBuf)>>();
+        self.sort_files(&mut files, |(path, _)| path);
+        let comparisons = files
             .into_iter()
-            .map(|(path, comparisons)| {
+            .map(|(current_path, last_path)| {
                 (
-                    path,
-                    comparisons.and_then(|comparisons| {
-                        let filename = path
-                            .file_name()
-                            .and_then(|v| v.to_str())
-                            .unwrap_or("unknown_filename");
-                        let outpath = self.diff_path.join(format!(
-                            "{}.diff.{}.pdf",
-                            filename,
-                            chrono::Utc::now().timestamp()
-                        ));
-                        if let Err(e) =
-                            self.pdf_editor
-                                .mark_differences(path, &comparisons, &outpath)
-                        {
-                            return Err(FileManagerError::PDFEditorError(e));
+                    current_path.as_path(),
+                    self.generate_comparison(current_path, last_path, cache, cancel),
+                )
+            })
+            .collect();
+        // The sink may be used by `end_cycle` after the last document.
+        self.sink.set_deadline(None);
+        self.sink.set_cancellation(None);
+        comparisons
+    }
+
+    /// The isolation files are compared with, `None` if they are compared in process, see
+    /// [`FileManagerConfig::isolation`].
+    fn isolation(&self) -> Option<&IsolationConfig> {
+        self.config
+            .isolation
+            .as_ref()
+            .filter(|_| !self.wants_thumbnails() && !self.pdf_comparison.config().keep_renders)
+    }
+
+    /// Compares `current_path` against `last_path` and hands it to the sink if it changed.
+    fn generate_comparison(
+        &mut self,
+        current_path: &Path,
+        last_path: &Path,
+        cache: &mut ComparisonCache,
+        cancel: Option<&CancellationToken>,
+    ) -> FileComparison {
+        let started = Instant::now();
+        if cancel.is_some_and(|v| v.is_cancelled()) {
+            return FileComparison::failed(
+                FileManagerError::Cancelled(current_path.to_path_buf()),
+                Duration::ZERO,
+                None,
+            );
+        }
+        let fingerprint = FileFingerprint::read(current_path).ok();
+        let too_large = fingerprint.as_ref().and_then(|fingerprint| {
+            let limit = self.config.max_file_size?;
+            (fingerprint.len > limit).then(|| FileManagerError::TooLarge {
+                path: current_path.to_path_buf(),
+                size: fingerprint.len,
+                limit,
+            })
+        });
+        if let Some(error) = too_large {
+            log::info!("Skipping {}: {}", current_path.to_string_lossy(), error);
+            return FileComparison::failed(error, started.elapsed(), fingerprint);
+        }
+        match self.quarantined.get(current_path) {
+            Some(quarantined) if fingerprint.as_ref() == Some(quarantined) => {
+                let error = FileManagerError::Quarantined(current_path.to_path_buf());
+                log::info!("Skipping {}: {}", current_path.to_string_lossy(), error);
+                return FileComparison::failed(error, started.elapsed(), fingerprint);
+            }
+            Some(_) => {
+                log::info!(
+                    "{} was modified since it was quarantined, it is compared again",
+                    current_path.to_string_lossy()
+                );
+                self.quarantined.remove(current_path);
+            }
+            None => {}
+        }
+        let key = self.comparison_key(current_path, last_path);
+        let isolation = self.isolation();
+        let failed = |e| FileManager::comparison_failed(&self.config, e, (current_path, last_path));
+        let cached = key
+            .as_ref()
+            .and_then(|key| cache.get(current_path, key))
+            .map(DocumentComparison::from);
+        let reused = cached.is_some();
+        let (result, mut timings) = match (cached, isolation) {
+            (Some(comparison), _) => {
+                log::info!(
+                    "Reusing the comparison of {}, neither version changed since it was compared",
+                    current_path.to_string_lossy()
+                );
+                let (result, timings) = FileManager::with_documents(
+                    &self.pdf_comparison,
+                    comparison,
+                    current_path,
+                    last_path,
+                );
+                (result.map_err(failed), timings)
+            }
+            (None, Some(isolation)) => {
+                let compared = isolation::compare(
+                    isolation,
+                    (current_path, last_path),
+                    self.pdf_comparison.config(),
+                    self.config.file_timeout.map(|v| started + v),
+                    cancel,
+                );
+                match compared {
+                    Ok(compared) => {
+                        let key = key.as_ref().filter(|key| {
+                            key.current == compared.current_sha256
+                                && key.previous == compared.previous_sha256
+                        });
+                        if let Some(key) = key {
+                            cache.insert(
+                                current_path,
+                                key,
+                                CachedComparison::from(&compared.comparison),
+                            );
+                        }
+                        let (result, mut timings) = FileManager::with_documents(
+                            &self.pdf_comparison,
+                            compared.comparison,
+                            current_path,
+                            last_path,
+                        );
+                        timings += compared.timings;
+                        (result.map_err(failed), timings)
+                    }
+                    Err(IsolationError::Comparison(e)) => (Err(failed(e)), Timings::default()),
+                    Err(IsolationError::TimedOut) => {
+                        let limit = [self.config.file_timeout, isolation.timeout]
+                            .into_iter()
+                            .flatten()
+                            .min()
+                            .unwrap_or_default();
+                        log::warn!(
+                            "Abandoned {} after {}",
+                            current_path.to_string_lossy(),
+                            humantime::format_duration(limit)
+                        );
+                        let error = FileManagerError::Timeout {
+                            path: current_path.to_path_buf(),
+                            limit,
+                        };
+                        (Err(error), Timings::default())
+                    }
+                    Err(IsolationError::Cancelled) => (
+                        Err(FileManagerError::Cancelled(current_path.to_path_buf())),
+                        Timings::default(),
+                    ),
+                    Err(e) => {
+                        if let IsolationError::Crashed { .. } = e {
+                            FileManager::quarantine(
+                                &mut self.quarantined,
+                                current_path,
+                                fingerprint.as_ref(),
+                                "it crashed its comparison",
+                            );
                         }
-                        Ok(outpath)
-                    }),
+                        let error = FileManagerError::Isolation {
+                            path: current_path.to_path_buf(),
+                            source: e,
+                        };
+                        (Err(error), Timings::default())
+                    }
+                }
+            }
+            (None, None) => {
+                let (result, timings) = self.pdf_comparison.compare_pdfs_loaded(
+                    current_path,
+                    last_path,
+                    self.wants_thumbnails().then_some(HTML_THUMBNAIL_WIDTH),
+                    self.config.file_timeout.map(|v| started + v),
+                    cancel,
+                );
+                let result = result.map(|loaded| {
+                    // Only recorded if the versions compared are the ones the key was made of.
+                    let compared = key.as_ref().filter(|key| {
+                        key.current == loaded.current.sha256
+                            && key.previous == loaded.previous.as_ref().map(|v| v.sha256)
+                    });
+                    if let Some(key) = compared {
+                        cache.insert(
+                            current_path,
+                            key,
+                            CachedComparison::from(&loaded.comparison),
+                        );
+                    }
+                    (loaded.comparison, Some((loaded.current, loaded.previous)))
+                });
+                (result.map_err(failed), timings)
+            }
+        };
+        let duration = started.elapsed();
+        let (mut comparison, documents) = match result {
+            Ok((comparison, documents)) => (Ok(comparison), documents),
+            Err(e) => (Err(e), None),
+        };
+        let changed = comparison.as_ref().is_ok_and(|comparison| {
+            comparison
+                .pages
+                .iter()
+                .any(|v| matches!(v, Comparison::Different(_)))
+        });
+        let (mut previous_output, mut failed_pages) = (None, Vec::new());
+        let output = match (&comparison, documents) {
+            (Ok(comparison), Some((mut current, previous))) if changed => {
+                let previous_path = Some(last_path).filter(|v| v.exists());
+                let written = FileManager::write_diff(
+                    self.sink.as_mut(),
+                    &self.config,
+                    (current_path, previous_path),
+                    &comparison.pages,
+                    fingerprint.as_ref(),
+                    (&mut current, previous.as_ref()),
+                    cancel,
+                );
+                Some(written.map(|written| {
+                    timings += written.timings;
+                    previous_output = written.previous_out;
+                    failed_pages = written.failed_pages;
+                    written.out
+                }))
+            }
+            _ => None,
+        };
+        if matches!(comparison, Err(FileManagerError::Timeout { .. }))
+            || matches!(output, Some(Err(FileManagerError::Timeout { .. })))
+        {
+            FileManager::quarantine(
+                &mut self.quarantined,
+                current_path,
+                fingerprint.as_ref(),
+                "it timed out",
+            );
+        }
+        let memory = comparison
+            .as_ref()
+            .map(|comparison| comparison.memory.clone())
+            .unwrap_or_default();
+        let (blank_pages, previous_blank_pages, ink_coverage, size_changes) = comparison
+            .as_mut()
+            .map(|comparison| {
+                (
+                    std::mem::take(&mut comparison.blank_pages),
+                    std::mem::take(&mut comparison.previous_blank_pages),
+                    std::mem::take(&mut comparison.ink_coverage),
+                    std::mem::take(&mut comparison.size_changes),
                 )
             })
-            .collect()
+            .unwrap_or_default();
+        let (comparisons, new_file, previous_pages, thumbnails, renders, changes) = match comparison
+        {
+            Ok(comparison) => (
+                Ok(comparison.pages),
+                comparison.new_file,
+                comparison.thumbnails.previous_page_count,
+                self.wants_thumbnails().then_some(comparison.thumbnails),
+                comparison.renders,
+                comparison.changes,
+            ),
+            Err(e) => (
+                Err(e),
+                false,
+                None,
+                None,
+                Vec::new(),
+                DocumentChanges::default(),
+            ),
+        };
+        FileComparison {
+            comparisons,
+            new_file,
+            duration,
+            timings,
+            memory,
+            thumbnails,
+            renders,
+            previous_pages,
+            blank_pages,
+            previous_blank_pages,
+            ink_coverage,
+            size_changes,
+            changes,
+            fingerprint,
+            output,
+            previous_output,
+            failed_pages,
+            cached: reused,
+        }
     }
 
-    fn generate_comparisons<'a>(
-        &self,
-        files: &'a HashMap<PathBuf, PathBuf>,
-    ) -> HashMap<&'a Path, Result<Vec<Comparison>, FileManagerError>> {
-        files
+    /// Records `path` in `quarantined`, so it is skipped in the following cycles until it is
+    /// no longer `fingerprint`, see [`SkipReason::Quarantined`].
+    fn quarantine(
+        quarantined: &mut HashMap<PathBuf, FileFingerprint>,
+        path: &Path,
+        fingerprint: Option<&FileFingerprint>,
+        reason: &str,
+    ) {
+        if let Some(fingerprint) = fingerprint {
+            log::warn!(
+                "Quarantining {} until it is modified, {}",
+                path.to_string_lossy(),
+                reason
+            );
+            quarantined.insert(path.to_path_buf(), fingerprint.clone());
+        }
+    }
+
+    /// Hands `comparison` of `current_path` on with both documents loaded if a page changed,
+    /// for the sink. Identical files aren't handed to the sink, they don't need them.
+    fn with_documents<'a>(
+        pdf_comparison: &'a PDFComparison,
+        comparison: DocumentComparison,
+        current_path: &Path,
+        last_path: &Path,
+    ) -> (Result<WithDocuments<'a>, PDFComparisonError>, Timings) {
+        match comparison
+            .pages
             .iter()
-            .filter_map(|(current_path, last_path)| {
-                match self.pdf_comparison.compare_pdfs(current_path, last_path) {
-                    Ok(res) => {
-                        res.iter().find(|v| match v {
-                            Comparison::Different(_) => true,
-                            Comparison::Identical => false,
-                        })?;
-                        Some((current_path.as_path(), Ok(res)))
-                    }
-                    Err(e) => Some((
-                        current_path.as_path(),
-                        Err(FileManagerError::PDFComparisonError(e)),
-                    )),
+            .any(|v| matches!(v, Comparison::Different(_)))
+        {
+            true => {
+                let (documents, timings) = pdf_comparison.load_pdfs(current_path, last_path);
+                (documents.map(|v| (comparison, Some(v))), timings)
+            }
+            false => (Ok((comparison, None)), Timings::default()),
+        }
+    }
+
+    /// The error of the file manager for the comparison of `current_path` against
+    /// `last_path` failing with `error`.
+    fn comparison_failed(
+        config: &FileManagerConfig,
+        error: PDFComparisonError,
+        (current_path, last_path): (&Path, &Path),
+    ) -> FileManagerError {
+        match error {
+            PDFComparisonError::TimedOut { .. } => FileManager::timed_out(config, current_path),
+            PDFComparisonError::Cancelled { .. } => {
+                FileManagerError::Cancelled(current_path.to_path_buf())
+            }
+            e @ (PDFComparisonError::UnableToReadPDF { .. }
+            | PDFComparisonError::UnableToLoadPDF { .. })
+                if e.document().side == DocumentSide::B =>
+            {
+                FileManagerError::BrokenBaseline {
+                    copy: last_path.to_path_buf(),
+                    source: e,
                 }
-            })
-            .collect()
+            }
+            e => FileManagerError::PDFComparisonError(e),
+        }
+    }
+
+    /// Key of comparing `current_path` against `last_path` in the [`ComparisonCache`].
+    /// `None` if comparisons aren't cached because thumbnails or renders are needed, which
+    /// a cached comparison lacks, or a file couldn't be read.
+    fn comparison_key(&self, current_path: &Path, last_path: &Path) -> Option<ComparisonKey> {
+        if self.wants_thumbnails() || self.pdf_comparison.config().keep_renders {
+            return None;
+        }
+        let current = file_digest_blocking(current_path).ok()?;
+        let previous = match file_digest_blocking(last_path) {
+            Ok(previous) => Some(previous),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(_) => return None,
+        };
+        // Renders, and with them the outcome, may change with the version of either.
+        let settings = format!(
+            "{}@{}",
+            self.pdf_comparison.config().settings_digest(),
+            env!("CARGO_PKG_VERSION")
+        );
+        let settings = match self.config.pdfium_version {
+            Some(version) => format!("{}@{}", settings, version),
+            None => settings,
+        };
+        Some(ComparisonKey {
+            current,
+            previous,
+            settings,
+        })
     }
 
+    /// Returns every pdf of `current_path` that needs to be processed together with the
+    /// path of its copy, and the number of files found. `depth` is the number of
+    /// directories `current_path` is below the root.
     fn find_updated_files(
         current_path: PathBuf,
         last_path: PathBuf,
-    ) -> BoxFuture<'static, Result<Vec<(PathBuf, PathBuf)>, FileManagerError>> {
+        settings: Arc<ScanSettings>,
+        depth: usize,
+    ) -> BoxFuture<'static, Result<Scan, FileManagerError>> {
         async move {
-            let mut entires = read_dir(current_path).await?;
-            let mut result = Vec::new();
-            while let Some(entry) = entires.next_entry().await? {
-                let file_type: FileTypeEnum = entry.file_type().await?.into();
+            if depth > settings.max_depth {
+                return Err(FileManagerError::TooDeep {
+                    path: current_path,
+                    limit: settings.max_depth,
+                });
+            }
+            let scanning = || FileManagerError::io(FileOperation::ScanningDirectory, &current_path);
+            let retry = &settings.retry;
+            let mut entires = retry
+                .run(FileOperation::ScanningDirectory, &current_path, || {
+                    read_dir(&current_path)
+                })
+                .await
+                .map_err(scanning())?;
+            let mut scan = Scan::default();
+            while let Some(entry) = entires.next_entry().await.map_err(scanning())? {
+                if settings.ignores(&entry.path()) {
+                    log::trace!("Ignoring {}", entry.path().to_string_lossy());
+                    scan.ignored += 1;
+                    continue;
+                }
+                let reading_metadata =
+                    || FileManagerError::io(FileOperation::ReadingMetadata, &entry.path());
+                let mut file_type = retry
+                    .run(FileOperation::ReadingMetadata, &entry.path(), || {
+                        entry.file_type()
+                    })
+                    .await
+                    .map_err(reading_metadata())?;
+                if file_type.is_symlink() {
+                    if settings.symlinks == SymlinkPolicy::Skip {
+                        log::trace!("Skipping the symlink {}", entry.path().to_string_lossy());
+                        scan.ignored += 1;
+                        continue;
+                    }
+                    match metadata(entry.path()).await {
+                        Ok(target) => file_type = target.file_type(),
+                        Err(e) => {
+                            log::warn!(
+                                "Skipping the broken symlink {}. Error: {}",
+                                entry.path().to_string_lossy(),
+                                e
+                            );
+                            scan.ignored += 1;
+                            continue;
+                        }
+                    }
+                }
+                if file_type.is_dir() && settings.symlinks == SymlinkPolicy::Follow {
+                    let canonical = tokio::fs::canonicalize(entry.path())
+                        .await
+                        .map_err(reading_metadata())?;
+                    let first_visit = settings
+                        .visited
+                        .lock()
+                        .expect("The visited directories are only locked to insert")
+                        .insert(canonical);
+                    if !first_visit {
+                        log::warn!(
+                            "Skipping {}, the directory was already scanned",
+                            entry.path().to_string_lossy()
+                        );
+                        scan.ignored += 1;
+                        continue;
+                    }
+                }
+                let file_type = FileTypeEnum::from(file_type);
+                if let FileTypeEnum::File = file_type {
+                    scan.scanned += 1;
+                    if !settings.is_pdf(&entry.path()).await {
+                        continue;
+                    }
+                    // Their state is kept in `diff_path`, the caller decides whether they
+                    // changed.
+                    if let Some(baseline) = settings.baseline(&entry.path()) {
+                        if settings.forces(&entry.path()) {
+                            scan.forced.insert(entry.path());
+                        }
+                        scan.overridden.push((entry.path(), baseline));
+                        continue;
+                    }
+                }
                 let file_name = entry.file_name();
                 let last_path_file_path = last_path.join(file_name);
-                let last_path_metadata = metadata(&last_path_file_path)
+                let last_path_metadata = retry
+                    .run(FileOperation::ReadingMetadata, &last_path_file_path, || {
+                        metadata(&last_path_file_path)
+                    })
                     .await
                     .map(|v| (FileTypeEnum::from(&v), v));
                 match (file_type, last_path_metadata) {
+                    (FileTypeEnum::File, Ok((FileTypeEnum::File, _))) if settings.all_files => {
+                        scan.files.push((entry.path(), last_path_file_path));
+                    }
                     (FileTypeEnum::File, Ok((FileTypeEnum::File, last_meta))) => {
-                        let current_meta = metadata(entry.path()).await?;
-                        if current_meta.modified()? > last_meta.modified()?
-                            && entry.path().extension() == Some(OsStr::new("pdf"))
-                        {
-                            result.push((entry.path(), last_path_file_path));
+                        let current_meta = retry
+                            .run(FileOperation::ReadingMetadata, &entry.path(), || {
+                                metadata(entry.path())
+                            })
+                            .await
+                            .map_err(reading_metadata())?;
+                        let last_modified = last_meta.modified().map_err(FileManagerError::io(
+                            FileOperation::ReadingMetadata,
+                            &last_path_file_path,
+                        ))?;
+                        // Copies get the modification time of their file, so any other time
+                        // is a modification, e.g. an older version restored from a backup.
+                        if current_meta.modified().map_err(reading_metadata())? != last_modified {
+                            scan.files.push((entry.path(), last_path_file_path));
+                        } else if settings.forces(&entry.path()) {
+                            scan.forced.insert(entry.path());
+                            scan.files.push((entry.path(), last_path_file_path));
                         }
                     }
                     (FileTypeEnum::File, Err(e)) => {
                         if let io::ErrorKind::NotFound = e.kind() {
-                            result.push((entry.path(), last_path_file_path));
+                            scan.files.push((entry.path(), last_path_file_path));
                         } else {
-                            return Err(FileManagerError::Io(e));
+                            return Err(FileManagerError::io(
+                                FileOperation::ReadingMetadata,
+                                &last_path_file_path,
+                            )(e));
                         }
                     }
                     (FileTypeEnum::File, Ok((FileTypeEnum::Dir, _last_meta))) => {
                         //wtf
-                        result.push((entry.path(), last_path_file_path));
+                        scan.files.push((entry.path(), last_path_file_path));
                     }
                     (FileTypeEnum::Dir, Err(e)) => {
                         if let io::ErrorKind::NotFound = e.kind() {
-                            result.append(
-                                &mut FileManager::find_updated_files(
+                            scan.append(
+                                FileManager::find_updated_files(
                                     entry.path(),
                                     last_path_file_path,
+                                    settings.clone(),
+                                    depth + 1,
                                 )
                                 .await?,
-                            )
+                            );
                         } else {
-                            return Err(FileManagerError::Io(e));
+                            return Err(FileManagerError::io(
+                                FileOperation::ReadingMetadata,
+                                &last_path_file_path,
+                            )(e));
                         }
                     }
                     (FileTypeEnum::Dir, _) => {
-                        result.append(
-                            &mut FileManager::find_updated_files(entry.path(), last_path_file_path)
-                                .await?,
+                        scan.append(
+                            FileManager::find_updated_files(
+                                entry.path(),
+                                last_path_file_path,
+                                settings.clone(),
+                                depth + 1,
+                            )
+                            .await?,
                         );
                     }
                 }
             }
 
-            Ok(result)
+            Ok(scan)
         }
         .boxed()
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use {
+        super::*,
+        globset::GlobSet,
+        std::{
+            fs,
+            os::unix::fs::{symlink, PermissionsExt},
+        },
+    };
+
+    /// A directory below the temp dir that is removed again when dropped.
+    struct Dir(PathBuf);
+
+    impl Dir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "pdf_diff_gen-files-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(path.join("current")).unwrap();
+            fs::create_dir_all(path.join("last")).unwrap();
+            Dir(path)
+        }
+
+        fn current(&self) -> PathBuf {
+            self.0.join("current")
+        }
+
+        fn last(&self) -> PathBuf {
+            self.0.join("last")
+        }
+    }
+
+    impl Drop for Dir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    /// Settings scanning `dir` for pdfs like the default configuration.
+    fn settings(dir: &Dir) -> ScanSettings {
+        ScanSettings {
+            root: dir.current(),
+            extensions: vec!["pdf".to_string()],
+            sniff_content: false,
+            include_hidden: false,
+            ignore: GlobSet::empty(),
+            retry: IoRetryPolicy::default(),
+            all_files: false,
+            force: None,
+            baselines: BaselineOverrides::default(),
+            symlinks: SymlinkPolicy::Skip,
+            max_depth: 64,
+            visited: Mutex::new(HashSet::from_iter(fs::canonicalize(dir.current()).ok())),
+        }
+    }
+
+    fn scan(dir: &Dir, settings: ScanSettings) -> Result<Scan, FileManagerError> {
+        block_on(FileManager::find_updated_files(
+            dir.current(),
+            dir.last(),
+            Arc::new(settings),
+            0,
+        ))
+    }
+
+    fn scanned_files(scan: &Scan) -> Vec<PathBuf> {
+        let mut files = scan
+            .files
+            .iter()
+            .map(|(file, _)| file.clone())
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn followed_symlink_loops_are_scanned_once() {
+        let dir = Dir::new("loop-follow");
+        fs::create_dir(dir.current().join("a")).unwrap();
+        fs::write(dir.current().join("a/report.pdf"), b"%PDF-").unwrap();
+        symlink("..", dir.current().join("a/loop")).unwrap();
+        let scan = scan(
+            &dir,
+            ScanSettings {
+                symlinks: SymlinkPolicy::Follow,
+                ..settings(&dir)
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            scanned_files(&scan),
+            vec![dir.current().join("a/report.pdf")]
+        );
+        assert_eq!(scan.scanned, 1);
+        assert_eq!(scan.ignored, 1);
+    }
+
+    #[test]
+    fn skipped_symlinks_are_ignored() {
+        let dir = Dir::new("loop-skip");
+        fs::create_dir(dir.current().join("a")).unwrap();
+        fs::write(dir.current().join("report.pdf"), b"%PDF-").unwrap();
+        symlink("..", dir.current().join("a/loop")).unwrap();
+        symlink("../report.pdf", dir.current().join("a/link.pdf")).unwrap();
+        let scan = scan(&dir, settings(&dir)).unwrap();
+        assert_eq!(scanned_files(&scan), vec![dir.current().join("report.pdf")]);
+        assert_eq!(scan.ignored, 2);
+    }
+
+    #[test]
+    fn directories_below_the_depth_limit_fail_the_scan() {
+        let dir = Dir::new("too-deep");
+        fs::create_dir_all(dir.current().join("a/b/c")).unwrap();
+        let limited = |max_depth| ScanSettings {
+            max_depth,
+            ..settings(&dir)
+        };
+        assert!(scan(&dir, limited(3)).is_ok());
+        assert!(matches!(
+            scan(&dir, limited(2)),
+            Err(FileManagerError::TooDeep { path, limit: 2 }) if path == dir.current().join("a/b/c")
+        ));
+    }
+
+    #[test]
+    fn new_files_are_compared_or_seeded_as_configured() {
+        let dir = Dir::new("seed");
+        let new = dir.current().join("new.pdf");
+        let modified = dir.current().join("modified.pdf");
+        fs::write(&new, b"%PDF-new").unwrap();
+        fs::write(&modified, b"%PDF-modified").unwrap();
+        fs::write(dir.last().join("modified.pdf"), b"%PDF-").unwrap();
+        let files = vec![
+            (new.clone(), dir.last().join("new.pdf")),
+            (modified.clone(), dir.last().join("modified.pdf")),
+        ];
+
+        let config = FileManagerConfig::default();
+        assert_eq!(config.new_files, NewFilePolicy::Diff);
+        let (seeded, remaining) = block_on(FileManager::seed_baselines(&config, files.clone()));
+        assert!(seeded.is_empty());
+        assert_eq!(remaining, HashMap::from_iter(files.clone()));
+        assert!(!dir.last().join("new.pdf").exists());
+
+        let config = FileManagerConfig {
+            new_files: NewFilePolicy::SeedBaseline,
+            ..Default::default()
+        };
+        let (seeded, remaining) = block_on(FileManager::seed_baselines(&config, files.clone()));
+        assert!(matches!(
+            seeded.as_slice(),
+            [(path, FileOutcome::Skipped { reason: SkipReason::NoBaseline })] if path == &new
+        ));
+        assert_eq!(remaining, HashMap::from_iter(files[1..].iter().cloned()));
+        assert_eq!(fs::read(dir.last().join("new.pdf")).unwrap(), b"%PDF-new");
+        assert_eq!(fs::read(dir.last().join("modified.pdf")).unwrap(), b"%PDF-");
+    }
+
+    #[test]
+    fn copies_keep_the_modification_time_and_permissions() {
+        let dir = Dir::new("copy-metadata");
+        let source = dir.current().join("report.pdf");
+        let copy = dir.last().join("report.pdf");
+        fs::write(&source, b"%PDF-").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o640)).unwrap();
+        let config = FileManagerConfig::default();
+        block_on(FileManager::update_copy(&config, &source, &copy)).unwrap();
+        let metadata = fs::metadata(&copy).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read(&copy).unwrap(), b"%PDF-");
+        assert_eq!(
+            fs::read_dir(dir.last()).unwrap().count(),
+            1,
+            "The partial copy was left behind"
+        );
+        assert!(scan(&dir, settings(&dir)).unwrap().files.is_empty());
+    }
+
+    /// Only checks the permissions if not run as root, root may write read-only files.
+    #[test]
+    fn read_only_files_and_copies_get_the_modification_time() {
+        let dir = Dir::new("read-only");
+        let source = dir.current().join("report.pdf");
+        let copy = dir.last().join("report.pdf");
+        fs::write(&source, b"%PDF-").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o444)).unwrap();
+        let config = FileManagerConfig::default();
+        block_on(FileManager::update_copy(&config, &source, &copy)).unwrap();
+        let metadata = fs::metadata(&copy).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o444);
+        assert!(scan(&dir, settings(&dir)).unwrap().files.is_empty());
+
+        // Marking an unchanged file as examined sets the time of its read-only copy.
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&source)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        fs::set_permissions(&source, fs::Permissions::from_mode(0o444)).unwrap();
+        assert_eq!(scan(&dir, settings(&dir)).unwrap().files.len(), 1);
+        copy_file_metadata(&fs::metadata(&source).unwrap(), &copy).unwrap();
+        assert_eq!(
+            fs::metadata(&copy).unwrap().modified().unwrap(),
+            SystemTime::UNIX_EPOCH
+        );
+        assert!(scan(&dir, settings(&dir)).unwrap().files.is_empty());
+    }
+
+    #[test]
+    fn files_with_another_modification_time_than_their_copy_are_modified() {
+        let dir = Dir::new("restored");
+        let file = dir.current().join("report.pdf");
+        fs::write(&file, b"%PDF-").unwrap();
+        let config = FileManagerConfig::default();
+        block_on(FileManager::update_copy(
+            &config,
+            &file,
+            &dir.last().join("report.pdf"),
+        ))
+        .unwrap();
+        assert!(scan(&dir, settings(&dir)).unwrap().files.is_empty());
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(
+            scanned_files(&scan(&dir, settings(&dir)).unwrap()),
+            vec![file]
+        );
+    }
+
+    #[test]
+    fn new_and_modified_files_are_pdfs_by_the_same_rules() {
+        let dir = Dir::new("extensions");
+        let names = ["REPORT.PDF", "drawing.ai", "notes.txt", "scanned", "text"];
+        for name in names {
+            let content: &[u8] = match name {
+                "notes.txt" | "text" => b"Not a pdf",
+                _ => b"%PDF-1.7",
+            };
+            fs::write(dir.current().join(name), content).unwrap();
+        }
+        // Copies of modified files, copies of new ones don't exist yet.
+        for name in ["Modified.Pdf", "modified.txt", "modified"] {
+            fs::write(dir.current().join(name), b"%PDF-1.7").unwrap();
+            fs::write(dir.last().join(name), b"%PDF-1.6").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(dir.last().join(name))
+                .unwrap()
+                .set_modified(SystemTime::UNIX_EPOCH)
+                .unwrap();
+        }
+        let files = |settings| {
+            scanned_files(&scan(&dir, settings).unwrap())
+                .iter()
+                .map(|v| v.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(files(settings(&dir)), ["Modified.Pdf", "REPORT.PDF"]);
+        assert_eq!(
+            files(ScanSettings {
+                extensions: vec!["pdf".to_string(), "AI".to_string()],
+                sniff_content: true,
+                ..settings(&dir)
+            }),
+            [
+                "Modified.Pdf",
+                "REPORT.PDF",
+                "drawing.ai",
+                "modified",
+                "scanned"
+            ]
+        );
+        assert_eq!(scan(&dir, settings(&dir)).unwrap().scanned, 8);
+    }
+}
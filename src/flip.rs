@@ -0,0 +1,50 @@
+use {
+    image::{
+        codecs::gif::{GifEncoder, Repeat},
+        Delay, Frame, ImageError, RgbImage,
+    },
+    std::{path::PathBuf, time::Duration},
+};
+
+/// Where and how [`crate::FileManager`] writes flip gifs of changed pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlipGifConfig {
+    /// Directory the gifs are written to, mirroring the layout of the watched tree.
+    pub dir: PathBuf,
+    /// How long each version is shown before flipping to the other one.
+    pub delay: Duration,
+}
+
+impl FlipGifConfig {
+    pub fn new(dir: PathBuf) -> Self {
+        FlipGifConfig {
+            dir,
+            delay: Duration::from_millis(800),
+        }
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// Encodes a looping gif alternating between `previous` and `current`, each shown for
+/// `delay`. Gifs only store delays in hundredths of a second, shorter ones are rounded.
+pub fn encode_flip_gif(
+    previous: &RgbImage,
+    current: &RgbImage,
+    delay: Duration,
+) -> Result<Vec<u8>, ImageError> {
+    let mut gif = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut gif);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let delay = Delay::from_saturating_duration(delay);
+        for image in [previous, current] {
+            let rgba = image::DynamicImage::ImageRgb8(image.clone()).into_rgba8();
+            encoder.encode_frame(Frame::from_parts(rgba, 0, 0, delay))?;
+        }
+    }
+    Ok(gif)
+}
@@ -0,0 +1,157 @@
+use {
+    futures::{stream, StreamExt},
+    std::{
+        path::{Path, PathBuf},
+        process::Stdio,
+        time::Duration,
+    },
+    tokio::process::Command,
+};
+
+use crate::pdf::PageStatistics;
+
+/// A command run for every successfully processed file.
+///
+/// The command is run by the system shell with the environment variables
+/// `PDF_DIFF_SOURCE`, `PDF_DIFF_OUTPUT` (empty if no artifact was written),
+/// `PDF_DIFF_CHANGED_PAGES` and `PDF_DIFF_CHANGED_FRACTION` (mean over all pages, 0-1).
+#[derive(Debug, Clone)]
+pub struct HookConfig {
+    pub command: String,
+    /// Invocations still running after this long are killed and count as failed.
+    pub timeout: Duration,
+    /// Maximum number of invocations running at the same time.
+    pub concurrency: usize,
+}
+
+impl HookConfig {
+    pub fn new(command: String) -> Self {
+        HookConfig {
+            command,
+            timeout: Duration::from_secs(60),
+            concurrency: 4,
+        }
+    }
+}
+
+/// A single file the hook is run for.
+#[derive(Debug, Clone)]
+pub struct HookInvocation {
+    pub source: PathBuf,
+    pub output: Option<PathBuf>,
+    pub changed_pages: usize,
+    pub changed_fraction: f64,
+}
+
+impl HookInvocation {
+    pub fn new(source: &Path, output: Option<&Path>, pages: &[PageStatistics]) -> Self {
+        let changed_fraction = match pages.len() {
+            0 => 0.,
+            len => pages.iter().map(|v| v.changed_fraction).sum::<f64>() / len as f64,
+        };
+        HookInvocation {
+            source: source.to_path_buf(),
+            output: output.map(|v| v.to_path_buf()),
+            changed_pages: pages.iter().filter(|v| v.changed()).count(),
+            changed_fraction,
+        }
+    }
+}
+
+/// Result of running the hook for a file, as it appears in reports.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HookOutcome {
+    pub exit_code: Option<i32>,
+    /// Why the command couldn't be run to completion, if it couldn't.
+    pub error: Option<String>,
+}
+
+impl HookOutcome {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none() && self.exit_code == Some(0)
+    }
+}
+
+/// Runs the hook for every invocation, at most `config.concurrency` at a time, and returns
+/// the outcomes keyed by the source path. Failures are logged.
+pub async fn run_hooks(
+    config: &HookConfig,
+    invocations: Vec<HookInvocation>,
+) -> Vec<(PathBuf, HookOutcome)> {
+    stream::iter(invocations)
+        .map(|invocation| async move {
+            let outcome = run_hook(config, &invocation).await;
+            if !outcome.succeeded() {
+                log::warn!(
+                    "The diff hook failed for {}: {}",
+                    invocation.source.to_string_lossy(),
+                    match (&outcome.error, outcome.exit_code) {
+                        (Some(error), _) => error.clone(),
+                        (None, Some(code)) => format!("exit code {}", code),
+                        (None, None) => String::from("terminated by a signal"),
+                    }
+                );
+            }
+            (invocation.source, outcome)
+        })
+        .buffer_unordered(config.concurrency.max(1))
+        .collect()
+        .await
+}
+
+async fn run_hook(config: &HookConfig, invocation: &HookInvocation) -> HookOutcome {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command
+        .arg(&config.command)
+        .env("PDF_DIFF_SOURCE", &invocation.source)
+        .env(
+            "PDF_DIFF_OUTPUT",
+            invocation.output.as_deref().unwrap_or(Path::new("")),
+        )
+        .env(
+            "PDF_DIFF_CHANGED_PAGES",
+            invocation.changed_pages.to_string(),
+        )
+        .env(
+            "PDF_DIFF_CHANGED_FRACTION",
+            format!("{:.4}", invocation.changed_fraction),
+        )
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    let mut child = match command.spawn() {
+        Ok(v) => v,
+        Err(e) => {
+            return HookOutcome {
+                exit_code: None,
+                error: Some(format!("unable to spawn the command: {}", e)),
+            }
+        }
+    };
+    match tokio::time::timeout(config.timeout, child.wait()).await {
+        Ok(Ok(status)) => HookOutcome {
+            exit_code: status.code(),
+            error: None,
+        },
+        Ok(Err(e)) => HookOutcome {
+            exit_code: None,
+            error: Some(format!("unable to wait for the command: {}", e)),
+        },
+        Err(_) => HookOutcome {
+            exit_code: None,
+            error: Some(format!(
+                "timed out after {}",
+                humantime::format_duration(config.timeout)
+            )),
+        },
+    }
+}
@@ -0,0 +1,414 @@
+//! Runs comparisons in child processes, so a malformed document crashing pdfium only fails
+//! its own file instead of taking the whole process down.
+//!
+//! The parent writes a json request naming both versions and the comparison settings to the
+//! stdin of the child, which answers with a single json response on its stdout and exits,
+//! see [`serve`]. Errors of the comparison are part of the response, a child exiting
+//! unsuccessfully without one crashed. The children matching a share of the pages of a
+//! document, see [`compare_threads`](crate::PDFComparisonBuilder::compare_threads), are
+//! talked to the same way.
+
+use {
+    crate::{
+        cancel::CancellationToken,
+        digests::CachedComparison,
+        pdf::{
+            ComparisonConfig, DocumentComparison, DocumentRef, PDFComparison, PDFComparisonError,
+            PageRangeMatch,
+        },
+        timings::Timings,
+    },
+    pdfium_render::prelude::{PdfPageIndex, Pdfium},
+    serde::{Deserialize, Serialize},
+    std::{
+        ffi::OsString,
+        io::{self, Read, Write},
+        ops::Range,
+        path::{Path, PathBuf},
+        process::{Child, Command, ExitStatus, Stdio},
+        sync::Arc,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    },
+};
+
+/// How often a running child is checked for having exited, passed its deadline or been
+/// cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Bytes of the end of the stderr of a crashed child kept for its error.
+const STDERR_TAIL: usize = 4096;
+
+/// Exit code of a child that couldn't answer for reasons other than the document, e.g.
+/// because it couldn't load pdfium or read the request. It doesn't count as a crash.
+pub const UNAVAILABLE_EXIT_CODE: i32 = 69;
+
+/// How the child process comparing a single document is started, see
+/// [`FileManagerConfig::isolation`](crate::FileManagerConfig::isolation).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsolationConfig {
+    /// Program run for every comparison, usually the running binary.
+    pub program: PathBuf,
+    /// Arguments making `program` answer a single request through [`serve`].
+    pub args: Vec<OsString>,
+    /// Time a child may take before it is killed. The `file_timeout` of the file manager
+    /// applies as well, whichever ends first.
+    pub timeout: Option<Duration>,
+}
+
+/// A comparison of `current` against `previous` sent to the child. With `pages`, only those
+/// pages of `current` are matched.
+#[derive(Serialize, Deserialize)]
+struct Request {
+    current: PathBuf,
+    previous: PathBuf,
+    config: ComparisonConfig,
+    #[serde(default)]
+    pages: Option<Range<PdfPageIndex>>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum Response {
+    Compared {
+        comparison: Box<CachedComparison>,
+        current_sha256: [u8; 32],
+        previous_sha256: Option<[u8; 32]>,
+        timings: Timings,
+    },
+    Matched(Box<PageRangeMatch>),
+    Failed(Failure),
+}
+
+/// Error of a comparison in the child. The errors the file manager skips files for are sent
+/// as they are, of all others only the message.
+#[derive(Serialize, Deserialize)]
+enum Failure {
+    TooManyPages {
+        document: DocumentRef,
+        pages: usize,
+        limit: usize,
+    },
+    Encrypted {
+        document: DocumentRef,
+    },
+    Other {
+        document: DocumentRef,
+        message: String,
+    },
+}
+
+impl From<PDFComparisonError> for Failure {
+    fn from(error: PDFComparisonError) -> Self {
+        match error {
+            PDFComparisonError::TooManyPages {
+                document,
+                pages,
+                limit,
+            } => Failure::TooManyPages {
+                document,
+                pages,
+                limit,
+            },
+            PDFComparisonError::Encrypted { document } => Failure::Encrypted { document },
+            e => Failure::Other {
+                document: e.document().clone(),
+                message: e.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IsolationError {
+    /// The child couldn't be started or talked to.
+    Io(io::Error),
+    /// The child exited unsuccessfully before answering, e.g. because pdfium crashed.
+    /// `stderr` is the end of what it wrote there.
+    Crashed { status: ExitStatus, stderr: String },
+    /// The child exited with [`UNAVAILABLE_EXIT_CODE`], `stderr` is the end of what it
+    /// wrote there.
+    Unavailable { stderr: String },
+    /// The request couldn't be written or the response couldn't be read.
+    InvalidMessage(serde_json::Error),
+    /// The child passed its deadline and was killed.
+    TimedOut,
+    /// The comparison was cancelled and the child killed.
+    Cancelled,
+    /// The comparison failed in the child with an error the file manager handles like its
+    /// own.
+    Comparison(PDFComparisonError),
+    /// The comparison of `document` failed in the child with an error only the message of is
+    /// sent back.
+    Failed {
+        document: DocumentRef,
+        message: String,
+    },
+}
+
+impl std::error::Error for IsolationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::InvalidMessage(e) => Some(e),
+            Self::Comparison(e) => e.source(),
+            Self::Crashed { .. }
+            | Self::Unavailable { .. }
+            | Self::TimedOut
+            | Self::Cancelled
+            | Self::Failed { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for IsolationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "unable to run the comparison process: {}", e),
+            Self::Crashed { status, stderr } if stderr.is_empty() => {
+                write!(f, "the comparison process crashed with {}", status)
+            }
+            Self::Crashed { status, stderr } => write!(
+                f,
+                "the comparison process crashed with {}: {}",
+                status, stderr
+            ),
+            Self::Unavailable { stderr } => {
+                write!(f, "the comparison process is unavailable: {}", stderr)
+            }
+            Self::InvalidMessage(e) => {
+                write!(f, "the comparison process sent an invalid message: {}", e)
+            }
+            Self::TimedOut => write!(f, "the comparison process passed its deadline"),
+            Self::Cancelled => write!(f, "the comparison process was cancelled"),
+            Self::Comparison(e) => write!(f, "{}", e),
+            Self::Failed { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<Failure> for IsolationError {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::TooManyPages {
+                document,
+                pages,
+                limit,
+            } => Self::Comparison(PDFComparisonError::TooManyPages {
+                document,
+                pages,
+                limit,
+            }),
+            Failure::Encrypted { document } => {
+                Self::Comparison(PDFComparisonError::Encrypted { document })
+            }
+            Failure::Other { document, message } => Self::Failed { document, message },
+        }
+    }
+}
+
+impl From<io::Error> for IsolationError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for IsolationError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::InvalidMessage(value)
+    }
+}
+
+/// A comparison made by a child, with the digests of the versions it compared.
+pub struct IsolatedComparison {
+    pub comparison: DocumentComparison,
+    pub current_sha256: [u8; 32],
+    pub previous_sha256: Option<[u8; 32]>,
+    pub timings: Timings,
+}
+
+/// Kills the child if it still runs when dropped and waits for it either way, so no zombie
+/// is left behind on any path.
+struct Reaped(Child);
+
+impl Drop for Reaped {
+    fn drop(&mut self) {
+        if let Ok(None) = self.0.try_wait() {
+            let _ = self.0.kill();
+        }
+        let _ = self.0.wait();
+    }
+}
+
+/// Reads `pipe` to its end on a thread of its own, so a child filling one pipe never blocks
+/// on the parent waiting for it.
+fn read_to_end(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut buffer)?;
+        }
+        Ok(buffer)
+    })
+}
+
+fn joined(reader: JoinHandle<io::Result<Vec<u8>>>) -> io::Result<Vec<u8>> {
+    reader
+        .join()
+        .map_err(|_| io::Error::other("the thread reading the comparison process panicked"))?
+}
+
+/// Compares `current` against `previous` with `settings` in a child started as `config`
+/// says. The child is killed at `deadline` or the deadline of `config`, whichever comes
+/// first, or once `cancel` is cancelled.
+pub fn compare(
+    config: &IsolationConfig,
+    (current, previous): (&Path, &Path),
+    settings: &ComparisonConfig,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> Result<IsolatedComparison, IsolationError> {
+    let request = Request {
+        current: current.to_path_buf(),
+        previous: previous.to_path_buf(),
+        config: settings.clone(),
+        pages: None,
+    };
+    match run(config, &request, deadline, cancel)? {
+        Response::Compared {
+            comparison,
+            current_sha256,
+            previous_sha256,
+            timings,
+        } => Ok(IsolatedComparison {
+            comparison: DocumentComparison::from(*comparison),
+            current_sha256,
+            previous_sha256,
+            timings,
+        }),
+        Response::Matched(_) => Err(unexpected_response()),
+        Response::Failed(failure) => Err(failure.into()),
+    }
+}
+
+/// Like `compare`, but only matches the `pages` of `current`, see
+/// [`PDFComparisonBuilder::compare_threads`](crate::PDFComparisonBuilder::compare_threads).
+pub(crate) fn match_pages(
+    config: &IsolationConfig,
+    (current, previous): (&Path, &Path),
+    settings: &ComparisonConfig,
+    pages: Range<PdfPageIndex>,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> Result<PageRangeMatch, IsolationError> {
+    let request = Request {
+        current: current.to_path_buf(),
+        previous: previous.to_path_buf(),
+        config: settings.clone(),
+        pages: Some(pages),
+    };
+    match run(config, &request, deadline, cancel)? {
+        Response::Matched(matched) => Ok(*matched),
+        Response::Compared { .. } => Err(unexpected_response()),
+        Response::Failed(failure) => Err(failure.into()),
+    }
+}
+
+fn unexpected_response() -> IsolationError {
+    IsolationError::InvalidMessage(serde::de::Error::custom(
+        "the response doesn't answer the request",
+    ))
+}
+
+/// Sends `request` to a child started as `config` says and reads its response, see
+/// `compare` for when the child is killed.
+fn run(
+    config: &IsolationConfig,
+    request: &Request,
+    deadline: Option<Instant>,
+    cancel: Option<&CancellationToken>,
+) -> Result<Response, IsolationError> {
+    let request = serde_json::to_vec(request)?;
+    let deadline = [deadline, config.timeout.map(|v| Instant::now() + v)]
+        .into_iter()
+        .flatten()
+        .min();
+    let mut child = Reaped(
+        Command::new(&config.program)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?,
+    );
+    let stdout = read_to_end(child.0.stdout.take());
+    let stderr = read_to_end(child.0.stderr.take());
+    if let Some(mut stdin) = child.0.stdin.take() {
+        // A child exiting before reading closes the pipe, which its exit status reports.
+        thread::spawn(move || stdin.write_all(&request));
+    }
+    let status = loop {
+        if let Some(status) = child.0.try_wait()? {
+            break status;
+        }
+        if deadline.is_some_and(|v| Instant::now() >= v) {
+            return Err(IsolationError::TimedOut);
+        }
+        if cancel.is_some_and(|v| v.is_cancelled()) {
+            return Err(IsolationError::Cancelled);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    let (stdout, stderr) = (joined(stdout)?, joined(stderr)?);
+    let tail = &stderr[stderr.len().saturating_sub(STDERR_TAIL)..];
+    let stderr = String::from_utf8_lossy(tail).trim().to_string();
+    match status.code() {
+        _ if status.success() => {}
+        Some(UNAVAILABLE_EXIT_CODE) => return Err(IsolationError::Unavailable { stderr }),
+        _ => return Err(IsolationError::Crashed { status, stderr }),
+    }
+    Ok(serde_json::from_slice(&stdout)?)
+}
+
+/// Answers a single request read from `input` with a comparison using `pdfium`, writing the
+/// response to `output`. This is the child side of [`IsolationConfig`], where the request
+/// is read from stdin and the response written to stdout. Errors of the comparison are sent
+/// back, the returned errors mean the request couldn't be read or answered, for which the
+/// child exits with [`UNAVAILABLE_EXIT_CODE`].
+pub fn serve(
+    pdfium: Arc<Pdfium>,
+    mut input: impl Read,
+    mut output: impl Write,
+) -> Result<(), IsolationError> {
+    let mut request = Vec::new();
+    input.read_to_end(&mut request)?;
+    let request: Request = serde_json::from_slice(&request)?;
+    let comparison = PDFComparison::builder(pdfium)
+        .config(request.config)
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let response = match request.pages {
+        Some(pages) => {
+            match comparison.match_page_range(&request.current, &request.previous, pages) {
+                Ok(matched) => Response::Matched(Box::new(matched)),
+                Err(e) => Response::Failed(Failure::from(e)),
+            }
+        }
+        None => {
+            let (current, previous) = (&request.current, &request.previous);
+            let (result, timings) =
+                comparison.compare_pdfs_loaded(current, previous, None, None, None);
+            match result {
+                Ok(loaded) => Response::Compared {
+                    comparison: Box::new(CachedComparison::from(&loaded.comparison)),
+                    current_sha256: loaded.current.sha256,
+                    previous_sha256: loaded.previous.as_ref().map(|v| v.sha256),
+                    timings,
+                },
+                Err(e) => Response::Failed(Failure::from(e)),
+            }
+        }
+    };
+    serde_json::to_writer(&mut output, &response)?;
+    output.flush()?;
+    Ok(())
+}
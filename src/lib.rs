@@ -0,0 +1,105 @@
+//! Generates diff pdfs highlighting the changes between two versions of a pdf document.
+//!
+//! [`PDFComparison`] matches the pages of two documents and locates the changed regions,
+//! [`PDFEditor`] turns the resulting [`Comparison`]s into a diff pdf and [`FileManager`]
+//! does both for every modified pdf of a directory tree. Other outputs can be produced by
+//! handing [`FileManager`] a different [`DiffSink`].
+//!
+//! Progress is reported through the [`log`] crate, so the embedding application decides
+//! what ends up where.
+//!
+//! The `serde` feature (enabled by default) derives `Serialize` and `Deserialize` for the
+//! comparison results. [`FileManager`] writes json reports and is only available with it.
+
+mod align;
+#[cfg(feature = "serde")]
+pub mod baselines;
+mod blur;
+mod cache;
+pub mod cancel;
+pub mod clock;
+#[cfg(feature = "serde")]
+pub mod csv;
+mod digests;
+mod duplex;
+pub mod fields;
+#[cfg(feature = "serde")]
+pub mod files;
+pub mod flip;
+pub mod hook;
+#[cfg(feature = "serde")]
+pub mod isolation;
+pub mod lock;
+mod matching;
+pub mod memory;
+pub mod metadata;
+pub mod metrics;
+#[cfg(feature = "serde")]
+pub mod notify;
+pub mod pages;
+pub mod paper;
+pub mod pdf;
+pub mod redaction;
+pub mod report;
+pub mod schedule;
+pub mod scheduler;
+pub mod selftest;
+#[cfg(feature = "serde")]
+pub mod severity;
+pub mod sheet;
+pub mod sink;
+#[cfg(feature = "serde")]
+pub mod startup;
+#[cfg(feature = "serde")]
+pub mod status;
+mod structure;
+pub mod timestamp;
+pub mod timings;
+pub mod version;
+
+#[cfg(feature = "serde")]
+pub use baselines::{BaselineOverride, BaselineOverrides};
+pub use cancel::CancellationToken;
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use fields::{ChangeKind, FieldChange};
+#[cfg(feature = "serde")]
+pub use files::{
+    FailedPage, FileManager, FileManagerConfig, FileManagerError, FileOperation, FileOutcome,
+    ForceSelection, IoRetryPolicy, NewFilePolicy, ProcessingOrder, RunReport, SkipReason,
+    SymlinkPolicy, UpdateReport,
+};
+pub use flip::FlipGifConfig;
+#[cfg(feature = "serde")]
+pub use isolation::{IsolationConfig, IsolationError};
+pub use memory::RenderMemory;
+pub use metadata::{AttachmentChange, AttachmentInfo, MetadataChange};
+pub use pages::{PageSelection, PageSelectionError};
+pub use paper::{PageSize, PageSizeChange};
+pub use pdf::{
+    compare_rendered_pages, get_pdfium, ChangeScore, Comparison, ComparisonBuildError,
+    ComparisonConfig, ComparisonConfigError, ComparisonOperation, DiffIdentity, DifferenceSegments,
+    DocumentChanges, DocumentComparison, DocumentRef, DocumentSide, EditOperation,
+    IdentitySettings, LoadedComparison, LoadedDocument, LoadedDocuments, MarkOutcome, MarkedDiff,
+    MergeSource, PDFComparison, PDFComparisonBuilder, PDFComparisonError, PDFEditor,
+    PDFEditorConfig, PDFEditorError, PageRenders, PageRotation, RenderFlags, RenderSettings,
+    RenderedComparison,
+};
+pub use redaction::Redaction;
+pub use schedule::{CronError, CronSchedule};
+pub use scheduler::{Cadence, CycleDurations, MissedTicks, Overrun, Scheduler, Tick};
+#[cfg(feature = "serde")]
+pub use severity::{Severity, SeverityRule, SeverityRuleError, SeverityRules};
+pub use sheet::ContactSheetConfig;
+#[cfg(feature = "serde")]
+pub use sink::JsonSink;
+pub use sink::{
+    CombinedDiff, CombinedOutput, CycleDocument, DiffSink, DiffSinkError, MarkedPdfSink, NoSink,
+    OutputLimit, OutputLimitAction,
+};
+#[cfg(feature = "serde")]
+pub use startup::{check_pdfium, StartupCheck, StartupError};
+#[cfg(feature = "serde")]
+pub use status::{CycleCounts, CycleStatus};
+pub use timestamp::{Timestamp, Timezone, TimezoneError};
+pub use timings::{TimingStage, Timings};
+pub use version::{PdfiumVersion, MIN_PDFIUM_BUILD};
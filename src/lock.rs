@@ -0,0 +1,144 @@
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Name of the lock file inside the locked directory.
+pub const LOCK_FILE_NAME: &str = ".pdf_diff_gen.lock";
+
+/// Errors of [`InstanceLock`].
+#[derive(Debug)]
+pub enum LockError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Another running process holds the lock. `pid` is the process id it recorded.
+    Held {
+        path: PathBuf,
+        pid: Option<u32>,
+    },
+}
+
+impl std::error::Error for LockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Held { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => {
+                write!(f, "failed to lock {}: {}", path.to_string_lossy(), source)
+            }
+            Self::Held { path, pid } => {
+                write!(
+                    f,
+                    "{} is locked by another instance",
+                    path.to_string_lossy()
+                )?;
+                match pid {
+                    Some(pid) => write!(f, " (pid {})", pid),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Advisory lock keeping a second instance from processing the same directories. The lock
+/// file records the process id of the holder and is unlocked when the lock is dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl InstanceLock {
+    /// Locks `dir`, failing with [`LockError::Held`] if another instance holds the lock.
+    ///
+    /// The operating system releases the lock of a crashed process, so a held lock always
+    /// belongs to a running one, possibly on another host sharing `dir`. It is never broken.
+    pub fn acquire(dir: &Path) -> Result<Self, LockError> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let io_error = |source| LockError::Io {
+            path: path.clone(),
+            source,
+        };
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+        InstanceLock::try_lock(&path)
+            .map_err(io_error)?
+            .map_err(|pid| LockError::Held { path, pid })
+    }
+
+    /// Like `acquire`, but waits for the holder to release the lock, checking every
+    /// `poll_interval`.
+    pub async fn wait(dir: &Path, poll_interval: Duration) -> Result<Self, LockError> {
+        let mut logged = false;
+        loop {
+            match InstanceLock::acquire(dir) {
+                Err(e @ LockError::Held { .. }) => {
+                    if !logged {
+                        log::info!("Waiting for the lock: {}", e);
+                        logged = true;
+                    }
+                    tokio::time::sleep(poll_interval).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Locks the file at `path` and records the process id, or returns the process id
+    /// recorded by the holder.
+    fn try_lock(path: &Path) -> io::Result<Result<Self, Option<u32>>> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {
+                file.set_len(0)?;
+                file.rewind()?;
+                write!(file, "{}", std::process::id())?;
+                file.sync_all()?;
+                Ok(Ok(InstanceLock {
+                    path: path.to_path_buf(),
+                    file,
+                }))
+            }
+            Err(TryLockError::WouldBlock) => {
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                Ok(Err(content.trim().parse().ok()))
+            }
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // The file is kept, removing it could let a waiting instance lock a deleted file.
+        let _ = self.file.set_len(0);
+        if let Err(e) = self.file.unlock() {
+            log::warn!(
+                "Unable to release the lock {}. Error: {}",
+                self.path.to_string_lossy(),
+                e
+            );
+        }
+    }
+}
@@ -1,51 +1,1675 @@
+use chrono::{Local, Utc};
 use clap::Parser;
-use std::{path::PathBuf, sync::Arc};
+use pdf_diff_gen::{
+    baselines,
+    cancel::CancellationToken,
+    files, flip, hook, isolation, lock, metrics, notify, pages, pdf, schedule,
+    scheduler::{self, Cadence, CycleDurations, MissedTicks, Scheduler, Tick},
+    selftest, severity, sheet, sink, startup, status,
+    timestamp::Timezone,
+    PdfiumVersion,
+};
+use pdfium_render::prelude::Pdfium;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-mod files;
-mod pdf;
+/// Built-in destinations for the comparison results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SinkKind {
+    /// Diff pdfs in the configured output style
+    MarkedPdf,
+    /// Per-page statistics as json files
+    Json,
+    /// Only the reports
+    None,
+}
 
 #[derive(Debug, Parser)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    paths: Option<WatchPaths>,
+    #[command(flatten)]
+    options: Options,
+    #[command(flatten)]
+    watch: WatchOptions,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Compare two directory trees once, exiting with 1 if any file differs, or with
+    /// --severity-rules if a file has a severity of warn or above
+    Batch(Box<BatchArgs>),
+    /// Watch every tree listed in a json config file from one process
+    Watch(Box<WatchConfigArgs>),
+    /// Print the status file written after every cycle, exiting with 1 if the last cycle
+    /// failed
+    Status(StatusArgs),
+    /// Check that the pdfium library can render, compare and mark documents, exiting with 1
+    /// if any step fails
+    SelfTest(SelfTestArgs),
+    /// Answer a single comparison request on stdin, run for every file with --isolate
+    #[command(hide = true)]
+    CompareInternal(CompareInternalArgs),
+}
+
+#[derive(Debug, clap::Args)]
+struct CompareInternalArgs {
+    /// Directory containing the pdfium library
+    #[arg(long)]
+    pdfium: PathBuf,
+}
+
+#[derive(Debug, clap::Args)]
+struct SelfTestArgs {
+    /// Directory containing the pdfium library
+    #[arg(long, default_value = ".")]
+    pdfium: PathBuf,
+    /// Pass the version check with a pdfium build older than the supported one
+    #[arg(long)]
+    allow_old_pdfium: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct StatusArgs {
+    /// The status file, or the diff_path it was written to
+    path: PathBuf,
+}
+
+// Paths of the watch loop, which runs without a subcommand.
+#[derive(Debug, clap::Args)]
+struct WatchPaths {
     current_path: PathBuf,
     last_path: PathBuf,
     diff_path: PathBuf,
     pdfium_path: PathBuf,
+    /// Time between the cycles, required unless --schedule is given
+    interval: Option<humantime::Duration>,
+    /// Cron expression with seconds the cycles run at instead of an interval, e.g.
+    /// "0 */10 8-18 * * MON-FRI"
+    #[arg(long, conflicts_with = "interval")]
+    schedule: Option<schedule::CronSchedule>,
+}
+
+#[derive(Debug, clap::Args)]
+struct BatchArgs {
+    /// Directory with the previous versions
+    previous_dir: PathBuf,
+    /// Directory with the current versions, diffs mark the changes on these
+    current_dir: PathBuf,
+    /// Directory the diffs and the report are written to
+    #[arg(long)]
+    out: PathBuf,
+    /// Directory containing the pdfium library
+    #[arg(long, default_value = ".")]
+    pdfium_path: PathBuf,
+    #[command(flatten)]
+    options: Options,
+}
+
+#[derive(Debug, clap::Args)]
+struct WatchConfigArgs {
+    /// Json file with an "entries" list, each with a name, current_path, last_path and
+    /// diff_path and optionally interval or schedule, pixel_tolerance, page_threshold,
+    /// marker_color, ignore and severity. It is read again on SIGHUP
+    config: PathBuf,
+    /// Also read the config file again whenever it is modified
+    #[arg(long)]
+    reload_on_change: bool,
+    /// Directory containing the pdfium library
+    #[arg(long, default_value = ".")]
+    pdfium_path: PathBuf,
+    /// Time between the cycles of entries without an interval or a schedule
+    #[arg(long, default_value = "1m")]
     interval: humantime::Duration,
+    /// Cron expression with seconds the cycles of entries without an interval or a schedule
+    /// run at, e.g. "0 */10 8-18 * * MON-FRI"
+    #[arg(long, conflicts_with = "interval")]
+    schedule: Option<schedule::CronSchedule>,
+    #[command(flatten)]
+    options: Options,
+    #[command(flatten)]
+    watch: WatchOptions,
+}
+
+/// Contents of the config file of the watch subcommand.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WatchConfig {
+    entries: Vec<WatchEntry>,
+}
+
+/// A tree of the config file. Unset overrides fall back to the command line options.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WatchEntry {
+    name: String,
+    current_path: PathBuf,
+    last_path: PathBuf,
+    diff_path: PathBuf,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    interval: Option<Duration>,
+    schedule: Option<String>,
+    pixel_tolerance: Option<u8>,
+    page_threshold: Option<f64>,
+    marker_color: Option<String>,
+    /// Replaces the --ignore patterns.
+    ignore: Option<Vec<String>>,
+    /// Replaces the rules of --severity-rules.
+    severity: Option<severity::SeverityRules>,
+}
+
+fn deserialize_duration<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let value = <Option<String> as serde::Deserialize>::deserialize(deserializer)?;
+    value
+        .map(|v| humantime::parse_duration(&v).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// The cadence given by either an interval or a schedule.
+fn cadence(
+    interval: Option<Duration>,
+    schedule: Option<schedule::CronSchedule>,
+) -> Result<Cadence, String> {
+    match (interval, schedule) {
+        (Some(_), Some(_)) => Err(String::from("an interval and a schedule can't be combined")),
+        (Some(interval), None) => Ok(Cadence::Interval(interval)),
+        (None, Some(schedule)) => Ok(Cadence::Schedule(Box::new(schedule))),
+        (None, None) => Err(String::from("either an interval or a schedule is required")),
+    }
+}
+
+// Settings shared by the watch loop and batch runs.
+#[derive(Debug, Clone, clap::Args)]
+struct Options {
+    /// Width pages are rendered at for comparing, in pixels
+    #[arg(long, default_value_t = 500)]
+    target_width: i32,
+    /// Maximum difference of a color channel for two pixels to still count as equal
+    #[arg(long, default_value_t = 0)]
+    pixel_tolerance: u8,
+    /// Fraction of differing pixels up to which a page still counts as identical
+    #[arg(long, default_value_t = 0.)]
+    page_threshold: f64,
+    /// Memory kept for page renders while comparing a file, e.g. 512MiB, 0 disables caching
+    #[arg(long, value_parser = parse_byte_size, default_value = "256MiB")]
+    render_cache: usize,
+    /// Approximate ceiling on the memory of all page renders of a file, e.g. 1GiB. Documents
+    /// exceeding it are rendered at a lower width instead of failing
+    #[arg(long, value_parser = parse_byte_size)]
+    render_memory_limit: Option<usize>,
+    /// Blur every render with a box of this radius in pixels before comparing, so text
+    /// shifted by a fraction of a pixel only differs within --pixel-tolerance
+    #[arg(long, default_value_t = 0)]
+    blur_radius: u32,
+    /// Threads comparing the rendered pages, per tree of a config file. All cores by default
+    #[arg(long)]
+    threads: Option<usize>,
+    /// Niceness of the comparing threads from -20 to 19, Linux only
+    #[arg(long, allow_hyphen_values = true)]
+    nice: Option<i32>,
+    /// Width of the thumbnails pages are first matched at, 0 matches at full resolution only
+    #[arg(long, default_value_t = 64)]
+    prepass_width: u32,
+    /// Thumbnail scores this close to each other fall back to matching at full resolution
+    #[arg(long, default_value_t = 0.01)]
+    prepass_epsilon: f64,
+    /// Don't render pages whose objects are identical to a page of the last version, which pays
+    /// off when most pages are unchanged
+    #[arg(long)]
+    structural_precheck: bool,
+    /// Don't render the contents of form fields, changed values are still listed
+    #[arg(long)]
+    no_form_data: bool,
+    /// Don't render annotations like comments and stamps
+    #[arg(long)]
+    no_annotations: bool,
+    /// Render text with subpixel anti-aliasing for LCD screens
+    #[arg(long)]
+    lcd_text: bool,
+    /// Render text without anti-aliasing
+    #[arg(long)]
+    no_text_antialiasing: bool,
+    /// Render images without anti-aliasing
+    #[arg(long)]
+    no_image_antialiasing: bool,
+    /// Render paths without anti-aliasing
+    #[arg(long)]
+    no_path_antialiasing: bool,
+    /// Render pages in shades of gray
+    #[arg(long)]
+    grayscale: bool,
+    /// Don't compare the document info and the embedded files
+    #[arg(long)]
+    no_metadata_comparison: bool,
+    /// Only compare these pages of the current version, e.g. 1-25,40,50-
+    #[arg(long)]
+    pages: Option<pages::PageSelection>,
+    /// Skip files larger than this, e.g. 200MiB
+    #[arg(long, value_parser = parse_byte_size)]
+    max_file_size: Option<usize>,
+    /// Skip documents with more pages than this
+    #[arg(long)]
+    max_pages: Option<usize>,
+    /// Only match pages against the pages of the last version this many pages around them,
+    /// pages without a match there are matched against all pages
+    #[arg(long)]
+    match_window: Option<usize>,
+    /// Match pages in sheets of this many pages, e.g. 2 for the fronts and backs of duplex
+    /// scans, so a dropped or inserted blank back page doesn't shift all later pages.
+    /// --match-window then counts sheets
+    #[arg(long)]
+    sheet_pages: Option<usize>,
+    /// Detect blank pages: blank pages of both versions compare as identical regardless of
+    /// scanner noise, others are reported as inserted or removed blank pages. Renders every
+    /// page of the last version
+    #[arg(long)]
+    detect_blank_pages: bool,
+    /// Fraction of a page, from 0 to 1, that may be covered by ink for it to count as blank.
+    /// The reports list the coverage of every page checked
+    #[arg(long, value_parser = parse_fraction, default_value_t = 0.0005)]
+    blank_ink_threshold: f64,
+    /// Compare pages whose size changed, e.g. from Letter to A4, over the area both
+    /// versions cover instead of reporting them as different as a whole
+    #[arg(long)]
+    compare_resized_pages: bool,
+    /// Detect redactions, solid black boxes over content of the last version. They are
+    /// marked in --redaction-color and listed apart from the other changes in the reports
+    #[arg(long)]
+    detect_redactions: bool,
+    /// Fraction of a page, from 0 to 1, a redaction has to cover at least
+    #[arg(
+        long,
+        requires = "detect_redactions",
+        value_parser = parse_fraction,
+        default_value_t = 0.001
+    )]
+    redaction_min_area: f64,
+    /// Fraction of its bounding box, from 0 to 1, a redaction has to fill with black at
+    /// least
+    #[arg(
+        long,
+        requires = "detect_redactions",
+        value_parser = parse_fraction,
+        default_value_t = 0.97
+    )]
+    redaction_solid_ratio: f64,
+    /// Abandon a file if comparing it or writing its diff takes longer than this, e.g. 10m. It
+    /// is skipped until it is modified
+    #[arg(long)]
+    file_timeout: Option<humantime::Duration>,
+    /// Compare every file in a child process, so a document crashing pdfium only fails its
+    /// own file, which is skipped until it is modified. Slower, a process is started per file
+    #[arg(long)]
+    isolate: bool,
+    /// Kill the child process comparing a file with --isolate after this long
+    #[arg(long, requires = "isolate")]
+    isolation_timeout: Option<humantime::Duration>,
+    /// Match the pages of every document in this many child processes at the same time, each
+    /// taking a share of the pages. Every child loads both versions of the document, so the
+    /// memory needed grows with the count
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    compare_threads: u32,
+    /// Extensions of the files treated as pdfs, case-insensitive
+    #[arg(long, value_delimiter = ',', default_value = "pdf")]
+    extensions: Vec<String>,
+    /// Treat files without an extension as pdfs if they start with %PDF-
+    #[arg(long)]
+    sniff_content: bool,
+    /// Also scan files and directories whose names start with a dot
+    #[arg(long)]
+    include_hidden: bool,
+    /// Glob of files and directories not to scan, relative to current_path, e.g. '*.tmp'
+    #[arg(long)]
+    ignore: Vec<globset::Glob>,
+    /// Json file with a list of rules assigning changed files a severity of info, warn or
+    /// critical, each with a severity and optionally a path glob, pages like 1-3 and a
+    /// min_change_fraction. The highest matching severity counts, info if none matches
+    #[arg(long, value_parser = parse_severity_rules)]
+    severity_rules: Option<severity::SeverityRules>,
+    /// Fraction of a document, from 0 to 1, that has to change for it to run the diff hook,
+    /// be posted to the webhook and count for the exit code of batch. Inserted and removed
+    /// pages count as fully changed. Diffs are written for every changed document
+    #[arg(long, value_parser = parse_fraction)]
+    alert_threshold: Option<f64>,
+    /// Leave the area only redactions changed out of --alert-threshold, documents only
+    /// changed by redactions don't alert
+    #[arg(long, requires = "detect_redactions")]
+    alert_ignores_redactions: bool,
+    /// Json file with a list of overrides comparing the files matching a path glob, relative
+    /// to current_path, against a shared baseline instead of their copies, e.g.
+    /// [{"path": "invoices/*.pdf", "baseline": "templates/invoice_master.pdf"}]. Relative
+    /// baselines are resolved against the directory of the file, the first match counts
+    #[arg(long, value_parser = parse_baselines)]
+    baselines: Option<baselines::BaselineOverrides>,
+    /// Process every pdf with a copy in the first cycle, even if it wasn't modified
+    #[arg(long)]
+    force: bool,
+    /// Glob of files processed in the first cycle even if they weren't modified, relative
+    /// to current_path
+    #[arg(long, conflicts_with = "force")]
+    force_path: Vec<globset::Glob>,
+    /// What is written into diff_path for every changed pdf
+    #[arg(long, value_enum, default_value_t = SinkKind::MarkedPdf)]
+    sink: SinkKind,
+    /// Also merge the diffs of every cycle into a combined-<timestamp>.pdf with a cover page
+    /// per document
+    #[arg(long)]
+    combined: bool,
+    /// Like --combined, but remove the diffs of the single documents once they are merged
+    #[arg(long)]
+    combined_only: bool,
+    /// Most diffs written per file within --diff-output-window, after which
+    /// --on-diff-output-limit applies. Unlimited by default
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_diff_outputs_per_file: Option<u64>,
+    /// Rolling window --max-diff-outputs-per-file counts the diffs of a file in, by the
+    /// timestamps of their names
+    #[arg(long, default_value = "1day")]
+    diff_output_window: humantime::Duration,
+    /// What happens to a file that reached --max-diff-outputs-per-file: overwrite its newest
+    /// diff, or skip it without updating its copy until the window has room again
+    #[arg(long, value_enum, default_value_t = sink::OutputLimitAction::Overwrite)]
+    on_diff_output_limit: sink::OutputLimitAction,
+    /// Layout of the generated diff pdfs
+    #[arg(long, value_enum, default_value_t = pdf::OutputStyle::Marked)]
+    output_style: pdf::OutputStyle,
+    /// How differences are marked in the generated diff pdfs
+    #[arg(long, value_enum, default_value_t = pdf::MarkerMode::Raster)]
+    marker_mode: pdf::MarkerMode,
+    /// Color of the difference markers as a hex triplet, e.g. ff0000
+    #[arg(long, value_parser = parse_hex_color, default_value = "ff0000")]
+    marker_color: [u8; 3],
+    /// Pattern of the raster marker bars. Hatched and dashed bars stay visible when printed
+    /// in black and white
+    #[arg(long, value_enum, default_value_t = pdf::MarkerStyle::Color)]
+    marker_style: pdf::MarkerStyle,
+    /// Color of the markers of redactions as a hex triplet, see --detect-redactions
+    #[arg(long, value_parser = parse_hex_color, default_value = "0066cc")]
+    redaction_color: [u8; 3],
+    /// Also mark the changed and removed pages on the previous version, written as
+    /// <filename>.diff-old.<timestamp>.pdf
+    #[arg(long)]
+    mark_previous_version: bool,
+    /// Color of the banner on the pages of documents without a previous version, as a hex
+    /// triplet
+    #[arg(long, value_parser = parse_hex_color, default_value = "0066cc")]
+    new_document_color: [u8; 3],
+    /// Width of the raster marker bars in page points
+    #[arg(long, default_value_t = 2.)]
+    marker_width: f32,
+    /// Page margin(s) the raster marker bars are drawn on
+    #[arg(long, value_enum, default_value_t = pdf::MarkerPosition::Left)]
+    marker_position: pdf::MarkerPosition,
+    /// Resolution of the raster markers in pixels per page point. Defaults to the resolution
+    /// pages are compared at, see --target-width
+    #[arg(long)]
+    overlay_pixels_per_point: Option<f32>,
+    /// Longest side in pixels of the raster markers and the overlay renders of a page,
+    /// larger pages get a proportionally lower resolution
+    #[arg(long, default_value_t = 16384, value_parser = clap::value_parser!(u32).range(1..))]
+    max_overlay_pixels: u32,
+    /// Draw the previous version of changed pages over the new one with this opacity (0-1)
+    #[arg(long)]
+    overlay_opacity: Option<f32>,
+    /// Color the previous version is tinted in when drawn as an overlay
+    #[arg(long, value_parser = parse_hex_color, default_value = "ff0000")]
+    overlay_color: [u8; 3],
+    /// Prepend a page listing all changed pages to marked diffs
+    #[arg(long)]
+    summary_page: bool,
+    /// Stamp a text label describing the change onto every changed page
+    #[arg(long)]
+    label: bool,
+    /// Template of the label, supports {date}, {regions}, {percent} and {matched_page}
+    #[arg(long)]
+    label_template: Option<String>,
+    /// Corner of the page the label is placed in
+    #[arg(long, value_enum, default_value_t = pdf::LabelCorner::TopRight)]
+    label_corner: pdf::LabelCorner,
+    /// Don't embed the paths and digests of the compared versions into marked diffs
+    #[arg(long)]
+    no_identity: bool,
+    /// Don't write a json report into diff_path after each update
+    #[arg(long)]
+    no_report: bool,
+    /// Append a row per processed file to this csv file after each update
+    #[arg(long)]
+    csv_report: Option<PathBuf>,
+    /// Write a png contact sheet of the pages of every changed document next to its diff
+    #[arg(long)]
+    contact_sheet: bool,
+    /// Columns of thumbnails on a contact sheet
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    contact_sheet_columns: u32,
+    /// Rows of thumbnails per contact sheet, longer documents get several sheets
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    contact_sheet_rows: u32,
+    /// Write a gif flipping between both versions of every changed page into this directory
+    #[arg(long)]
+    flip_gifs: Option<PathBuf>,
+    /// How long each version is shown in the flip gifs
+    #[arg(long, default_value = "800ms")]
+    flip_delay: humantime::Duration,
+    /// Write an html report with thumbnails of all changed pages into this directory
+    #[arg(long)]
+    html_report: Option<PathBuf>,
+    /// Number of retries of file system operations failing with a transient error
+    #[arg(long, default_value_t = 3)]
+    io_retries: u32,
+    /// Delay before retrying a file system operation
+    #[arg(long, default_value = "500ms")]
+    io_retry_delay: humantime::Duration,
+    /// Whether symbolic links in current_path are followed
+    #[arg(long, value_enum, default_value_t = files::SymlinkPolicy::Skip)]
+    symlinks: files::SymlinkPolicy,
+    /// Deepest directory below current_path that is scanned
+    #[arg(long, default_value_t = 64)]
+    max_depth: usize,
+    /// Fail at startup if last_path or diff_path doesn't exist instead of creating it
+    #[arg(long)]
+    no_create_dirs: bool,
+    /// Run with a pdfium build older than the supported one instead of refusing to, renders
+    /// may differ from the ones of supported builds
+    #[arg(long)]
+    allow_old_pdfium: bool,
+    /// Order the files of a cycle are processed and reported in
+    #[arg(long, value_enum, default_value_t = files::ProcessingOrder::Path)]
+    order: files::ProcessingOrder,
+    /// How pages are rotated when rendered for comparing
+    #[arg(long, value_enum, default_value_t = pdf::PageRotation::Landscape)]
+    rotation: pdf::PageRotation,
+    /// Hex color transparent areas of pages are rendered on
+    #[arg(long, value_parser = parse_hex_color, default_value = "ffffff")]
+    background: [u8; 3],
+    /// Print the time spent loading, rendering, matching and marking every file
+    #[arg(long)]
+    timings: bool,
+    /// Zone the timestamps of the reports, the status files, the names of the diffs and the
+    /// dates of the labels are written in: utc, local or an offset like +02:00
+    #[arg(long, default_value_t = Timezone::Utc)]
+    timezone: Timezone,
+}
+
+// Settings only used by the watch loop.
+#[derive(Debug, clap::Args)]
+struct WatchOptions {
+    /// Keep the last version of files whose pages are identical even if their metadata,
+    /// attachments or form fields changed
+    #[arg(long)]
+    no_copy_document_changes: bool,
+    /// Copy encrypted files to last_path, so they are only looked at again once modified
+    #[arg(long)]
+    copy_encrypted: bool,
+    /// Shell command run for every processed file, see PDF_DIFF_* environment variables
+    #[arg(long)]
+    on_diff: Option<String>,
+    /// Time after which a running on-diff command is killed
+    #[arg(long, default_value = "60s")]
+    on_diff_timeout: humantime::Duration,
+    /// Maximum number of on-diff commands running at the same time
+    #[arg(long, default_value_t = 4)]
+    on_diff_concurrency: usize,
+    /// Post a json payload for every changed file to this url after each update
+    #[arg(long)]
+    webhook_url: Option<String>,
+    /// Timeout of a single webhook request
+    #[arg(long, default_value = "10s")]
+    webhook_timeout: humantime::Duration,
+    /// Number of retries after a webhook request failed with a server error
+    #[arg(long, default_value_t = 3)]
+    webhook_retries: u32,
+    /// Shared secret the webhook payloads are signed with (HMAC-SHA256)
+    #[arg(long)]
+    webhook_secret: Option<String>,
+    /// Send one webhook payload per update instead of one per changed file
+    #[arg(long)]
+    webhook_batch: bool,
+    /// Only post files with at least this severity, see --severity-rules
+    #[arg(long, value_enum)]
+    webhook_min_severity: Option<severity::Severity>,
+    /// Serve Prometheus metrics at /metrics on this address, e.g. 0.0.0.0:9184
+    #[arg(long)]
+    metrics_listen: Option<SocketAddr>,
+    /// Compare the content of every copy written to last_path with its source
+    #[arg(long)]
+    verify_copies: bool,
+    /// Wait for another instance using diff_path to exit instead of exiting
+    #[arg(long)]
+    wait_for_lock: bool,
+    /// Copy files without a previous version to last_path instead of diffing them against
+    /// an empty document
+    #[arg(long)]
+    init_baseline: bool,
+    /// Treat files without a previous version as new even if their content matches the
+    /// previous version of a file that disappeared
+    #[arg(long)]
+    no_rename_detection: bool,
+    /// Write the status file here instead of into diff_path. With a config file the name of
+    /// every entry is prepended to the file name
+    #[arg(long)]
+    healthcheck_file: Option<PathBuf>,
+    /// Move every cycle by a random offset of up to this much in either direction
+    #[arg(long, default_value = "0s")]
+    jitter: humantime::Duration,
+    /// Least time between the end of a cycle and the start of the next one
+    #[arg(long, default_value = "0s")]
+    min_gap: humantime::Duration,
+    /// What happens to the ticks of the interval or schedule that pass while a cycle runs.
+    /// Skipping measures an interval from the end of the previous cycle
+    #[arg(long, value_enum, default_value_t = MissedTicks::Skip)]
+    missed_ticks: MissedTicks,
+}
+
+impl WatchOptions {
+    fn scheduler(&self, cadence: Cadence) -> Scheduler {
+        Scheduler::new(cadence)
+            .with_jitter(self.jitter.into())
+            .with_min_gap(self.min_gap.into())
+            .with_missed_ticks(self.missed_ticks)
+    }
+}
+
+/// Records a cycle of the tree labeled `entry` in the metrics that took `duration`, warning
+/// while the recent cycles take longer than the cadence of `scheduler`.
+fn record_cycle(
+    entry: &str,
+    scheduler: &Scheduler,
+    durations: &mut CycleDurations,
+    duration: Duration,
+) {
+    durations.record(duration);
+    let metrics = metrics::metrics();
+    if let Some(average) = durations.average() {
+        metrics
+            .average_cycle_duration
+            .with_label_values(&[entry])
+            .set(average.as_secs_f64());
+    }
+    let overrun = durations.overrun(scheduler, &Local::now());
+    metrics
+        .cadence_overrun
+        .with_label_values(&[entry])
+        .set(overrun.is_some() as i64);
+    if let Some(overrun) = overrun {
+        match entry.is_empty() {
+            true => eprintln!("Warning: The {}", overrun),
+            false => eprintln!("Warning: The {} of {}", overrun, entry),
+        }
+    }
+}
+
+fn parse_hex_color(value: &str) -> Result<[u8; 3], String> {
+    let value = value.trim_start_matches('#');
+    if value.len() != 6 || !value.is_ascii() {
+        return Err(format!("Expected a color like ff0000, got {}", value));
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&value[i..i + 2], 16).map_err(|e| format!("Invalid color: {}", e))
+    };
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if (0. ..=1.).contains(&fraction) => Ok(fraction),
+        Ok(fraction) => Err(format!("{} isn't between 0 and 1", fraction)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn parse_severity_rules(path: &str) -> Result<severity::SeverityRules, String> {
+    std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|v| serde_json::from_slice(&v).map_err(|e| e.to_string()))
+}
+
+fn parse_baselines(path: &str) -> Result<baselines::BaselineOverrides, String> {
+    let overrides: baselines::BaselineOverrides = std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|v| serde_json::from_slice(&v).map_err(|e| e.to_string()))?;
+    Ok(overrides.relative_to(Path::new(path).parent().unwrap_or(Path::new(""))))
+}
+
+fn parse_byte_size(value: &str) -> Result<usize, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<usize>()
+        .map_err(|_| format!("Expected a size like 512MiB, got {}", value))?;
+    let multiplier: usize = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        _ => return Err(format!("Unknown size unit {}", unit)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size {} is too large", value))
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    match args.command {
+        Some(Command::Batch(batch)) => run_batch(*batch).await,
+        Some(Command::Watch(watch)) => run_watch_config(*watch).await,
+        Some(Command::Status(status)) => print_status(status).await,
+        Some(Command::SelfTest(self_test)) => run_self_test(self_test),
+        Some(Command::CompareInternal(args)) => compare_internal(args),
+        None => {
+            let paths = args
+                .paths
+                .expect("The paths are required without a subcommand");
+            run_watch(paths, args.options, args.watch).await
+        }
+    }
+}
+
+/// Checks the directories of a tree before its first cycle, exiting with 2 if one is
+/// invalid.
+fn check_paths(current_path: &Path, last_path: &Path, diff_path: &Path, options: &Options) {
+    let check = startup::StartupCheck::new(
+        current_path.to_path_buf(),
+        last_path.to_path_buf(),
+        diff_path.to_path_buf(),
+    )
+    .create_missing(!options.no_create_dirs);
+    if let Err(e) = check.run() {
+        exit_invalid("Invalid paths", e);
+    }
+}
+
+/// How the child processes comparing the files are started with --isolate, running this
+/// binary with the pdfium library in `pdfium_dir`.
+fn isolation_config(options: &Options, pdfium_dir: &Path) -> Option<isolation::IsolationConfig> {
+    options.isolate.then(|| child_config(options, pdfium_dir))
+}
+
+/// How a child process answering comparison requests is started, for --isolate and
+/// --compare-threads.
+fn child_config(options: &Options, pdfium_dir: &Path) -> isolation::IsolationConfig {
+    let program = std::env::current_exe()
+        .unwrap_or_else(|e| exit_invalid("Unable to find the binary to isolate with", e));
+    isolation::IsolationConfig {
+        program,
+        args: vec![
+            "compare-internal".into(),
+            "--pdfium".into(),
+            pdfium_dir.as_os_str().to_owned(),
+        ],
+        timeout: options.isolation_timeout.map(Into::into),
+    }
+}
 
-    let pdfium = Arc::new(
-        pdf::get_pdfium(&args.pdfium_path).expect("Unable to load PDFium from provided Path"),
+/// The child side of --isolate, exiting with the code the parent doesn't blame the document
+/// for if the request can't be answered.
+fn compare_internal(args: CompareInternalArgs) {
+    let pdfium = pdf::get_pdfium(&args.pdfium).unwrap_or_else(|e| {
+        eprintln!("Unable to load pdfium: {}", e);
+        std::process::exit(isolation::UNAVAILABLE_EXIT_CODE)
+    });
+    let served = isolation::serve(
+        Arc::new(pdfium),
+        std::io::stdin().lock(),
+        std::io::stdout().lock(),
     );
+    if let Err(e) = served {
+        eprintln!("Unable to answer the comparison request: {}", e);
+        std::process::exit(isolation::UNAVAILABLE_EXIT_CODE)
+    }
+}
 
-    let file_manager =
-        files::FileManager::new(pdfium, args.current_path, args.last_path, args.diff_path);
+/// Binds the pdfium library in `pdfium_dir` and detects its version, exiting with 2 if it
+/// can't be used.
+fn load_pdfium(pdfium_dir: &Path, options: &Options) -> (Arc<Pdfium>, Option<PdfiumVersion>) {
+    match startup::check_pdfium(pdfium_dir, options.allow_old_pdfium) {
+        Ok((pdfium, version)) => (Arc::new(pdfium), version),
+        Err(e) => exit_invalid("Unable to load pdfium", e),
+    }
+}
 
-    loop {
-        match file_manager.update().await {
-            Ok(v) => {
-                v.iter().for_each(|(path, result)| match result {
-                    Ok(v) => println!(
-                        "Updated {} successfully to {}",
-                        path.to_string_lossy(),
-                        v.to_string_lossy()
-                    ),
-                    Err(e) => println!(
-                        "Unable to update {}. FileManagerError: {}",
-                        path.to_string_lossy(),
-                        e
-                    ),
-                });
+/// Prints `error` and exits with 2, for invalid settings at startup.
+fn exit_invalid(context: &str, error: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", context, error);
+    std::process::exit(2);
+}
+
+fn build_comparison(
+    pdfium: Arc<Pdfium>,
+    pdfium_dir: &Path,
+    options: &Options,
+) -> Result<pdf::PDFComparison, pdf::ComparisonConfigError> {
+    let mut comparison_builder = pdf::PDFComparison::builder(pdfium)
+        .target_width(options.target_width)
+        .pixel_tolerance(options.pixel_tolerance)
+        .page_threshold(options.page_threshold)
+        .render_cache_bytes(options.render_cache)
+        .prepass_width(options.prepass_width)
+        .prepass_epsilon(options.prepass_epsilon)
+        .structural_precheck(options.structural_precheck)
+        .background(options.background)
+        .rotation(options.rotation)
+        .render_form_data(!options.no_form_data)
+        .render_annotations(!options.no_annotations)
+        .render_flags(pdf::RenderFlags {
+            lcd_text: options.lcd_text,
+            text_antialiasing: !options.no_text_antialiasing,
+            image_antialiasing: !options.no_image_antialiasing,
+            path_antialiasing: !options.no_path_antialiasing,
+            grayscale: options.grayscale,
+        })
+        .compare_metadata(!options.no_metadata_comparison)
+        .keep_renders(options.flip_gifs.is_some())
+        .locate_previous_differences(options.mark_previous_version)
+        .blur_radius(options.blur_radius)
+        .detect_blank_pages(options.detect_blank_pages)
+        .blank_ink_threshold(options.blank_ink_threshold)
+        .compare_resized_pages(options.compare_resized_pages)
+        .detect_redactions(options.detect_redactions)
+        .redaction_min_area(options.redaction_min_area)
+        .redaction_solid_ratio(options.redaction_solid_ratio);
+    if let Some(pages) = options.pages.clone() {
+        comparison_builder = comparison_builder.pages(pages);
+    }
+    if let Some(max_pages) = options.max_pages {
+        comparison_builder = comparison_builder.max_pages(max_pages);
+    }
+    if let Some(match_window) = options.match_window {
+        comparison_builder = comparison_builder.match_window(match_window);
+    }
+    if let Some(sheet_pages) = options.sheet_pages {
+        comparison_builder = comparison_builder.sheet_pages(sheet_pages);
+    }
+    if let Some(limit) = options.render_memory_limit {
+        comparison_builder = comparison_builder.render_memory_limit(limit);
+    }
+    if let Some(threads) = options.threads {
+        comparison_builder = comparison_builder.threads(threads);
+    }
+    if let Some(nice) = options.nice {
+        comparison_builder = comparison_builder.nice(nice);
+    }
+    if options.compare_threads > 1 {
+        comparison_builder = comparison_builder.compare_threads(
+            options.compare_threads as usize,
+            child_config(options, pdfium_dir),
+        );
+    }
+    comparison_builder.build()
+}
+
+fn build_sink(
+    pdfium: Arc<Pdfium>,
+    pdfium_version: Option<PdfiumVersion>,
+    options: &Options,
+    diff_path: &Path,
+) -> Box<dyn sink::DiffSink> {
+    let output_limit = options
+        .max_diff_outputs_per_file
+        .map(|max_outputs| sink::OutputLimit {
+            max_outputs: max_outputs as usize,
+            window: options.diff_output_window.into(),
+            action: options.on_diff_output_limit,
+        });
+    let editor_config = pdf::PDFEditorConfig {
+        output_style: options.output_style,
+        marker_mode: options.marker_mode,
+        marker_color: options.marker_color,
+        marker_style: options.marker_style,
+        redaction_color: options.redaction_color,
+        marker_width: options.marker_width,
+        marker_position: options.marker_position,
+        overlay_resolution: options.overlay_pixels_per_point.map_or(
+            pdf::OverlayResolution::RenderWidth(options.target_width),
+            pdf::OverlayResolution::PixelsPerPoint,
+        ),
+        max_overlay_pixels: options.max_overlay_pixels,
+        overlay: options.overlay_opacity.map(|opacity| pdf::OverlayConfig {
+            opacity,
+            color: options.overlay_color,
+        }),
+        summary_page: options.summary_page,
+        label: options.label.then(|| pdf::LabelConfig {
+            template: options
+                .label_template
+                .clone()
+                .unwrap_or_else(|| pdf::LabelConfig::default().template),
+            corner: options.label_corner,
+        }),
+        timezone: options.timezone,
+        identity: (!options.no_identity).then_some(pdf::IdentitySettings {
+            target_width: options.target_width,
+            pixel_tolerance: options.pixel_tolerance,
+            pdfium_version,
+        }),
+        new_document_color: options.new_document_color,
+        background: options.background,
+        ..Default::default()
+    };
+    match options.sink {
+        SinkKind::MarkedPdf => {
+            let mut sink = sink::MarkedPdfSink::new(
+                pdf::PDFEditor::new(pdfium, editor_config),
+                diff_path.to_path_buf(),
+            )
+            .with_combined_diff(match (options.combined, options.combined_only) {
+                (_, true) => sink::CombinedDiff::Only,
+                (true, false) => sink::CombinedDiff::Alongside,
+                (false, false) => sink::CombinedDiff::Off,
+            })
+            .with_previous_version_diff(options.mark_previous_version);
+            if let Some(limit) = output_limit {
+                sink = sink.with_output_limit(limit);
+            }
+            Box::new(sink)
+        }
+        SinkKind::Json => {
+            let mut sink = sink::JsonSink::new(diff_path.to_path_buf());
+            if let Some(limit) = output_limit {
+                sink = sink.with_output_limit(limit);
+            }
+            Box::new(sink)
+        }
+        SinkKind::None => Box::new(sink::NoSink),
+    }
+}
+
+/// The files the first cycle processes even if they weren't modified, `None` if neither
+/// --force nor --force-path is given.
+fn force_selection(options: &Options) -> Option<files::ForceSelection> {
+    if options.force {
+        return Some(files::ForceSelection::All);
+    }
+    if options.force_path.is_empty() {
+        return None;
+    }
+    let mut builder = globset::GlobSetBuilder::new();
+    options.force_path.iter().for_each(|glob| {
+        builder.add(glob.clone());
+    });
+    match builder.build() {
+        Ok(v) => Some(files::ForceSelection::Matching(v)),
+        Err(e) => exit_invalid("Invalid force patterns", e),
+    }
+}
+
+/// The file manager settings taken from `options`, everything else at its default.
+fn file_manager_config(options: &Options) -> files::FileManagerConfig {
+    let ignore = match options
+        .ignore
+        .iter()
+        .fold(globset::GlobSetBuilder::new(), |mut builder, glob| {
+            builder.add(glob.clone());
+            builder
+        })
+        .build()
+    {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Invalid ignore patterns: {}", e);
+            std::process::exit(2);
+        }
+    };
+    files::FileManagerConfig {
+        write_report: !options.no_report,
+        html_report: options.html_report.clone(),
+        csv_report: options.csv_report.clone(),
+        contact_sheet: options.contact_sheet.then_some(sheet::ContactSheetConfig {
+            columns: options.contact_sheet_columns,
+            rows: options.contact_sheet_rows,
+        }),
+        flip_gifs: options
+            .flip_gifs
+            .clone()
+            .map(|dir| flip::FlipGifConfig::new(dir).with_delay(options.flip_delay.into())),
+        max_file_size: options.max_file_size.map(|v| v as u64),
+        extensions: options.extensions.clone(),
+        sniff_content: options.sniff_content,
+        include_hidden: options.include_hidden,
+        ignore,
+        severity: options.severity_rules.clone(),
+        baselines: options.baselines.clone().unwrap_or_default(),
+        alert_threshold: options.alert_threshold,
+        alert_ignores_redactions: options.alert_ignores_redactions,
+        timezone: options.timezone,
+        io_retry: files::IoRetryPolicy {
+            retries: options.io_retries,
+            delay: options.io_retry_delay.into(),
+        },
+        symlinks: options.symlinks,
+        max_depth: options.max_depth,
+        order: options.order,
+        file_timeout: options.file_timeout.map(Into::into),
+        ..Default::default()
+    }
+}
+
+fn print_report(report: &files::UpdateReport, timings: bool) {
+    report
+        .processed
+        .iter()
+        .for_each(|(path, outcome)| match outcome {
+            files::FileOutcome::DiffWritten {
+                new_file: true,
+                out,
+                ..
+            } => println!(
+                "{} is new, every page is marked{}",
+                path.to_string_lossy(),
+                out.as_ref()
+                    .map(|out| format!(" in {}", out.to_string_lossy()))
+                    .unwrap_or_default()
+            ),
+            files::FileOutcome::DiffWritten {
+                out: Some(out),
+                previous_out,
+                ..
+            } => println!(
+                "Updated {} successfully to {}{}",
+                path.to_string_lossy(),
+                out.to_string_lossy(),
+                previous_out
+                    .as_ref()
+                    .map(|out| format!(", the previous version to {}", out.to_string_lossy()))
+                    .unwrap_or_default()
+            ),
+            files::FileOutcome::DiffWritten { out: None, .. } => {
+                println!("Updated {} successfully", path.to_string_lossy())
+            }
+            files::FileOutcome::Unchanged { changes, .. } if changes.is_empty() => {
+                println!("{} has no visible changes", path.to_string_lossy())
+            }
+            files::FileOutcome::Unchanged { .. } => println!(
+                "{} has no visible changes, but its metadata, attachments or form fields changed",
+                path.to_string_lossy()
+            ),
+            files::FileOutcome::Renamed { from } => println!(
+                "{} was renamed from {}",
+                path.to_string_lossy(),
+                from.to_string_lossy()
+            ),
+            files::FileOutcome::Removed => {
+                println!(
+                    "{} only exists in the previous version",
+                    path.to_string_lossy()
+                )
+            }
+            files::FileOutcome::Skipped { reason } => {
+                println!("Skipped {}: {}", path.to_string_lossy(), reason)
+            }
+            files::FileOutcome::Failed { error } => println!(
+                "Unable to update {}. FileManagerError: {}",
+                path.to_string_lossy(),
+                error
+            ),
+        });
+    for (path, outcome) in &report.processed {
+        if let files::FileOutcome::Unchanged {
+            changes, memory, ..
+        }
+        | files::FileOutcome::DiffWritten {
+            changes, memory, ..
+        } = outcome
+        {
+            for line in changes.lines() {
+                println!("  {}: {}", path.to_string_lossy(), line);
+            }
+            if let Some(warning) = memory.warning() {
+                println!("  {}: {}", path.to_string_lossy(), warning);
+            }
+        }
+        if let files::FileOutcome::DiffWritten { failed_pages, .. } = outcome {
+            for failed in failed_pages {
+                println!(
+                    "  {}: page {} is in the diff without markers: {}",
+                    path.to_string_lossy(),
+                    failed.page + 1,
+                    failed.error
+                );
+            }
+        }
+    }
+    if let Some(combined) = &report.combined {
+        println!("Combined the diffs into {}", combined.to_string_lossy());
+    }
+    if let Some(severity) = report.severity {
+        println!("Highest severity: {}", severity);
+    }
+    println!("{}", report);
+    if timings && !report.processed.is_empty() {
+        for (path, outcome) in &report.processed {
+            if let files::FileOutcome::Unchanged {
+                timings, memory, ..
+            }
+            | files::FileOutcome::DiffWritten {
+                timings, memory, ..
+            } = outcome
+            {
+                println!(
+                    "  {}: {}, peak renders {:.1}MiB",
+                    path.to_string_lossy(),
+                    timings,
+                    memory.peak_bytes as f64 / (1024. * 1024.)
+                );
             }
+        }
+        println!("Timings: {}", report.timings());
+    }
+}
+
+/// Compares the trees once. Exits with 0 without differences, 1 if a file changed, was
+/// added or removed and 2 if a file or the run failed. With --alert-threshold only files
+/// reaching it count as differences, with severity rules only cycles with a file of at least
+/// [`severity::Severity::Warn`].
+async fn run_batch(args: BatchArgs) {
+    check_paths(
+        &args.current_dir,
+        &args.previous_dir,
+        &args.out,
+        &args.options,
+    );
+    let (pdfium, pdfium_version) = load_pdfium(&args.pdfium_path, &args.options);
+    let mut file_manager = files::FileManager::new(
+        build_comparison(pdfium.clone(), &args.pdfium_path, &args.options)
+            .unwrap_or_else(|e| exit_invalid("Invalid comparison settings", e)),
+        build_sink(pdfium, pdfium_version, &args.options, &args.out),
+        args.current_dir,
+        args.previous_dir,
+        args.out,
+        files::FileManagerConfig {
+            pdfium_version,
+            isolation: isolation_config(&args.options, &args.pdfium_path),
+            ..file_manager_config(&args.options)
+        },
+    );
+    match file_manager.compare_trees().await {
+        Ok(report) => {
+            print_report(&report, args.options.timings);
+            if report.failed() > 0 {
+                std::process::exit(2);
+            }
+            let differs = report.alerts > 0
+                && match args.options.severity_rules {
+                    Some(_) => report.severity >= Some(severity::Severity::Warn),
+                    None => true,
+                };
+            if differs {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!("Unable to compare the directories. FileManagerError: {}", e);
+            std::process::exit(2);
+        }
+    }
+}
+
+/// Takes the instance lock of `diff_path`, exiting if another instance holds it.
+async fn lock_diff_path(diff_path: &Path, wait: bool) -> lock::InstanceLock {
+    let lock = match wait {
+        true => lock::InstanceLock::wait(diff_path, Duration::from_secs(5)).await,
+        false => lock::InstanceLock::acquire(diff_path),
+    };
+    match lock {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("Unable to start: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn watch_file_manager_config(options: &Options, watch: &WatchOptions) -> files::FileManagerConfig {
+    files::FileManagerConfig {
+        on_diff: watch.on_diff.clone().map(|command| hook::HookConfig {
+            command,
+            timeout: watch.on_diff_timeout.into(),
+            concurrency: watch.on_diff_concurrency,
+        }),
+        webhook: watch.webhook_url.clone().map(|url| notify::WebhookConfig {
+            url,
+            timeout: watch.webhook_timeout.into(),
+            retries: watch.webhook_retries,
+            secret: watch.webhook_secret.clone(),
+            batch: watch.webhook_batch,
+            min_severity: watch.webhook_min_severity,
+        }),
+        verify_copies: watch.verify_copies,
+        new_files: if watch.init_baseline {
+            files::NewFilePolicy::SeedBaseline
+        } else {
+            files::NewFilePolicy::Diff
+        },
+        detect_renames: !watch.no_rename_detection,
+        copy_document_changes: !watch.no_copy_document_changes,
+        copy_encrypted: watch.copy_encrypted,
+        status_path: watch.healthcheck_file.clone(),
+        ..file_manager_config(options)
+    }
+}
+
+/// Sends `shutdown` and cancels the returned token on Ctrl-C, so a running cycle stops
+/// after the current page instead of running to its end.
+fn spawn_shutdown_handler(shutdown: &tokio::sync::watch::Sender<bool>) -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let shutdown = shutdown.clone();
+    let token = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
+            let _ = shutdown.send(true);
+        }
+    });
+    cancel
+}
+
+/// Serves the metrics if configured, until `shutdown` becomes true.
+fn spawn_metrics_server(
+    watch: &WatchOptions,
+    shutdown_received: &tokio::sync::watch::Receiver<bool>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    watch.metrics_listen.map(|addr| {
+        let mut shutdown_received = shutdown_received.clone();
+        tokio::spawn(async move {
+            let shutdown = async move {
+                let _ = shutdown_received.wait_for(|v| *v).await;
+            };
+            if let Err(e) = metrics::serve(addr, shutdown).await {
+                log::error!("Unable to serve metrics on {}. Error: {}", addr, e);
+            }
+        })
+    })
+}
+
+async fn run_watch(paths: WatchPaths, options: Options, watch: WatchOptions) {
+    check_paths(
+        &paths.current_path,
+        &paths.last_path,
+        &paths.diff_path,
+        &options,
+    );
+    let scheduler = cadence(paths.interval.map(Into::into), paths.schedule)
+        .map(|cadence| watch.scheduler(cadence))
+        .unwrap_or_else(|e| exit_invalid("Invalid arguments", e));
+    let _lock = lock_diff_path(&paths.diff_path, watch.wait_for_lock).await;
+
+    let (pdfium, pdfium_version) = load_pdfium(&paths.pdfium_path, &options);
+
+    let mut file_manager = files::FileManager::new(
+        build_comparison(pdfium.clone(), &paths.pdfium_path, &options)
+            .unwrap_or_else(|e| exit_invalid("Invalid comparison settings", e)),
+        build_sink(pdfium, pdfium_version, &options, &paths.diff_path),
+        paths.current_path,
+        paths.last_path,
+        paths.diff_path,
+        files::FileManagerConfig {
+            pdfium_version,
+            isolation: isolation_config(&options, &paths.pdfium_path),
+            ..watch_file_manager_config(&options, &watch)
+        },
+    );
+    if let Some(selection) = force_selection(&options) {
+        file_manager.force_next_cycle(selection);
+    }
+
+    let (shutdown, shutdown_received) = tokio::sync::watch::channel(false);
+    let metrics_server = spawn_metrics_server(&watch, &shutdown_received);
+    let cancel = spawn_shutdown_handler(&shutdown);
+    let mut stopping = shutdown_received.clone();
+    let mut scheduled = Local::now();
+    let mut durations = CycleDurations::default();
+
+    loop {
+        let started = Instant::now();
+        match file_manager.update(Some(&cancel)).await {
+            Ok(report) => print_report(&report, options.timings),
             Err(e) => {
                 println!("Error updating pdf. FileManagerError: {}", e)
             }
         }
+        if cancel.is_cancelled() {
+            break;
+        }
+        record_cycle("", &scheduler, &mut durations, started.elapsed());
+
+        let Some(next) = scheduler.next_tick(&scheduled, &Local::now()) else {
+            println!("The schedule doesn't fire anymore, exiting");
+            break;
+        };
+        println!(
+            "Next cycle at {}",
+            options.timezone.at(next.due.with_timezone(&Utc))
+        );
+        scheduled = next.scheduled;
+        tokio::select! {
+            _ = scheduler::sleep_until(next.due) => {}
+            _ = stopping.wait_for(|v| *v) => break,
+        }
+    }
+
+    let _ = shutdown.send(true);
+    if let Some(metrics_server) = metrics_server {
+        let _ = metrics_server.await;
+    }
+}
+
+/// Reads the config file of the watch subcommand, failing if it lists no entry or an entry
+/// twice.
+fn read_watch_config(path: &Path) -> Result<WatchConfig, String> {
+    let config = std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|v| serde_json::from_slice::<WatchConfig>(&v).map_err(|e| e.to_string()))?;
+    if config.entries.is_empty() {
+        return Err(String::from("it doesn't list any entries"));
+    }
+    let mut names = std::collections::HashSet::new();
+    for entry in &config.entries {
+        if !names.insert(entry.name.as_str()) {
+            return Err(format!("the entry {} is listed more than once", entry.name));
+        }
+    }
+    Ok(config)
+}
+
+impl WatchEntry {
+    /// `options` with the overrides of this entry applied.
+    fn options(&self, options: &Options) -> Result<Options, String> {
+        let mut options = options.clone();
+        if let Some(pixel_tolerance) = self.pixel_tolerance {
+            options.pixel_tolerance = pixel_tolerance;
+        }
+        if let Some(page_threshold) = self.page_threshold {
+            options.page_threshold = page_threshold;
+        }
+        if let Some(marker_color) = &self.marker_color {
+            options.marker_color = parse_hex_color(marker_color)
+                .map_err(|e| format!("Invalid marker color of the entry {}: {}", self.name, e))?;
+        }
+        if let Some(ignore) = &self.ignore {
+            options.ignore = ignore
+                .iter()
+                .map(|v| globset::Glob::new(v))
+                .collect::<Result<Vec<globset::Glob>, globset::Error>>()
+                .map_err(|e| format!("Invalid ignore pattern of the entry {}: {}", self.name, e))?;
+        }
+        if let Some(severity) = &self.severity {
+            options.severity_rules = Some(severity.clone());
+        }
+        Ok(options)
+    }
+
+    fn file_manager_config(
+        &self,
+        options: &Options,
+        watch: &WatchOptions,
+    ) -> files::FileManagerConfig {
+        files::FileManagerConfig {
+            entry: Some(self.name.clone()),
+            status_path: watch.healthcheck_file.as_ref().map(|path| {
+                path.with_file_name(format!(
+                    "{}-{}",
+                    self.name,
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ))
+            }),
+            ..watch_file_manager_config(options, watch)
+        }
+    }
 
-        tokio::time::sleep(args.interval.into()).await;
+    /// The interval or schedule of this entry, `default` if it has neither.
+    fn cadence(&self, default: &Cadence) -> Result<Cadence, String> {
+        let schedule = self
+            .schedule
+            .as_ref()
+            .map(|v| v.parse::<schedule::CronSchedule>())
+            .transpose()
+            .map_err(|e| format!("Invalid schedule of the entry {}: {}", self.name, e))?;
+        match (self.interval, schedule) {
+            (None, None) => Ok(default.clone()),
+            (interval, schedule) => cadence(interval, schedule)
+                .map_err(|e| format!("Invalid entry {}: {}", self.name, e)),
+        }
+    }
+
+    fn same_paths(&self, other: &WatchEntry) -> bool {
+        self.current_path == other.current_path
+            && self.last_path == other.last_path
+            && self.diff_path == other.diff_path
+    }
+}
+
+/// An entry of the config file with its manager and its next cycle, `None` if its schedule
+/// doesn't fire anymore.
+struct WatchedTree {
+    entry: WatchEntry,
+    file_manager: files::FileManager,
+    scheduler: Scheduler,
+    due: Option<Tick<Local>>,
+    durations: CycleDurations,
+}
+
+/// Receives SIGHUP. On platforms without it nothing is ever received.
+struct Hangups {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangups {
+    fn new() -> Self {
+        Hangups {
+            #[cfg(unix)]
+            signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .inspect_err(|e| eprintln!("Unable to handle SIGHUP: {}", e))
+                .ok(),
+        }
+    }
+
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            signal.recv().await;
+            return;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+/// How often the config file is checked for modifications with --reload-on-change.
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Applies the config file to the running trees. Settings of an entry that are invalid or
+/// can't be applied without a restart, like its paths, keep the entry at its previous
+/// settings and are reported.
+fn reload_watch_config(
+    args: &WatchConfigArgs,
+    (pdfium, pdfium_version): (&Arc<Pdfium>, Option<PdfiumVersion>),
+    default_cadence: &Cadence,
+    trees: &mut [WatchedTree],
+) {
+    let config = match read_watch_config(&args.config) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!(
+                "Unable to reload the config file {}, keeping the previous settings: {}",
+                args.config.to_string_lossy(),
+                e
+            );
+            return;
+        }
+    };
+    let mut entries = config
+        .entries
+        .into_iter()
+        .map(|v| (v.name.clone(), v))
+        .collect::<std::collections::HashMap<String, WatchEntry>>();
+    for tree in trees.iter_mut() {
+        let Some(entry) = entries.remove(&tree.entry.name) else {
+            eprintln!(
+                "Removing the entry {} requires a restart, it is still watched",
+                tree.entry.name
+            );
+            continue;
+        };
+        if !entry.same_paths(&tree.entry) {
+            eprintln!(
+                "Changing the paths of the entry {} requires a restart, keeping its previous settings",
+                entry.name
+            );
+            continue;
+        }
+        let scheduler = match entry.cadence(default_cadence) {
+            Ok(v) => args.watch.scheduler(v),
+            Err(e) => {
+                eprintln!("{}, keeping its previous settings", e);
+                continue;
+            }
+        };
+        let comparison = entry.options(&args.options).and_then(|options| {
+            build_comparison(pdfium.clone(), &args.pdfium_path, &options)
+                .map(|comparison| (options, comparison))
+                .map_err(|e| {
+                    format!(
+                        "Invalid comparison settings of the entry {}: {}",
+                        entry.name, e
+                    )
+                })
+        });
+        let (options, comparison) = match comparison {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{}, keeping its previous settings", e);
+                continue;
+            }
+        };
+        tree.file_manager.set_comparison(comparison);
+        tree.file_manager.set_sink(build_sink(
+            pdfium.clone(),
+            pdfium_version,
+            &options,
+            &entry.diff_path,
+        ));
+        tree.file_manager.set_config(files::FileManagerConfig {
+            pdfium_version,
+            isolation: isolation_config(&options, &args.pdfium_path),
+            ..entry.file_manager_config(&options, &args.watch)
+        });
+        if scheduler != tree.scheduler {
+            let now = Local::now();
+            tree.due = scheduler.next_tick(&now, &now);
+            tree.scheduler = scheduler;
+        }
+        tree.entry = entry;
+    }
+    for name in entries.keys() {
+        eprintln!(
+            "Adding the entry {} requires a restart, it isn't watched yet",
+            name
+        );
+    }
+    println!("Reloaded the config file {}", args.config.to_string_lossy());
+}
+
+/// Watches every entry of the config file with a single pdfium instance. The cycles of the
+/// entries run one after another, each entry is due again its interval after its last cycle
+/// finished or at the next time of its schedule, unless --missed-ticks keeps them on their
+/// ticks. On SIGHUP, or with --reload-on-change once the file is modified, the config file
+/// is read again between cycles.
+async fn run_watch_config(args: WatchConfigArgs) {
+    let config = read_watch_config(&args.config).unwrap_or_else(|e| {
+        exit_invalid(
+            &format!(
+                "Unable to read the config file {}",
+                args.config.to_string_lossy()
+            ),
+            e,
+        )
+    });
+    let default_cadence = match &args.schedule {
+        Some(schedule) => Cadence::Schedule(Box::new(schedule.clone())),
+        None => Cadence::Interval(args.interval.into()),
+    };
+    for entry in &config.entries {
+        check_paths(
+            &entry.current_path,
+            &entry.last_path,
+            &entry.diff_path,
+            &args.options,
+        );
+    }
+    let (pdfium, pdfium_version) = load_pdfium(&args.pdfium_path, &args.options);
+
+    let mut locks = Vec::new();
+    let mut trees = Vec::new();
+    for entry in config.entries {
+        locks.push(lock_diff_path(&entry.diff_path, args.watch.wait_for_lock).await);
+        let options = entry
+            .options(&args.options)
+            .unwrap_or_else(|e| exit_invalid("Invalid entry", e));
+        let scheduler = entry
+            .cadence(&default_cadence)
+            .map(|cadence| args.watch.scheduler(cadence))
+            .unwrap_or_else(|e| exit_invalid("Invalid entry", e));
+        let mut file_manager = files::FileManager::new(
+            build_comparison(pdfium.clone(), &args.pdfium_path, &options)
+                .unwrap_or_else(|e| exit_invalid("Invalid comparison settings", e)),
+            build_sink(pdfium.clone(), pdfium_version, &options, &entry.diff_path),
+            entry.current_path.clone(),
+            entry.last_path.clone(),
+            entry.diff_path.clone(),
+            files::FileManagerConfig {
+                pdfium_version,
+                isolation: isolation_config(&options, &args.pdfium_path),
+                ..entry.file_manager_config(&options, &args.watch)
+            },
+        );
+        if let Some(selection) = force_selection(&options) {
+            file_manager.force_next_cycle(selection);
+        }
+        trees.push(WatchedTree {
+            entry,
+            file_manager,
+            // The first cycle of every entry runs right away, like without a config file.
+            due: Some(Tick {
+                scheduled: Local::now(),
+                due: Local::now(),
+            }),
+            scheduler,
+            durations: CycleDurations::default(),
+        });
+    }
+
+    let (shutdown, shutdown_received) = tokio::sync::watch::channel(false);
+    let metrics_server = spawn_metrics_server(&args.watch, &shutdown_received);
+    let cancel = spawn_shutdown_handler(&shutdown);
+    let mut stopping = shutdown_received.clone();
+    let mut hangups = Hangups::new();
+    let config_modified = || {
+        std::fs::metadata(&args.config)
+            .and_then(|v| v.modified())
+            .ok()
+    };
+    let mut last_modified = config_modified();
+
+    loop {
+        let Some((index, due)) = (0..trees.len())
+            .filter_map(|i| Some((i, trees[i].due.as_ref()?.due)))
+            .min_by_key(|(_, due)| *due)
+        else {
+            println!("The schedules of all entries don't fire anymore, exiting");
+            break;
+        };
+        let reload = tokio::select! {
+            _ = scheduler::sleep_until(due) => false,
+            _ = hangups.recv() => true,
+            _ = tokio::time::sleep(CONFIG_POLL_INTERVAL), if args.reload_on_change => {
+                let modified = config_modified();
+                std::mem::replace(&mut last_modified, modified) != modified
+            }
+            _ = stopping.wait_for(|v| *v) => break,
+        };
+        if reload {
+            last_modified = config_modified();
+            reload_watch_config(
+                &args,
+                (&pdfium, pdfium_version),
+                &default_cadence,
+                &mut trees,
+            );
+            continue;
+        }
+        let tree = &mut trees[index];
+        let started = Instant::now();
+        match tree.file_manager.update(Some(&cancel)).await {
+            Ok(report) => {
+                println!("{}:", tree.entry.name);
+                print_report(&report, args.options.timings)
+            }
+            Err(e) => {
+                println!(
+                    "Error updating {}. FileManagerError: {}",
+                    tree.entry.name, e
+                )
+            }
+        }
+        if cancel.is_cancelled() {
+            break;
+        }
+        record_cycle(
+            &tree.entry.name,
+            &tree.scheduler,
+            &mut tree.durations,
+            started.elapsed(),
+        );
+        let scheduled = tree.due.take().map_or_else(Local::now, |v| v.scheduled);
+        tree.due = tree.scheduler.next_tick(&scheduled, &Local::now());
+        match &tree.due {
+            Some(next) => println!(
+                "Next cycle of {} at {}",
+                tree.entry.name,
+                args.options.timezone.at(next.due.with_timezone(&Utc))
+            ),
+            None => println!("The schedule of {} doesn't fire anymore", tree.entry.name),
+        }
+    }
+
+    let _ = shutdown.send(true);
+    if let Some(metrics_server) = metrics_server {
+        let _ = metrics_server.await;
+    }
+}
+
+fn run_self_test(args: SelfTestArgs) {
+    let steps = selftest::run_self_test(&args.pdfium, args.allow_old_pdfium);
+    steps.iter().for_each(|step| println!("{}", step));
+    if steps.iter().any(|step| step.result.is_err()) {
+        std::process::exit(1);
+    }
+    println!("pdfium works, {} steps passed", steps.len());
+}
+
+async fn print_status(args: StatusArgs) {
+    let path = match args.path.is_dir() {
+        true => args.path.join(status::STATUS_FILE_NAME),
+        false => args.path,
+    };
+    match status::CycleStatus::read(&path).await {
+        Ok(status) => {
+            println!("{}", status);
+            if status.last_error.is_some() {
+                std::process::exit(1);
+            }
+        }
+        Err(e) => {
+            eprintln!(
+                "Unable to read the status file {}: {}",
+                path.to_string_lossy(),
+                e
+            );
+            std::process::exit(2);
+        }
     }
 }
@@ -0,0 +1,80 @@
+use {pdfium_render::prelude::PdfPageIndex, std::collections::HashSet};
+
+/// A page of the previous version a page can be matched to and its number of differing
+/// pixels.
+pub(crate) type Candidate = (PdfPageIndex, usize);
+
+/// The pages of the previous version one page of the current version can be matched to.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct PageCandidates {
+    pub candidates: Vec<Candidate>,
+    /// Most differing pixels for which the page still counts as identical.
+    pub identical: usize,
+}
+
+impl PageCandidates {
+    /// Candidates as good as the best one, or that count as identical to the page. All of
+    /// them are equally valid matches, e.g. several blank pages of the previous version.
+    fn eligible(&self) -> impl Iterator<Item = Candidate> + '_ {
+        let best = self
+            .candidates
+            .iter()
+            .map(|(_, differing)| *differing)
+            .min();
+        let limit = best.map(|best| best.max(self.identical));
+        self.candidates
+            .iter()
+            .copied()
+            .filter(move |(_, differing)| Some(*differing) <= limit)
+    }
+}
+
+/// Matches every page of the current version to one of its `pages`, `None` for pages
+/// without any candidate.
+///
+/// Pages with several equally good candidates, like repeated blank pages or disclaimers,
+/// would otherwise all claim the first of them and leave the corresponding pages unmatched.
+/// Every page of the previous version is therefore only handed out once: the page with the
+/// fewest differing pixels gets it, ties go to the page whose match keeps the page order of
+/// its neighbours, and the others fall back to their next eligible candidate. Only a page
+/// left without any unclaimed candidate shares its best match with another page.
+pub(crate) fn assign(pages: &[PageCandidates]) -> Vec<Option<Candidate>> {
+    // The offset between the versions at every page, taken from the closest preceding page
+    // with a single eligible candidate, e.g. shifted by one after an inserted page.
+    let mut shift = 0;
+    let shifts = pages
+        .iter()
+        .enumerate()
+        .map(|(a, page)| {
+            if let [(b, _)] = page.eligible().collect::<Vec<Candidate>>().as_slice() {
+                shift = *b as i64 - a as i64;
+            }
+            shift
+        })
+        .collect::<Vec<i64>>();
+    let penalty = |a: usize, b: PdfPageIndex| (b as i64 - a as i64 - shifts[a]).unsigned_abs();
+
+    let mut pairs = pages
+        .iter()
+        .enumerate()
+        .flat_map(|(a, page)| page.eligible().map(move |candidate| (a, candidate)))
+        .collect::<Vec<(usize, Candidate)>>();
+    pairs.sort_by_key(|(a, (b, differing))| (*differing, penalty(*a, *b), *a));
+
+    let mut assigned = vec![None; pages.len()];
+    let mut claimed = HashSet::new();
+    for (a, (b, differing)) in pairs {
+        if assigned[a].is_none() && claimed.insert(b) {
+            assigned[a] = Some((b, differing));
+        }
+    }
+    for (a, page) in pages.iter().enumerate() {
+        if assigned[a].is_none() {
+            assigned[a] = page
+                .eligible()
+                .min_by_key(|(b, differing)| (*differing, penalty(a, *b)));
+        }
+    }
+    assigned
+}
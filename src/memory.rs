@@ -0,0 +1,90 @@
+/// Narrowest width pages are rendered at to stay within a render memory limit, in pixels.
+const MIN_REDUCED_WIDTH: i32 = 100;
+
+/// Approximate memory the page renders of a single comparison took, see
+/// [`crate::ComparisonConfig::render_memory_limit`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderMemory {
+    /// Most bytes of renders alive at the same time.
+    pub peak_bytes: usize,
+    /// Width pages were rendered at instead of the target width to stay within the limit.
+    pub reduced_width: Option<i32>,
+}
+
+impl RenderMemory {
+    /// Why the comparison is less precise than configured, if it is.
+    pub fn warning(&self) -> Option<String> {
+        self.reduced_width.map(|width| {
+            format!(
+                "pages were rendered at a width of {} pixels to stay within the render memory \
+                 limit",
+                width
+            )
+        })
+    }
+}
+
+/// Tracks the bytes of the renders of a comparison: those in the render cache, the ones
+/// currently in use and those kept for the result, like thumbnails.
+#[derive(Debug, Default)]
+pub(crate) struct RenderBudget {
+    limit: Option<usize>,
+    /// Renders kept until the comparison ends, outside of the cache.
+    retained: usize,
+    /// Renders in use while comparing two pages, assumed to be two of the largest so far.
+    in_use: usize,
+    usage: RenderMemory,
+}
+
+impl RenderBudget {
+    pub fn new(limit: Option<usize>) -> Self {
+        RenderBudget {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// The width and maximum height to render at instead of `size` if two renders of
+    /// `largest_page` bytes at that size don't fit into the limit. Render sizes grow with the
+    /// square of the width, so both are scaled by the square root of the excess.
+    pub fn reduce(&mut self, size: (i32, i32), largest_page: usize) -> Option<(i32, i32)> {
+        let limit = self.limit?;
+        if 2 * largest_page <= limit {
+            return None;
+        }
+        let scale = (limit as f64 / (2 * largest_page) as f64).sqrt();
+        let width = ((size.0 as f64 * scale) as i32).max(MIN_REDUCED_WIDTH.min(size.0));
+        let height = ((size.1 as f64 * scale) as i32).max(1);
+        self.usage.reduced_width = Some(width);
+        Some((width, height))
+    }
+
+    /// Records a render of `bytes` that is about to be used.
+    pub fn rendered(&mut self, bytes: usize) {
+        self.in_use = self.in_use.max(2 * bytes);
+    }
+
+    /// Records `bytes` kept until the comparison ends.
+    pub fn retain(&mut self, bytes: usize) {
+        self.retained += bytes;
+    }
+
+    /// Most bytes the render cache may hold besides the other renders, at most `configured`.
+    pub fn cache_capacity(&self, configured: usize) -> usize {
+        match self.limit {
+            Some(limit) => configured.min(limit.saturating_sub(self.retained + self.in_use)),
+            None => configured,
+        }
+    }
+
+    /// Updates the peak with the `cached` bytes of the render cache.
+    pub fn record(&mut self, cached: usize) {
+        let live = cached + self.retained + self.in_use;
+        self.usage.peak_bytes = self.usage.peak_bytes.max(live);
+    }
+
+    pub fn usage(&self) -> RenderMemory {
+        self.usage.clone()
+    }
+}
@@ -0,0 +1,154 @@
+use {
+    crate::{
+        digests::{content_digest, hex_digest},
+        fields::{diff_values, ChangeKind},
+    },
+    pdfium_render::prelude::*,
+    std::collections::BTreeMap,
+};
+
+/// An entry of the document information dictionary that differs between the versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MetadataChange {
+    /// Name of the entry, e.g. `title` or `producer`.
+    pub key: String,
+    pub kind: ChangeKind,
+    pub previous: Option<String>,
+    pub current: Option<String>,
+}
+
+impl std::fmt::Display for MetadataChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = |v: &Option<String>| v.clone().unwrap_or_default();
+        match self.kind {
+            ChangeKind::Added => write!(f, "{} added: {:?}", self.key, value(&self.current)),
+            ChangeKind::Removed => write!(f, "{} removed: {:?}", self.key, value(&self.previous)),
+            ChangeKind::Changed => write!(
+                f,
+                "{} changed: {:?} -> {:?}",
+                self.key,
+                value(&self.previous),
+                value(&self.current)
+            ),
+        }
+    }
+}
+
+/// Size and content digest of an embedded file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttachmentInfo {
+    pub size: usize,
+    /// Hex encoded SHA-256 digest of the content, `None` if pdfium couldn't extract it.
+    pub sha256: Option<String>,
+}
+
+/// An embedded file that was added, removed or whose content changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttachmentChange {
+    pub name: String,
+    pub kind: ChangeKind,
+    pub previous: Option<AttachmentInfo>,
+    pub current: Option<AttachmentInfo>,
+}
+
+impl std::fmt::Display for AttachmentChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let size = |v: &Option<AttachmentInfo>| v.as_ref().map_or(0, |v| v.size);
+        match self.kind {
+            ChangeKind::Added => write!(
+                f,
+                "attachment {} added ({} bytes)",
+                self.name,
+                size(&self.current)
+            ),
+            ChangeKind::Removed => write!(
+                f,
+                "attachment {} removed ({} bytes)",
+                self.name,
+                size(&self.previous)
+            ),
+            ChangeKind::Changed => write!(
+                f,
+                "attachment {} changed ({} -> {} bytes)",
+                self.name,
+                size(&self.previous),
+                size(&self.current)
+            ),
+        }
+    }
+}
+
+fn tag_name(tag: PdfDocumentMetadataTagType) -> &'static str {
+    match tag {
+        PdfDocumentMetadataTagType::Title => "title",
+        PdfDocumentMetadataTagType::Author => "author",
+        PdfDocumentMetadataTagType::Subject => "subject",
+        PdfDocumentMetadataTagType::Keywords => "keywords",
+        PdfDocumentMetadataTagType::Creator => "creator",
+        PdfDocumentMetadataTagType::Producer => "producer",
+        PdfDocumentMetadataTagType::CreationDate => "creation_date",
+        PdfDocumentMetadataTagType::ModificationDate => "modification_date",
+    }
+}
+
+/// The entries of the information dictionary pdfium exposes. Empty entries count as missing.
+fn metadata_values(document: &PdfDocument) -> BTreeMap<String, String> {
+    document
+        .metadata()
+        .iter()
+        .filter(|tag| !tag.value().is_empty())
+        .map(|tag| {
+            (
+                tag_name(tag.tag_type()).to_string(),
+                tag.value().to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Every embedded file of `document` by name. Of several files with the same name only the
+/// first one is kept.
+fn attachments(document: &PdfDocument) -> BTreeMap<String, AttachmentInfo> {
+    let mut attachments = BTreeMap::new();
+    for attachment in document.attachments().iter() {
+        attachments
+            .entry(attachment.name())
+            .or_insert_with(|| AttachmentInfo {
+                size: attachment.len(),
+                sha256: attachment
+                    .save_to_bytes()
+                    .ok()
+                    .map(|content| hex_digest(&content_digest(&content))),
+            });
+    }
+    attachments
+}
+
+/// Every entry of the information dictionary that differs between `a` and `b`.
+pub(crate) fn metadata_changes(a: &PdfDocument, b: &PdfDocument) -> Vec<MetadataChange> {
+    diff_values(metadata_values(a), metadata_values(b))
+        .into_iter()
+        .map(|(key, kind, previous, current)| MetadataChange {
+            key,
+            kind,
+            previous,
+            current,
+        })
+        .collect()
+}
+
+/// Every embedded file that differs between `a` and `b`, ordered by name.
+pub(crate) fn attachment_changes(a: &PdfDocument, b: &PdfDocument) -> Vec<AttachmentChange> {
+    diff_values(attachments(a), attachments(b))
+        .into_iter()
+        .map(|(name, kind, previous, current)| AttachmentChange {
+            name,
+            kind,
+            previous,
+            current,
+        })
+        .collect()
+}
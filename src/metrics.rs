@@ -0,0 +1,188 @@
+use {
+    prometheus::{
+        Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec,
+        Opts, Registry, TextEncoder,
+    },
+    std::{future::Future, net::SocketAddr, sync::OnceLock},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+};
+
+/// Counters of the whole process, updated by [`FileManager`](crate::FileManager) and
+/// [`PDFComparison`](crate::PDFComparison). The metrics of [`FileManager`](crate::FileManager)
+/// are labeled `entry` with the name of its tree, see
+/// [`FileManagerConfig::entry`](crate::FileManagerConfig::entry).
+pub struct Metrics {
+    registry: Registry,
+    pub files_scanned: IntCounterVec,
+    pub files_compared: IntCounterVec,
+    pub files_changed: IntCounterVec,
+    pub files_errored: IntCounterVec,
+    pub pages_rendered: IntCounter,
+    pub render_cache_hits: IntCounter,
+    pub render_cache_misses: IntCounter,
+    pub comparison_duration: HistogramVec,
+    /// Time spent per file in every [`TimingStage`](crate::TimingStage), also labeled `stage`.
+    pub stage_duration: HistogramVec,
+    pub last_successful_cycle: IntGaugeVec,
+    /// Rolling average of the cycle durations of a watch loop, updated by the binary.
+    pub average_cycle_duration: GaugeVec,
+    /// 1 while the cycles of a watch loop take longer on average than their cadence.
+    pub cadence_overrun: IntGaugeVec,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// The metrics of the process, registered on first use.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        Metrics::new().expect("The metric names and options are valid and registered once")
+    })
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some(String::from("pdf_diff")), None)?;
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounter::new(name, help)?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok::<IntCounter, prometheus::Error>(counter)
+        };
+        let entry_counter = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["entry"])?;
+            registry.register(Box::new(counter.clone()))?;
+            Ok::<IntCounterVec, prometheus::Error>(counter)
+        };
+        let metrics = Metrics {
+            files_scanned: entry_counter("files_scanned_total", "Files found in current_path")?,
+            files_compared: entry_counter("files_compared_total", "Files compared to their copy")?,
+            files_changed: entry_counter(
+                "files_changed_total",
+                "Files an artifact was written for",
+            )?,
+            files_errored: entry_counter(
+                "files_errored_total",
+                "Files that couldn't be processed",
+            )?,
+            pages_rendered: counter("pages_rendered_total", "Pages rendered for comparing")?,
+            render_cache_hits: counter(
+                "render_cache_hits_total",
+                "Page renders taken from the render cache",
+            )?,
+            render_cache_misses: counter(
+                "render_cache_misses_total",
+                "Page renders missing from the render cache",
+            )?,
+            comparison_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "comparison_duration_seconds",
+                    "Time spent comparing a single file",
+                )
+                .buckets(vec![0.1, 0.5, 1., 2.5, 5., 10., 30., 60., 120., 300.]),
+                &["entry"],
+            )?,
+            stage_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "stage_duration_seconds",
+                    "Time spent in a single stage of processing a file",
+                )
+                .buckets(vec![0.01, 0.05, 0.1, 0.5, 1., 2.5, 5., 10., 30., 60.]),
+                &["entry", "stage"],
+            )?,
+            last_successful_cycle: IntGaugeVec::new(
+                Opts::new(
+                    "last_successful_cycle_timestamp_seconds",
+                    "Unix time of the last update cycle that finished without an error",
+                ),
+                &["entry"],
+            )?,
+            average_cycle_duration: GaugeVec::new(
+                Opts::new(
+                    "average_cycle_duration_seconds",
+                    "Average duration of the last update cycles of a watch loop",
+                ),
+                &["entry"],
+            )?,
+            cadence_overrun: IntGaugeVec::new(
+                Opts::new(
+                    "cadence_overrun",
+                    "1 while update cycles take longer on average than the time between them",
+                ),
+                &["entry"],
+            )?,
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.comparison_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.stage_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.last_successful_cycle.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.average_cycle_duration.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.cadence_overrun.clone()))?;
+        Ok(metrics)
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            log::error!("Unable to encode the metrics. Error: {}", e);
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+/// Serves the metrics at `GET /metrics` on `addr` until `shutdown` completes.
+pub async fn serve(addr: SocketAddr, shutdown: impl Future<Output = ()>) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("Serving metrics on http://{}/metrics", addr);
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => return Ok(()),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream).await {
+                            log::debug!("Unable to answer a metrics request. Error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("Unable to accept a metrics connection. Error: {}", e),
+            },
+        }
+    }
+}
+
+/// Answers a single request. Only the request line is looked at.
+async fn respond(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut buffer = [0; 1024];
+    let read = stream.read(&mut buffer).await?;
+    let request = String::from_utf8_lossy(&buffer[..read]);
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            ("200 OK", "text/plain; version=0.0.4", metrics().render())
+        }
+        _ => ("404 Not Found", "text/plain", String::from("Not found\n")),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
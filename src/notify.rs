@@ -0,0 +1,181 @@
+use {
+    hmac::{Hmac, KeyInit, Mac},
+    serde::Serialize,
+    sha2::Sha256,
+    std::{fmt::Write, path::PathBuf, time::Duration},
+};
+
+use crate::{
+    files::{
+        serialize_lossy_optional_path, serialize_lossy_path, FileReport, FileResult, RunReport,
+    },
+    pdf::{ChangeScore, PageStatistics},
+    severity::Severity,
+    timestamp::Timestamp,
+};
+
+/// Header carrying the hex encoded HMAC-SHA256 of the request body, prefixed with `sha256=`.
+pub const SIGNATURE_HEADER: &str = "X-PDF-Diff-Signature-256";
+
+/// Delay before the first retry, doubled for every further retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Where and how the changes of an update cycle are posted.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub timeout: Duration,
+    /// Number of retries after a server error or a failed connection.
+    pub retries: u32,
+    /// Shared secret the body is signed with, see [`SIGNATURE_HEADER`].
+    pub secret: Option<String>,
+    /// Sends a single payload listing all changed files instead of one per file.
+    pub batch: bool,
+    /// Only files with at least this severity are posted, files without a severity always
+    /// are, see [`crate::FileManagerConfig::severity`].
+    pub min_severity: Option<Severity>,
+}
+
+impl WebhookConfig {
+    pub fn new(url: String) -> Self {
+        WebhookConfig {
+            url,
+            timeout: Duration::from_secs(10),
+            retries: 3,
+            secret: None,
+            batch: false,
+            min_severity: None,
+        }
+    }
+}
+
+/// Payload posted for a single changed file.
+#[derive(Debug, Serialize)]
+pub struct FilePayload<'a> {
+    pub timestamp: Timestamp,
+    /// Path of the file relative to `current_path`.
+    #[serde(serialize_with = "serialize_lossy_path")]
+    pub path: &'a PathBuf,
+    #[serde(serialize_with = "serialize_lossy_optional_path")]
+    pub output: Option<&'a PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<Severity>,
+    pub score: Option<ChangeScore>,
+    pub pages: Vec<&'a PageStatistics>,
+}
+
+/// Payload posted once per cycle with `batch` set.
+#[derive(Debug, Serialize)]
+pub struct BatchPayload<'a> {
+    pub timestamp: Timestamp,
+    pub files: Vec<FilePayload<'a>>,
+}
+
+impl<'a> FilePayload<'a> {
+    /// Only the changed pages are part of the payload.
+    fn new(timestamp: Timestamp, file: &'a FileReport) -> Self {
+        FilePayload {
+            timestamp,
+            path: &file.path,
+            output: file.output.as_ref(),
+            severity: file.severity,
+            score: file.score,
+            pages: file.pages.iter().filter(|v| v.changed()).collect(),
+        }
+    }
+}
+
+/// Posts the changed files of `report` that alert to the webhook, see
+/// [`crate::FileManagerConfig::alert_threshold`]. Failures are logged and otherwise ignored.
+pub async fn notify(config: &WebhookConfig, report: &RunReport) {
+    let changed = report
+        .files
+        .iter()
+        .filter(|v| matches!(v.result, FileResult::Changed | FileResult::New) && v.alert)
+        .filter(|v| {
+            config
+                .min_severity
+                .is_none_or(|min| v.severity.is_none_or(|severity| severity >= min))
+        })
+        .map(|v| FilePayload::new(report.timestamp, v))
+        .collect::<Vec<FilePayload>>();
+    if changed.is_empty() {
+        return;
+    }
+    let client = match reqwest::Client::builder().timeout(config.timeout).build() {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Unable to create the webhook client. Error: {}", e);
+            return;
+        }
+    };
+
+    let bodies = if config.batch {
+        vec![serde_json::to_vec(&BatchPayload {
+            timestamp: report.timestamp,
+            files: changed,
+        })]
+    } else {
+        changed.iter().map(serde_json::to_vec).collect()
+    };
+    for body in bodies {
+        match body {
+            Ok(body) => {
+                if let Err(e) = post(&client, config, body).await {
+                    log::error!("Unable to notify {}. Error: {}", config.url, e);
+                }
+            }
+            Err(e) => log::error!("Unable to serialize the webhook payload. Error: {}", e),
+        }
+    }
+}
+
+/// Posts `body`, retrying with exponential backoff on server errors and failed connections.
+async fn post(
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+    body: Vec<u8>,
+) -> Result<(), String> {
+    let signature = config.secret.as_ref().map(|secret| sign(secret, &body));
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        let mut request = client
+            .post(&config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        let error = match request.send().await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) if response.status().is_server_error() => {
+                format!("server responded with {}", response.status())
+            }
+            Ok(response) => return Err(format!("server responded with {}", response.status())),
+            Err(e) => e.to_string(),
+        };
+        if attempt >= config.retries {
+            return Err(error);
+        }
+        attempt += 1;
+        log::warn!(
+            "Webhook request failed ({}), retrying in {}",
+            error,
+            humantime::format_duration(backoff)
+        );
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = <Hmac<Sha256> as KeyInit>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let mut signature = String::from("sha256=");
+    mac.finalize().into_bytes().iter().for_each(|byte| {
+        let _ = write!(signature, "{:02x}", byte);
+    });
+    signature
+}
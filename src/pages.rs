@@ -0,0 +1,108 @@
+use std::{error::Error, str::FromStr};
+
+/// Pages of a document given as comma separated one based pages and ranges, e.g.
+/// `1-25,40,50-`. Ranges may be open on either end and overlap, pages past the end of a
+/// document are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageSelection {
+    /// Zero based, inclusive ranges. `None` extends to the last page.
+    ranges: Vec<(usize, Option<usize>)>,
+}
+
+impl PageSelection {
+    /// Whether the zero based `page` is selected.
+    pub fn contains(&self, page: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|(start, end)| page >= *start && end.is_none_or(|end| page <= end))
+    }
+}
+
+/// Invalid page selection passed to [`PageSelection::from_str`].
+#[derive(Debug)]
+pub enum PageSelectionError {
+    Empty,
+    InvalidPage(String),
+    /// Pages are counted from 1.
+    ZeroPage,
+    ReversedRange {
+        start: usize,
+        end: usize,
+    },
+}
+
+impl Error for PageSelectionError {}
+
+impl std::fmt::Display for PageSelectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "The page selection is empty"),
+            Self::InvalidPage(v) => write!(f, "Expected a page number, got {}", v),
+            Self::ZeroPage => write!(f, "Pages are counted from 1, got 0"),
+            Self::ReversedRange { start, end } => {
+                write!(f, "The range {}-{} ends before it starts", start, end)
+            }
+        }
+    }
+}
+
+impl FromStr for PageSelection {
+    type Err = PageSelectionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let page = |value: &str| match value.trim().parse::<usize>() {
+            Ok(0) => Err(PageSelectionError::ZeroPage),
+            Ok(page) => Ok(page),
+            Err(_) => Err(PageSelectionError::InvalidPage(value.trim().to_string())),
+        };
+        let ranges = s
+            .split(',')
+            .filter(|part| !part.trim().is_empty())
+            .map(|part| {
+                let (start, end) = match part.split_once('-') {
+                    Some((start, end)) => (
+                        match start.trim() {
+                            "" => 1,
+                            start => page(start)?,
+                        },
+                        match end.trim() {
+                            "" => None,
+                            end => Some(page(end)?),
+                        },
+                    ),
+                    None => {
+                        let page = page(part)?;
+                        (page, Some(page))
+                    }
+                };
+                match end {
+                    Some(end) if end < start => {
+                        Err(PageSelectionError::ReversedRange { start, end })
+                    }
+                    _ => Ok((start - 1, end.map(|end| end - 1))),
+                }
+            })
+            .collect::<Result<Vec<(usize, Option<usize>)>, PageSelectionError>>()?;
+        if ranges.is_empty() {
+            return Err(PageSelectionError::Empty);
+        }
+        Ok(PageSelection { ranges })
+    }
+}
+
+impl std::fmt::Display for PageSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (start, end)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match end {
+                Some(end) if end == start => write!(f, "{}", start + 1)?,
+                Some(end) => write!(f, "{}-{}", start + 1, end + 1)?,
+                None => write!(f, "{}-", start + 1)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use {crate::pdf::Comparison, pdfium_render::prelude::*};
+
+/// Points two sizes may differ by and still be the same size, exporters round them.
+const SIZE_TOLERANCE: f32 = 2.;
+
+/// Portrait width and height of the named paper sizes, in points.
+const PAPER_SIZES: [(&str, f32, f32); 7] = [
+    ("A3", 841.89, 1190.55),
+    ("A4", 595.28, 841.89),
+    ("A5", 419.53, 595.28),
+    ("B5", 498.9, 708.66),
+    ("Letter", 612., 792.),
+    ("Legal", 612., 1008.),
+    ("Tabloid", 792., 1224.),
+];
+
+/// Width and height of a page as displayed, in points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageSize {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl PageSize {
+    /// Size of `page`, rotated as displayed.
+    pub fn of(page: &PdfPage) -> Self {
+        PageSize {
+            width: page.width().value,
+            height: page.height().value,
+        }
+    }
+
+    /// Whether both sides are within the rounding of exporters of `other`.
+    pub fn same_size(&self, other: &PageSize) -> bool {
+        (self.width - other.width).abs() <= SIZE_TOLERANCE
+            && (self.height - other.height).abs() <= SIZE_TOLERANCE
+    }
+
+    /// Name of the paper size in either orientation, like `A4`, if it is a common one.
+    pub fn name(&self) -> Option<&'static str> {
+        let portrait = PageSize {
+            width: self.width.min(self.height),
+            height: self.width.max(self.height),
+        };
+        PAPER_SIZES
+            .iter()
+            .find(|(_, width, height)| {
+                portrait.same_size(&PageSize {
+                    width: *width,
+                    height: *height,
+                })
+            })
+            .map(|(name, ..)| *name)
+    }
+}
+
+/// The name of the size, followed by `landscape` for landscape pages, or width and height in
+/// millimeters for other sizes.
+impl std::fmt::Display for PageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let landscape = self.width > self.height;
+        match self.name() {
+            Some(name) if landscape => write!(f, "{} landscape", name),
+            Some(name) => write!(f, "{}", name),
+            None => {
+                let millimeters = |points: f32| points * 25.4 / 72.;
+                write!(
+                    f,
+                    "{:.0} x {:.0} mm",
+                    millimeters(self.width),
+                    millimeters(self.height)
+                )
+            }
+        }
+    }
+}
+
+/// Sizes of a page and the page of the previous version it was matched to, if they differ.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageSizeChange {
+    pub previous: PageSize,
+    pub current: PageSize,
+}
+
+impl std::fmt::Display for PageSizeChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "page size changed from {} to {}",
+            self.previous, self.current
+        )
+    }
+}
+
+/// Size change of every page of `current` whose matched page of `previous` in `comparisons`
+/// has a different size, `None` for the other pages.
+pub(crate) fn size_changes(
+    current: &PdfDocument,
+    previous: &PdfDocument,
+    comparisons: &[Comparison],
+) -> Vec<Option<PageSizeChange>> {
+    let size = |document: &PdfDocument, page: usize| {
+        let page = PdfPageIndex::try_from(page).ok()?;
+        document.pages().get(page).ok().map(|v| PageSize::of(&v))
+    };
+    comparisons
+        .iter()
+        .enumerate()
+        .map(|(page, comparison)| {
+            let matched_page = match comparison {
+                Comparison::Identical(matched_page) => *matched_page,
+                Comparison::Different(segments) => segments.matched_page?,
+            };
+            let change = PageSizeChange {
+                previous: size(previous, matched_page as usize)?,
+                current: size(current, page)?,
+            };
+            (!change.current.same_size(&change.previous)).then_some(change)
+        })
+        .collect()
+}
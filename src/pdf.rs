@@ -1,405 +1,6048 @@
 use {
-    image::{RgbImage, Rgba, RgbaImage},
+    crate::{
+        cache::RenderCache,
+        cancel::CancellationToken,
+        digests::{content_digest, hex_digest},
+        duplex::SheetMatch,
+        fields::FieldChange,
+        matching::{Candidate, PageCandidates},
+        memory::{RenderBudget, RenderMemory},
+        metadata::{AttachmentChange, MetadataChange},
+        pages::PageSelection,
+        paper::PageSizeChange,
+        redaction::Redaction,
+        timestamp::Timezone,
+        timings::{TimingStage, Timings},
+        version::PdfiumVersion,
+    },
+    image::{Pixel, Rgb, RgbImage, Rgba, RgbaImage},
     pdfium_render::prelude::*,
     rayon::prelude::*,
     std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
         error::Error,
-        path::Path,
+        ops::Range,
+        path::{Path, PathBuf},
         sync::{atomic::AtomicUsize, Arc},
+        time::{Instant, SystemTime},
     },
 };
 
-#[derive(Debug)]
+#[cfg(feature = "serde")]
+use crate::isolation::IsolationConfig;
+
+/// Result of comparing a single page of the current version against the previous version.
+///
+/// With the `serde` feature, variants are tagged by name: `{"Identical": 3}` or
+/// `{"Different": {"segments": [[0.1, 0.25]], "matched_page": 3}}`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Comparison {
-    Identical,
+    /// The page is identical to the contained page of the previous version.
+    Identical(PdfPageIndex),
     Different(DifferenceSegments),
 }
 
 impl Comparison {
+    /// Builds the comparison for a matched page pair. Both renders are required whenever
+    /// `sim` is `Similar` with a non-zero score and need to have the same dimensions.
     pub fn from_similarity(
         sim: &PageSimilarity,
         img_a: Option<RgbImage>,
         img_b: Option<RgbImage>,
-    ) -> Self {
-        match sim {
+        config: &ComparisonConfig,
+    ) -> Result<Self, ComparisonBuildError> {
+        Ok(match sim {
             PageSimilarity::Different => Comparison::Different(DifferenceSegments {
                 segments: vec![(0., 1.)],
+                matched_page: None,
+                previous_segments: Vec::new(),
+                redactions: Vec::new(),
             }),
-            PageSimilarity::Similar(_index, sim) => {
+            PageSimilarity::Similar(index, sim) => {
                 if *sim == 0 {
-                    Comparison::Identical
+                    Comparison::Identical(*index)
                 } else {
-                    let img_a = img_a.unwrap();
-                    let img_b = img_b.unwrap();
-                    let num_rows = img_a.rows().len();
-                    let mut difference_builder = DifferenceSegementsBuilder::build();
-                    img_a
-                        .rows()
-                        .zip(img_b.rows())
-                        .enumerate()
-                        .for_each(|(index, (r_a, r_b))| {
-                            let mut equal = true;
-                            for (p_a, p_b) in r_a.zip(r_b) {
-                                if p_a != p_b {
-                                    equal = false;
-                                    break;
-                                }
-                            }
-                            difference_builder.step(index as f64 / (num_rows - 1) as f64, !equal);
-                        });
-                    Comparison::Different(difference_builder.finish())
+                    let (img_a, img_b) = match (img_a, img_b) {
+                        (Some(img_a), Some(img_b)) => (img_a, img_b),
+                        (None, _) => {
+                            return Err(ComparisonBuildError::MissingRender(DocumentSide::A))
+                        }
+                        (_, None) => {
+                            return Err(ComparisonBuildError::MissingRender(DocumentSide::B))
+                        }
+                    };
+                    // Segments are fractions of the whole page, also if only a part of it is
+                    // compared.
+                    let (rows_a, rows_b) = (img_a.height() as usize, img_b.height() as usize);
+                    let columns_a = img_a.width();
+                    let (img_a, img_b) = match img_a.dimensions() == img_b.dimensions() {
+                        true => (img_a, img_b),
+                        false if config.compare_resized_pages => {
+                            let (width, height) = overlap(&img_a, &img_b);
+                            (
+                                image::imageops::crop_imm(&img_a, 0, 0, width, height).to_image(),
+                                image::imageops::crop_imm(&img_b, 0, 0, width, height).to_image(),
+                            )
+                        }
+                        // Rows are compared by position, which is meaningless for renders of
+                        // different sizes.
+                        false => {
+                            return Err(ComparisonBuildError::DimensionMismatch {
+                                a: img_a.dimensions(),
+                                b: img_b.dimensions(),
+                            })
+                        }
+                    };
+                    let pixels = img_a.width() as f64 * img_a.height() as f64;
+                    if *sim as f64 <= config.page_threshold * pixels {
+                        return Ok(Comparison::Identical(*index));
+                    }
+                    let redactions = match config.detect_redactions {
+                        true => crate::redaction::find_redactions(
+                            &img_a,
+                            &img_b,
+                            config.redaction_min_area,
+                            config.redaction_solid_ratio,
+                            config.background,
+                        ),
+                        false => Vec::new(),
+                    };
+                    let img_a = match redactions.is_empty() {
+                        true => img_a,
+                        // The antialiased edges of a box aren't black, so a margin around it
+                        // is taken from the previous version as well.
+                        false => crate::redaction::unredacted(
+                            &img_a,
+                            &img_b,
+                            &redactions
+                                .iter()
+                                .map(|v| v.grown(config.blur_radius + 1, img_a.dimensions()))
+                                .collect::<Vec<_>>(),
+                        ),
+                    };
+                    let mut difference_builder = DifferenceSegementsBuilder::build(*index, rows_a);
+                    crate::align::changed_rows(&img_a, &img_b, config.pixel_tolerance)
+                        .into_iter()
+                        .for_each(|changed| difference_builder.step(changed));
+                    let mut segments = difference_builder.finish();
+                    segments.redactions = redactions
+                        .iter()
+                        .map(|v| v.redaction((columns_a, rows_a as u32)))
+                        .collect();
+                    if config.locate_previous_differences {
+                        // Rows are aligned, so the changed rows of the previous version lie
+                        // elsewhere once content moved within the page.
+                        let mut previous_builder =
+                            DifferenceSegementsBuilder::build(*index, rows_b);
+                        crate::align::changed_rows(&img_b, &img_a, config.pixel_tolerance)
+                            .into_iter()
+                            .for_each(|changed| previous_builder.step(changed));
+                        segments.previous_segments = previous_builder.finish().segments;
+                    }
+                    Comparison::Different(segments)
+                }
+            }
+        })
+    }
+
+    /// The comparisons of the `previous_pages` pages of the previous version against the
+    /// current version, given the `comparisons` of the current version. Pages matched by an
+    /// identical page are identical to it, pages only matched by changed pages get their
+    /// [`DifferenceSegments::previous_segments`], or their segments if those weren't
+    /// located. Pages no page was matched to were removed and differ as a whole.
+    pub fn for_previous_version(
+        comparisons: &[Comparison],
+        previous_pages: usize,
+    ) -> Vec<Comparison> {
+        let mut previous: Vec<Option<Comparison>> = vec![None; previous_pages];
+        for (page, comparison) in comparisons.iter().enumerate() {
+            let Ok(page) = PdfPageIndex::try_from(page) else {
+                continue;
+            };
+            match comparison {
+                Comparison::Identical(matched_page) => {
+                    if let Some(slot) = previous.get_mut(*matched_page as usize) {
+                        if !matches!(slot, Some(Comparison::Identical(_))) {
+                            *slot = Some(Comparison::Identical(page));
+                        }
+                    }
+                }
+                Comparison::Different(seg) => {
+                    let Some(matched_page) = seg.matched_page else {
+                        continue;
+                    };
+                    if let Some(slot @ None) = previous.get_mut(matched_page as usize) {
+                        let segments = match seg.previous_segments.is_empty() {
+                            true => seg.segments.clone(),
+                            false => seg.previous_segments.clone(),
+                        };
+                        *slot = Some(Comparison::Different(DifferenceSegments {
+                            previous_segments: seg.segments.clone(),
+                            redactions: Vec::new(),
+                            segments,
+                            matched_page: Some(page),
+                        }));
+                    }
                 }
             }
         }
+        previous
+            .into_iter()
+            .map(|comparison| {
+                comparison.unwrap_or_else(|| {
+                    Comparison::Different(DifferenceSegments {
+                        segments: vec![(0., 1.)],
+                        matched_page: None,
+                        previous_segments: Vec::new(),
+                        redactions: Vec::new(),
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// Why [`Comparison::from_similarity`] couldn't build a comparison.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonBuildError {
+    /// The render of the page of `side` is required but wasn't provided.
+    MissingRender(DocumentSide),
+    /// The renders of the page pair have different dimensions, in pixels.
+    DimensionMismatch { a: (u32, u32), b: (u32, u32) },
+}
+
+impl Error for ComparisonBuildError {}
+
+impl std::fmt::Display for ComparisonBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingRender(side) => write!(f, "the page render of {} is missing", side),
+            Self::DimensionMismatch { a, b } => write!(
+                f,
+                "the renders have different dimensions: {}x{} and {}x{}",
+                a.0, a.1, b.0, b.1
+            ),
+        }
     }
 }
 
+/// Width and height of the area `a` and `b` both cover from the top left.
+fn overlap(a: &RgbImage, b: &RgbImage) -> (u32, u32) {
+    (a.width().min(b.width()), a.height().min(b.height()))
+}
+
+/// Whether no channel of the two pixels differs by more than `tolerance`.
+pub(crate) fn pixels_match(a: &Rgb<u8>, b: &Rgb<u8>, tolerance: u8) -> bool {
+    a.0.iter()
+        .zip(b.0.iter())
+        .all(|(a, b)| a.abs_diff(*b) <= tolerance)
+}
+
+/// Collects runs of changed rows into segments. Row `i` of `rows` spans `i / rows` to
+/// `(i + 1) / rows` of the page height, so a segment always covers its rows completely and
+/// even a single changed row gives a segment of non-zero length.
 struct DifferenceSegementsBuilder {
     segments: DifferenceSegments,
-    current_segment: Option<(f64, f64)>,
+    rows: usize,
+    /// Rows seen so far.
+    row: usize,
+    /// First row of the run of changed rows ending at the current row, if there is one.
+    current_segment: Option<usize>,
 }
 
 impl DifferenceSegementsBuilder {
-    pub fn build() -> Self {
+    pub fn build(matched_page: PdfPageIndex, rows: usize) -> Self {
         DifferenceSegementsBuilder {
             segments: DifferenceSegments {
                 segments: Vec::new(),
+                matched_page: Some(matched_page),
+                previous_segments: Vec::new(),
+                redactions: Vec::new(),
             },
+            rows: rows.max(1),
+            row: 0,
             current_segment: None,
         }
     }
 
-    pub fn step(&mut self, position: f64, hit: bool) {
-        match &self.current_segment {
-            Some(v) => {
-                if hit {
-                    self.current_segment = Some((v.0, position));
-                } else {
-                    self.segments.segments.push(*v);
-                    self.current_segment = None;
-                }
-            }
-            None => {
-                if hit {
-                    self.current_segment = Some((position, position))
-                }
+    /// Records whether the next row changed.
+    pub fn step(&mut self, hit: bool) {
+        match (self.current_segment, hit) {
+            (None, true) => self.current_segment = Some(self.row),
+            (Some(start), false) => {
+                self.push(start);
+                self.current_segment = None;
             }
+            _ => {}
         }
+        self.row += 1;
     }
 
     pub fn finish(mut self) -> DifferenceSegments {
-        match self.current_segment {
-            Some(v) => {
-                self.segments.segments.push(v);
-                self.segments
-            }
-            None => self.segments,
+        if let Some(start) = self.current_segment {
+            self.push(start);
         }
+        self.segments
     }
-}
 
-#[derive(Debug)]
-pub struct DifferenceSegments {
-    pub segments: Vec<(f64, f64)>,
+    /// Adds the segment from row `start` up to the current row, excluding it.
+    fn push(&mut self, start: usize) {
+        let fraction = |row: usize| (row as f64 / self.rows as f64).min(1.);
+        self.segments
+            .segments
+            .push((fraction(start), fraction(self.row)));
+    }
 }
 
-#[derive(Debug)]
-enum Similiarity {
-    Different,
-    Similar(usize),
-}
+#[cfg(test)]
+mod segment_tests {
+    use super::DifferenceSegementsBuilder;
 
-impl Similiarity {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (Similiarity::Different, Similiarity::Different) => std::cmp::Ordering::Equal,
-            (Similiarity::Different, Similiarity::Similar(_)) => std::cmp::Ordering::Greater,
-            (Similiarity::Similar(_), Similiarity::Different) => std::cmp::Ordering::Less,
-            (Similiarity::Similar(s), Similiarity::Similar(o)) => s.cmp(o),
+    fn segments(hits: &[bool]) -> Vec<(f64, f64)> {
+        let mut builder = DifferenceSegementsBuilder::build(0, hits.len());
+        for hit in hits {
+            builder.step(*hit);
         }
+        builder.finish().segments
     }
-}
 
-#[derive(Debug)]
-pub enum PageSimilarity {
-    Different,
-    Similar(u16, usize),
+    #[test]
+    fn segments_cover_exactly_the_changed_rows() {
+        for rows in 1..=12 {
+            for pattern in 0u32..1 << rows {
+                let hits = (0..rows)
+                    .map(|i| pattern & 1 << i != 0)
+                    .collect::<Vec<bool>>();
+                let segments = segments(&hits);
+                for (start, end) in &segments {
+                    assert!(0. <= *start && start < end && *end <= 1., "{:?}", segments);
+                }
+                for pair in segments.windows(2) {
+                    assert!(pair[0].1 < pair[1].0, "{:?} overlap or touch", segments);
+                }
+                for (row, hit) in hits.iter().enumerate() {
+                    let center = (row as f64 + 0.5) / rows as f64;
+                    let covered = segments.iter().any(|(s, e)| *s <= center && center <= *e);
+                    assert_eq!(covered, *hit, "row {} of {:?}: {:?}", row, hits, segments);
+                }
+                let rows = rows as f64;
+                for value in segments.iter().flat_map(|(s, e)| [s, e]) {
+                    assert!((value * rows - (value * rows).round()).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_single_changed_row_spans_the_page() {
+        assert_eq!(segments(&[true]), vec![(0., 1.)]);
+        assert_eq!(segments(&[false]), Vec::new());
+        assert_eq!(segments(&[]), Vec::new());
+    }
 }
 
-#[derive(Debug)]
-pub enum PDFComparisonError {
-    UnableToLoadPDF(PdfiumError),
-    UnableToRenderPDF(PdfiumError),
-    PdfiumError(PdfiumError),
+/// Vertical ranges of a page that differ from the previous version, as fractions of the page
+/// height measured from the top.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DifferenceSegments {
+    /// `[start, end]` pairs, serialized as two element arrays. Computed segments are sorted,
+    /// don't overlap and span whole rows of the render, so `start < end`. Segments from
+    /// other sources may have zero length, markers give them a minimum height.
+    pub segments: Vec<(f64, f64)>,
+    /// Page of the previous version the segments were computed against, if any page matched.
+    pub matched_page: Option<PdfPageIndex>,
+    /// The differing ranges of `matched_page`, located on that page. Only computed with
+    /// [`ComparisonConfig::locate_previous_differences`], empty otherwise.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub previous_segments: Vec<(f64, f64)>,
+    /// Redactions of the page, see [`ComparisonConfig::detect_redactions`]. `segments` only
+    /// cover the other differences, rows of a redaction are only part of them if something
+    /// else changed there too.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub redactions: Vec<Redaction>,
 }
 
-impl Error for PDFComparisonError {}
+impl DifferenceSegments {
+    /// Rows of an overlay of `geometry` the markers of the segments cover.
+    pub fn marker_rows(&self, geometry: &OverlayGeometry) -> Vec<Range<u32>> {
+        self.segments
+            .iter()
+            .map(|segment| geometry.marker_rows(*segment))
+            .collect()
+    }
 
-impl std::fmt::Display for PDFComparisonError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::UnableToLoadPDF(e) => write!(f, "Was unable to load pdf: {}", e),
-            Self::UnableToRenderPDF(e) => write!(f, "Was unable to render a pdf. Error: {}", e),
-            Self::PdfiumError(e) => write!(f, "Unkown or unexpected pdfium error: {}", e),
+    /// Rows of an overlay of `geometry` the markers of the redactions cover.
+    pub fn redaction_rows(&self, geometry: &OverlayGeometry) -> Vec<Range<u32>> {
+        self.redactions
+            .iter()
+            .map(|v| geometry.marker_rows((v.top, v.bottom)))
+            .collect()
+    }
+
+    /// Fraction of the page height covered by the segments and the redactions.
+    pub fn changed_fraction(&self) -> f64 {
+        let mut ranges = self
+            .segments
+            .iter()
+            .copied()
+            .chain(self.redactions.iter().map(|v| (v.top, v.bottom)))
+            .collect::<Vec<_>>();
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut covered = 0.;
+        let mut end = f64::NEG_INFINITY;
+        for (start, stop) in ranges {
+            covered += (stop - start.max(end)).max(0.);
+            end = end.max(stop);
         }
+        covered.min(1.)
     }
-}
 
-impl From<PdfiumError> for PDFComparisonError {
-    fn from(value: PdfiumError) -> Self {
-        Self::PdfiumError(value)
+    /// Fraction of the page height only the redactions cover, which
+    /// [`Self::changed_fraction`] includes.
+    pub fn redacted_fraction(&self) -> f64 {
+        let segments = self
+            .segments
+            .iter()
+            .map(|(start, end)| end - start)
+            .sum::<f64>()
+            .min(1.);
+        (self.changed_fraction() - segments).max(0.)
     }
 }
 
-pub fn get_pdfium(path: &Path) -> Result<Pdfium, PdfiumError> {
-    Ok(Pdfium::new(Pdfium::bind_to_library(
-        Pdfium::pdfium_platform_library_name_at_path(path),
-    )?))
+/// Per-page statistics of a comparison as they appear in reports.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageStatistics {
+    pub page: usize,
+    pub matched_page: Option<PdfPageIndex>,
+    pub changed_fraction: f64,
+    pub segments: Vec<(f64, f64)>,
+    /// See [`DocumentComparison::ink_coverage`], to tune
+    /// [`ComparisonConfig::blank_ink_threshold`] with.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ink_coverage: Option<f64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub blank: bool,
+    /// See [`DocumentComparison::size_changes`]. `changed_fraction` then only covers the
+    /// area both versions have.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub size_change: Option<PageSizeChange>,
+    /// See [`DifferenceSegments::redactions`], `changed_fraction` includes them.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub redactions: Vec<Redaction>,
 }
 
-pub struct PDFComparison {
-    pdfium: Arc<Pdfium>,
-    render_config: PdfRenderConfig,
-}
+impl PageStatistics {
+    pub fn from_comparisons(differences: &[Comparison]) -> Vec<PageStatistics> {
+        differences
+            .iter()
+            .enumerate()
+            .map(|(page, difference)| match difference {
+                Comparison::Identical(matched_page) => PageStatistics {
+                    page,
+                    matched_page: Some(*matched_page),
+                    changed_fraction: 0.,
+                    segments: Vec::new(),
+                    ink_coverage: None,
+                    blank: false,
+                    size_change: None,
+                    redactions: Vec::new(),
+                },
+                Comparison::Different(seg) => PageStatistics {
+                    page,
+                    matched_page: seg.matched_page,
+                    changed_fraction: seg.changed_fraction(),
+                    segments: seg.segments.clone(),
+                    ink_coverage: None,
+                    blank: false,
+                    size_change: None,
+                    redactions: seg.redactions.clone(),
+                },
+            })
+            .collect()
+    }
 
-impl PDFComparison {
-    pub fn new(pdfium: Arc<Pdfium>) -> Self {
-        let render_config = PdfRenderConfig::new()
-            .set_target_width(500)
-            .set_maximum_height(10000)
-            .rotate_if_landscape(PdfPageRenderRotation::Degrees90, true);
+    /// Whether the page has any segments or redactions.
+    pub fn changed(&self) -> bool {
+        !self.segments.is_empty() || !self.redactions.is_empty()
+    }
 
-        PDFComparison {
-            pdfium,
-            render_config,
+    /// Adds the `size_changes` of every page to `statistics`.
+    pub fn set_size_changes(
+        statistics: &mut [PageStatistics],
+        size_changes: &[Option<PageSizeChange>],
+    ) {
+        for (page, size_change) in statistics.iter_mut().zip(size_changes) {
+            page.size_change = *size_change;
         }
     }
 
-    pub fn compare_pdfs(&self, a: &Path, b: &Path) -> Result<Vec<Comparison>, PDFComparisonError> {
-        println!(
-            "Now comparing: {} and {}",
-            a.to_string_lossy(),
-            b.to_string_lossy()
-        );
+    /// How the page changed, e.g. `p. 2 - 3.5% changed` or `p. 2 - 3.5% changed, 1 redaction`.
+    pub fn caption(&self) -> String {
+        let redactions = match self.redactions.len() {
+            0 => String::new(),
+            1 => String::from(", 1 redaction"),
+            n => format!(", {} redactions", n),
+        };
+        match (self.matched_page, &self.size_change) {
+            (Some(_), Some(size_change)) => format!(
+                "p. {} - {}, content {:.1}% different{}",
+                self.page + 1,
+                size_change,
+                self.changed_fraction * 100.,
+                redactions
+            ),
+            (Some(_), None) => format!(
+                "p. {} - {:.1}% changed{}",
+                self.page + 1,
+                self.changed_fraction * 100.,
+                redactions
+            ),
+            (None, _) => format!("p. {} - inserted", self.page + 1),
+        }
+    }
 
-        let pdf_a = self.pdfium.load_pdf_from_file(a, None);
-        let pdf_b = self.pdfium.load_pdf_from_file(b, None);
-        let (pdf_a, pdf_b) = match (pdf_a, pdf_b) {
-            (Ok(pdf_a), Ok(pdf_b)) => (Arc::new(pdf_a), Arc::new(pdf_b)),
-            (Ok(pdf_a), Err(_e)) => {
-                return Ok((0..pdf_a.pages().len())
-                    .map(|_| {
-                        Comparison::Different(DifferenceSegments {
-                            segments: vec![(0., 1.)],
-                        })
-                    })
-                    .collect())
+    /// Adds the `ink_coverage` of every page to `statistics` and marks the `blank_pages`.
+    pub fn set_blankness(
+        statistics: &mut [PageStatistics],
+        ink_coverage: &[Option<f64>],
+        blank_pages: &[PdfPageIndex],
+    ) {
+        for (page, coverage) in statistics.iter_mut().zip(ink_coverage) {
+            page.ink_coverage = *coverage;
+        }
+        for page in blank_pages {
+            if let Some(v) = statistics.get_mut(*page as usize) {
+                v.blank = true;
             }
-            (Err(e), _) => return Err(PDFComparisonError::UnableToLoadPDF(e)),
-        };
+        }
+    }
+}
 
-        let page_similarities = self.find_min_similarity_for_pdf(pdf_a.clone(), pdf_b.clone())?;
+/// Serialized as `"modified"`, `"inserted"` or `"removed"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PageChangeKind {
+    Modified,
+    Inserted,
+    Removed,
+}
 
-        println!("Now rendering similiarities!");
+/// Describes a single changed page of a comparison.
+///
+/// These entries are what an outline of the diff would consist of. pdfium has no API for
+/// writing bookmarks, so they are only listed on the summary page, grouped like an outline.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PageChange {
+    /// Page in the current version, or in the previous version for removed pages.
+    pub page: usize,
+    pub kind: PageChangeKind,
+    pub changed_fraction: f64,
+    /// Page of the generated diff showing the change, if it is part of the output.
+    pub output_page: Option<usize>,
+    /// Whether the inserted or removed page is blank, see
+    /// [`ComparisonConfig::detect_blank_pages`]. Blank pages have no changed area.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub blank: bool,
+    /// Part of `changed_fraction` only redactions cover, see
+    /// [`DifferenceSegments::redacted_fraction`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub redacted_fraction: f64,
+}
 
-        page_similarities
+impl PageChange {
+    /// Lists the changed pages of `differences` in page order, followed by the pages of the
+    /// previous version that no current page was matched against. Inserted pages without
+    /// any segments are blank, removed pages are marked blank by [`Self::mark_blank`].
+    pub fn from_comparisons(
+        differences: &[Comparison],
+        previous_page_count: Option<PdfPageIndex>,
+    ) -> Vec<PageChange> {
+        let mut matched = vec![false; previous_page_count.unwrap_or(0) as usize];
+        let mut changes = Vec::new();
+        differences
             .iter()
             .enumerate()
-            .map(|(index, sim)| {
-                let img_a;
-                let img_b;
-                match sim {
-                    PageSimilarity::Different => {
-                        img_a = None;
-                        img_b = None;
-                    }
-                    PageSimilarity::Similar(page_b, _) => {
-                        println!("Redering similarity of pages {} and {}", index, page_b);
-                        img_a = Some(self.render_pdf_page(pdf_a.clone(), index as u16)?);
-                        img_b = Some(self.render_pdf_page(pdf_b.clone(), *page_b)?);
+            .for_each(|(page, difference)| match difference {
+                Comparison::Identical(matched_page) => {
+                    if let Some(v) = matched.get_mut(*matched_page as usize) {
+                        *v = true;
                     }
                 }
-                Ok::<Comparison, PDFComparisonError>(Comparison::from_similarity(sim, img_a, img_b))
-            })
-            .collect::<Result<Vec<Comparison>, PDFComparisonError>>()
+                Comparison::Different(seg) => {
+                    let kind = match seg.matched_page {
+                        Some(matched_page) => {
+                            if let Some(v) = matched.get_mut(matched_page as usize) {
+                                *v = true;
+                            }
+                            PageChangeKind::Modified
+                        }
+                        None => PageChangeKind::Inserted,
+                    };
+                    changes.push(PageChange {
+                        page,
+                        kind,
+                        changed_fraction: seg.changed_fraction(),
+                        output_page: None,
+                        blank: kind == PageChangeKind::Inserted && seg.segments.is_empty(),
+                        redacted_fraction: seg.redacted_fraction(),
+                    });
+                }
+            });
+        matched
+            .iter()
+            .enumerate()
+            .filter(|(_, matched)| !**matched)
+            .for_each(|(page, _)| {
+                changes.push(PageChange {
+                    page,
+                    kind: PageChangeKind::Removed,
+                    changed_fraction: 1.,
+                    output_page: None,
+                    blank: false,
+                    redacted_fraction: 0.,
+                })
+            });
+        changes
     }
 
-    fn find_min_similarity_for_pdf(
-        &self,
-        pdf_a: Arc<PdfDocument>,
-        pdf_b: Arc<PdfDocument>,
-    ) -> Result<Vec<PageSimilarity>, PDFComparisonError> {
-        (0..pdf_a.pages().len())
-            .map(|a| {
-                println!("Working on page {}", a);
-                self.find_min_similarity(&self.render_pdf_page(pdf_a.clone(), a)?, pdf_b.clone())
-            })
-            .collect()
+    /// Marks the removed pages among the `previous_blank_pages` of the previous version as
+    /// blank, see [`DocumentComparison::previous_blank_pages`].
+    pub fn mark_blank(changes: &mut [PageChange], previous_blank_pages: &[PdfPageIndex]) {
+        changes
+            .iter_mut()
+            .filter(|v| v.kind == PageChangeKind::Removed)
+            .filter(|v| previous_blank_pages.contains(&(v.page as PdfPageIndex)))
+            .for_each(|v| {
+                v.blank = true;
+                v.changed_fraction = 0.;
+            });
     }
 
-    fn find_min_similarity(
-        &self,
-        img_a: &RgbImage,
-        pdf_b: Arc<PdfDocument>,
-    ) -> Result<PageSimilarity, PDFComparisonError> {
-        let comparisons = (0..pdf_b.pages().len())
-            .map(|i| {
-                println!("Comparing to page: {}", i);
-                Ok::<(u16, Similiarity), PDFComparisonError>((
-                    i,
-                    PDFComparison::compare_images(img_a, &self.render_pdf_page(pdf_b.clone(), i)?),
-                ))
-            })
-            .collect::<Result<Vec<(u16, Similiarity)>, PDFComparisonError>>()?;
+    pub fn outline_title(&self) -> String {
+        match self.kind {
+            PageChangeKind::Modified => format!(
+                "p. {} - {:.1}% changed",
+                self.page + 1,
+                self.changed_fraction * 100.
+            ),
+            PageChangeKind::Inserted if self.blank => format!("p. {} - blank", self.page + 1),
+            PageChangeKind::Inserted => format!("p. {}", self.page + 1),
+            PageChangeKind::Removed if self.blank => {
+                format!("previous p. {} - blank", self.page + 1)
+            }
+            PageChangeKind::Removed => format!("previous p. {}", self.page + 1),
+        }
+    }
+}
 
-        Ok(match comparisons.into_iter().min_by(|a, b| a.1.cmp(&b.1)) {
-            Some((i, sim)) => match sim {
-                Similiarity::Similar(sim) => PageSimilarity::Similar(i, sim),
-                Similiarity::Different => PageSimilarity::Different,
-            },
-            None => PageSimilarity::Different,
-        })
+/// How much of a document changed as a whole, see [`ChangeScore::from_comparisons`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChangeScore {
+    /// Fraction of the pages that changed.
+    pub changed_pages: f64,
+    /// Fraction of the area of all pages together that changed, the mean of the changed
+    /// fractions of the pages.
+    pub changed_fraction: f64,
+    /// Part of `changed_fraction` only redactions cover, see
+    /// [`ComparisonConfig::detect_redactions`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub redacted_fraction: f64,
+}
+
+impl ChangeScore {
+    /// Score of a document that changed entirely, like a removed one.
+    pub const ENTIRE: ChangeScore = ChangeScore {
+        changed_pages: 1.,
+        changed_fraction: 1.,
+        redacted_fraction: 0.,
+    };
+
+    /// Scores `differences` against a previous version of `previous_page_count` pages,
+    /// `None` if there is none.
+    ///
+    /// Inserted pages, which match no previous page, and removed pages, previous pages no
+    /// page was matched against, count as fully changed. Removed pages aren't part of the
+    /// current version, so they are added to its page count: replacing the only page of a
+    /// document scores 1 both ways. Modified pages count with the fraction of their height
+    /// the segments cover. A new document scores 1, a document without pages 0.
+    pub fn from_comparisons(
+        differences: &[Comparison],
+        previous_page_count: Option<PdfPageIndex>,
+    ) -> Self {
+        let changes = PageChange::from_comparisons(differences, previous_page_count);
+        ChangeScore::from_changes(&changes, differences.len())
     }
 
-    fn compare_images(img_a: &RgbImage, img_b: &RgbImage) -> Similiarity {
-        let similarity = AtomicUsize::new(0);
-        if img_a.dimensions() != img_b.dimensions() {
-            return Similiarity::Different;
+    /// Like `from_comparisons` for the `changes` of a current version of `pages` pages.
+    /// Blank inserted and removed pages count as changed pages without any changed area.
+    pub fn from_changes(changes: &[PageChange], pages: usize) -> Self {
+        let removed = changes
+            .iter()
+            .filter(|v| v.kind == PageChangeKind::Removed)
+            .count();
+        let pages = pages + removed;
+        if pages == 0 {
+            return ChangeScore::default();
         }
-        (0..img_a.dimensions().0).into_par_iter().for_each(|x| {
-            (0..img_a.dimensions().1).into_par_iter().for_each(|y| {
-                if img_a.get_pixel(x, y) != img_b.get_pixel(x, y) {
-                    similarity.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                }
+        let changed_area = changes
+            .iter()
+            .map(|v| match v.kind {
+                PageChangeKind::Modified => v.changed_fraction,
+                PageChangeKind::Inserted | PageChangeKind::Removed if v.blank => 0.,
+                PageChangeKind::Inserted | PageChangeKind::Removed => 1.,
             })
-        });
-        Similiarity::Similar(similarity.into_inner())
+            .sum::<f64>();
+        let redacted_area = changes
+            .iter()
+            .filter(|v| v.kind == PageChangeKind::Modified)
+            .map(|v| v.redacted_fraction)
+            .sum::<f64>();
+        ChangeScore {
+            changed_pages: changes.len() as f64 / pages as f64,
+            changed_fraction: changed_area / pages as f64,
+            redacted_fraction: redacted_area / pages as f64,
+        }
     }
 
-    fn render_pdf_page(
-        &self,
-        pdf: Arc<PdfDocument>,
-        page: u16,
-    ) -> Result<RgbImage, PDFComparisonError> {
-        match pdf
-            .pages()
-            .get(page)?
-            .render_with_config(&self.render_config)
-        {
-            Ok(bitmap) => Ok(bitmap.as_image().into_rgb8()),
-            Err(e) => Err(PDFComparisonError::UnableToRenderPDF(e)),
-        }
+    /// `changed_fraction` without the area only redactions cover.
+    pub fn substantive_fraction(&self) -> f64 {
+        (self.changed_fraction - self.redacted_fraction).max(0.)
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Similiarity {
+    Different,
+    Similar(usize),
+}
+
+/// Best match of a page among the pages of the previous version and its number of differing
+/// pixels.
+///
+/// With the `serde` feature, serialized as `"Different"` or `{"Similar": [page, pixels]}`.
 #[derive(Debug)]
-pub enum PDFEditorError {
-    UnableToLoadPDF(PdfiumError),
-    UnableToSavePDF(PdfiumError),
-    UnableToModifyPDF(PdfiumError),
-    PdfiumError(PdfiumError),
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PageSimilarity {
+    Different,
+    Similar(PdfPageIndex, usize),
 }
 
-impl Error for PDFEditorError {}
+/// Identifies one of the two documents taking part in a comparison: `A` is the current
+/// version, `B` the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DocumentSide {
+    A,
+    B,
+}
 
-impl std::fmt::Display for PDFEditorError {
+impl std::fmt::Display for DocumentSide {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::UnableToLoadPDF(e) => write!(f, "Was unable to load a pdf. Error: {}", e),
-            Self::PdfiumError(e) => write!(f, "Unkown or unexpected pdfium error: {}", e),
-            Self::UnableToSavePDF(e) => write!(f, "Was unable to save the pdf: {}", e),
-            Self::UnableToModifyPDF(e) => write!(
-                f,
-                "Was unable to create pdf object or modify the pdf. Error: {}",
-                e
-            ),
+            Self::A => write!(f, "document A (current version)"),
+            Self::B => write!(f, "document B (previous version)"),
         }
     }
 }
 
-impl From<PdfiumError> for PDFEditorError {
-    fn from(value: PdfiumError) -> Self {
-        PDFEditorError::PdfiumError(value)
-    }
+/// A document taking part in a comparison or an edit. The path is only known for documents
+/// read from a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DocumentRef {
+    pub side: DocumentSide,
+    pub path: Option<PathBuf>,
 }
 
-pub struct PDFEditor {
-    pdfium: Arc<Pdfium>,
+impl DocumentRef {
+    pub fn new(side: DocumentSide) -> Self {
+        DocumentRef { side, path: None }
+    }
 }
 
-impl PDFEditor {
-    pub fn new(pdfium: Arc<Pdfium>) -> Self {
-        PDFEditor { pdfium }
+impl std::fmt::Display for DocumentRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "{}", path.to_string_lossy()),
+            None => write!(f, "{}", self.side),
+        }
     }
+}
 
-    pub fn mark_differences(
-        &self,
-        in_path: &Path,
-        differences: &[Comparison],
-        out_path: &Path,
-    ) -> Result<(), PDFEditorError> {
-        let mut pdf = match self.pdfium.load_pdf_from_file(in_path, None) {
-            Ok(v) => v,
-            Err(e) => return Err(PDFEditorError::UnableToLoadPDF(e)),
-        };
-
-        let mut page_shift: i16 = 0;
-
-        differences
-            .iter()
-            .enumerate()
-            .try_for_each(|(index, difference)| match difference {
-                Comparison::Identical => {
-                    let _ = pdf
-                        .pages_mut()
-                        .get((index as i16 + page_shift) as u16)?
-                        .delete();
-                    page_shift -= 1;
-                    Ok::<(), PDFEditorError>(())
-                }
-                Comparison::Different(seg) => {
-                    let mut p = pdf.pages_mut().get((index as i16 + page_shift) as u16)?;
-                    self.mark_page_differences(&pdf, &mut p, seg)?;
-                    Ok(())
-                }
-            })?;
+/// Step of a comparison an error occurred in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparisonOperation {
+    RenderingThumbnails,
+    MatchingPages,
+    LocatingDifferences,
+    DetectingBlankPages,
+}
 
-        if let Err(e) = pdf.save_to_file(out_path) {
-            return Err(PDFEditorError::UnableToSavePDF(e));
+impl std::fmt::Display for ComparisonOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RenderingThumbnails => write!(f, "rendering thumbnails"),
+            Self::MatchingPages => write!(f, "matching pages"),
+            Self::LocatingDifferences => write!(f, "locating the changed regions"),
+            Self::DetectingBlankPages => write!(f, "detecting blank pages"),
         }
-
-        Ok(())
     }
+}
 
-    fn mark_page_differences<'a>(
-        &self,
-        doc: &PdfDocument<'a>,
-        page: &mut PdfPage<'a>,
-        segments: &DifferenceSegments,
-    ) -> Result<(), PDFEditorError> {
-        let image_width = page.width().value as u32 * 5;
-        let image_height = page.height().value as u32 * 5;
+/// Errors of [`PDFComparison`]. Page numbers are zero based, but displayed one based.
+#[derive(Debug)]
+pub enum PDFComparisonError {
+    UnableToReadPDF {
+        document: DocumentRef,
+        source: std::io::Error,
+    },
+    UnableToLoadPDF {
+        document: DocumentRef,
+        source: PdfiumError,
+    },
+    UnableToRenderPage {
+        document: DocumentRef,
+        page: PdfPageIndex,
+        operation: ComparisonOperation,
+        source: PdfiumError,
+    },
+    PageIndexOutOfRange {
+        document: DocumentRef,
+        page: usize,
+    },
+    /// `document` has more pages than `max_pages`, it was loaded but not rendered.
+    TooManyPages {
+        document: DocumentRef,
+        pages: usize,
+        limit: usize,
+    },
+    /// `document` is encrypted and can't be opened without a password.
+    Encrypted {
+        document: DocumentRef,
+    },
+    /// The comparison of `document` was abandoned because it passed its deadline.
+    TimedOut {
+        document: DocumentRef,
+    },
+    /// The comparison of `document` was stopped through its [`CancellationToken`].
+    Cancelled {
+        document: DocumentRef,
+    },
+    /// The changed regions of `page` of `document` couldn't be located.
+    UnableToLocateDifferences {
+        document: DocumentRef,
+        page: usize,
+        source: ComparisonBuildError,
+    },
+    /// `document` was compared, but is still shared and can't be handed back as loaded.
+    DocumentStillShared {
+        document: DocumentRef,
+    },
+}
 
-        let mut buffer = RgbaImage::new(image_width, image_height);
+impl PDFComparisonError {
+    pub fn document(&self) -> &DocumentRef {
+        match self {
+            Self::UnableToReadPDF { document, .. }
+            | Self::UnableToLoadPDF { document, .. }
+            | Self::UnableToRenderPage { document, .. }
+            | Self::PageIndexOutOfRange { document, .. }
+            | Self::TooManyPages { document, .. }
+            | Self::Encrypted { document }
+            | Self::TimedOut { document }
+            | Self::Cancelled { document }
+            | Self::UnableToLocateDifferences { document, .. }
+            | Self::DocumentStillShared { document } => document,
+        }
+    }
 
-        segments.segments.iter().for_each(|(start, end)| {
-            (((image_height as f64 * *start).floor() as u32)
-                ..(image_height as f64 * *end).floor() as u32)
-                .for_each(|row| {
-                    (0..10.min(image_width)).for_each(|column| {
-                        buffer.put_pixel(column, row, Rgba([255, 0, 0, 255]));
-                    });
-                });
-        });
+    /// Error loading the `side` document, [`Self::Encrypted`] if it needs a password.
+    fn loading(side: DocumentSide, source: PdfiumError) -> Self {
+        match source {
+            PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError) => {
+                Self::Encrypted {
+                    document: DocumentRef::new(side),
+                }
+            }
+            source => Self::UnableToLoadPDF {
+                document: DocumentRef::new(side),
+                source,
+            },
+        }
+    }
 
-        let object = match PdfPageImageObject::new_with_height(doc, &buffer.into(), page.height()) {
-            Ok(v) => v,
-            Err(e) => return Err(PDFEditorError::UnableToModifyPDF(e)),
+    /// Records `path` as the location of the `side` document, if the error concerns it.
+    pub fn with_path(mut self, side: DocumentSide, path: &Path) -> Self {
+        let document = match &mut self {
+            Self::UnableToReadPDF { document, .. }
+            | Self::UnableToLoadPDF { document, .. }
+            | Self::UnableToRenderPage { document, .. }
+            | Self::PageIndexOutOfRange { document, .. }
+            | Self::TooManyPages { document, .. }
+            | Self::Encrypted { document }
+            | Self::TimedOut { document }
+            | Self::Cancelled { document }
+            | Self::UnableToLocateDifferences { document, .. }
+            | Self::DocumentStillShared { document } => document,
         };
+        if document.side == side && document.path.is_none() {
+            document.path = Some(path.to_path_buf());
+        }
+        self
+    }
+}
 
-        if let Err(e) = page.objects_mut().add_image_object(object) {
-            return Err(PDFEditorError::UnableToModifyPDF(e));
+impl Error for PDFComparisonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::UnableToReadPDF { source, .. } => Some(source),
+            Self::UnableToLoadPDF { source, .. } | Self::UnableToRenderPage { source, .. } => {
+                Some(source)
+            }
+            Self::UnableToLocateDifferences { source, .. } => Some(source),
+            Self::PageIndexOutOfRange { .. }
+            | Self::TooManyPages { .. }
+            | Self::Encrypted { .. }
+            | Self::TimedOut { .. }
+            | Self::Cancelled { .. }
+            | Self::DocumentStillShared { .. } => None,
         }
-        Ok(())
+    }
+}
+
+impl std::fmt::Display for PDFComparisonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnableToReadPDF { document, source } => {
+                write!(f, "failed to read {}: {}", document, source)
+            }
+            Self::UnableToLoadPDF { document, source } => {
+                write!(f, "failed to load {}: {}", document, source)
+            }
+            Self::UnableToRenderPage {
+                document,
+                page,
+                operation,
+                source,
+            } => write!(
+                f,
+                "failed to render page {} of {} while {}: {}",
+                *page as usize + 1,
+                document,
+                operation,
+                source
+            ),
+            Self::PageIndexOutOfRange { document, page } => write!(
+                f,
+                "page {} of {} exceeds the number of pages supported by pdfium",
+                page + 1,
+                document
+            ),
+            Self::TooManyPages {
+                document,
+                pages,
+                limit,
+            } => write!(
+                f,
+                "{} has {} pages, more than the limit of {}",
+                document, pages, limit
+            ),
+            Self::Encrypted { document } => {
+                write!(f, "{} is encrypted and requires a password", document)
+            }
+            Self::TimedOut { document } => {
+                write!(f, "comparing {} took longer than allowed", document)
+            }
+            Self::Cancelled { document } => write!(f, "comparing {} was cancelled", document),
+            Self::UnableToLocateDifferences {
+                document,
+                page,
+                source,
+            } => write!(
+                f,
+                "failed to locate the changed regions of page {} of {}: {}",
+                page + 1,
+                document,
+                source
+            ),
+            Self::DocumentStillShared { document } => {
+                write!(f, "{} is still shared after comparing it", document)
+            }
+        }
+    }
+}
+
+/// Downscaled renders of the current document, collected while comparing.
+pub struct Thumbnails {
+    /// One thumbnail per page of the current document. Pages identified as identical without
+    /// rendering them get an empty image.
+    pub pages: Vec<RgbImage>,
+    /// Number of pages of the previous version, if it could be loaded.
+    pub previous_page_count: Option<PdfPageIndex>,
+}
+
+impl Thumbnails {
+    fn placeholder() -> RgbImage {
+        RgbImage::new(0, 0)
+    }
+
+    fn downscale(img: &RgbImage, width: u32) -> RgbImage {
+        let width = width.clamp(1, img.width().max(1));
+        let height = (img.height() as u64 * width as u64 / img.width().max(1) as u64).max(1);
+        image::imageops::thumbnail(img, width, height as u32)
+    }
+}
+
+/// Everything found comparing the current version of a document against the previous version.
+pub struct DocumentComparison {
+    /// One [`Comparison`] per page of the current version.
+    pub pages: Vec<Comparison>,
+    /// Empty unless thumbnails were requested.
+    pub thumbnails: Thumbnails,
+    /// Changes the pages don't show. Empty if there is no previous version.
+    pub changes: DocumentChanges,
+    /// Whether there was no previous version, in which case every page counts as inserted.
+    pub new_file: bool,
+    /// Renders of the changed pages, empty unless [`ComparisonConfig::keep_renders`] is set.
+    pub renders: Vec<PageRenders>,
+    pub memory: RenderMemory,
+    /// Number of pages rendered, not counting renders taken from the render cache.
+    pub pages_rendered: usize,
+    /// Blank pages of the current version, only detected with
+    /// [`ComparisonConfig::detect_blank_pages`] or when pages are matched in sheets.
+    pub blank_pages: Vec<PdfPageIndex>,
+    /// Blank pages of the previous version, see `blank_pages`.
+    pub previous_blank_pages: Vec<PdfPageIndex>,
+    /// Fraction of every page of the current version covered by ink, see
+    /// [`PDFComparison::is_blank`]. `None` for pages that weren't checked, like the ones
+    /// the structural pre-check finds identical, or all if blank pages aren't detected.
+    pub ink_coverage: Vec<Option<f64>>,
+    /// For every page of the current version, the sizes of it and its matched page if they
+    /// differ. Empty if there is no previous version.
+    pub size_changes: Vec<Option<PageSizeChange>>,
+}
+
+/// A document as loaded by [`PDFComparison::compare_pdfs_loaded`], so [`PDFEditor`] can
+/// mark it without reading and parsing the file again.
+pub struct LoadedDocument<'a> {
+    pub document: PdfDocument<'a>,
+    /// Path the document was read from, `None` if it was loaded from memory.
+    pub path: Option<PathBuf>,
+    /// SHA-256 digest of the file content.
+    pub sha256: [u8; 32],
+    pub modified: Option<SystemTime>,
+}
+
+/// The current and, if there is one, the previous version loaded by
+/// [`PDFComparison::load_pdfs`].
+pub type LoadedDocuments<'a> = (LoadedDocument<'a>, Option<LoadedDocument<'a>>);
+
+/// A [`DocumentComparison`] along with the documents it was computed from.
+pub struct LoadedComparison<'a> {
+    pub comparison: DocumentComparison,
+    pub current: LoadedDocument<'a>,
+    /// `None` if there was no previous version or it was treated as missing, e.g. because
+    /// it is encrypted.
+    pub previous: Option<LoadedDocument<'a>>,
+}
+
+/// A changed page of the current version and the page of the previous version it was
+/// matched to, rendered at the comparison resolution.
+pub struct PageRenders {
+    pub page: usize,
+    pub matched_page: PdfPageIndex,
+    pub current: RgbImage,
+    pub previous: RgbImage,
+}
+
+/// Changes of a document that aren't part of its rendered pages, found also if every page
+/// looks identical.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DocumentChanges {
+    /// Form fields with a different value.
+    pub fields: Vec<FieldChange>,
+    /// Entries of the information dictionary, only compared with `compare_metadata`.
+    pub metadata: Vec<MetadataChange>,
+    /// Embedded files, only compared with `compare_metadata`.
+    pub attachments: Vec<AttachmentChange>,
+}
+
+impl DocumentChanges {
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.metadata.is_empty() && self.attachments.is_empty()
+    }
+
+    /// Every change, one line each.
+    pub fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        let fields = self.fields.iter().map(|v| format!("field {}", v));
+        let metadata = self.metadata.iter().map(|v| format!("metadata {}", v));
+        let attachments = self.attachments.iter().map(|v| v.to_string());
+        fields.chain(metadata).chain(attachments)
+    }
+}
+
+/// Binds to the pdfium library in the directory `path`.
+pub fn get_pdfium(path: &Path) -> Result<Pdfium, PdfiumError> {
+    Ok(Pdfium::new(Pdfium::bind_to_library(
+        Pdfium::pdfium_platform_library_name_at_path(path),
+    )?))
+}
+
+/// How pages are rotated when rendered for comparing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PageRotation {
+    /// Renders pages as displayed.
+    Off,
+    /// Rotates every landscape page by 90 degrees, in both documents.
+    #[default]
+    Landscape,
+    /// Undoes the rotation flag of every page, so a page flagged as rotated compares equal to
+    /// the same content without the flag.
+    Normalize,
+}
+
+/// How pdfium draws text, images and paths. Builds of pdfium differ in their hinting and
+/// anti-aliasing, pinning these keeps the renders of different machines closer together.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RenderFlags {
+    /// Subpixel anti-aliasing of text, optimized for LCD screens.
+    pub lcd_text: bool,
+    pub text_antialiasing: bool,
+    pub image_antialiasing: bool,
+    pub path_antialiasing: bool,
+    /// Renders in shades of gray instead of colors.
+    pub grayscale: bool,
+}
+
+impl Default for RenderFlags {
+    /// The defaults of pdfium-render.
+    fn default() -> Self {
+        RenderFlags {
+            lcd_text: false,
+            text_antialiasing: true,
+            image_antialiasing: true,
+            path_antialiasing: true,
+            grayscale: false,
+        }
+    }
+}
+
+impl RenderFlags {
+    fn apply(&self, render_config: PdfRenderConfig) -> PdfRenderConfig {
+        render_config
+            .use_lcd_text_rendering(self.lcd_text)
+            .set_text_smoothing(self.text_antialiasing)
+            .set_image_smoothing(self.image_antialiasing)
+            .set_path_smoothing(self.path_antialiasing)
+            .use_grayscale_rendering(self.grayscale)
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    match enabled {
+        true => "on",
+        false => "off",
+    }
+}
+
+impl std::fmt::Display for RenderFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "lcd text {}, text anti-aliasing {}, image anti-aliasing {}, path anti-aliasing {}, \
+             grayscale {}",
+            on_off(self.lcd_text),
+            on_off(self.text_antialiasing),
+            on_off(self.image_antialiasing),
+            on_off(self.path_antialiasing),
+            on_off(self.grayscale)
+        )
+    }
+}
+
+/// Settings of a [`PDFComparison`], created through [`PDFComparisonBuilder`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ComparisonConfig {
+    /// Width pages are rendered at for comparing, in pixels.
+    pub target_width: i32,
+    /// Maximum height pages are rendered at for comparing, in pixels.
+    pub maximum_height: i32,
+    /// Maximum difference of a color channel for two pixels to still count as equal.
+    pub pixel_tolerance: u8,
+    /// Fraction of differing pixels up to which a page still counts as identical.
+    pub page_threshold: f64,
+    /// Maximum size of the page renders kept in memory while comparing two documents, in
+    /// bytes. Pages evicted from the cache are rendered again when needed.
+    pub render_cache_bytes: usize,
+    /// Approximate ceiling on the bytes of all renders of a comparison alive at the same
+    /// time, including the render cache and thumbnails. The cache shrinks to stay below it
+    /// and documents whose pages wouldn't fit are rendered at a lower width, which is
+    /// reported in [`DocumentComparison::memory`]. Never fails a comparison.
+    pub render_memory_limit: Option<usize>,
+    /// Width of the thumbnails pages are matched at before the best candidate is compared
+    /// at full resolution, in pixels. With 0 every pair of pages is compared at full
+    /// resolution.
+    pub prepass_width: u32,
+    /// Candidates whose fractions of differing thumbnail pixels are at most this far apart
+    /// are ambiguous, in which case the page is matched at full resolution.
+    pub prepass_epsilon: f64,
+    /// Pages whose objects are identical to a page of the previous version count as
+    /// identical without being rendered. Pages that look the same but are built differently
+    /// are still compared visually, as are pages with transparency, masks or clip paths that
+    /// can't be described. Off by default: describing the objects of a page, which includes
+    /// hashing its fonts and images, only pays off when most pages are unchanged.
+    pub structural_precheck: bool,
+    /// Pages of the current version that are compared. All others count as identical and
+    /// aren't rendered.
+    pub pages: Option<PageSelection>,
+    /// Documents with more pages than this aren't compared, see
+    /// [`PDFComparisonError::TooManyPages`].
+    pub max_pages: Option<usize>,
+    /// Only match a page against the pages of the previous version at most this many pages
+    /// before or after its own index. Pages without a candidate within `page_threshold` in
+    /// the window are matched against all pages. Keeps matching large documents whose pages
+    /// rarely move far linear in the page count. Counts sheets if pages are matched in
+    /// sheets, without falling back to all of them.
+    pub match_window: Option<usize>,
+    /// Matches the pages in sheets of this many pages instead of one by one, e.g. 2 for the
+    /// fronts and backs of duplex scans. Sheets are aligned in order, where either version
+    /// may lack blank pages at the end of a sheet without shifting the later sheets. A
+    /// blank page the other version lacks counts as identical, though a blank page lacking
+    /// from the current version is still reported as removed. Blank pages match any blank
+    /// page. Every page of both versions is rendered, including those the structural
+    /// pre-check finds identical.
+    pub sheet_pages: Option<usize>,
+    /// Detects the blank pages of both versions, rendering every page of the previous
+    /// version. Blank pages only match blank pages and count as identical to them
+    /// regardless of scanner noise. A blank page without a blank counterpart is an inserted
+    /// blank page, which differs without any segments, and blank pages of the previous
+    /// version no page was matched against are removed blank pages, see
+    /// [`PageChange::blank`]. Always on when pages are matched in sheets.
+    pub detect_blank_pages: bool,
+    /// Fraction of a page that may be covered by ink, any pixel that differs from
+    /// [`Self::background`] by more than scanner noise, for it to count as blank, see
+    /// [`PDFComparison::is_blank`].
+    pub blank_ink_threshold: f64,
+    /// Compares pages whose renders differ in size, e.g. because the paper size changed
+    /// between A4 and Letter, over the area both renders cover from the top left instead of
+    /// counting them as different. Renders of pages with the same aspect ratio, like A4 and
+    /// A3, have the same size regardless. The sizes of matched pages that differ are
+    /// reported in [`DocumentComparison::size_changes`] either way.
+    pub compare_resized_pages: bool,
+    /// Color transparent areas of pages are rendered on, so both versions are composited
+    /// over the same background regardless of the pdfium defaults.
+    pub background: [u8; 3],
+    pub rotation: PageRotation,
+    /// Whether the contents of form fields are rendered.
+    pub render_form_data: bool,
+    /// Whether annotations like comments and stamps are rendered.
+    pub render_annotations: bool,
+    /// Anti-aliasing and color settings of pdfium, pinned so renders of different machines
+    /// match.
+    pub render_flags: RenderFlags,
+    /// Whether the information dictionary and the embedded files are compared. Their
+    /// changes are reported in [`DocumentChanges`] and never mark any page.
+    pub compare_metadata: bool,
+    /// Whether the renders of changed pages matched to a page of the previous version are
+    /// kept in [`DocumentComparison::renders`].
+    pub keep_renders: bool,
+    /// Whether the differences are also located on the matched pages of the previous
+    /// version, see [`DifferenceSegments::previous_segments`]. Aligns the rows of every
+    /// changed page a second time.
+    pub locate_previous_differences: bool,
+    /// Detects redactions, rectangles blacked out in the current version over content of
+    /// the previous version, on changed pages. They are reported in
+    /// [`DifferenceSegments::redactions`] apart from the other differences, which are
+    /// located as if the previous content were still there.
+    pub detect_redactions: bool,
+    /// Fraction of a page a redaction has to cover at least.
+    pub redaction_min_area: f64,
+    /// Fraction of its bounding box a redaction has to fill with black at least.
+    pub redaction_solid_ratio: f64,
+    /// Number of threads comparing the rendered pixels, `None` uses all cores. Either this
+    /// or [`Self::nice`] gives the comparison a thread pool of its own, otherwise it runs on
+    /// the global rayon pool.
+    pub threads: Option<usize>,
+    /// Radius of the box blur applied to every page right after rendering it, 0 disables
+    /// it. Text rasterized a fraction of a pixel apart then only differs slightly, which
+    /// [`Self::pixel_tolerance`] can absorb, while changes larger than the radius still
+    /// register. Pages are matched, aligned and kept in [`DocumentComparison::renders`]
+    /// blurred, as are the thumbnails.
+    pub blur_radius: u32,
+    /// Niceness the comparison threads run at, from -20 to 19. Linux only, it is ignored with
+    /// a warning elsewhere.
+    pub nice: Option<i32>,
+}
+
+impl Default for ComparisonConfig {
+    fn default() -> Self {
+        ComparisonConfig {
+            target_width: 500,
+            maximum_height: 10000,
+            pixel_tolerance: 0,
+            page_threshold: 0.,
+            render_cache_bytes: 256 * 1024 * 1024,
+            render_memory_limit: None,
+            prepass_width: 64,
+            prepass_epsilon: 0.01,
+            structural_precheck: false,
+            pages: None,
+            max_pages: None,
+            match_window: None,
+            sheet_pages: None,
+            detect_blank_pages: false,
+            blank_ink_threshold: 0.0005,
+            compare_resized_pages: false,
+            background: [255, 255, 255],
+            rotation: PageRotation::Landscape,
+            render_form_data: true,
+            render_annotations: true,
+            render_flags: RenderFlags::default(),
+            compare_metadata: true,
+            keep_renders: false,
+            locate_previous_differences: false,
+            detect_redactions: false,
+            redaction_min_area: 0.001,
+            redaction_solid_ratio: 0.97,
+            blur_radius: 0,
+            threads: None,
+            nice: None,
+        }
+    }
+}
+
+impl ComparisonConfig {
+    /// Hex encoded digest of the settings the outcome of a comparison depends on and the
+    /// version of this crate. Settings that only change how fast it runs or what is kept of
+    /// it, the render cache, the threads and their niceness and the renders, are left out.
+    pub fn settings_digest(&self) -> String {
+        // Destructured, so every new setting has to be sorted into one of the groups.
+        let ComparisonConfig {
+            target_width,
+            maximum_height,
+            pixel_tolerance,
+            page_threshold,
+            render_memory_limit,
+            prepass_width,
+            prepass_epsilon,
+            structural_precheck,
+            pages,
+            max_pages,
+            match_window,
+            sheet_pages,
+            detect_blank_pages,
+            blank_ink_threshold,
+            compare_resized_pages,
+            background,
+            rotation,
+            render_form_data,
+            render_annotations,
+            render_flags,
+            compare_metadata,
+            locate_previous_differences,
+            detect_redactions,
+            redaction_min_area,
+            redaction_solid_ratio,
+            blur_radius,
+            render_cache_bytes: _,
+            keep_renders: _,
+            threads: _,
+            nice: _,
+        } = self;
+        let outcome: [&dyn std::fmt::Debug; 26] = [
+            target_width,
+            maximum_height,
+            pixel_tolerance,
+            page_threshold,
+            render_memory_limit,
+            prepass_width,
+            prepass_epsilon,
+            structural_precheck,
+            pages,
+            max_pages,
+            match_window,
+            sheet_pages,
+            detect_blank_pages,
+            blank_ink_threshold,
+            compare_resized_pages,
+            background,
+            rotation,
+            render_form_data,
+            render_annotations,
+            render_flags,
+            compare_metadata,
+            locate_previous_differences,
+            detect_redactions,
+            redaction_min_area,
+            redaction_solid_ratio,
+            blur_radius,
+        ];
+        let settings = format!("{} {:?}", env!("CARGO_PKG_VERSION"), outcome);
+        hex_digest(&content_digest(settings.as_bytes()))
+    }
+}
+
+/// Invalid settings passed to [`PDFComparisonBuilder`].
+#[derive(Debug)]
+pub enum ComparisonConfigError {
+    InvalidTargetWidth(i32),
+    InvalidMaximumHeight(i32),
+    InvalidPageThreshold(f64),
+    InvalidPrepassEpsilon(f64),
+    InvalidSheetPages(usize),
+    InvalidBlankInkThreshold(f64),
+    InvalidRedactionMinArea(f64),
+    InvalidRedactionSolidRatio(f64),
+    InvalidThreads(usize),
+    InvalidNice(i32),
+    InvalidCompareThreads(usize),
+    ThreadPool(rayon::ThreadPoolBuildError),
+}
+
+impl Error for ComparisonConfigError {}
+
+impl std::fmt::Display for ComparisonConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidTargetWidth(v) => {
+                write!(f, "The target width must be positive, got {}", v)
+            }
+            Self::InvalidMaximumHeight(v) => {
+                write!(f, "The maximum height must be positive, got {}", v)
+            }
+            Self::InvalidPageThreshold(v) => {
+                write!(f, "The page threshold must be between 0 and 1, got {}", v)
+            }
+            Self::InvalidPrepassEpsilon(v) => {
+                write!(f, "The prepass epsilon must be between 0 and 1, got {}", v)
+            }
+            Self::InvalidSheetPages(v) => {
+                write!(f, "A sheet must have at least one page, got {}", v)
+            }
+            Self::InvalidBlankInkThreshold(v) => {
+                write!(
+                    f,
+                    "The blank ink threshold must be between 0 and 1, got {}",
+                    v
+                )
+            }
+            Self::InvalidRedactionMinArea(v) => {
+                write!(
+                    f,
+                    "The minimum redaction area must be between 0 and 1, got {}",
+                    v
+                )
+            }
+            Self::InvalidRedactionSolidRatio(v) => {
+                write!(
+                    f,
+                    "The redaction solid ratio must be between 0 and 1, got {}",
+                    v
+                )
+            }
+            Self::InvalidThreads(v) => {
+                write!(f, "The number of threads must be positive, got {}", v)
+            }
+            Self::InvalidNice(v) => {
+                write!(f, "The niceness must be between -20 and 19, got {}", v)
+            }
+            Self::InvalidCompareThreads(v) => {
+                write!(
+                    f,
+                    "The number of compare threads must be positive, got {}",
+                    v
+                )
+            }
+            Self::ThreadPool(e) => write!(f, "Unable to create the thread pool: {}", e),
+        }
+    }
+}
+
+/// Builds a [`PDFComparison`], see [`PDFComparison::builder`].
+pub struct PDFComparisonBuilder {
+    pdfium: Arc<Pdfium>,
+    config: ComparisonConfig,
+    #[cfg(feature = "serde")]
+    workers: Option<PageWorkers>,
+}
+
+impl PDFComparisonBuilder {
+    /// Replaces every setting with those of `config`, e.g. of another comparison's
+    /// [`PDFComparison::config`]. Validated by [`Self::build`] like the others.
+    pub fn config(mut self, config: ComparisonConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn target_width(mut self, target_width: i32) -> Self {
+        self.config.target_width = target_width;
+        self
+    }
+
+    pub fn maximum_height(mut self, maximum_height: i32) -> Self {
+        self.config.maximum_height = maximum_height;
+        self
+    }
+
+    pub fn pixel_tolerance(mut self, pixel_tolerance: u8) -> Self {
+        self.config.pixel_tolerance = pixel_tolerance;
+        self
+    }
+
+    pub fn page_threshold(mut self, page_threshold: f64) -> Self {
+        self.config.page_threshold = page_threshold;
+        self
+    }
+
+    pub fn render_cache_bytes(mut self, render_cache_bytes: usize) -> Self {
+        self.config.render_cache_bytes = render_cache_bytes;
+        self
+    }
+
+    pub fn render_memory_limit(mut self, render_memory_limit: usize) -> Self {
+        self.config.render_memory_limit = Some(render_memory_limit);
+        self
+    }
+
+    pub fn prepass_width(mut self, prepass_width: u32) -> Self {
+        self.config.prepass_width = prepass_width;
+        self
+    }
+
+    pub fn prepass_epsilon(mut self, prepass_epsilon: f64) -> Self {
+        self.config.prepass_epsilon = prepass_epsilon;
+        self
+    }
+
+    pub fn structural_precheck(mut self, structural_precheck: bool) -> Self {
+        self.config.structural_precheck = structural_precheck;
+        self
+    }
+
+    pub fn pages(mut self, pages: PageSelection) -> Self {
+        self.config.pages = Some(pages);
+        self
+    }
+
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.config.max_pages = Some(max_pages);
+        self
+    }
+
+    pub fn match_window(mut self, match_window: usize) -> Self {
+        self.config.match_window = Some(match_window);
+        self
+    }
+
+    pub fn sheet_pages(mut self, sheet_pages: usize) -> Self {
+        self.config.sheet_pages = Some(sheet_pages);
+        self
+    }
+
+    pub fn compare_resized_pages(mut self, compare_resized_pages: bool) -> Self {
+        self.config.compare_resized_pages = compare_resized_pages;
+        self
+    }
+
+    pub fn detect_blank_pages(mut self, detect_blank_pages: bool) -> Self {
+        self.config.detect_blank_pages = detect_blank_pages;
+        self
+    }
+
+    pub fn blank_ink_threshold(mut self, blank_ink_threshold: f64) -> Self {
+        self.config.blank_ink_threshold = blank_ink_threshold;
+        self
+    }
+
+    pub fn background(mut self, background: [u8; 3]) -> Self {
+        self.config.background = background;
+        self
+    }
+
+    pub fn rotation(mut self, rotation: PageRotation) -> Self {
+        self.config.rotation = rotation;
+        self
+    }
+
+    pub fn render_form_data(mut self, render_form_data: bool) -> Self {
+        self.config.render_form_data = render_form_data;
+        self
+    }
+
+    pub fn render_annotations(mut self, render_annotations: bool) -> Self {
+        self.config.render_annotations = render_annotations;
+        self
+    }
+
+    pub fn render_flags(mut self, render_flags: RenderFlags) -> Self {
+        self.config.render_flags = render_flags;
+        self
+    }
+
+    pub fn compare_metadata(mut self, compare_metadata: bool) -> Self {
+        self.config.compare_metadata = compare_metadata;
+        self
+    }
+
+    pub fn keep_renders(mut self, keep_renders: bool) -> Self {
+        self.config.keep_renders = keep_renders;
+        self
+    }
+
+    pub fn locate_previous_differences(mut self, locate_previous_differences: bool) -> Self {
+        self.config.locate_previous_differences = locate_previous_differences;
+        self
+    }
+
+    pub fn detect_redactions(mut self, detect_redactions: bool) -> Self {
+        self.config.detect_redactions = detect_redactions;
+        self
+    }
+
+    pub fn redaction_min_area(mut self, redaction_min_area: f64) -> Self {
+        self.config.redaction_min_area = redaction_min_area;
+        self
+    }
+
+    pub fn redaction_solid_ratio(mut self, redaction_solid_ratio: f64) -> Self {
+        self.config.redaction_solid_ratio = redaction_solid_ratio;
+        self
+    }
+
+    pub fn blur_radius(mut self, blur_radius: u32) -> Self {
+        self.config.blur_radius = blur_radius;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = Some(threads);
+        self
+    }
+
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.config.nice = Some(nice);
+        self
+    }
+
+    /// Matches the pages of every document in `threads` child processes started as
+    /// `isolation` says, each taking an equal share of the pages of the current version. A
+    /// child has a pdfium binding of its own, so the children render at the same time. The
+    /// results are the same as when matching in this process, where the differences of the
+    /// matched pages are still located.
+    ///
+    /// Every child loads both versions and renders the pages of the previous version it
+    /// compares against, with a prepass all of them, so the memory needed grows with
+    /// `threads`. Documents compared from memory, with thumbnails or in sheets are matched in
+    /// this process, as are documents one of whose children fails. 1, the default, matches
+    /// every document in this process.
+    #[cfg(feature = "serde")]
+    pub fn compare_threads(mut self, threads: usize, isolation: IsolationConfig) -> Self {
+        self.workers = Some(PageWorkers { threads, isolation });
+        self
+    }
+
+    /// Checks every setting on its own. No combination of valid settings is invalid: the
+    /// [`match_window`](ComparisonConfig::match_window) and the prepass work on sheets too,
+    /// settings for a step that is off, like the redaction settings without
+    /// [`detect_redactions`](Self::detect_redactions) or the prepass epsilon with a prepass
+    /// width of 0, are unused, and [`sheet_pages`](Self::sheet_pages) overrides the
+    /// settings sheets can't do without, like detecting blank pages, as documented. A
+    /// default can't be told apart from a value set on purpose, so an unused setting isn't
+    /// an error either.
+    pub fn build(self) -> Result<PDFComparison, ComparisonConfigError> {
+        let config = self.config;
+        if config.target_width <= 0 {
+            return Err(ComparisonConfigError::InvalidTargetWidth(
+                config.target_width,
+            ));
+        }
+        if config.maximum_height <= 0 {
+            return Err(ComparisonConfigError::InvalidMaximumHeight(
+                config.maximum_height,
+            ));
+        }
+        if !(0. ..=1.).contains(&config.page_threshold) {
+            return Err(ComparisonConfigError::InvalidPageThreshold(
+                config.page_threshold,
+            ));
+        }
+        if !(0. ..=1.).contains(&config.prepass_epsilon) {
+            return Err(ComparisonConfigError::InvalidPrepassEpsilon(
+                config.prepass_epsilon,
+            ));
+        }
+        if let Some(sheet_pages @ 0) = config.sheet_pages {
+            return Err(ComparisonConfigError::InvalidSheetPages(sheet_pages));
+        }
+        if !(0. ..=1.).contains(&config.blank_ink_threshold) {
+            return Err(ComparisonConfigError::InvalidBlankInkThreshold(
+                config.blank_ink_threshold,
+            ));
+        }
+        if !(0. ..=1.).contains(&config.redaction_min_area) {
+            return Err(ComparisonConfigError::InvalidRedactionMinArea(
+                config.redaction_min_area,
+            ));
+        }
+        if !(0. ..=1.).contains(&config.redaction_solid_ratio) {
+            return Err(ComparisonConfigError::InvalidRedactionSolidRatio(
+                config.redaction_solid_ratio,
+            ));
+        }
+
+        if let Some(threads @ 0) = config.threads {
+            return Err(ComparisonConfigError::InvalidThreads(threads));
+        }
+        if let Some(nice) = config.nice.filter(|v| !(-20..=19).contains(v)) {
+            return Err(ComparisonConfigError::InvalidNice(nice));
+        }
+        #[cfg(feature = "serde")]
+        if let Some(PageWorkers { threads: 0, .. }) = self.workers {
+            return Err(ComparisonConfigError::InvalidCompareThreads(0));
+        }
+
+        let render_config =
+            PDFComparison::render_config(&config, (config.target_width, config.maximum_height));
+        let pool = match config.threads.is_some() || config.nice.is_some() {
+            true => Some(thread_pool(config.threads, config.nice)?),
+            false => None,
+        };
+        Ok(PDFComparison {
+            pdfium: self.pdfium,
+            render_config,
+            config,
+            pool,
+            #[cfg(feature = "serde")]
+            workers: self.workers.filter(|v| v.threads > 1),
+        })
+    }
+}
+
+/// The thread pool of a comparison with `threads`, all cores if `None`, running at `nice`.
+fn thread_pool(
+    threads: Option<usize>,
+    nice: Option<i32>,
+) -> Result<rayon::ThreadPool, ComparisonConfigError> {
+    let mut builder = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.unwrap_or(0))
+        .thread_name(|index| format!("pdf-compare-{}", index));
+    if let Some(nice) = nice {
+        builder = builder.start_handler(move |_| set_niceness(nice));
+    }
+    builder.build().map_err(ComparisonConfigError::ThreadPool)
+}
+
+/// Sets the niceness of the calling thread. Only Linux applies `setpriority` to threads,
+/// elsewhere it would change the niceness of the whole process.
+#[cfg(target_os = "linux")]
+fn set_niceness(nice: i32) {
+    // SAFETY: setpriority only reads its arguments, 0 refers to the calling thread.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } != 0 {
+        log::warn!(
+            "Unable to set the niceness of the comparison threads to {}. Error: {}",
+            nice,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_niceness(nice: i32) {
+    log::warn!(
+        "The niceness {} is ignored, it is only supported on Linux",
+        nice
+    );
+}
+
+/// Result of [`compare_rendered_pages`].
+#[derive(Debug)]
+pub struct RenderedComparison {
+    /// One comparison per page of the current version.
+    pub pages: Vec<Comparison>,
+    /// The match of every page of the current version among the pages of the previous
+    /// version, with its number of differing pixels.
+    pub matches: Vec<PageSimilarity>,
+    /// Time spent matching the pages and locating their differences.
+    pub timings: Timings,
+    /// See [`DocumentComparison::blank_pages`].
+    pub blank_pages: Vec<PdfPageIndex>,
+    /// See [`DocumentComparison::previous_blank_pages`].
+    pub previous_blank_pages: Vec<PdfPageIndex>,
+    /// See [`DocumentComparison::ink_coverage`].
+    pub ink_coverage: Vec<Option<f64>>,
+}
+
+/// Matches the pages `a` of the current version against the pages `b` of the previous
+/// version and locates the changed regions of every page of `a`, like [`PDFComparison`]
+/// does for the pages it renders. The pages are rendered elsewhere, so no pdfium library is
+/// needed. Only pages with the same dimensions can match.
+///
+/// The settings of `config` about rendering and documents, like the target width or the
+/// structural pre-check, don't apply. It runs on the global rayon pool, see
+/// [`PDFComparison::compare_rendered_pages`] for the pool of a comparison.
+pub fn compare_rendered_pages(
+    a: &[RgbImage],
+    b: &[RgbImage],
+    config: &ComparisonConfig,
+) -> Result<RenderedComparison, PDFComparisonError> {
+    PageMatcher { config, pool: None }.compare_rendered(a, b)
+}
+
+/// Matches the pages of two documents and locates the changed regions of every page.
+///
+/// Pages are rendered one after another: pdfium isn't thread safe and pdfium-render keeps a
+/// process wide lock for as long as a [`Pdfium`] binding exists, so a second binding can't
+/// be created to render on another thread. Comparing the rendered pixels runs on the rayon
+/// thread pool, or on one of its own with [`ComparisonConfig::threads`] or
+/// [`ComparisonConfig::nice`]. To render the pages of a document at the same time, they are
+/// matched in child processes with a binding of their own, see
+/// [`PDFComparisonBuilder::compare_threads`].
+pub struct PDFComparison {
+    pdfium: Arc<Pdfium>,
+    render_config: PdfRenderConfig,
+    config: ComparisonConfig,
+    /// Created once, so the threads are reused by every comparison.
+    pool: Option<rayon::ThreadPool>,
+    /// `None` if the pages are matched in this process.
+    #[cfg(feature = "serde")]
+    workers: Option<PageWorkers>,
+}
+
+/// The child processes the pages of a document are matched in, see
+/// [`PDFComparisonBuilder::compare_threads`].
+#[cfg(feature = "serde")]
+struct PageWorkers {
+    threads: usize,
+    isolation: IsolationConfig,
+}
+
+/// The candidates of a range of pages of the current version matched in a child process,
+/// with what the child learned about the pages it rendered.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub(crate) struct PageRangeMatch {
+    candidates: Vec<PageCandidates>,
+    digests: Vec<((DocumentSide, PdfPageIndex), RenderDigest)>,
+    ink_coverage: Vec<((DocumentSide, PdfPageIndex), f64)>,
+    pages_rendered: usize,
+    /// Digests of the versions the child read, which have to be the ones the parent loaded.
+    sha256: ([u8; 32], [u8; 32]),
+}
+
+impl PDFComparison {
+    /// Creates a comparison with the default settings.
+    pub fn new(pdfium: Arc<Pdfium>) -> Self {
+        PDFComparison::builder(pdfium)
+            .build()
+            .expect("The default comparison config is valid")
+    }
+
+    pub fn builder(pdfium: Arc<Pdfium>) -> PDFComparisonBuilder {
+        PDFComparisonBuilder {
+            pdfium,
+            config: ComparisonConfig::default(),
+            #[cfg(feature = "serde")]
+            workers: None,
+        }
+    }
+
+    pub fn config(&self) -> &ComparisonConfig {
+        &self.config
+    }
+
+    /// Compares the current version `a` against the previous version `b`, returning one
+    /// [`Comparison`] per page of `a`. If `b` doesn't exist, every page counts as different.
+    /// If it exists but can't be read or loaded, that's an error. Once `cancel` is
+    /// cancelled, the comparison stops before the next page render with
+    /// [`PDFComparisonError::Cancelled`].
+    pub fn compare_pdfs(
+        &self,
+        a: &Path,
+        b: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Vec<Comparison>, PDFComparisonError> {
+        let state = self.state(Interrupt::new(None, cancel));
+        self.compare_paths(a, b, None, &state)
+            .map(|comparison| comparison.pages)
+    }
+
+    /// Like `compare_pdfs`, but yields the comparison of every page as soon as its
+    /// differences are located, so e.g. [`PDFEditor::mark_differences_iter`] can start
+    /// marking while the later pages are still being compared. All pages are matched before
+    /// the first one is yielded. Errors end the iteration, including those of reading,
+    /// loading and matching the documents, which are yielded before any page.
+    pub fn compare_pdfs_iter<'a>(
+        &'a self,
+        a: &Path,
+        b: &Path,
+        cancel: Option<&'a CancellationToken>,
+    ) -> impl Iterator<Item = Result<(PdfPageIndex, Comparison), PDFComparisonError>> + 'a {
+        let state = self.state(Interrupt::new(None, cancel));
+        let with_paths = {
+            let (a, b) = (a.to_path_buf(), b.to_path_buf());
+            move |e: PDFComparisonError| {
+                e.with_path(DocumentSide::A, &a)
+                    .with_path(DocumentSide::B, &b)
+            }
+        };
+        let matched = self
+            .read_paths(a, b, &state)
+            .and_then(|(bytes_a, bytes_b)| {
+                self.match_documents(
+                    bytes_a,
+                    bytes_b,
+                    |bytes| self.pdfium.load_pdf_from_byte_vec(bytes, None),
+                    None,
+                    &state,
+                    true,
+                )
+            });
+        let (pages, error) = match matched {
+            Ok(matched) => (
+                Some(PageComparisons {
+                    comparison: self,
+                    matched,
+                    state,
+                    next: 0,
+                    failed: false,
+                }),
+                None,
+            ),
+            Err(e) => (None, Some(e)),
+        };
+        error
+            .map(Err)
+            .into_iter()
+            .chain(pages.into_iter().flatten())
+            .map(move |result| result.map_err(&with_paths))
+    }
+
+    /// Like `compare_pdfs`, but additionally keeps a downscaled copy of every page render of
+    /// `a`, `thumbnail_width` pixels wide.
+    pub fn compare_pdfs_with_thumbnails(
+        &self,
+        a: &Path,
+        b: &Path,
+        thumbnail_width: u32,
+    ) -> Result<(Vec<Comparison>, Thumbnails), PDFComparisonError> {
+        let state = self.state(Interrupt::default());
+        self.compare_paths(a, b, Some(thumbnail_width), &state)
+            .map(|comparison| (comparison.pages, comparison.thumbnails))
+    }
+
+    /// Like `compare_pdfs`, keeping thumbnails if `thumbnail_width` is set and finding the
+    /// [`DocumentChanges`]. Additionally returns the time spent in every stage, also if the
+    /// comparison failed. Once `deadline` passes, the comparison is abandoned before the
+    /// next page render with [`PDFComparisonError::TimedOut`], `cancel` works like for
+    /// `compare_pdfs`.
+    pub fn compare_pdfs_timed(
+        &self,
+        a: &Path,
+        b: &Path,
+        thumbnail_width: Option<u32>,
+        deadline: Option<Instant>,
+        cancel: Option<&CancellationToken>,
+    ) -> (Result<DocumentComparison, PDFComparisonError>, Timings) {
+        let state = self.state(Interrupt::new(deadline, cancel));
+        let result = self.compare_paths(a, b, thumbnail_width, &state);
+        (result, state.timings.into_inner())
+    }
+
+    /// Like `compare_pdfs_timed`, but additionally returns both documents as loaded for the
+    /// comparison. Handing them to [`PDFEditor::mark_differences_in_document`] saves reading
+    /// and parsing them again, which takes a while for large scans.
+    pub fn compare_pdfs_loaded(
+        &self,
+        a: &Path,
+        b: &Path,
+        thumbnail_width: Option<u32>,
+        deadline: Option<Instant>,
+        cancel: Option<&CancellationToken>,
+    ) -> (Result<LoadedComparison<'_>, PDFComparisonError>, Timings) {
+        let state = self.state(Interrupt::new(deadline, cancel));
+        let result = self
+            .read_paths(a, b, &state)
+            .and_then(|(bytes_a, bytes_b)| {
+                let modified =
+                    |path: &Path| std::fs::metadata(path).and_then(|v| v.modified()).ok();
+                let (modified_a, modified_b) = (modified(a), modified(b));
+                let (digest_a, digest_b) =
+                    state.timings.borrow_mut().time(TimingStage::Loading, || {
+                        (
+                            content_digest(&bytes_a),
+                            bytes_b.as_deref().map(content_digest),
+                        )
+                    });
+                let matched = self.match_documents(
+                    bytes_a,
+                    bytes_b,
+                    |bytes| self.pdfium.load_pdf_from_byte_vec(bytes, None),
+                    thumbnail_width,
+                    &state,
+                    true,
+                )?;
+                let (comparison, (pdf_a, pdf_b)) = self.locate_all_differences(matched, &state)?;
+                // The renders only borrow the documents while they are drawn.
+                let unwrap = |pdf, side| {
+                    Arc::try_unwrap(pdf).map_err(|_| PDFComparisonError::DocumentStillShared {
+                        document: DocumentRef::new(side),
+                    })
+                };
+                let previous = match pdf_b.zip(digest_b) {
+                    Some((pdf_b, digest_b)) => Some(LoadedDocument {
+                        document: unwrap(pdf_b, DocumentSide::B)?,
+                        path: Some(b.to_path_buf()),
+                        sha256: digest_b,
+                        modified: modified_b,
+                    }),
+                    None => None,
+                };
+                Ok(LoadedComparison {
+                    comparison,
+                    current: LoadedDocument {
+                        document: unwrap(pdf_a, DocumentSide::A)?,
+                        path: Some(a.to_path_buf()),
+                        sha256: digest_a,
+                        modified: modified_a,
+                    },
+                    previous,
+                })
+            })
+            .map_err(|e| {
+                e.with_path(DocumentSide::A, a)
+                    .with_path(DocumentSide::B, b)
+            });
+        (result, state.timings.into_inner())
+    }
+
+    /// Loads `a` and `b` like `compare_pdfs_loaded` without comparing them, to mark the
+    /// [`Comparison`]s of an earlier comparison of the same versions. The previous version
+    /// is `None` if `b` doesn't exist or is encrypted.
+    pub fn load_pdfs(
+        &self,
+        a: &Path,
+        b: &Path,
+    ) -> (Result<LoadedDocuments<'_>, PDFComparisonError>, Timings) {
+        let state = self.state(Interrupt::default());
+        let load = |bytes: Vec<u8>, side, path: &Path| {
+            let modified = std::fs::metadata(path).and_then(|v| v.modified()).ok();
+            state.timings.borrow_mut().time(TimingStage::Loading, || {
+                let sha256 = content_digest(&bytes);
+                self.pdfium
+                    .load_pdf_from_byte_vec(bytes, None)
+                    .map(|document| LoadedDocument {
+                        document,
+                        path: Some(path.to_path_buf()),
+                        sha256,
+                        modified,
+                    })
+                    .map_err(|source| PDFComparisonError::loading(side, source))
+            })
+        };
+        let result = self
+            .read_paths(a, b, &state)
+            .and_then(|(bytes_a, bytes_b)| {
+                let current = load(bytes_a, DocumentSide::A, a)?;
+                let previous = match bytes_b.map(|bytes| load(bytes, DocumentSide::B, b)) {
+                    Some(Ok(previous)) => Some(previous),
+                    Some(Err(PDFComparisonError::Encrypted { .. })) | None => None,
+                    Some(Err(e)) => return Err(e),
+                };
+                Ok((current, previous))
+            })
+            .map_err(|e| {
+                e.with_path(DocumentSide::A, a)
+                    .with_path(DocumentSide::B, b)
+            });
+        (result, state.timings.into_inner())
+    }
+
+    /// Compares two documents held in memory.
+    pub fn compare_pdf_bytes(
+        &self,
+        a: &[u8],
+        b: &[u8],
+    ) -> Result<Vec<Comparison>, PDFComparisonError> {
+        let state = self.state(Interrupt::default());
+        self.match_documents(
+            a,
+            Some(b),
+            |bytes| self.pdfium.load_pdf_from_byte_slice(bytes, None),
+            None,
+            &state,
+            false,
+        )
+        .and_then(|matched| self.locate_all_differences(matched, &state))
+        .map(|(comparison, ..)| comparison.pages)
+    }
+
+    /// Like [`compare_rendered_pages`], but runs on the thread pool of the comparison.
+    pub fn compare_rendered_pages(
+        &self,
+        a: &[RgbImage],
+        b: &[RgbImage],
+    ) -> Result<RenderedComparison, PDFComparisonError> {
+        self.matcher().compare_rendered(a, b)
+    }
+
+    /// Whether `image`, e.g. a page render, counts as blank: at most
+    /// [`ComparisonConfig::blank_ink_threshold`] of its pixels are covered by ink, anything
+    /// that differs from the background by more than scanner noise.
+    pub fn is_blank(&self, image: &RgbImage) -> bool {
+        self.matcher().is_blank(image)
+    }
+
+    /// Renders every page of the document at `path` like for comparing and returns the
+    /// blank ones, see [`PDFComparison::is_blank`].
+    pub fn blank_pages(&self, path: &Path) -> Result<Vec<PdfPageIndex>, PDFComparisonError> {
+        let state = self.state(Interrupt::default());
+        let document = || DocumentRef {
+            side: DocumentSide::A,
+            path: Some(path.to_path_buf()),
+        };
+        let bytes = std::fs::read(path).map_err(|source| PDFComparisonError::UnableToReadPDF {
+            document: document(),
+            source,
+        })?;
+        let pdf = self
+            .pdfium
+            .load_pdf_from_byte_vec(bytes, None)
+            .map(Arc::new)
+            .map_err(|source| PDFComparisonError::UnableToLoadPDF {
+                document: document(),
+                source,
+            })?;
+        let mut blank = Vec::new();
+        for page in 0..pdf.pages().len() {
+            let img = self
+                .render_pdf_page(
+                    pdf.clone(),
+                    &state,
+                    DocumentSide::A,
+                    page,
+                    ComparisonOperation::DetectingBlankPages,
+                )
+                .map_err(|e| e.with_path(DocumentSide::A, path))?;
+            if self.is_blank(&img) {
+                blank.push(page);
+            }
+        }
+        Ok(blank)
+    }
+
+    fn state<'a>(&self, interrupt: Interrupt<'a>) -> ComparisonState<'a> {
+        ComparisonState::new(&self.config, interrupt)
+    }
+
+    fn matcher(&self) -> PageMatcher<'_> {
+        PageMatcher {
+            config: &self.config,
+            pool: self.pool.as_ref(),
+        }
+    }
+
+    /// The pages of `pdf`, rendered whenever they are needed.
+    fn pages<'c, 'a>(&'c self, pdf: &'c Arc<PdfDocument<'a>>) -> DocumentPages<'c, 'a> {
+        DocumentPages {
+            comparison: self,
+            pdf,
+        }
+    }
+
+    fn compare_paths(
+        &self,
+        a: &Path,
+        b: &Path,
+        thumbnail_width: Option<u32>,
+        state: &ComparisonState,
+    ) -> Result<DocumentComparison, PDFComparisonError> {
+        self.read_paths(a, b, state)
+            .and_then(|(bytes_a, bytes_b)| {
+                self.match_documents(
+                    bytes_a.as_slice(),
+                    bytes_b.as_deref(),
+                    |bytes| self.pdfium.load_pdf_from_byte_slice(bytes, None),
+                    thumbnail_width,
+                    state,
+                    true,
+                )
+                .and_then(|matched| self.locate_all_differences(matched, state))
+                .map(|(comparison, ..)| comparison)
+            })
+            .map_err(|e| {
+                e.with_path(DocumentSide::A, a)
+                    .with_path(DocumentSide::B, b)
+            })
+    }
+
+    /// Reads `a` and `b`, `None` for `b` if it doesn't exist.
+    fn read_paths(
+        &self,
+        a: &Path,
+        b: &Path,
+        state: &ComparisonState,
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), PDFComparisonError> {
+        log::info!(
+            "Now comparing: {} and {}",
+            a.to_string_lossy(),
+            b.to_string_lossy()
+        );
+
+        let (bytes_a, bytes_b) = state.timings.borrow_mut().time(TimingStage::Loading, || {
+            (std::fs::read(a), std::fs::read(b))
+        });
+        let reading = |side: DocumentSide, path: &Path| {
+            let path = path.to_path_buf();
+            move |source| PDFComparisonError::UnableToReadPDF {
+                document: DocumentRef {
+                    side,
+                    path: Some(path),
+                },
+                source,
+            }
+        };
+        let bytes_a = bytes_a.map_err(reading(DocumentSide::A, a))?;
+        // Only a missing previous version means the file is new. One that exists but can't
+        // be read is likely damaged and must not be replaced by the next copy.
+        let bytes_b = match bytes_b {
+            Ok(bytes_b) => Some(bytes_b),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(reading(DocumentSide::B, b)(e)),
+        };
+        #[cfg(feature = "serde")]
+        if let (Some(_), Some(bytes_b)) = (&self.workers, &bytes_b) {
+            let sha256 = state.timings.borrow_mut().time(TimingStage::Loading, || {
+                (content_digest(&bytes_a), content_digest(bytes_b))
+            });
+            state.sources.replace(Some(Sources {
+                paths: (a.to_path_buf(), b.to_path_buf()),
+                sha256,
+            }));
+        }
+        Ok((bytes_a, bytes_b))
+    }
+
+    /// Loads `a` and `b` with `load` and matches every page of `a` against `b`. Without `b`
+    /// every page of `a` counts as inserted. If `encrypted_as_new` is set, an encrypted `b`
+    /// is treated like a missing one, though the file isn't reported as new.
+    fn match_documents<'a, S>(
+        &self,
+        a: S,
+        b: Option<S>,
+        load: impl Fn(S) -> Result<PdfDocument<'a>, PdfiumError>,
+        thumbnail_width: Option<u32>,
+        state: &ComparisonState,
+        encrypted_as_new: bool,
+    ) -> Result<MatchedPages<'a>, PDFComparisonError> {
+        let load = |bytes, side| {
+            state.timings.borrow_mut().time(TimingStage::Loading, || {
+                load(bytes).map_err(|source| PDFComparisonError::loading(side, source))
+            })
+        };
+        let pdf_a = Arc::new(load(a, DocumentSide::A)?);
+        let pages = pdf_a.pages().len() as usize;
+        if let Some(limit) = self.config.max_pages.filter(|limit| pages > *limit) {
+            return Err(PDFComparisonError::TooManyPages {
+                document: DocumentRef::new(DocumentSide::A),
+                pages,
+                limit,
+            });
+        }
+        let new_file = b.is_none();
+        let pdf_b = match b.map(|b| load(b, DocumentSide::B)).transpose() {
+            Ok(pdf_b) => pdf_b.map(Arc::new),
+            // An encrypted copy is intact, it's just not comparable.
+            Err(e @ PDFComparisonError::Encrypted { .. }) if encrypted_as_new => {
+                log::warn!(
+                    "The previous version is encrypted, treating every page as new. Error: {}",
+                    e
+                );
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        self.limit_resolution(&pdf_a, pdf_b.as_deref(), state);
+        let Some(pdf_b) = pdf_b else {
+            let mut thumbnails = Thumbnails {
+                pages: Vec::new(),
+                previous_page_count: None,
+            };
+            if let Some(width) = thumbnail_width {
+                for page in 0..pdf_a.pages().len() {
+                    if !self.matcher().is_selected(page) {
+                        thumbnails.pages.push(Thumbnails::placeholder());
+                        continue;
+                    }
+                    let img = self.render_pdf_page(
+                        pdf_a.clone(),
+                        state,
+                        DocumentSide::A,
+                        page,
+                        ComparisonOperation::RenderingThumbnails,
+                    )?;
+                    let thumbnail = Thumbnails::downscale(&img, width);
+                    state.memory.borrow_mut().retain(thumbnail.as_raw().len());
+                    thumbnails.pages.push(thumbnail);
+                }
+            }
+            let similarities = (0..pdf_a.pages().len())
+                .map(|page| match self.matcher().is_selected(page) {
+                    true => PageSimilarity::Different,
+                    false => PageSimilarity::Similar(page, 0),
+                })
+                .collect();
+            return Ok(MatchedPages {
+                pdf_a,
+                pdf_b: None,
+                similarities,
+                thumbnails,
+                changes: DocumentChanges::default(),
+                new_file,
+            });
+        };
+
+        let mut thumbnails = Thumbnails {
+            pages: Vec::new(),
+            previous_page_count: Some(pdf_b.pages().len()),
+        };
+        let changes = state.timings.borrow_mut().time(TimingStage::Matching, || {
+            let mut changes = DocumentChanges {
+                fields: crate::fields::field_changes(&pdf_a, &pdf_b),
+                ..Default::default()
+            };
+            if self.config.compare_metadata {
+                changes.metadata = crate::metadata::metadata_changes(&pdf_a, &pdf_b);
+                changes.attachments = crate::metadata::attachment_changes(&pdf_a, &pdf_b);
+            }
+            changes
+        });
+        // Pages are rendered while matching, which is recorded separately.
+        let rendering = state.timings.borrow().get(TimingStage::Rendering);
+        let started = Instant::now();
+        let in_workers = match thumbnail_width {
+            Some(_) => None,
+            None => self.match_in_workers(pdf_a.pages().len(), state)?,
+        };
+        let similarities = match in_workers {
+            Some(similarities) => similarities,
+            None => {
+                let structural_matches = match self.config.structural_precheck {
+                    true => crate::structure::structural_matches(&pdf_a, &pdf_b),
+                    false => Vec::new(),
+                };
+                self.matcher().match_pages(
+                    (&self.pages(&pdf_a), &self.pages(&pdf_b)),
+                    &structural_matches,
+                    state,
+                    thumbnail_width.map(|width| (width, &mut thumbnails.pages)),
+                )?
+            }
+        };
+        let rendered = state.timings.borrow().get(TimingStage::Rendering) - rendering;
+        state.timings.borrow_mut().add(
+            TimingStage::Matching,
+            started.elapsed().saturating_sub(rendered),
+        );
+        Ok(MatchedPages {
+            pdf_a,
+            pdf_b: Some(pdf_b),
+            similarities,
+            thumbnails,
+            changes,
+            new_file: false,
+        })
+    }
+
+    /// Matches the `pages` pages of the current version in the children of
+    /// [`PDFComparisonBuilder::compare_threads`], each taking a range of them, and records
+    /// what they learned about the pages in `state`. `None` if the pages are to be matched
+    /// in this process: without children, in sheets, for documents that weren't read from
+    /// files, or if a child failed.
+    #[cfg(feature = "serde")]
+    fn match_in_workers(
+        &self,
+        pages: PdfPageIndex,
+        state: &ComparisonState,
+    ) -> Result<Option<Vec<PageSimilarity>>, PDFComparisonError> {
+        let sources = state.sources.borrow();
+        let (Some(workers), Some(sources), None) =
+            (&self.workers, sources.as_ref(), self.config.sheet_pages)
+        else {
+            return Ok(None);
+        };
+        let ranges = worker_ranges(pages, workers.threads);
+        if ranges.len() < 2 {
+            return Ok(None);
+        }
+        let (a, b) = (sources.paths.0.as_path(), sources.paths.1.as_path());
+        let interrupt = state.interrupt;
+        let matched = std::thread::scope(|scope| {
+            let children = ranges
+                .into_iter()
+                .map(|pages| {
+                    let child = scope.spawn({
+                        let pages = pages.clone();
+                        move || {
+                            crate::isolation::match_pages(
+                                &workers.isolation,
+                                (a, b),
+                                &self.config,
+                                pages,
+                                interrupt.deadline,
+                                interrupt.cancel,
+                            )
+                        }
+                    });
+                    (pages, child)
+                })
+                .collect::<Vec<_>>();
+            children
+                .into_iter()
+                .map(|(pages, child)| (pages, child.join()))
+                .collect::<Vec<_>>()
+        });
+        let mut results = Vec::new();
+        for (pages, joined) in matched {
+            let result = match joined {
+                Ok(result) => result,
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .copied()
+                        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                        .unwrap_or_default();
+                    log::error!(
+                        "The thread matching pages {} to {} of {} panicked, matching them in \
+                         this process: {}",
+                        pages.start + 1,
+                        pages.end,
+                        a.to_string_lossy(),
+                        message
+                    );
+                    return Ok(None);
+                }
+            };
+            match result {
+                Ok(matched) if matched.sha256 == sources.sha256 => results.push(matched),
+                Ok(_) => {
+                    log::warn!(
+                        "{} or its copy changed while its pages were matched, matching them \
+                         in this process",
+                        a.to_string_lossy()
+                    );
+                    return Ok(None);
+                }
+                Err(e) => {
+                    interrupt.check_comparison(DocumentSide::A)?;
+                    log::warn!(
+                        "Unable to match pages {} to {} of {} in a child process, matching all \
+                         of them in this process. Error: {}",
+                        pages.start + 1,
+                        pages.end,
+                        a.to_string_lossy(),
+                        e
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+        let mut candidates = Vec::with_capacity(pages as usize);
+        for matched in results {
+            candidates.extend(matched.candidates);
+            state.digests.borrow_mut().extend(matched.digests);
+            state.ink_coverage.borrow_mut().extend(matched.ink_coverage);
+            state
+                .pages_rendered
+                .set(state.pages_rendered.get() + matched.pages_rendered);
+        }
+        Ok(Some(PageMatcher::assign(&candidates)))
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn match_in_workers(
+        &self,
+        _: PdfPageIndex,
+        _: &ComparisonState,
+    ) -> Result<Option<Vec<PageSimilarity>>, PDFComparisonError> {
+        Ok(None)
+    }
+
+    /// Matches the `pages` of the current version `a` against the previous version `b`, the
+    /// child side of [`PDFComparisonBuilder::compare_threads`].
+    #[cfg(feature = "serde")]
+    pub(crate) fn match_page_range(
+        &self,
+        a: &Path,
+        b: &Path,
+        pages: Range<PdfPageIndex>,
+    ) -> Result<PageRangeMatch, PDFComparisonError> {
+        let state = self.state(Interrupt::default());
+        let (bytes_a, bytes_b) = self.read_paths(a, b, &state)?;
+        let bytes_b = bytes_b.ok_or_else(|| PDFComparisonError::UnableToReadPDF {
+            document: DocumentRef {
+                side: DocumentSide::B,
+                path: Some(b.to_path_buf()),
+            },
+            source: std::io::ErrorKind::NotFound.into(),
+        })?;
+        let sha256 = (content_digest(&bytes_a), content_digest(&bytes_b));
+        let load = |bytes, side| {
+            self.pdfium
+                .load_pdf_from_byte_slice(bytes, None)
+                .map(Arc::new)
+                .map_err(|source| PDFComparisonError::loading(side, source))
+        };
+        let pdf_a = load(&bytes_a, DocumentSide::A)?;
+        let pdf_b = load(&bytes_b, DocumentSide::B)?;
+        self.limit_resolution(&pdf_a, Some(&pdf_b), &state);
+        let structural_matches = match self.config.structural_precheck {
+            true => crate::structure::structural_matches(&pdf_a, &pdf_b),
+            false => Vec::new(),
+        };
+        let candidates = self.matcher().page_candidates(
+            (&self.pages(&pdf_a), &self.pages(&pdf_b)),
+            &structural_matches,
+            &state,
+            None,
+            pages,
+        )?;
+        Ok(PageRangeMatch {
+            candidates,
+            digests: state.digests.take().into_iter().collect(),
+            ink_coverage: state.ink_coverage.take().into_iter().collect(),
+            pages_rendered: state.pages_rendered.get(),
+            sha256,
+        })
+    }
+
+    /// Renders both documents at a lower resolution if two renders of their largest page
+    /// exceed [`ComparisonConfig::render_memory_limit`].
+    fn limit_resolution<'a>(
+        &self,
+        pdf_a: &PdfDocument<'a>,
+        pdf_b: Option<&PdfDocument<'a>>,
+        state: &ComparisonState,
+    ) {
+        if self.config.render_memory_limit.is_none() {
+            return;
+        }
+        let size = (self.config.target_width, self.config.maximum_height);
+        // Pages may be rotated for comparing, so both orientations are accounted for.
+        let largest_page = std::iter::once(pdf_a)
+            .chain(pdf_b)
+            .flat_map(|pdf| pdf.pages().page_sizes().unwrap_or_default())
+            .map(|rect| {
+                let (width, height) = (rect.width().value.abs(), rect.height().value.abs());
+                let aspect = (height / width.max(1.)).max(width / height.max(1.));
+                let height = (size.0 as f32 * aspect).min(size.1 as f32);
+                size.0 as usize * height as usize * 3
+            })
+            .max()
+            .unwrap_or_default();
+        if let Some(reduced) = state.memory.borrow_mut().reduce(size, largest_page) {
+            log::warn!(
+                "Rendering at a width of {} instead of {} to stay within the render memory limit",
+                reduced.0,
+                size.0
+            );
+            let render_config = PDFComparison::render_config(&self.config, reduced);
+            state.reduced.replace(Some((reduced, render_config)));
+        }
+    }
+
+    /// Locates the differences of every page of `matched`, handing back its documents.
+    fn locate_all_differences<'a>(
+        &self,
+        matched: MatchedPages<'a>,
+        state: &ComparisonState,
+    ) -> Result<(DocumentComparison, Documents<'a>), PDFComparisonError> {
+        log::debug!("Now rendering similiarities!");
+        let mut renders = Vec::new();
+        let pages = (0..matched.similarities.len())
+            .map(|index| {
+                let (comparison, page_renders) = matched.locate_differences(self, index, state)?;
+                renders.extend(page_renders);
+                Ok(comparison)
+            })
+            .collect::<Result<Vec<Comparison>, PDFComparisonError>>()?;
+        let threshold = self.config.blank_ink_threshold;
+        let (ink_coverage, blank_pages) = state.blankness(DocumentSide::A, pages.len(), threshold);
+        let previous_pages = matched
+            .pdf_b
+            .as_ref()
+            .map_or(0, |v| v.pages().len() as usize);
+        let (_, previous_blank_pages) = state.blankness(DocumentSide::B, previous_pages, threshold);
+        let size_changes = match &matched.pdf_b {
+            Some(pdf_b) => crate::paper::size_changes(&matched.pdf_a, pdf_b, &pages),
+            None => Vec::new(),
+        };
+        let comparison = DocumentComparison {
+            pages,
+            thumbnails: matched.thumbnails,
+            changes: matched.changes,
+            new_file: matched.new_file,
+            renders,
+            memory: state.memory.borrow().usage(),
+            pages_rendered: state.pages_rendered.get(),
+            blank_pages,
+            previous_blank_pages,
+            ink_coverage,
+            size_changes,
+        };
+        Ok((comparison, (matched.pdf_a, matched.pdf_b)))
+    }
+
+    /// Render settings for pages rendered at a width and maximum height of `size`.
+    fn render_config(config: &ComparisonConfig, size: (i32, i32)) -> PdfRenderConfig {
+        let render_config = PDFComparison::base_render_config(config, size);
+        match config.rotation {
+            PageRotation::Landscape => {
+                render_config.rotate_if_landscape(PdfPageRenderRotation::Degrees90, true)
+            }
+            PageRotation::Off | PageRotation::Normalize => render_config,
+        }
+    }
+
+    /// Render settings without any rotation.
+    fn base_render_config(config: &ComparisonConfig, size: (i32, i32)) -> PdfRenderConfig {
+        let [r, g, b] = config.background;
+        config.render_flags.apply(
+            PdfRenderConfig::new()
+                .set_target_width(size.0)
+                .set_maximum_height(size.1)
+                .set_clear_color(PdfColor::new(r, g, b, 255))
+                .render_form_data(config.render_form_data)
+                .render_annotations(config.render_annotations),
+        )
+    }
+
+    /// With [`PageRotation::Normalize`], the render settings of a `page` with a rotation
+    /// flag: the page is rotated back and sized by its unrotated width, so it renders like
+    /// the same page without the flag.
+    fn rotated_render_config(&self, page: &PdfPage, size: (i32, i32)) -> Option<PdfRenderConfig> {
+        if self.config.rotation != PageRotation::Normalize {
+            return None;
+        }
+        let [r, g, b] = self.config.background;
+        // For quarter turns pdfium-render swaps the constraints along with the page, so the
+        // height settings apply to the width of the result and the other way around.
+        let quarter_turn = |rotation| {
+            self.config.render_flags.apply(
+                PdfRenderConfig::new()
+                    .set_target_height(size.0)
+                    .set_maximum_width(size.1)
+                    .set_clear_color(PdfColor::new(r, g, b, 255))
+                    .render_form_data(self.config.render_form_data)
+                    .render_annotations(self.config.render_annotations)
+                    .rotate(rotation, true),
+            )
+        };
+        match page.rotation().ok()? {
+            PdfPageRenderRotation::Degrees90 => {
+                Some(quarter_turn(PdfPageRenderRotation::Degrees270))
+            }
+            PdfPageRenderRotation::Degrees270 => {
+                Some(quarter_turn(PdfPageRenderRotation::Degrees90))
+            }
+            PdfPageRenderRotation::Degrees180 => Some(
+                PDFComparison::base_render_config(&self.config, size)
+                    .rotate(PdfPageRenderRotation::Degrees180, false),
+            ),
+            PdfPageRenderRotation::None => None,
+        }
+    }
+
+    /// Renders `page` of `pdf`, or takes it from the render cache if it was rendered recently.
+    fn render_pdf_page(
+        &self,
+        pdf: Arc<PdfDocument>,
+        state: &ComparisonState,
+        side: DocumentSide,
+        page: PdfPageIndex,
+        operation: ComparisonOperation,
+    ) -> Result<Arc<RgbImage>, PDFComparisonError> {
+        // Checked before taking a page from the cache as well, so a comparison served from
+        // the cache still stops between two pages.
+        state.interrupt.check_comparison(side)?;
+        if let Some(image) = state.cache.borrow_mut().get(side, page) {
+            return Ok(image);
+        }
+        let reduced = state.reduced.borrow();
+        let (size, render_config) = match &*reduced {
+            Some((size, render_config)) => (*size, render_config),
+            None => (
+                (self.config.target_width, self.config.maximum_height),
+                &self.render_config,
+            ),
+        };
+        let rendered = state.timings.borrow_mut().time(TimingStage::Rendering, || {
+            pdf.pages().get(page).and_then(|v| {
+                let rotated = self.rotated_render_config(&v, size);
+                v.render_with_config(rotated.as_ref().unwrap_or(render_config))
+                    .map(|bitmap| {
+                        let mut image = bitmap.as_image().into_rgb8();
+                        crate::blur::box_blur(
+                            &mut image,
+                            self.config.blur_radius,
+                            &mut state.blur_scratch.borrow_mut(),
+                        );
+                        image
+                    })
+            })
+        });
+        match rendered {
+            Ok(image) => {
+                crate::metrics::metrics().pages_rendered.inc();
+                state.pages_rendered.set(state.pages_rendered.get() + 1);
+                state.digests.borrow_mut().insert(
+                    (side, page),
+                    RenderDigest {
+                        dimensions: image.dimensions(),
+                        sha256: content_digest(image.as_raw()),
+                    },
+                );
+                let image = Arc::new(image);
+                let mut memory = state.memory.borrow_mut();
+                let mut cache = state.cache.borrow_mut();
+                memory.rendered(image.as_raw().len());
+                cache.set_max_bytes(memory.cache_capacity(self.config.render_cache_bytes));
+                cache.insert(side, page, image.clone());
+                memory.record(cache.used_bytes());
+                Ok(image)
+            }
+            Err(source) => Err(PDFComparisonError::UnableToRenderPage {
+                document: DocumentRef::new(side),
+                page,
+                operation,
+                source,
+            }),
+        }
+    }
+}
+
+/// The matching and segmentation layer of a comparison, working on page images from any
+/// [`PageSource`].
+#[derive(Clone, Copy)]
+struct PageMatcher<'c> {
+    config: &'c ComparisonConfig,
+    /// See [`ComparisonConfig::threads`], the global rayon pool if `None`.
+    pool: Option<&'c rayon::ThreadPool>,
+}
+
+impl PageMatcher<'_> {
+    /// See [`compare_rendered_pages`].
+    fn compare_rendered(
+        &self,
+        a: &[RgbImage],
+        b: &[RgbImage],
+    ) -> Result<RenderedComparison, PDFComparisonError> {
+        let state = ComparisonState::new(self.config, Interrupt::default());
+        let a = self.rendered_pages(a, DocumentSide::A, &state)?;
+        let b = self.rendered_pages(b, DocumentSide::B, &state)?;
+        let started = Instant::now();
+        let matches = self.match_pages((&a, &b), &[], &state, None)?;
+        state
+            .timings
+            .borrow_mut()
+            .add(TimingStage::Matching, started.elapsed());
+        let pages = (0..matches.len())
+            .map(|index| {
+                self.locate_differences((&a, Some(&b)), &matches, index, &state)
+                    .map(|(comparison, _)| comparison)
+            })
+            .collect::<Result<Vec<Comparison>, PDFComparisonError>>()?;
+        let threshold = self.config.blank_ink_threshold;
+        let (ink_coverage, blank_pages) = state.blankness(DocumentSide::A, a.0.len(), threshold);
+        let (_, previous_blank_pages) = state.blankness(DocumentSide::B, b.0.len(), threshold);
+        Ok(RenderedComparison {
+            pages,
+            matches,
+            blank_pages,
+            previous_blank_pages,
+            ink_coverage,
+            timings: state.timings.into_inner(),
+        })
+    }
+
+    /// `images` as the pages of `side`, blurred like renders, with their digests recorded in
+    /// `state`. Fails like [`PDFComparison`] for too many pages.
+    fn rendered_pages(
+        &self,
+        images: &[RgbImage],
+        side: DocumentSide,
+        state: &ComparisonState,
+    ) -> Result<RenderedPages, PDFComparisonError> {
+        let limit = match side {
+            DocumentSide::A => self.config.max_pages,
+            DocumentSide::B => None,
+        }
+        .unwrap_or(usize::MAX)
+        .min(PdfPageIndex::MAX as usize);
+        if images.len() > limit {
+            return Err(PDFComparisonError::TooManyPages {
+                document: DocumentRef::new(side),
+                pages: images.len(),
+                limit,
+            });
+        }
+        let pages = images
+            .iter()
+            .enumerate()
+            .map(|(page, image)| {
+                let mut image = image.clone();
+                crate::blur::box_blur(
+                    &mut image,
+                    self.config.blur_radius,
+                    &mut state.blur_scratch.borrow_mut(),
+                );
+                state.digests.borrow_mut().insert(
+                    (side, page as PdfPageIndex),
+                    RenderDigest {
+                        dimensions: image.dimensions(),
+                        sha256: content_digest(image.as_raw()),
+                    },
+                );
+                Arc::new(image)
+            })
+            .collect();
+        Ok(RenderedPages(pages))
+    }
+
+    /// Builds the comparison of page `index` of the current version `a` from its match in
+    /// `similarities` among the pages of `b`, along with its renders if they are kept and
+    /// the page changed.
+    fn locate_differences(
+        &self,
+        (a, b): (&dyn PageSource, Option<&dyn PageSource>),
+        similarities: &[PageSimilarity],
+        index: usize,
+        state: &ComparisonState,
+    ) -> Result<(Comparison, Option<PageRenders>), PDFComparisonError> {
+        let sim = &similarities[index];
+        let blank = PdfPageIndex::try_from(index)
+            .is_ok_and(|page| state.is_blank(page, self.config.blank_ink_threshold));
+        if matches!(sim, PageSimilarity::Different) && blank {
+            log::debug!("Page {} is an inserted blank page", index);
+            let comparison = Comparison::Different(DifferenceSegments {
+                segments: Vec::new(),
+                matched_page: None,
+                previous_segments: Vec::new(),
+                redactions: Vec::new(),
+            });
+            return Ok((comparison, None));
+        }
+        if let PageSimilarity::Similar(page_b, differing) = sim {
+            if *differing > 0 && self.counts_as_identical(state, index, *page_b, *differing) {
+                log::debug!(
+                    "Page {} is within the page threshold of page {} without rendering",
+                    index,
+                    page_b
+                );
+                return Ok((Comparison::Identical(*page_b), None));
+            }
+        }
+        let (img_a, img_b) = match (sim, b) {
+            (PageSimilarity::Similar(page_b, differing), Some(b)) if *differing > 0 => {
+                log::debug!("Redering similarity of pages {} and {}", index, page_b);
+                let page_a = PdfPageIndex::try_from(index).map_err(|_| {
+                    PDFComparisonError::PageIndexOutOfRange {
+                        document: DocumentRef::new(DocumentSide::A),
+                        page: index,
+                    }
+                })?;
+                let img_a = a.page_image(
+                    state,
+                    DocumentSide::A,
+                    page_a,
+                    ComparisonOperation::LocatingDifferences,
+                )?;
+                let img_b = b.page_image(
+                    state,
+                    DocumentSide::B,
+                    *page_b,
+                    ComparisonOperation::LocatingDifferences,
+                )?;
+                (
+                    Some(Arc::unwrap_or_clone(img_a)),
+                    Some(Arc::unwrap_or_clone(img_b)),
+                )
+            }
+            _ => (None, None),
+        };
+        let renders = match (self.config.keep_renders, sim, &img_a, &img_b) {
+            (true, PageSimilarity::Similar(matched_page, _), Some(current), Some(previous)) => {
+                Some(PageRenders {
+                    page: index,
+                    matched_page: *matched_page,
+                    current: current.clone(),
+                    previous: previous.clone(),
+                })
+            }
+            _ => None,
+        };
+        let comparison = state
+            .timings
+            .borrow_mut()
+            .time(TimingStage::Segments, || {
+                Comparison::from_similarity(sim, img_a, img_b, self.config)
+            })
+            .map_err(|source| PDFComparisonError::UnableToLocateDifferences {
+                document: DocumentRef::new(DocumentSide::A),
+                page: index,
+                source,
+            })?;
+        // Pages within the page threshold count as identical and don't need them.
+        let renders = renders.filter(|_| matches!(comparison, Comparison::Different(_)));
+        if let Some(renders) = &renders {
+            let bytes = renders.current.as_raw().len() + renders.previous.as_raw().len();
+            state.memory.borrow_mut().retain(bytes);
+        }
+        Ok((comparison, renders))
+    }
+
+    /// Matches every page of `a` against `b`, on thumbnails first unless `prepass_width` is
+    /// 0. Pages that aren't selected or have `structural_matches` aren't rendered. If
+    /// `thumbnails` is set, a downscaled copy of every render of `a` is appended to it.
+    /// Pages claiming the same page of `b` are resolved by [`crate::matching::assign`].
+    fn match_pages(
+        &self,
+        (a, b): (&dyn PageSource, &dyn PageSource),
+        structural_matches: &[Vec<PdfPageIndex>],
+        state: &ComparisonState,
+        thumbnails: Option<(u32, &mut Vec<RgbImage>)>,
+    ) -> Result<Vec<PageSimilarity>, PDFComparisonError> {
+        if let Some(sheet_pages) = self.config.sheet_pages {
+            return self.match_sheets((a, b), structural_matches, state, (sheet_pages, thumbnails));
+        }
+        let candidates = self.page_candidates(
+            (a, b),
+            structural_matches,
+            state,
+            thumbnails,
+            0..a.page_count(),
+        )?;
+        Ok(PageMatcher::assign(&candidates))
+    }
+
+    /// The candidates of the `pages` of `a` among the pages of `b`, see `match_pages`. They
+    /// only depend on the page itself, so the pages of a document can be split between
+    /// several matchers, see [`PDFComparisonBuilder::compare_threads`].
+    fn page_candidates(
+        &self,
+        (a, b): (&dyn PageSource, &dyn PageSource),
+        structural_matches: &[Vec<PdfPageIndex>],
+        state: &ComparisonState,
+        mut thumbnails: Option<(u32, &mut Vec<RgbImage>)>,
+        pages: Range<PdfPageIndex>,
+    ) -> Result<Vec<PageCandidates>, PDFComparisonError> {
+        // Pages that aren't selected count as identical to the page at the same index.
+        let known_matches = |a: PdfPageIndex| match self.is_selected(a) {
+            true => structural_matches
+                .get(a as usize)
+                .cloned()
+                .unwrap_or_default(),
+            false => vec![a],
+        };
+        let needs_matching = pages.clone().any(|page| known_matches(page).is_empty());
+        let blank_b = match needs_matching {
+            true => self.blank_pages(b, state)?,
+            false => Vec::new(),
+        };
+        let is_blank_b = |page_b: PdfPageIndex| blank_b.get(page_b as usize).copied() == Some(true);
+
+        let prepass_b = match self.config.prepass_width {
+            0 => None,
+            _ if !needs_matching => None,
+            width => Some(
+                (0..b.page_count())
+                    .map(|page_b| {
+                        b.page_image(
+                            state,
+                            DocumentSide::B,
+                            page_b,
+                            ComparisonOperation::MatchingPages,
+                        )
+                        .map(|img_b| {
+                            let thumbnail = Thumbnails::downscale(&img_b, width);
+                            state.memory.borrow_mut().retain(thumbnail.as_raw().len());
+                            thumbnail
+                        })
+                    })
+                    .collect::<Result<Vec<RgbImage>, PDFComparisonError>>()?,
+            ),
+        };
+        pages
+            .map(|page_a| {
+                log::debug!("Working on page {}", page_a);
+                let known = known_matches(page_a);
+                if !known.is_empty() {
+                    log::debug!(
+                        "Page {} is identical to pages {:?} without rendering",
+                        page_a,
+                        known
+                    );
+                    if let Some((_, thumbnails)) = thumbnails.as_mut() {
+                        thumbnails.push(Thumbnails::placeholder());
+                    }
+                    return Ok(PageCandidates {
+                        candidates: known.into_iter().map(|b| (b, 0)).collect(),
+                        identical: 0,
+                    });
+                }
+                let img_a = a.page_image(
+                    state,
+                    DocumentSide::A,
+                    page_a,
+                    ComparisonOperation::MatchingPages,
+                )?;
+                if let Some((width, thumbnails)) = thumbnails.as_mut() {
+                    let thumbnail = Thumbnails::downscale(&img_a, *width);
+                    state.memory.borrow_mut().retain(thumbnail.as_raw().len());
+                    thumbnails.push(thumbnail);
+                }
+                if self.detects_blank_pages()
+                    && self.detect_blank(state, (DocumentSide::A, page_a), &img_a)
+                {
+                    log::debug!("Page {} is blank, matching it to the blank pages", page_a);
+                    return Ok(PageCandidates {
+                        candidates: (0..b.page_count())
+                            .filter(|page_b| is_blank_b(*page_b))
+                            .map(|page_b| (page_b, 0))
+                            .collect(),
+                        identical: 0,
+                    });
+                }
+                let pixels = img_a.width() as f64 * img_a.height() as f64;
+                let identical = (self.config.page_threshold * pixels) as usize;
+                // Pages that aren't blank only match pages that aren't blank either.
+                let content = |candidates: Vec<Candidate>| {
+                    candidates
+                        .into_iter()
+                        .filter(|(page_b, _)| !is_blank_b(*page_b))
+                        .collect::<Vec<Candidate>>()
+                };
+                let find_candidates = |pages: Range<PdfPageIndex>| match &prepass_b {
+                    Some(prepass_b) => {
+                        let candidates = self.find_min_similarity_prepass(
+                            (page_a, &img_a),
+                            prepass_b,
+                            b,
+                            pages.clone(),
+                            state,
+                        )?;
+                        match content(candidates.clone()) {
+                            found if found.is_empty() && !candidates.is_empty() => self
+                                .find_min_similarity((page_a, &img_a), b, pages, state)
+                                .map(content),
+                            found => Ok(found),
+                        }
+                    }
+                    None => self
+                        .find_min_similarity((page_a, &img_a), b, pages, state)
+                        .map(content),
+                };
+                let all_pages = 0..b.page_count();
+                let window = self.match_window(page_a, b.page_count());
+                let candidates = match window == all_pages {
+                    true => find_candidates(all_pages)?,
+                    false => {
+                        let candidates = find_candidates(window)?;
+                        match candidates
+                            .iter()
+                            .any(|(_, differing)| *differing <= identical)
+                        {
+                            true => candidates,
+                            false => {
+                                log::trace!("No match within the window, matching all pages");
+                                find_candidates(all_pages)?
+                            }
+                        }
+                    }
+                };
+                Ok(PageCandidates {
+                    candidates,
+                    identical,
+                })
+            })
+            .collect()
+    }
+
+    /// Matches every page to one of its `candidates`, see [`crate::matching::assign`].
+    fn assign(candidates: &[PageCandidates]) -> Vec<PageSimilarity> {
+        crate::matching::assign(candidates)
+            .into_iter()
+            .map(|matched| match matched {
+                Some((page_b, differing)) => PageSimilarity::Similar(page_b, differing),
+                None => PageSimilarity::Different,
+            })
+            .collect()
+    }
+
+    /// Like `match_pages`, but aligns the pages in sheets of `sheet_pages` pages, see
+    /// [`ComparisonConfig::sheet_pages`]. Page pairs are scored on thumbnails unless
+    /// `prepass_width` is 0, only the aligned pairs are compared at full resolution. Records
+    /// the blank pages of `a` in `state`.
+    fn match_sheets(
+        &self,
+        (a, b): (&dyn PageSource, &dyn PageSource),
+        structural_matches: &[Vec<PdfPageIndex>],
+        state: &ComparisonState,
+        (sheet_pages, mut thumbnails): (usize, Option<(u32, &mut Vec<RgbImage>)>),
+    ) -> Result<Vec<PageSimilarity>, PDFComparisonError> {
+        let prepass = |img: &RgbImage| match self.config.prepass_width {
+            0 => None,
+            width => {
+                let thumbnail = Thumbnails::downscale(img, width);
+                state.memory.borrow_mut().retain(thumbnail.as_raw().len());
+                Some(thumbnail)
+            }
+        };
+        let (mut blank_a, mut prepass_a) = (Vec::new(), Vec::new());
+        for page_a in 0..a.page_count() {
+            if !self.is_selected(page_a) {
+                if let Some((_, thumbnails)) = thumbnails.as_mut() {
+                    thumbnails.push(Thumbnails::placeholder());
+                }
+                blank_a.push(false);
+                prepass_a.push(None);
+                continue;
+            }
+            let img_a = a.page_image(
+                state,
+                DocumentSide::A,
+                page_a,
+                ComparisonOperation::MatchingPages,
+            )?;
+            if let Some((width, thumbnails)) = thumbnails.as_mut() {
+                let thumbnail = Thumbnails::downscale(&img_a, *width);
+                state.memory.borrow_mut().retain(thumbnail.as_raw().len());
+                thumbnails.push(thumbnail);
+            }
+            blank_a.push(self.detect_blank(state, (DocumentSide::A, page_a), &img_a));
+            prepass_a.push(prepass(&img_a));
+        }
+        let (mut blank_b, mut prepass_b) = (Vec::new(), Vec::new());
+        for page_b in 0..b.page_count() {
+            let img_b = b.page_image(
+                state,
+                DocumentSide::B,
+                page_b,
+                ComparisonOperation::MatchingPages,
+            )?;
+            blank_b.push(self.detect_blank(state, (DocumentSide::B, page_b), &img_b));
+            prepass_b.push(prepass(&img_b));
+        }
+
+        let mut differing = HashMap::new();
+        let window = self
+            .config
+            .match_window
+            .map(|v| v.saturating_mul(sheet_pages));
+        let mut full_resolution = |page_a: PdfPageIndex, page_b: PdfPageIndex| {
+            if let Some(similarity) = differing.get(&(page_a, page_b)) {
+                return Ok(*similarity);
+            }
+            let similarity = match structural_matches
+                .get(page_a as usize)
+                .is_some_and(|v| v.contains(&page_b))
+            {
+                true => Similiarity::Similar(0),
+                false => {
+                    let operation = ComparisonOperation::MatchingPages;
+                    let img_a = a.page_image(state, DocumentSide::A, page_a, operation)?;
+                    let img_b = b.page_image(state, DocumentSide::B, page_b, operation)?;
+                    self.differing_pixels(state, (page_a, &img_a), (page_b, &img_b))
+                }
+            };
+            differing.insert((page_a, page_b), similarity);
+            Ok::<Similiarity, PDFComparisonError>(similarity)
+        };
+        let aligned =
+            crate::duplex::align_sheets((&blank_a, &blank_b), sheet_pages, |page_a, page_b| {
+                let (page_a, page_b) = (page_a as PdfPageIndex, page_b as PdfPageIndex);
+                if !self.is_selected(page_a) {
+                    return Ok((page_a == page_b).then_some(0.));
+                }
+                if window.is_some_and(|window| page_a.abs_diff(page_b) as usize > window) {
+                    return Ok(None);
+                }
+                let (similarity, pixels) =
+                    match (&prepass_a[page_a as usize], &prepass_b[page_b as usize]) {
+                        (Some(thumbnail_a), Some(thumbnail_b)) => (
+                            self.compare_images(
+                                thumbnail_a,
+                                thumbnail_b,
+                                self.config.pixel_tolerance,
+                            ),
+                            thumbnail_a.width() as f64 * thumbnail_a.height() as f64,
+                        ),
+                        _ => {
+                            let similarity = full_resolution(page_a, page_b)?;
+                            let pixels = state
+                                .digest(DocumentSide::A, page_a)
+                                .map_or(1., |v| v.dimensions.0 as f64 * v.dimensions.1 as f64);
+                            (similarity, pixels)
+                        }
+                    };
+                Ok(match similarity {
+                    Similiarity::Similar(differing) => Some(differing as f64 / pixels.max(1.)),
+                    Similiarity::Different => None,
+                })
+            })?;
+
+        let nearest_blank = |page_a: usize| {
+            (0..blank_b.len())
+                .filter(|page_b| blank_b[*page_b])
+                .min_by_key(|page_b| page_b.abs_diff(page_a))
+        };
+        aligned
+            .into_iter()
+            .enumerate()
+            .map(|(page_a, matched)| {
+                let page_a_index = page_a as PdfPageIndex;
+                Ok(match matched {
+                    _ if !self.is_selected(page_a_index) => {
+                        PageSimilarity::Similar(page_a_index, 0)
+                    }
+                    SheetMatch::Pair(page_b) if blank_a[page_a] && blank_b[page_b] => {
+                        PageSimilarity::Similar(page_b as PdfPageIndex, 0)
+                    }
+                    SheetMatch::Pair(page_b) if blank_a[page_a] || blank_b[page_b] => {
+                        PageSimilarity::Different
+                    }
+                    SheetMatch::Pair(page_b) => {
+                        match full_resolution(page_a_index, page_b as PdfPageIndex)? {
+                            Similiarity::Similar(differing) => {
+                                PageSimilarity::Similar(page_b as PdfPageIndex, differing)
+                            }
+                            Similiarity::Different => PageSimilarity::Different,
+                        }
+                    }
+                    SheetMatch::Tolerated(sheet_end) => PageSimilarity::Similar(
+                        nearest_blank(page_a).unwrap_or(sheet_end) as PdfPageIndex,
+                        0,
+                    ),
+                    SheetMatch::Unmatched => PageSimilarity::Different,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether `img` is covered by ink on at most [`ComparisonConfig::blank_ink_threshold`]
+    /// of its pixels.
+    fn is_blank(&self, img: &RgbImage) -> bool {
+        crate::duplex::ink_coverage(img, self.config.background) <= self.config.blank_ink_threshold
+    }
+
+    /// Like `is_blank` for `img`, the render of `page` of `side`, whose ink coverage is
+    /// recorded in `state`.
+    fn detect_blank(
+        &self,
+        state: &ComparisonState,
+        page: (DocumentSide, PdfPageIndex),
+        img: &RgbImage,
+    ) -> bool {
+        let coverage = *state
+            .ink_coverage
+            .borrow_mut()
+            .entry(page)
+            .or_insert_with(|| crate::duplex::ink_coverage(img, self.config.background));
+        coverage <= self.config.blank_ink_threshold
+    }
+
+    /// Whether blank pages are detected, see [`ComparisonConfig::detect_blank_pages`].
+    fn detects_blank_pages(&self) -> bool {
+        self.config.detect_blank_pages || self.config.sheet_pages.is_some()
+    }
+
+    /// Renders every page of `b` and returns which of them are blank, or nothing if blank
+    /// pages aren't detected.
+    fn blank_pages(
+        &self,
+        b: &dyn PageSource,
+        state: &ComparisonState,
+    ) -> Result<Vec<bool>, PDFComparisonError> {
+        if !self.detects_blank_pages() {
+            return Ok(Vec::new());
+        }
+        (0..b.page_count())
+            .map(|page_b| {
+                b.page_image(
+                    state,
+                    DocumentSide::B,
+                    page_b,
+                    ComparisonOperation::MatchingPages,
+                )
+                .map(|img_b| self.detect_blank(state, (DocumentSide::B, page_b), &img_b))
+            })
+            .collect()
+    }
+
+    /// The pages of a document with `pages` pages that page `a` is matched against, see
+    /// [`ComparisonConfig::match_window`].
+    fn match_window(&self, a: PdfPageIndex, pages: PdfPageIndex) -> Range<PdfPageIndex> {
+        match self.config.match_window {
+            Some(window) => {
+                let window = PdfPageIndex::try_from(window).unwrap_or(PdfPageIndex::MAX);
+                let start = a.saturating_sub(window).min(pages);
+                start..a.saturating_add(window).saturating_add(1).min(pages)
+            }
+            None => 0..pages,
+        }
+    }
+
+    /// Matches `img_a`, the render of page `a`, against the thumbnails `prepass_b` of the
+    /// `pages` of `pages_b` and only compares the best candidate at full resolution. Falls
+    /// back to `find_min_similarity` if the thumbnails don't single out a candidate or it
+    /// can't be compared at full resolution.
+    fn find_min_similarity_prepass(
+        &self,
+        (a, img_a): (PdfPageIndex, &RgbImage),
+        prepass_b: &[RgbImage],
+        pages_b: &dyn PageSource,
+        pages: Range<PdfPageIndex>,
+        state: &ComparisonState,
+    ) -> Result<Vec<Candidate>, PDFComparisonError> {
+        let thumbnail_a = Thumbnails::downscale(img_a, self.config.prepass_width);
+        let pixels = thumbnail_a.width() as f64 * thumbnail_a.height() as f64;
+        // Thumbnails of pages with different dimensions differ in size as well, so pages
+        // without a candidate of the same size can't match at full resolution either.
+        let mut candidates = pages
+            .clone()
+            .zip(&prepass_b[pages.start as usize..])
+            .filter_map(|(b, thumbnail_b)| {
+                match self.compare_images(&thumbnail_a, thumbnail_b, self.config.pixel_tolerance) {
+                    Similiarity::Similar(differing) => Some((b, differing as f64 / pixels)),
+                    Similiarity::Different => None,
+                }
+            })
+            .collect::<Vec<(PdfPageIndex, f64)>>();
+        candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let page_b = match candidates.as_slice() {
+            [] => return Ok(Vec::new()),
+            [(_, best), (_, second), ..] if second - best <= self.config.prepass_epsilon => {
+                log::trace!("Thumbnails are ambiguous, matching at full resolution");
+                return self.find_min_similarity((a, img_a), pages_b, pages, state);
+            }
+            [(page_b, _), ..] => *page_b,
+        };
+        log::trace!("Thumbnails matched page: {}", page_b);
+        let img_b = pages_b.page_image(
+            state,
+            DocumentSide::B,
+            page_b,
+            ComparisonOperation::MatchingPages,
+        )?;
+        match self.differing_pixels(state, (a, img_a), (page_b, &img_b)) {
+            Similiarity::Similar(differing) => Ok(vec![(page_b, differing)]),
+            Similiarity::Different => self.find_min_similarity((a, img_a), pages_b, pages, state),
+        }
+    }
+
+    /// Compares `img_a`, the render of page `a`, against the `pages` of `pages_b` and
+    /// returns the pages it can be matched to.
+    fn find_min_similarity(
+        &self,
+        (a, img_a): (PdfPageIndex, &RgbImage),
+        pages_b: &dyn PageSource,
+        pages: Range<PdfPageIndex>,
+        state: &ComparisonState,
+    ) -> Result<Vec<Candidate>, PDFComparisonError> {
+        let mut candidates = Vec::new();
+        for i in pages {
+            log::trace!("Comparing to page: {}", i);
+            let img_b = pages_b.page_image(
+                state,
+                DocumentSide::B,
+                i,
+                ComparisonOperation::MatchingPages,
+            )?;
+            if let Similiarity::Similar(differing) =
+                self.differing_pixels(state, (a, img_a), (i, &img_b))
+            {
+                candidates.push((i, differing));
+            }
+        }
+        Ok(candidates)
+    }
+
+    /// Differing pixels of `img_a` and `img_b`, only of the area both cover with
+    /// [`ComparisonConfig::compare_resized_pages`].
+    fn compare_images(&self, img_a: &RgbImage, img_b: &RgbImage, tolerance: u8) -> Similiarity {
+        let similarity = AtomicUsize::new(0);
+        if img_a.dimensions() != img_b.dimensions() && !self.config.compare_resized_pages {
+            return Similiarity::Different;
+        }
+        let (width, height) = overlap(img_a, img_b);
+        self.install(|| {
+            (0..width).into_par_iter().for_each(|x| {
+                (0..height).into_par_iter().for_each(|y| {
+                    if !pixels_match(img_a.get_pixel(x, y), img_b.get_pixel(x, y), tolerance) {
+                        similarity.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                })
+            })
+        });
+        Similiarity::Similar(similarity.into_inner())
+    }
+
+    /// Differing pixels of the renders `img_a` of page `a` and `img_b` of page `b`. Renders
+    /// with the same digest are identical without comparing their pixels.
+    fn differing_pixels(
+        &self,
+        state: &ComparisonState,
+        (a, img_a): (PdfPageIndex, &RgbImage),
+        (b, img_b): (PdfPageIndex, &RgbImage),
+    ) -> Similiarity {
+        match state.identical_renders(a, b) {
+            true => Similiarity::Similar(0),
+            false => self.compare_images(img_a, img_b, self.config.pixel_tolerance),
+        }
+    }
+
+    /// Whether page `a` of the current version counts as identical to its match `b` with
+    /// `differing` pixels, judged by the digests of their renders from matching the pages.
+    fn counts_as_identical(
+        &self,
+        state: &ComparisonState,
+        a: usize,
+        b: PdfPageIndex,
+        differing: usize,
+    ) -> bool {
+        let Ok(a) = PdfPageIndex::try_from(a) else {
+            return false;
+        };
+        if state.identical_renders(a, b) {
+            return true;
+        }
+        state.digest(DocumentSide::A, a).is_some_and(|digest| {
+            let pixels = digest.dimensions.0 as f64 * digest.dimensions.1 as f64;
+            differing as f64 <= self.config.page_threshold * pixels
+        })
+    }
+
+    /// Runs `op` on the thread pool of the comparison, parallel iterators inside it use
+    /// that pool instead of the global one.
+    fn install<R: Send>(&self, op: impl FnOnce() -> R + Send) -> R {
+        match self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    fn is_selected(&self, page: PdfPageIndex) -> bool {
+        self.config
+            .pages
+            .as_ref()
+            .is_none_or(|pages| pages.contains(page as usize))
+    }
+}
+
+/// The current and the previous version of a comparison.
+type Documents<'a> = (Arc<PdfDocument<'a>>, Option<Arc<PdfDocument<'a>>>);
+
+/// Two loaded documents with every page of the current version matched, whose differences
+/// are located one page after another.
+struct MatchedPages<'a> {
+    pdf_a: Arc<PdfDocument<'a>>,
+    pdf_b: Option<Arc<PdfDocument<'a>>>,
+    similarities: Vec<PageSimilarity>,
+    thumbnails: Thumbnails,
+    changes: DocumentChanges,
+    new_file: bool,
+}
+
+impl MatchedPages<'_> {
+    /// Locates the differences of page `index` of the current version, rendering the pages
+    /// with `comparison`.
+    fn locate_differences(
+        &self,
+        comparison: &PDFComparison,
+        index: usize,
+        state: &ComparisonState,
+    ) -> Result<(Comparison, Option<PageRenders>), PDFComparisonError> {
+        let a = comparison.pages(&self.pdf_a);
+        let b = self.pdf_b.as_ref().map(|pdf| comparison.pages(pdf));
+        comparison.matcher().locate_differences(
+            (&a, b.as_ref().map(|b| b as &dyn PageSource)),
+            &self.similarities,
+            index,
+            state,
+        )
+    }
+}
+
+/// Where the page images a [`PageMatcher`] works on come from, the rendering layer of a
+/// comparison.
+trait PageSource {
+    fn page_count(&self) -> PdfPageIndex;
+
+    /// The image of `page`, which is of the document on `side`. `operation` is what it's
+    /// needed for.
+    fn page_image(
+        &self,
+        state: &ComparisonState,
+        side: DocumentSide,
+        page: PdfPageIndex,
+        operation: ComparisonOperation,
+    ) -> Result<Arc<RgbImage>, PDFComparisonError>;
+}
+
+/// A loaded document whose pages `comparison` renders, see [`PDFComparison::render_pdf_page`].
+struct DocumentPages<'c, 'a> {
+    comparison: &'c PDFComparison,
+    pdf: &'c Arc<PdfDocument<'a>>,
+}
+
+impl PageSource for DocumentPages<'_, '_> {
+    fn page_count(&self) -> PdfPageIndex {
+        self.pdf.pages().len()
+    }
+
+    fn page_image(
+        &self,
+        state: &ComparisonState,
+        side: DocumentSide,
+        page: PdfPageIndex,
+        operation: ComparisonOperation,
+    ) -> Result<Arc<RgbImage>, PDFComparisonError> {
+        self.comparison
+            .render_pdf_page(self.pdf.clone(), state, side, page, operation)
+    }
+}
+
+/// Pages rendered before the comparison, see [`compare_rendered_pages`].
+struct RenderedPages(Vec<Arc<RgbImage>>);
+
+impl PageSource for RenderedPages {
+    fn page_count(&self) -> PdfPageIndex {
+        self.0.len() as PdfPageIndex
+    }
+
+    fn page_image(
+        &self,
+        _: &ComparisonState,
+        side: DocumentSide,
+        page: PdfPageIndex,
+        _: ComparisonOperation,
+    ) -> Result<Arc<RgbImage>, PDFComparisonError> {
+        self.0
+            .get(page as usize)
+            .cloned()
+            .ok_or(PDFComparisonError::PageIndexOutOfRange {
+                document: DocumentRef::new(side),
+                page: page as usize,
+            })
+    }
+}
+
+/// Iterator of [`PDFComparison::compare_pdfs_iter`], locating the differences of the next
+/// page whenever it's advanced.
+struct PageComparisons<'a> {
+    comparison: &'a PDFComparison,
+    matched: MatchedPages<'a>,
+    state: ComparisonState<'a>,
+    next: usize,
+    failed: bool,
+}
+
+impl Iterator for PageComparisons<'_> {
+    type Item = Result<(PdfPageIndex, Comparison), PDFComparisonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.next >= self.matched.similarities.len() {
+            return None;
+        }
+        let index = self.next;
+        self.next += 1;
+        let result = self
+            .matched
+            .locate_differences(self.comparison, index, &self.state)
+            .map(|(comparison, _)| (index as PdfPageIndex, comparison));
+        self.failed = result.is_err();
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = match self.failed {
+            true => 0,
+            false => self.matched.similarities.len() - self.next,
+        };
+        (remaining.min(1), Some(remaining))
+    }
+}
+
+/// What a single comparison shares between its steps.
+struct ComparisonState<'a> {
+    cache: RefCell<RenderCache>,
+    memory: RefCell<RenderBudget>,
+    /// Width and maximum height pages are rendered at instead of the configured ones, with
+    /// the matching render settings, see [`ComparisonConfig::render_memory_limit`].
+    reduced: RefCell<Option<((i32, i32), PdfRenderConfig)>>,
+    timings: RefCell<Timings>,
+    /// Checked before every page render.
+    interrupt: Interrupt<'a>,
+    /// Reused by every blurred render, see [`ComparisonConfig::blur_radius`].
+    blur_scratch: RefCell<Vec<u8>>,
+    /// Digest of every page rendered so far, kept when the render itself is evicted from
+    /// the cache.
+    digests: RefCell<HashMap<(DocumentSide, PdfPageIndex), RenderDigest>>,
+    pages_rendered: Cell<usize>,
+    /// Ink coverage of every page checked for being blank.
+    ink_coverage: RefCell<HashMap<(DocumentSide, PdfPageIndex), f64>>,
+    /// Where the versions were read from, if both were, for matching their pages in child
+    /// processes.
+    #[cfg(feature = "serde")]
+    sources: RefCell<Option<Sources>>,
+}
+
+/// The files the versions of a comparison were read from and the digests of what was read.
+#[cfg(feature = "serde")]
+struct Sources {
+    paths: (PathBuf, PathBuf),
+    sha256: ([u8; 32], [u8; 32]),
+}
+
+/// Splits `pages` pages into at most `threads` ranges of about the same length.
+#[cfg(feature = "serde")]
+fn worker_ranges(pages: PdfPageIndex, threads: usize) -> Vec<Range<PdfPageIndex>> {
+    let len = (pages as usize).div_ceil(threads.max(1)).max(1);
+    (0..pages as usize)
+        .step_by(len)
+        .map(|start| start as PdfPageIndex..(start + len).min(pages as usize) as PdfPageIndex)
+        .collect()
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod worker_range_tests {
+    use super::worker_ranges;
+
+    #[test]
+    fn ranges_cover_every_page_once_in_order() {
+        for pages in 0..40 {
+            for threads in 1..10 {
+                let ranges = worker_ranges(pages, threads);
+                assert!(ranges.len() <= threads, "{} pages, {:?}", pages, ranges);
+                let covered = ranges.iter().cloned().flatten().collect::<Vec<_>>();
+                assert_eq!(covered, (0..pages).collect::<Vec<_>>());
+                assert!(ranges.iter().all(|range| !range.is_empty()));
+            }
+        }
+        assert_eq!(worker_ranges(10, 4), vec![0..3, 3..6, 6..9, 9..10]);
+    }
+}
+
+/// Identifies the pixels of a page render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct RenderDigest {
+    dimensions: (u32, u32),
+    sha256: [u8; 32],
+}
+
+impl<'a> ComparisonState<'a> {
+    fn new(config: &ComparisonConfig, interrupt: Interrupt<'a>) -> Self {
+        ComparisonState {
+            cache: RefCell::new(RenderCache::new(config.render_cache_bytes)),
+            memory: RefCell::new(RenderBudget::new(config.render_memory_limit)),
+            reduced: RefCell::new(None),
+            timings: RefCell::default(),
+            interrupt,
+            blur_scratch: RefCell::default(),
+            digests: RefCell::default(),
+            pages_rendered: Cell::new(0),
+            ink_coverage: RefCell::default(),
+            #[cfg(feature = "serde")]
+            sources: RefCell::default(),
+        }
+    }
+
+    /// The ink coverage of the first `pages` pages of `side`, `None` for pages that weren't
+    /// checked, and the pages at most `threshold` of which is covered.
+    fn blankness(
+        &self,
+        side: DocumentSide,
+        pages: usize,
+        threshold: f64,
+    ) -> (Vec<Option<f64>>, Vec<PdfPageIndex>) {
+        let coverage = self.ink_coverage.borrow();
+        let ink_coverage = (0..pages)
+            .map(|page| {
+                let page = PdfPageIndex::try_from(page).ok()?;
+                coverage.get(&(side, page)).copied()
+            })
+            .collect::<Vec<Option<f64>>>();
+        let blank = (0..pages)
+            .filter(|page| ink_coverage[*page].is_some_and(|v| v <= threshold))
+            .filter_map(|page| PdfPageIndex::try_from(page).ok())
+            .collect();
+        (ink_coverage, blank)
+    }
+
+    /// Whether page `a` of the current version was found blank with `threshold`.
+    fn is_blank(&self, a: PdfPageIndex, threshold: f64) -> bool {
+        self.ink_coverage
+            .borrow()
+            .get(&(DocumentSide::A, a))
+            .is_some_and(|v| *v <= threshold)
+    }
+
+    fn digest(&self, side: DocumentSide, page: PdfPageIndex) -> Option<RenderDigest> {
+        self.digests.borrow().get(&(side, page)).copied()
+    }
+
+    /// Whether page `a` of the current version and page `b` of the previous version were
+    /// rendered to the same pixels.
+    fn identical_renders(&self, a: PdfPageIndex, b: PdfPageIndex) -> bool {
+        match (
+            self.digest(DocumentSide::A, a),
+            self.digest(DocumentSide::B, b),
+        ) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// What stops a comparison or edit between two pages: a deadline passing or a token being
+/// cancelled.
+#[derive(Debug, Clone, Copy, Default)]
+struct Interrupt<'a> {
+    deadline: Option<Instant>,
+    cancel: Option<&'a CancellationToken>,
+}
+
+enum Interruption {
+    TimedOut,
+    Cancelled,
+}
+
+impl<'a> Interrupt<'a> {
+    fn new(deadline: Option<Instant>, cancel: Option<&'a CancellationToken>) -> Self {
+        Interrupt { deadline, cancel }
+    }
+
+    /// Why the work has to stop, if it has to. Cancelling wins over the deadline.
+    fn check(&self) -> Option<Interruption> {
+        if self.cancel.is_some_and(|v| v.is_cancelled()) {
+            return Some(Interruption::Cancelled);
+        }
+        self.deadline
+            .is_some_and(|v| Instant::now() >= v)
+            .then_some(Interruption::TimedOut)
+    }
+
+    /// Like `check`, as the error of a comparison of the document on `side`.
+    fn check_comparison(&self, side: DocumentSide) -> Result<(), PDFComparisonError> {
+        let document = DocumentRef::new(side);
+        match self.check() {
+            Some(Interruption::TimedOut) => Err(PDFComparisonError::TimedOut { document }),
+            Some(Interruption::Cancelled) => Err(PDFComparisonError::Cancelled { document }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Step of an edit an error occurred in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditOperation {
+    CreatingDocument,
+    RemovingIdenticalPages,
+    CopyingChangedPage,
+    OverlayingPreviousVersion,
+    MarkingDifferences,
+    StampingLabel,
+    StampingBanner,
+    StampingIdentity,
+    ComposingSideBySide,
+    WritingSummary,
+    MergingDocuments,
+}
+
+impl std::fmt::Display for EditOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CreatingDocument => write!(f, "creating the output document"),
+            Self::RemovingIdenticalPages => write!(f, "removing identical pages"),
+            Self::CopyingChangedPage => write!(f, "copying the changed page into the excerpt"),
+            Self::OverlayingPreviousVersion => write!(f, "overlaying the previous version"),
+            Self::MarkingDifferences => write!(f, "marking differences"),
+            Self::StampingLabel => write!(f, "stamping the label"),
+            Self::StampingBanner => write!(f, "stamping the new document banner"),
+            Self::StampingIdentity => write!(f, "embedding the identity of the versions"),
+            Self::ComposingSideBySide => write!(f, "composing the side-by-side view"),
+            Self::WritingSummary => write!(f, "writing the summary"),
+            Self::MergingDocuments => write!(f, "merging the diffs"),
+        }
+    }
+}
+
+/// Errors of [`PDFEditor`]. Page numbers are zero based, but displayed one based.
+#[derive(Debug)]
+pub enum PDFEditorError {
+    UnableToReadPDF {
+        document: DocumentRef,
+        source: std::io::Error,
+    },
+    UnableToWritePDF {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    UnableToLoadPDF {
+        document: DocumentRef,
+        source: PdfiumError,
+    },
+    /// Serializing the diff of `document` failed.
+    UnableToSavePDF {
+        document: DocumentRef,
+        source: PdfiumError,
+    },
+    /// Reading or modifying `page` of `document` failed, or editing `document` itself if
+    /// there is no page.
+    UnableToModifyPDF {
+        document: DocumentRef,
+        page: Option<usize>,
+        operation: EditOperation,
+        source: PdfiumError,
+    },
+    PageIndexOutOfRange {
+        document: DocumentRef,
+        page: usize,
+    },
+    PageCountMismatch {
+        document: DocumentRef,
+        expected: usize,
+        got: usize,
+    },
+    /// The comparison streamed into [`PDFEditor::mark_differences_iter`] failed.
+    ComparisonFailed(PDFComparisonError),
+    /// Writing the diff of `document` was abandoned because it passed its deadline.
+    TimedOut {
+        document: DocumentRef,
+    },
+    /// Writing the diff of `document` was stopped through its [`CancellationToken`].
+    Cancelled {
+        document: DocumentRef,
+    },
+}
+
+impl PDFEditorError {
+    fn modifying(
+        side: DocumentSide,
+        page: Option<usize>,
+        operation: EditOperation,
+    ) -> impl FnOnce(PdfiumError) -> PDFEditorError {
+        move |source| PDFEditorError::UnableToModifyPDF {
+            document: DocumentRef::new(side),
+            page,
+            operation,
+            source,
+        }
+    }
+
+    /// The document the error concerns, if any.
+    pub fn document(&self) -> Option<&DocumentRef> {
+        match self {
+            Self::UnableToReadPDF { document, .. }
+            | Self::UnableToLoadPDF { document, .. }
+            | Self::UnableToSavePDF { document, .. }
+            | Self::UnableToModifyPDF { document, .. }
+            | Self::PageIndexOutOfRange { document, .. }
+            | Self::PageCountMismatch { document, .. }
+            | Self::TimedOut { document }
+            | Self::Cancelled { document } => Some(document),
+            Self::ComparisonFailed(e) => Some(e.document()),
+            Self::UnableToWritePDF { .. } => None,
+        }
+    }
+
+    /// The document of an error of marking, `None` for failed comparisons and writes.
+    fn document_mut(&mut self) -> Option<&mut DocumentRef> {
+        match self {
+            Self::UnableToReadPDF { document, .. }
+            | Self::UnableToLoadPDF { document, .. }
+            | Self::UnableToSavePDF { document, .. }
+            | Self::UnableToModifyPDF { document, .. }
+            | Self::PageIndexOutOfRange { document, .. }
+            | Self::PageCountMismatch { document, .. }
+            | Self::TimedOut { document }
+            | Self::Cancelled { document } => Some(document),
+            Self::UnableToWritePDF { .. } | Self::ComparisonFailed(_) => None,
+        }
+    }
+
+    /// Records `path` as the location of the `side` document, if the error concerns it.
+    pub fn with_path(mut self, side: DocumentSide, path: &Path) -> Self {
+        if let Self::ComparisonFailed(e) = self {
+            return Self::ComparisonFailed(e.with_path(side, path));
+        }
+        if let Some(document) = self.document_mut() {
+            if document.side == side && document.path.is_none() {
+                document.path = Some(path.to_path_buf());
+            }
+        }
+        self
+    }
+
+    /// Turns the sides around for an error of marking the previous version in the place of
+    /// the current one, see [`PDFEditor::mark_previous_version`].
+    fn for_previous_version(mut self) -> Self {
+        if let Some(document) = self.document_mut() {
+            document.side = match document.side {
+                DocumentSide::A => DocumentSide::B,
+                DocumentSide::B => DocumentSide::A,
+            };
+        }
+        self
+    }
+
+    /// Records the paths of both documents that are known.
+    fn with_paths(self, a: Option<&Path>, b: Option<&Path>) -> Self {
+        let error = match a {
+            Some(a) => self.with_path(DocumentSide::A, a),
+            None => self,
+        };
+        match b {
+            Some(b) => error.with_path(DocumentSide::B, b),
+            None => error,
+        }
+    }
+}
+
+impl Error for PDFEditorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::UnableToReadPDF { source, .. } | Self::UnableToWritePDF { source, .. } => {
+                Some(source)
+            }
+            Self::UnableToLoadPDF { source, .. }
+            | Self::UnableToSavePDF { source, .. }
+            | Self::UnableToModifyPDF { source, .. } => Some(source),
+            Self::PageIndexOutOfRange { .. }
+            | Self::PageCountMismatch { .. }
+            | Self::TimedOut { .. }
+            | Self::Cancelled { .. } => None,
+            Self::ComparisonFailed(e) => Some(e),
+        }
+    }
+}
+
+impl std::fmt::Display for PDFEditorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnableToReadPDF { document, source } => {
+                write!(f, "failed to read {}: {}", document, source)
+            }
+            Self::UnableToWritePDF { path, source } => {
+                write!(f, "failed to write {}: {}", path.to_string_lossy(), source)
+            }
+            Self::UnableToLoadPDF { document, source } => {
+                write!(f, "failed to load {}: {}", document, source)
+            }
+            Self::UnableToSavePDF { document, source } => {
+                write!(f, "failed to save the diff of {}: {}", document, source)
+            }
+            Self::UnableToModifyPDF {
+                document,
+                page: Some(page),
+                operation,
+                source,
+            } => write!(
+                f,
+                "failed to modify page {} of {} while {}: {}",
+                page + 1,
+                document,
+                operation,
+                source
+            ),
+            Self::UnableToModifyPDF {
+                document,
+                page: None,
+                operation,
+                source,
+            } => write!(
+                f,
+                "failed to modify {} while {}: {}",
+                document, operation, source
+            ),
+            Self::PageIndexOutOfRange { document, page } => write!(
+                f,
+                "page {} of {} exceeds the number of pages supported by pdfium",
+                page + 1,
+                document
+            ),
+            Self::PageCountMismatch {
+                document,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{} has {} pages, but {} comparisons were provided",
+                document, expected, got
+            ),
+            Self::ComparisonFailed(e) => write!(f, "{}", e),
+            Self::TimedOut { document } => {
+                write!(
+                    f,
+                    "writing the diff of {} took longer than allowed",
+                    document
+                )
+            }
+            Self::Cancelled { document } => {
+                write!(f, "writing the diff of {} was cancelled", document)
+            }
+        }
+    }
+}
+
+/// Whether every changed page of a diff could be marked. Pages that failed are still part
+/// of the diff, without their markers.
+#[derive(Debug, Default)]
+pub enum MarkOutcome {
+    #[default]
+    Complete,
+    PartialSuccess {
+        failed_pages: Vec<(PdfPageIndex, PDFEditorError)>,
+    },
+}
+
+impl MarkOutcome {
+    fn from_failures(failed_pages: Vec<(PdfPageIndex, PDFEditorError)>) -> Self {
+        match failed_pages.is_empty() {
+            true => Self::Complete,
+            false => Self::PartialSuccess { failed_pages },
+        }
+    }
+
+    /// The pages of the current version that couldn't be marked, with the reason.
+    pub fn failed_pages(&self) -> &[(PdfPageIndex, PDFEditorError)] {
+        match self {
+            Self::Complete => &[],
+            Self::PartialSuccess { failed_pages } => failed_pages,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Self::Complete)
+    }
+
+    fn with_paths(self, current: Option<&Path>, previous: Option<&Path>) -> Self {
+        match self {
+            Self::Complete => Self::Complete,
+            Self::PartialSuccess { failed_pages } => Self::PartialSuccess {
+                failed_pages: failed_pages
+                    .into_iter()
+                    .map(|(page, e)| (page, e.with_paths(current, previous)))
+                    .collect(),
+            },
+        }
+    }
+
+    /// See [`PDFEditorError::for_previous_version`].
+    fn for_previous_version(self) -> Self {
+        match self {
+            Self::Complete => Self::Complete,
+            Self::PartialSuccess { failed_pages } => Self::PartialSuccess {
+                failed_pages: failed_pages
+                    .into_iter()
+                    .map(|(page, e)| (page, e.for_previous_version()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// A written diff, with the time spent in every stage and whether every page was marked.
+#[derive(Debug, Default)]
+pub struct MarkedDiff {
+    pub timings: Timings,
+    pub outcome: MarkOutcome,
+}
+
+impl From<Timings> for MarkedDiff {
+    fn from(timings: Timings) -> Self {
+        MarkedDiff {
+            timings,
+            outcome: MarkOutcome::Complete,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MarkerMode {
+    /// Paints the markers into an image object placed on top of the page.
+    Raster,
+    /// Adds a square annotation for every difference segment.
+    Annotation,
+}
+
+/// How the raster markers are painted. Patterns stay apart from the page content when
+/// printed in black and white, where a solid colored bar turns into an indistinct gray.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MarkerStyle {
+    /// Solid bars in the marker color.
+    #[default]
+    Color,
+    /// Diagonal stripes.
+    Hatch,
+    /// Dashes along the bar.
+    Dashed,
+}
+
+impl MarkerStyle {
+    /// Whether the pixel at `column` and `row` of a marker, counted from its top left, is
+    /// painted in a pattern repeating every `period` pixels, see
+    /// [`OverlayGeometry::pattern_period`].
+    pub fn paints(&self, (column, row): (u32, u32), period: u32) -> bool {
+        let period = period.max(2);
+        match self {
+            Self::Color => true,
+            // Every other stripe of half a period, rising to the right.
+            Self::Hatch => (column + row) % period < period / 2,
+            // Every marker starts with a dash, so even the shortest ones show one.
+            Self::Dashed => row % period < (period * 2).div_ceil(3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputStyle {
+    /// Marks the differences on a copy of the current document.
+    Marked,
+    /// Places the previous and the current version of each changed page next to each other.
+    SideBySide,
+    /// Copies the changed pages into a new document and marks them there, each with a
+    /// header stating its page number in the current version. The current document is only
+    /// read, no page of it is deleted.
+    Excerpt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LabelCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Text stamped onto every changed page.
+///
+/// The template may contain the placeholders `{date}` (modification date of the current
+/// version), `{regions}`, `{percent}` and `{matched_page}`.
+#[derive(Debug, Clone)]
+pub struct LabelConfig {
+    pub template: String,
+    pub corner: LabelCorner,
+}
+
+impl Default for LabelConfig {
+    fn default() -> Self {
+        LabelConfig {
+            template: String::from(
+                "CHANGED {date} - {regions} regions, {percent}% of page, vs. previous p. {matched_page}",
+            ),
+            corner: LabelCorner::TopRight,
+        }
+    }
+}
+
+/// Name of the file [`PDFEditor`] embeds into marked diffs, see [`DiffIdentity`].
+pub const IDENTITY_ATTACHMENT_NAME: &str = "pdf_diff_gen-identity.txt";
+
+/// Comparison settings recorded in the [`DiffIdentity`] of marked diffs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentitySettings {
+    pub target_width: i32,
+    pub pixel_tolerance: u8,
+    /// Version of the pdfium build that rendered the pages, if known.
+    pub pdfium_version: Option<PdfiumVersion>,
+}
+
+/// Settings of `config`, the pdfium version is unknown to it.
+impl From<&ComparisonConfig> for IdentitySettings {
+    fn from(config: &ComparisonConfig) -> Self {
+        IdentitySettings {
+            target_width: config.target_width,
+            pixel_tolerance: config.pixel_tolerance,
+            pdfium_version: None,
+        }
+    }
+}
+
+/// Settings that change how pages are rendered for comparing, recorded in the status file
+/// so runs with different settings can be told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderSettings {
+    pub target_width: i32,
+    pub maximum_height: i32,
+    pub background: [u8; 3],
+    pub render_form_data: bool,
+    pub render_annotations: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: RenderFlags,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub blur_radius: u32,
+}
+
+impl From<&ComparisonConfig> for RenderSettings {
+    fn from(config: &ComparisonConfig) -> Self {
+        RenderSettings {
+            target_width: config.target_width,
+            maximum_height: config.maximum_height,
+            background: config.background,
+            render_form_data: config.render_form_data,
+            render_annotations: config.render_annotations,
+            flags: config.render_flags,
+            blur_radius: config.blur_radius,
+        }
+    }
+}
+
+impl std::fmt::Display for RenderSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b] = self.background;
+        write!(
+            f,
+            "width {}, maximum height {}, background #{:02x}{:02x}{:02x}, form data {}, \
+             annotations {}, {}, blur radius {}",
+            self.target_width,
+            self.maximum_height,
+            r,
+            g,
+            b,
+            on_off(self.render_form_data),
+            on_off(self.render_annotations),
+            self.flags,
+            self.blur_radius
+        )
+    }
+}
+
+/// The two versions a diff was made from, so it can still be traced back once it is passed
+/// around. The digests are the SHA-256 digests change detection uses, hex encoded.
+///
+/// pdfium can't write the information dictionary or XMP metadata, so [`PDFEditor`] embeds
+/// this as the text file [`IDENTITY_ATTACHMENT_NAME`] and prints it on the summary page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffIdentity {
+    /// Path of the current version, `None` if it was marked from memory.
+    pub source: Option<PathBuf>,
+    pub source_sha256: String,
+    pub baseline: Option<PathBuf>,
+    /// `None` if there was no previous version.
+    pub baseline_sha256: Option<String>,
+    pub tool_version: String,
+    pub settings: IdentitySettings,
+}
+
+impl DiffIdentity {
+    /// Identity of a diff of `source` against `baseline`, given with their paths and their
+    /// content digests.
+    fn new(
+        source: (Option<&Path>, [u8; 32]),
+        baseline: (Option<&Path>, Option<[u8; 32]>),
+        settings: IdentitySettings,
+    ) -> Self {
+        DiffIdentity {
+            source: source.0.map(Path::to_path_buf),
+            source_sha256: hex_digest(&source.1),
+            baseline: baseline.0.map(Path::to_path_buf),
+            baseline_sha256: baseline.1.map(|digest| hex_digest(&digest)),
+            tool_version: String::from(env!("CARGO_PKG_VERSION")),
+            settings,
+        }
+    }
+
+    /// Content of the embedded file, one `key: value` line per field. Missing values are
+    /// left empty.
+    pub fn to_text(&self) -> String {
+        let path = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .map(|v| v.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        format!(
+            "source: {}\nsource_sha256: {}\nbaseline: {}\nbaseline_sha256: {}\n\
+             tool: pdf_diff_gen {}\npdfium: {}\ntarget_width: {}\npixel_tolerance: {}\n",
+            path(&self.source),
+            self.source_sha256,
+            path(&self.baseline),
+            self.baseline_sha256.as_deref().unwrap_or_default(),
+            self.tool_version,
+            self.settings
+                .pdfium_version
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            self.settings.target_width,
+            self.settings.pixel_tolerance
+        )
+    }
+}
+
+/// Single line for the summary page, with the file names and shortened digests.
+impl std::fmt::Display for DiffIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .and_then(|v| v.file_name())
+                .map(|v| v.to_string_lossy().into_owned())
+        };
+        let short = |digest: &str| digest.chars().take(12).collect::<String>();
+        write!(
+            f,
+            "{} ({})",
+            name(&self.source).unwrap_or_else(|| String::from("current")),
+            short(&self.source_sha256)
+        )?;
+        match &self.baseline_sha256 {
+            Some(digest) => write!(
+                f,
+                " vs. {} ({})",
+                name(&self.baseline).unwrap_or_else(|| String::from("previous")),
+                short(digest)
+            ),
+            None => write!(f, " vs. no previous version"),
+        }?;
+        write!(f, ", pdf_diff_gen {}", self.tool_version)?;
+        if let Some(version) = self.settings.pdfium_version {
+            write!(f, ", pdfium {}", version)?;
+        }
+        write!(
+            f,
+            ", width {}, tolerance {}",
+            self.settings.target_width, self.settings.pixel_tolerance
+        )
+    }
+}
+
+/// What is stamped onto a marked diff besides the markers.
+struct Stamps<'a> {
+    label_date: &'a str,
+    identity: Option<DiffIdentity>,
+    /// Whether there is no previous version, which gets the pages the new document banner
+    /// instead of markers.
+    new_document: bool,
+}
+
+/// Text of the banner across the top of every page of a marked document without a previous
+/// version, instead of the markers of changed pages.
+pub const NEW_DOCUMENT_BANNER: &str = "NEW DOCUMENT \u{2014} no previous version";
+const BANNER_HEIGHT: f32 = 22.;
+const BANNER_FONT_SIZE: f32 = 12.;
+
+const LABEL_FONT_SIZE: f32 = 8.;
+const LABEL_MIN_FONT_SIZE: f32 = 4.;
+const LABEL_MARGIN: f32 = 6.;
+const LABEL_COLOR: PdfColor = PdfColor::new(200, 0, 0, 255);
+/// Color of the page number header of the pages of an excerpt, see [`OutputStyle::Excerpt`].
+const EXCERPT_HEADER_COLOR: PdfColor = PdfColor::new(90, 90, 90, 255);
+/// Average glyph width of Helvetica relative to the font size, used to estimate label widths.
+const LABEL_GLYPH_WIDTH: f32 = 0.55;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MarkerPosition {
+    Left,
+    Right,
+    Both,
+}
+
+/// Default resolution of the raster overlay in pixels per page point.
+const OVERLAY_PIXELS_PER_POINT: f32 = 5.;
+
+/// Default longest side of the raster overlay and the overlay renders of a page in pixels.
+const MAX_OVERLAY_PIXELS: u32 = 16384;
+
+/// Resolution of the raster markers, see [`MarkerMode::Raster`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverlayResolution {
+    /// The same number of pixels per page point on every page.
+    PixelsPerPoint(f32),
+    /// Pages as many pixels wide as the comparison renders them, see
+    /// [`RenderSettings::target_width`], so marker edges fall on the rows the segments were
+    /// measured on.
+    RenderWidth(i32),
+}
+
+impl Default for OverlayResolution {
+    fn default() -> Self {
+        Self::PixelsPerPoint(OVERLAY_PIXELS_PER_POINT)
+    }
+}
+
+impl From<&RenderSettings> for OverlayResolution {
+    fn from(settings: &RenderSettings) -> Self {
+        Self::RenderWidth(settings.target_width)
+    }
+}
+
+impl OverlayResolution {
+    /// Size of the overlay of a page of `width` by `height` points. Pages whose overlay
+    /// would be longer than `max_pixels` on either side get proportionally fewer pixels per
+    /// point.
+    pub fn geometry(&self, (width, height): (f32, f32), max_pixels: u32) -> OverlayGeometry {
+        let mut pixels_per_point = match *self {
+            Self::PixelsPerPoint(pixels_per_point) => pixels_per_point,
+            Self::RenderWidth(target_width) => target_width as f32 / width,
+        };
+        let longest = width.max(height) * pixels_per_point;
+        if longest > max_pixels as f32 {
+            pixels_per_point *= max_pixels as f32 / longest;
+        }
+        if !pixels_per_point.is_finite() || pixels_per_point < 0. {
+            pixels_per_point = 0.;
+        }
+        OverlayGeometry {
+            page_size: (width, height),
+            pixels_per_point,
+            width: (width * pixels_per_point).round() as u32,
+            height: (height * pixels_per_point).round() as u32,
+        }
+    }
+}
+
+/// Size of the raster overlay of a page, see [`OverlayResolution::geometry`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayGeometry {
+    /// Width and height of the page in points.
+    pub page_size: (f32, f32),
+    pub pixels_per_point: f32,
+    /// Width of the overlay in pixels.
+    pub width: u32,
+    /// Height of the overlay in pixels.
+    pub height: u32,
+}
+
+impl OverlayGeometry {
+    /// Rows of the overlay the marker of the segment `(start, end)` covers, measured from the
+    /// top. Markers are at least `MIN_MARKER_HEIGHT` points high.
+    pub fn marker_rows(&self, (start, end): (f64, f64)) -> Range<u32> {
+        let (top, bottom) = PDFEditor::segment_extent(start, end, self.page_size.1);
+        let first = ((top * self.pixels_per_point).floor() as u32).min(self.height);
+        let last = ((bottom * self.pixels_per_point).ceil() as u32).min(self.height);
+        first..last
+    }
+
+    /// Pixels the patterns of [`MarkerStyle`] repeat after, the same length in points at
+    /// every resolution.
+    pub fn pattern_period(&self) -> u32 {
+        ((MARKER_PATTERN_PERIOD * self.pixels_per_point).round() as u32).max(2)
+    }
+}
+
+/// Minimum height of a marker in page points, so that zero-length segments remain visible.
+const MIN_MARKER_HEIGHT: f32 = 2.;
+
+/// Length in page points the patterns of [`MarkerStyle`] repeat after.
+const MARKER_PATTERN_PERIOD: f32 = 6.;
+
+/// Resolution the previous version is rendered at for overlays, in pixels per page point.
+const OVERLAY_RENDER_PIXELS_PER_POINT: f32 = 2.;
+
+const SUMMARY_MARGIN: f32 = 50.;
+const SUMMARY_LINE_HEIGHT: f32 = 16.;
+const SUMMARY_INDENT: f32 = 15.;
+
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayConfig {
+    /// Opacity of the old content between 0 and 1.
+    pub opacity: f32,
+    /// Color the old content is tinted in.
+    pub color: [u8; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct PDFEditorConfig {
+    pub output_style: OutputStyle,
+    pub marker_mode: MarkerMode,
+    pub marker_color: [u8; 3],
+    /// Color of the markers of [`DifferenceSegments::redactions`], drawn beneath those of
+    /// the other differences.
+    pub redaction_color: [u8; 3],
+    /// Pattern of the raster markers. Annotations and the markers of side by side output
+    /// are solid.
+    pub marker_style: MarkerStyle,
+    /// Width of the raster marker bars in page points.
+    pub marker_width: f32,
+    pub marker_position: MarkerPosition,
+    /// Resolution of the raster markers.
+    pub overlay_resolution: OverlayResolution,
+    /// Longest side in pixels of the raster markers and of the renders of overlays and side
+    /// by side output of a page. Larger pages are rendered at a lower resolution.
+    pub max_overlay_pixels: u32,
+    /// Draws the matched page of the previous version on top of changed pages.
+    pub overlay: Option<OverlayConfig>,
+    /// Prepends a page listing all changed pages to marked diffs.
+    pub summary_page: bool,
+    /// Stamps a text label onto every changed page of marked diffs.
+    pub label: Option<LabelConfig>,
+    /// Zone the dates of the labels are written in.
+    pub timezone: Timezone,
+    /// Embeds a [`DiffIdentity`] recording these settings into marked diffs.
+    pub identity: Option<IdentitySettings>,
+    /// Color of the banner marking the pages of documents without a previous version, see
+    /// [`NEW_DOCUMENT_BANNER`].
+    pub new_document_color: [u8; 3],
+    pub annotation_author: String,
+    pub annotation_comment: String,
+    /// Color transparent areas of pages are rendered on for overlays and side by side
+    /// output. Should match [`ComparisonConfig::background`].
+    pub background: [u8; 3],
+}
+
+impl Default for PDFEditorConfig {
+    fn default() -> Self {
+        PDFEditorConfig {
+            output_style: OutputStyle::Marked,
+            marker_mode: MarkerMode::Raster,
+            marker_color: [255, 0, 0],
+            redaction_color: [0, 102, 204],
+            marker_style: MarkerStyle::Color,
+            marker_width: 2.,
+            marker_position: MarkerPosition::Left,
+            overlay_resolution: OverlayResolution::default(),
+            max_overlay_pixels: MAX_OVERLAY_PIXELS,
+            overlay: None,
+            summary_page: false,
+            label: None,
+            timezone: Timezone::Utc,
+            identity: None,
+            new_document_color: [0, 102, 204],
+            annotation_author: String::from("pdf_diff_gen"),
+            annotation_comment: String::from("changed vs. previous version"),
+            background: [255, 255, 255],
+        }
+    }
+}
+
+/// A document of a combined diff, see [`PDFEditor::merge_documents`].
+#[derive(Debug, Clone)]
+pub struct MergeSource {
+    /// Shown on the cover page of the document, usually the path of the source document.
+    pub title: String,
+    /// The diff whose pages follow the cover page, `None` if there is none.
+    pub diff: Option<PathBuf>,
+    /// Shown on the cover page below the title, e.g. why there is no diff.
+    pub note: Option<String>,
+}
+
+/// Turns [`Comparison`]s into diff pdfs.
+pub struct PDFEditor {
+    pdfium: Arc<Pdfium>,
+    config: PDFEditorConfig,
+    deadline: Option<Instant>,
+}
+
+impl PDFEditor {
+    pub fn new(pdfium: Arc<Pdfium>, config: PDFEditorConfig) -> Self {
+        PDFEditor {
+            pdfium,
+            config,
+            deadline: None,
+        }
+    }
+
+    /// Abandons writing a diff with [`PDFEditorError::TimedOut`] before the next page once
+    /// `deadline` passes. Applies to every following diff until it's replaced.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Fails if the deadline passed or `cancel` was cancelled, checked before every page.
+    fn check_interrupted(&self, cancel: Option<&CancellationToken>) -> Result<(), PDFEditorError> {
+        let document = DocumentRef::new(DocumentSide::A);
+        match Interrupt::new(self.deadline, cancel).check() {
+            Some(Interruption::TimedOut) => Err(PDFEditorError::TimedOut { document }),
+            Some(Interruption::Cancelled) => Err(PDFEditorError::Cancelled { document }),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the diff pdf for `current_path` in the configured output style and returns
+    /// the time spent loading, marking and saving. Pages that can't be marked are kept
+    /// unmarked and reported in the [`MarkOutcome`], only failing to load or save the
+    /// documents fails the diff. Once `cancel` is cancelled, the diff is abandoned before
+    /// the next page with [`PDFEditorError::Cancelled`].
+    pub fn generate_diff(
+        &self,
+        current_path: &Path,
+        previous_path: Option<&Path>,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        match self.config.output_style {
+            OutputStyle::Marked | OutputStyle::Excerpt => {
+                self.mark_differences(current_path, previous_path, differences, out_path, cancel)
+            }
+            OutputStyle::SideBySide => self
+                .render_side_by_side(current_path, previous_path, differences, out_path, cancel)
+                .map(MarkedDiff::from),
+        }
+    }
+
+    /// Like `generate_diff`, but writes the diff from the documents loaded by
+    /// [`PDFComparison::compare_pdfs_loaded`] instead of the files. `current` is modified by
+    /// the marked style, it only holds the changed pages afterwards.
+    pub fn generate_diff_in_document(
+        &self,
+        current: &mut LoadedDocument,
+        previous: Option<&LoadedDocument>,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        match self.config.output_style {
+            OutputStyle::Marked | OutputStyle::Excerpt => {
+                self.mark_differences_in_document(current, previous, differences, out_path, cancel)
+            }
+            OutputStyle::SideBySide => {
+                let mut timings = Timings::default();
+                self.compose_side_by_side_documents(
+                    &current.document,
+                    previous.map(|v| &v.document),
+                    differences,
+                    out_path,
+                    &mut timings,
+                    cancel,
+                )
+                .map(|()| MarkedDiff::from(timings))
+                .map_err(|e| {
+                    e.with_paths(
+                        current.path.as_deref(),
+                        previous.and_then(|v| v.path.as_deref()),
+                    )
+                })
+            }
+        }
+    }
+
+    /// Creates a new document with one double-width page per changed page, showing the
+    /// previous version on the left and the current version on the right. Pages without a
+    /// counterpart get a gray placeholder on the missing side.
+    pub fn render_side_by_side(
+        &self,
+        in_a: &Path,
+        in_b: Option<&Path>,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Timings, PDFEditorError> {
+        let mut timings = Timings::default();
+        let result = self
+            .compose_side_by_side(in_a, in_b, differences, out_path, &mut timings, cancel)
+            .map(|()| timings);
+        match in_b {
+            Some(in_b) => result.map_err(|e| {
+                e.with_path(DocumentSide::A, in_a)
+                    .with_path(DocumentSide::B, in_b)
+            }),
+            None => result.map_err(|e| e.with_path(DocumentSide::A, in_a)),
+        }
+    }
+
+    /// Writes a single pdf to `out_path` holding a cover page for every document of
+    /// `documents`, followed by the pages of its diff. A diff that can't be loaded or copied
+    /// is left out with a note on its cover page instead of failing the merge.
+    pub fn merge_documents(
+        &self,
+        documents: &[MergeSource],
+        out_path: &Path,
+    ) -> Result<(), PDFEditorError> {
+        let modifying =
+            || PDFEditorError::modifying(DocumentSide::A, None, EditOperation::MergingDocuments);
+        let mut out = self
+            .pdfium
+            .create_new_pdf()
+            .map_err(PDFEditorError::modifying(
+                DocumentSide::A,
+                None,
+                EditOperation::CreatingDocument,
+            ))
+            .map_err(|e| e.with_path(DocumentSide::A, out_path))?;
+        let font = out.fonts_mut().helvetica();
+        let title_font = out.fonts_mut().helvetica_bold();
+
+        for document in documents {
+            let diff = document.diff.as_ref().map(|diff| {
+                self.pdfium.load_pdf_from_file(diff, None).map_err(|e| {
+                    format!(
+                        "The diff {} couldn't be loaded: {}",
+                        diff.to_string_lossy(),
+                        e
+                    )
+                })
+            });
+            let mut notes = document.note.iter().cloned().collect::<Vec<String>>();
+            if let Some(Err(e)) = &diff {
+                log::warn!("Leaving a diff out of the combined diff. {}", e);
+                notes.push(e.clone());
+            }
+            let cover_index = out.pages().len();
+            let size = PdfPagePaperSize::a4();
+            let mut cover = out
+                .pages_mut()
+                .create_page_at_end(size)
+                .map_err(modifying())
+                .map_err(|e| e.with_path(DocumentSide::A, out_path))?;
+            let mut y = size.height().value - SUMMARY_MARGIN;
+            let lines = std::iter::once((document.title.clone(), title_font, 16.))
+                .chain(notes.into_iter().map(|note| (note, font, 11.)));
+            for (line, font, font_size) in lines {
+                cover
+                    .objects_mut()
+                    .create_text_object(
+                        PdfPoints::new(SUMMARY_MARGIN),
+                        PdfPoints::new(y),
+                        line,
+                        font,
+                        PdfPoints::new(font_size),
+                    )
+                    .map_err(modifying())
+                    .map_err(|e| e.with_path(DocumentSide::A, out_path))?;
+                y -= 2. * SUMMARY_LINE_HEIGHT;
+            }
+            let Some(Ok(diff)) = diff else {
+                continue;
+            };
+            if let Err(e) = out.pages_mut().append(&diff) {
+                log::warn!(
+                    "Leaving the diff of {} out of the combined diff. Error: {}",
+                    document.title,
+                    e
+                );
+                // Pages copied before the failure would be attributed to the next document.
+                while out.pages().len() > cover_index + 1 {
+                    let last = out.pages().len() - 1;
+                    out.pages()
+                        .get(last)
+                        .and_then(|page| page.delete())
+                        .map_err(modifying())
+                        .map_err(|e| e.with_path(DocumentSide::A, out_path))?;
+                }
+            }
+        }
+
+        out.save_to_file(out_path)
+            .map_err(|source| PDFEditorError::UnableToSavePDF {
+                document: DocumentRef {
+                    side: DocumentSide::A,
+                    path: Some(out_path.to_path_buf()),
+                },
+                source,
+            })
+    }
+
+    fn compose_side_by_side(
+        &self,
+        in_a: &Path,
+        in_b: Option<&Path>,
+        differences: &[Comparison],
+        out_path: &Path,
+        timings: &mut Timings,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(), PDFEditorError> {
+        let loading = Instant::now();
+        let pdf_a = self
+            .pdfium
+            .load_pdf_from_file(in_a, None)
+            .map_err(|source| PDFEditorError::UnableToLoadPDF {
+                document: DocumentRef::new(DocumentSide::A),
+                source,
+            })?;
+        let pdf_b = in_b.map(|in_b| self.pdfium.load_pdf_from_file(in_b, None));
+        timings.add(TimingStage::Loading, loading.elapsed());
+        let pdf_b = match pdf_b {
+            Some(Ok(v)) => Some(v),
+            Some(Err(source)) => {
+                return Err(PDFEditorError::UnableToLoadPDF {
+                    document: DocumentRef::new(DocumentSide::B),
+                    source,
+                })
+            }
+            None => None,
+        };
+        self.compose_side_by_side_documents(
+            &pdf_a,
+            pdf_b.as_ref(),
+            differences,
+            out_path,
+            timings,
+            cancel,
+        )
+    }
+
+    /// Composes the side by side diff of the loaded `pdf_a` and `pdf_b` into `out_path`.
+    fn compose_side_by_side_documents(
+        &self,
+        pdf_a: &PdfDocument,
+        pdf_b: Option<&PdfDocument>,
+        differences: &[Comparison],
+        out_path: &Path,
+        timings: &mut Timings,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(), PDFEditorError> {
+        let marking = Instant::now();
+        PDFEditor::check_page_count(pdf_a, differences.len())?;
+        let mut out = self
+            .pdfium
+            .create_new_pdf()
+            .map_err(PDFEditorError::modifying(
+                DocumentSide::A,
+                None,
+                EditOperation::CreatingDocument,
+            ))?;
+
+        differences
+            .iter()
+            .enumerate()
+            .try_for_each(|(index, difference)| {
+                let seg = match difference {
+                    Comparison::Identical(_) => return Ok(()),
+                    Comparison::Different(seg) => seg,
+                };
+                self.check_interrupted(cancel)?;
+                let page_index = PdfPageIndex::try_from(index).map_err(|_| {
+                    PDFEditorError::PageIndexOutOfRange {
+                        document: DocumentRef::new(DocumentSide::A),
+                        page: index,
+                    }
+                })?;
+                let modifying_a = || {
+                    PDFEditorError::modifying(
+                        DocumentSide::A,
+                        Some(index),
+                        EditOperation::ComposingSideBySide,
+                    )
+                };
+                let page_a = pdf_a.pages().get(page_index).map_err(modifying_a())?;
+                let page_b = match (&pdf_b, seg.matched_page) {
+                    (Some(pdf_b), Some(matched_page)) => Some((
+                        matched_page,
+                        pdf_b
+                            .pages()
+                            .get(matched_page)
+                            .map_err(PDFEditorError::modifying(
+                                DocumentSide::B,
+                                Some(matched_page as usize),
+                                EditOperation::ComposingSideBySide,
+                            ))?,
+                    )),
+                    _ => None,
+                };
+
+                let half_width = page_a
+                    .width()
+                    .value
+                    .max(page_b.as_ref().map_or(0., |(_, v)| v.width().value));
+                let height = page_a
+                    .height()
+                    .value
+                    .max(page_b.as_ref().map_or(0., |(_, v)| v.height().value));
+                let mut page = out
+                    .pages_mut()
+                    .create_page_at_end(PdfPagePaperSize::from_points(
+                        PdfPoints::new(half_width * 2.),
+                        PdfPoints::new(height),
+                    ))
+                    .map_err(modifying_a())?;
+
+                match &page_b {
+                    Some((matched_page, page_b)) => self
+                        .place_page(&mut page, page_b, 0., height, seg)
+                        .map_err(PDFEditorError::modifying(
+                            DocumentSide::B,
+                            Some(*matched_page as usize),
+                            EditOperation::ComposingSideBySide,
+                        ))?,
+                    None => PDFEditor::place_placeholder(&mut page, 0., half_width, height)
+                        .map_err(modifying_a())?,
+                }
+                self.place_page(&mut page, &page_a, half_width, height, seg)
+                    .map_err(modifying_a())?;
+                Ok::<(), PDFEditorError>(())
+            })?;
+        timings.add(TimingStage::Marking, marking.elapsed());
+
+        timings.time(TimingStage::Saving, || {
+            out.save_to_file(out_path)
+                .map_err(|source| PDFEditorError::UnableToSavePDF {
+                    document: DocumentRef::new(DocumentSide::A),
+                    source,
+                })
+        })
+    }
+
+    /// Renders `source` into `page` with its top left corner at `x`, `target_height` and
+    /// marks the difference segments along its left edge.
+    fn place_page(
+        &self,
+        page: &mut PdfPage,
+        source: &PdfPage,
+        x: f32,
+        target_height: f32,
+        segments: &DifferenceSegments,
+    ) -> Result<(), PdfiumError> {
+        let width = source.width().value;
+        let height = source.height().value;
+        let geometry = self.render_geometry((width, height));
+        let render_config = PdfRenderConfig::new()
+            .set_target_width(geometry.width as i32)
+            .set_target_height(geometry.height as i32)
+            .set_clear_color(self.background_color());
+        let image = source.render_with_config(&render_config)?.as_image();
+        let y = target_height - height;
+        page.objects_mut().create_image_object(
+            PdfPoints::new(x),
+            PdfPoints::new(y),
+            &image,
+            Some(PdfPoints::new(width)),
+            Some(PdfPoints::new(height)),
+        )?;
+
+        let bar_width = self.config.marker_width.clamp(0., width);
+        PDFEditor::colored_segments(&self.config, segments).try_for_each(
+            |((start, end), [r, g, b])| {
+                let (top, bottom) = PDFEditor::segment_extent(start, end, height);
+                page.objects_mut()
+                    .create_path_object_rect(
+                        PdfRect::new_from_values(
+                            y + height - bottom,
+                            x,
+                            y + height - top,
+                            x + bar_width,
+                        ),
+                        None,
+                        None,
+                        Some(PdfColor::new(r, g, b, 255)),
+                    )
+                    .map(|_| ())
+            },
+        )
+    }
+
+    /// The vertical ranges of the redactions of `segments` in the redaction color followed
+    /// by the segments in the marker color, so the latter are drawn on top.
+    fn colored_segments<'a>(
+        config: &PDFEditorConfig,
+        segments: &'a DifferenceSegments,
+    ) -> impl Iterator<Item = ((f64, f64), [u8; 3])> + 'a {
+        let (redaction_color, marker_color) = (config.redaction_color, config.marker_color);
+        segments
+            .redactions
+            .iter()
+            .map(move |v| ((v.top, v.bottom), redaction_color))
+            .chain(segments.segments.iter().map(move |v| (*v, marker_color)))
+    }
+
+    fn place_placeholder(
+        page: &mut PdfPage,
+        x: f32,
+        width: f32,
+        height: f32,
+    ) -> Result<(), PdfiumError> {
+        page.objects_mut().create_path_object_rect(
+            PdfRect::new_from_values(0., x, height, x + width),
+            None,
+            None,
+            Some(PdfColor::new(210, 210, 210, 255)),
+        )?;
+        Ok(())
+    }
+
+    /// Writes a copy of `in_path` to `out_path` that only contains the changed pages, each
+    /// marked according to the configuration, and returns the time spent in every stage.
+    /// Failing pages and `cancel` work like for `generate_diff`.
+    pub fn mark_differences(
+        &self,
+        in_path: &Path,
+        previous_path: Option<&Path>,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        self.mark_differences_to(
+            in_path,
+            previous_path,
+            self.needs_previous(differences),
+            differences.iter().cloned().map(Ok),
+            out_path,
+            cancel,
+        )
+    }
+
+    /// Marks the pages of the previous version at `previous_path` that changed or were
+    /// removed, with the current version at `current_path` taking the place of the previous
+    /// version, e.g. to show what was removed. `differences` are the comparisons of the
+    /// current version, as for `mark_differences`, and are turned around with
+    /// [`Comparison::for_previous_version`]. Returns `None` without writing anything if no
+    /// page of the previous version changed.
+    pub fn mark_previous_version(
+        &self,
+        previous_path: &Path,
+        current_path: &Path,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<Option<MarkedDiff>, PDFEditorError> {
+        let document = DocumentRef {
+            side: DocumentSide::B,
+            path: Some(previous_path.to_path_buf()),
+        };
+        let previous_pages = self
+            .pdfium
+            .load_pdf_from_file(previous_path, None)
+            .map_err(|source| PDFEditorError::UnableToLoadPDF { document, source })?
+            .pages()
+            .len() as usize;
+        let previous_differences = Comparison::for_previous_version(differences, previous_pages);
+        if previous_differences
+            .iter()
+            .all(|v| matches!(v, Comparison::Identical(_)))
+        {
+            return Ok(None);
+        }
+        // Marked as if the previous version were the current one, so the sides of what
+        // went wrong are turned back around.
+        self.mark_differences(
+            previous_path,
+            Some(current_path),
+            &previous_differences,
+            out_path,
+            cancel,
+        )
+        .map(|diff| {
+            Some(MarkedDiff {
+                outcome: diff.outcome.for_previous_version(),
+                ..diff
+            })
+        })
+        .map_err(PDFEditorError::for_previous_version)
+    }
+
+    /// Like `mark_differences`, but marks every page as soon as `differences` yields its
+    /// comparison, e.g. straight from [`PDFComparison::compare_pdfs_iter`]. The comparisons
+    /// have to be in page order, their page indices aren't checked. An error of the
+    /// comparison stops marking with [`PDFEditorError::ComparisonFailed`]. The time spent
+    /// comparing is part of the marking stage of the returned timings.
+    pub fn mark_differences_iter(
+        &self,
+        in_path: &Path,
+        previous_path: Option<&Path>,
+        differences: impl IntoIterator<Item = Result<(PdfPageIndex, Comparison), PDFComparisonError>>,
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        self.mark_differences_to(
+            in_path,
+            previous_path,
+            self.may_need_previous(),
+            differences.into_iter().map(|result| {
+                result
+                    .map(|(_, comparison)| comparison)
+                    .map_err(PDFEditorError::ComparisonFailed)
+            }),
+            out_path,
+            cancel,
+        )
+    }
+
+    /// Marks `differences` on a copy of `in_path` written to `out_path`. `previous_path` is
+    /// only loaded if `needs_previous`, and only read at all if it is needed or its digest
+    /// is part of the identity.
+    fn mark_differences_to(
+        &self,
+        in_path: &Path,
+        previous_path: Option<&Path>,
+        needs_previous: bool,
+        differences: impl IntoIterator<Item = Result<Comparison, PDFEditorError>>,
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        let mut timings = Timings::default();
+        let loading = Instant::now();
+        let read = |side: DocumentSide, path: &Path| {
+            std::fs::read(path).map_err(|source| PDFEditorError::UnableToReadPDF {
+                document: DocumentRef {
+                    side,
+                    path: Some(path.to_path_buf()),
+                },
+                source,
+            })
+        };
+        let input = read(DocumentSide::A, in_path)?;
+        let previous = previous_path
+            .filter(|_| needs_previous || self.config.identity.is_some())
+            .map(|previous_path| read(DocumentSide::B, previous_path))
+            .transpose()?;
+        let identity = self.config.identity.map(|settings| {
+            DiffIdentity::new(
+                (Some(in_path), content_digest(&input)),
+                (previous_path, previous.as_deref().map(content_digest)),
+                settings,
+            )
+        });
+        let modified = std::fs::metadata(in_path).and_then(|v| v.modified()).ok();
+        let label_date = self.label_date(modified);
+        timings.add(TimingStage::Loading, loading.elapsed());
+
+        let stamps = Stamps {
+            label_date: &label_date,
+            identity,
+            new_document: previous_path.is_none(),
+        };
+        let (output, outcome) = self
+            .mark(
+                &input,
+                previous.as_deref().filter(|_| needs_previous),
+                differences,
+                &stamps,
+                &mut timings,
+                cancel,
+            )
+            .map_err(|e| e.with_paths(Some(in_path), previous_path))?;
+        PDFEditor::write_output(out_path, output, &mut timings)?;
+        Ok(MarkedDiff {
+            timings,
+            outcome: outcome.with_paths(Some(in_path), previous_path),
+        })
+    }
+
+    /// Like `mark_differences`, but marks `current` as loaded by
+    /// [`PDFComparison::compare_pdfs_loaded`] instead of reading and parsing the file again.
+    /// Identical pages are removed from `current` in the process, unless the excerpt style
+    /// leaves it untouched. `previous` is only used if the summary page or the overlay needs
+    /// it.
+    pub fn mark_differences_in_document(
+        &self,
+        current: &mut LoadedDocument,
+        previous: Option<&LoadedDocument>,
+        differences: &[Comparison],
+        out_path: &Path,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<MarkedDiff, PDFEditorError> {
+        let mut timings = Timings::default();
+        let (current_path, previous_path) = (
+            current.path.as_deref(),
+            previous.and_then(|v| v.path.as_deref()),
+        );
+        let identity = self.config.identity.map(|settings| {
+            DiffIdentity::new(
+                (current_path, current.sha256),
+                (previous_path, previous.map(|v| v.sha256)),
+                settings,
+            )
+        });
+        let label_date = self.label_date(current.modified);
+        let stamps = Stamps {
+            label_date: &label_date,
+            identity,
+            new_document: previous.is_none(),
+        };
+        let (output, outcome) = self
+            .mark_document(
+                &mut current.document,
+                previous
+                    .filter(|_| self.needs_previous(differences))
+                    .map(|v| &v.document),
+                differences.iter().cloned().map(Ok),
+                &stamps,
+                &mut timings,
+                cancel,
+            )
+            .map_err(|e| e.with_paths(current_path, previous_path))?;
+        PDFEditor::write_output(out_path, output, &mut timings)?;
+        Ok(MarkedDiff {
+            timings,
+            outcome: outcome.with_paths(current_path, previous_path),
+        })
+    }
+
+    /// Date of the labels of a version last modified at `modified`, in the configured
+    /// zone. The modification time keeps labels stable when the same version is processed
+    /// again.
+    fn label_date(&self, modified: Option<SystemTime>) -> String {
+        modified
+            .map(|v| self.config.timezone.at(v.into()).date())
+            .unwrap_or_else(|| String::from("unknown date"))
+    }
+
+    fn write_output(
+        out_path: &Path,
+        output: Vec<u8>,
+        timings: &mut Timings,
+    ) -> Result<(), PDFEditorError> {
+        timings.time(TimingStage::Saving, || {
+            std::fs::write(out_path, output).map_err(|source| PDFEditorError::UnableToWritePDF {
+                path: out_path.to_path_buf(),
+                source,
+            })
+        })
+    }
+
+    /// Like `mark_differences`, but reads the current version `input` and the optional
+    /// previous version from memory and returns the resulting document with the outcome of
+    /// marking it. Labels are dated with the current day.
+    pub fn mark_differences_bytes(
+        &self,
+        input: &[u8],
+        previous: Option<&[u8]>,
+        differences: &[Comparison],
+    ) -> Result<(Vec<u8>, MarkOutcome), PDFEditorError> {
+        let label_date = self.config.timezone.at(chrono::Utc::now()).date();
+        let identity = self.config.identity.map(|settings| {
+            DiffIdentity::new(
+                (None, content_digest(input)),
+                (None, previous.map(content_digest)),
+                settings,
+            )
+        });
+        let stamps = Stamps {
+            label_date: &label_date,
+            identity,
+            new_document: previous.is_none(),
+        };
+        self.mark(
+            input,
+            previous.filter(|_| self.needs_previous(differences)),
+            differences.iter().cloned().map(Ok),
+            &stamps,
+            &mut Timings::default(),
+            None,
+        )
+    }
+
+    /// Whether marking `differences` requires the previous version of the document.
+    fn needs_previous(&self, differences: &[Comparison]) -> bool {
+        let has_matched_pages = differences.iter().any(|v| match v {
+            Comparison::Different(seg) => seg.matched_page.is_some(),
+            Comparison::Identical(_) => false,
+        });
+        self.config.summary_page || (self.config.overlay.is_some() && has_matched_pages)
+    }
+
+    /// Whether marking any differences might require the previous version, for comparisons
+    /// that aren't known yet.
+    fn may_need_previous(&self) -> bool {
+        self.config.summary_page || self.config.overlay.is_some()
+    }
+
+    /// Marks `differences` on `input`, loading `previous` if given. Comparisons are marked
+    /// one after another as they are yielded.
+    fn mark(
+        &self,
+        input: &[u8],
+        previous: Option<&[u8]>,
+        differences: impl IntoIterator<Item = Result<Comparison, PDFEditorError>>,
+        stamps: &Stamps,
+        timings: &mut Timings,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(Vec<u8>, MarkOutcome), PDFEditorError> {
+        let loading = Instant::now();
+        let load = |bytes, side| {
+            self.pdfium
+                .load_pdf_from_byte_slice(bytes, None)
+                .map_err(|source| PDFEditorError::UnableToLoadPDF {
+                    document: DocumentRef::new(side),
+                    source,
+                })
+        };
+        let mut pdf = load(input, DocumentSide::A)?;
+        let previous = previous
+            .map(|previous| load(previous, DocumentSide::B))
+            .transpose()?;
+        timings.add(TimingStage::Loading, loading.elapsed());
+        self.mark_document(
+            &mut pdf,
+            previous.as_ref(),
+            differences,
+            stamps,
+            timings,
+            cancel,
+        )
+    }
+
+    /// Marks `differences` on the loaded `pdf` and returns it saved, `previous` is used for
+    /// the summary and the overlay. With [`OutputStyle::Excerpt`] the changed pages are
+    /// marked on copies in a new document, which is returned instead, and `pdf` is left as
+    /// it is. A page failing to be marked is kept as it is and reported in the outcome, the
+    /// following pages are still marked.
+    fn mark_document(
+        &self,
+        pdf: &mut PdfDocument,
+        previous: Option<&PdfDocument>,
+        differences: impl IntoIterator<Item = Result<Comparison, PDFEditorError>>,
+        stamps: &Stamps,
+        timings: &mut Timings,
+        cancel: Option<&CancellationToken>,
+    ) -> Result<(Vec<u8>, MarkOutcome), PDFEditorError> {
+        let expected = pdf.pages().len() as usize;
+        let differences = differences.into_iter();
+        // Known counts are checked before anything is marked, others once they run out.
+        if let (got, Some(upper)) = differences.size_hint() {
+            if got == upper {
+                PDFEditor::check_page_count(pdf, got)?;
+            }
+        }
+
+        let marking = Instant::now();
+        let mut excerpt = match self.config.output_style {
+            OutputStyle::Excerpt => Some(self.pdfium.create_new_pdf().map_err(
+                PDFEditorError::modifying(DocumentSide::A, None, EditOperation::CreatingDocument),
+            )?),
+            OutputStyle::Marked | OutputStyle::SideBySide => None,
+        };
+        let fonts = match excerpt.as_mut() {
+            Some(out) => PDFEditor::fonts(out),
+            None => PDFEditor::fonts(pdf),
+        };
+
+        let mut deleted_pages: usize = 0;
+        let mut output_pages: Vec<Option<usize>> = vec![None; expected];
+        // Only kept for the summary, which needs every comparison.
+        let mut marked = Vec::new();
+        let mut failed_pages = Vec::new();
+        let mut got = 0;
+
+        for (index, difference) in differences.enumerate() {
+            let difference = difference?;
+            got = index + 1;
+            if index >= expected {
+                return Err(PDFEditorError::PageCountMismatch {
+                    document: DocumentRef::new(DocumentSide::A),
+                    expected,
+                    got,
+                });
+            }
+            self.check_interrupted(cancel)?;
+            let page_index = PDFEditor::shifted_page_index(index, deleted_pages)?;
+            match &difference {
+                Comparison::Identical(_) if excerpt.is_some() => {}
+                Comparison::Identical(_) => {
+                    // Only shift the following pages if the page was actually removed,
+                    // otherwise every later marker would end up one page off.
+                    let deleted = pdf
+                        .pages_mut()
+                        .get(page_index)
+                        .and_then(|page| page.delete())
+                        .map_err(PDFEditorError::modifying(
+                            DocumentSide::A,
+                            Some(index),
+                            EditOperation::RemovingIdenticalPages,
+                        ));
+                    match deleted {
+                        Ok(()) => deleted_pages += 1,
+                        Err(e) => {
+                            output_pages[index] = Some(page_index as usize);
+                            log::warn!(
+                                "Unable to delete identical page {}. Keeping it. Error: {}",
+                                index + 1,
+                                e
+                            )
+                        }
+                    }
+                }
+                Comparison::Different(seg) => {
+                    let result = match excerpt.as_mut() {
+                        Some(out) => {
+                            PDFEditor::copy_changed_page(out, pdf, index).and_then(|out_index| {
+                                output_pages[index] = Some(out_index as usize);
+                                self.stamp_page_number(out, (index, out_index), stamps, fonts.0)?;
+                                self.mark_changed_page(
+                                    out,
+                                    previous,
+                                    (index, out_index),
+                                    seg,
+                                    stamps,
+                                    fonts,
+                                )
+                            })
+                        }
+                        None => {
+                            output_pages[index] = Some(page_index as usize);
+                            self.mark_changed_page(
+                                pdf,
+                                previous,
+                                (index, page_index),
+                                seg,
+                                stamps,
+                                fonts,
+                            )
+                        }
+                    };
+                    if let Err(e) = result {
+                        log::warn!(
+                            "Unable to mark page {}. Keeping it unmarked. Error: {}",
+                            index + 1,
+                            e
+                        );
+                        // Below the page count of the document, which fits a page index.
+                        failed_pages.push((index as PdfPageIndex, e));
+                    }
+                }
+            }
+            if self.config.summary_page {
+                marked.push(difference);
+            }
+        }
+        // Identical pages were deleted by now, so the count is compared to the one before.
+        if got != expected {
+            return Err(PDFEditorError::PageCountMismatch {
+                document: DocumentRef::new(DocumentSide::A),
+                expected,
+                got,
+            });
+        }
+
+        let changes = self.config.summary_page.then(|| {
+            let mut changes =
+                PageChange::from_comparisons(&marked, previous.as_ref().map(|v| v.pages().len()));
+            changes
+                .iter_mut()
+                .filter(|v| v.kind != PageChangeKind::Removed)
+                .for_each(|v| v.output_page = output_pages[v.page]);
+            changes
+        });
+        let output = match excerpt.as_mut() {
+            Some(out) => self.finish_document(out, changes.as_deref(), stamps, (timings, marking)),
+            None => self.finish_document(pdf, changes.as_deref(), stamps, (timings, marking)),
+        }?;
+        Ok((output, MarkOutcome::from_failures(failed_pages)))
+    }
+
+    /// Prepends the summary of `changes` to the marked `pdf`, embeds the identity and saves
+    /// it. The marking stage, which started at `marking`, ends before saving.
+    fn finish_document(
+        &self,
+        pdf: &mut PdfDocument,
+        changes: Option<&[PageChange]>,
+        stamps: &Stamps,
+        (timings, marking): (&mut Timings, Instant),
+    ) -> Result<Vec<u8>, PDFEditorError> {
+        if let Some(changes) = changes {
+            self.prepend_summary(pdf, changes, stamps.new_document, stamps.identity.as_ref())?;
+        }
+        if let Some(identity) = &stamps.identity {
+            pdf.attachments_mut()
+                .create_attachment_from_bytes(
+                    IDENTITY_ATTACHMENT_NAME,
+                    identity.to_text().as_bytes(),
+                )
+                .map_err(PDFEditorError::modifying(
+                    DocumentSide::A,
+                    None,
+                    EditOperation::StampingIdentity,
+                ))?;
+        }
+        timings.add(TimingStage::Marking, marking.elapsed());
+
+        timings.time(TimingStage::Saving, || {
+            pdf.save_to_bytes()
+                .map_err(|source| PDFEditorError::UnableToSavePDF {
+                    document: DocumentRef::new(DocumentSide::A),
+                    source,
+                })
+        })
+    }
+
+    /// The fonts of labels and of the banner in `pdf`.
+    fn fonts(pdf: &mut PdfDocument) -> (PdfFontToken, PdfFontToken) {
+        (
+            pdf.fonts_mut().helvetica(),
+            pdf.fonts_mut().helvetica_bold(),
+        )
+    }
+
+    /// Appends a copy of page `index` of `pdf` to the excerpt `out`, returning its index in
+    /// `out`.
+    fn copy_changed_page(
+        out: &mut PdfDocument,
+        pdf: &PdfDocument,
+        index: usize,
+    ) -> Result<PdfPageIndex, PDFEditorError> {
+        let out_index = out.pages().len();
+        // Below the page count of `pdf`, which fits a page index.
+        out.pages_mut()
+            .copy_page_from_document(pdf, index as PdfPageIndex, out_index)
+            .map_err(PDFEditorError::modifying(
+                DocumentSide::A,
+                Some(index),
+                EditOperation::CopyingChangedPage,
+            ))?;
+        Ok(out_index)
+    }
+
+    /// Stamps the page number of page `index` of the current version onto its copy at
+    /// `out_index` of an excerpt, in the top left corner unless the label or the new
+    /// document banner takes it.
+    fn stamp_page_number(
+        &self,
+        out: &mut PdfDocument,
+        (index, out_index): (usize, PdfPageIndex),
+        stamps: &Stamps,
+        font: PdfFontToken,
+    ) -> Result<(), PDFEditorError> {
+        let modifying = || {
+            PDFEditorError::modifying(DocumentSide::A, Some(index), EditOperation::StampingLabel)
+        };
+        let corner = match (stamps.new_document, &self.config.label) {
+            (true, _) => LabelCorner::BottomLeft,
+            (false, Some(label)) if label.corner == LabelCorner::TopLeft => LabelCorner::TopRight,
+            (false, _) => LabelCorner::TopLeft,
+        };
+        let mut page = out.pages_mut().get(out_index).map_err(modifying())?;
+        PDFEditor::stamp_label(
+            &mut page,
+            &format!("Page {}", index + 1),
+            corner,
+            (font, EXCERPT_HEADER_COLOR),
+        )
+        .map_err(modifying())
+    }
+
+    /// Marks the changed page `index` of the current version, at `page_index` of the
+    /// partially edited `pdf`, with the overlay, the markers and the label or the banner.
+    fn mark_changed_page(
+        &self,
+        pdf: &mut PdfDocument,
+        previous: Option<&PdfDocument>,
+        (index, page_index): (usize, PdfPageIndex),
+        seg: &DifferenceSegments,
+        stamps: &Stamps,
+        (label_font, banner_font): (PdfFontToken, PdfFontToken),
+    ) -> Result<(), PDFEditorError> {
+        let modifying =
+            |operation| PDFEditorError::modifying(DocumentSide::A, Some(index), operation);
+        let mut p = pdf
+            .pages_mut()
+            .get(page_index)
+            .map_err(modifying(EditOperation::MarkingDifferences))?;
+        if let (Some(overlay), Some(previous), Some(matched_page)) =
+            (&self.config.overlay, previous, seg.matched_page)
+        {
+            previous
+                .pages()
+                .get(matched_page)
+                .and_then(|old_page| self.overlay_page(pdf, &mut p, &old_page, overlay))
+                .map_err(modifying(EditOperation::OverlayingPreviousVersion))?;
+        }
+        // Every page of a new document differs, a full height marker on each of them would
+        // read like a heavily edited document.
+        if stamps.new_document {
+            self.stamp_new_document_banner(&mut p, banner_font)
+                .map_err(modifying(EditOperation::StampingBanner))?;
+        } else {
+            self.mark_page_differences(&mut p, seg)
+                .map_err(modifying(EditOperation::MarkingDifferences))?;
+            if let Some(label) = &self.config.label {
+                let text = PDFEditor::label_text(&label.template, stamps.label_date, seg);
+                PDFEditor::stamp_label(&mut p, &text, label.corner, (label_font, LABEL_COLOR))
+                    .map_err(modifying(EditOperation::StampingLabel))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Inserts one or more pages at the start of `pdf` listing every entry of `changes`,
+    /// grouped by the kind of change and titled as a new document if `new_document` is set.
+    /// The `output_page`s of `changes` must not yet account for the inserted pages.
+    ///
+    /// pdfium can only create link annotations pointing at URIs, so the entries don't link
+    /// to the pages they describe. The `identity` is printed at the foot of the first page.
+    fn prepend_summary(
+        &self,
+        pdf: &mut PdfDocument,
+        changes: &[PageChange],
+        new_document: bool,
+        identity: Option<&DiffIdentity>,
+    ) -> Result<(), PDFEditorError> {
+        let size = match pdf.pages().first() {
+            Ok(page) => PdfPagePaperSize::from_points(page.width(), page.height()),
+            Err(_) => PdfPagePaperSize::a4(),
+        };
+        let font = pdf.fonts_mut().helvetica();
+        let title_font = pdf.fonts_mut().helvetica_bold();
+        let height = size.height().value;
+        let lines_per_page = (((height - 2. * SUMMARY_MARGIN - 2. * SUMMARY_LINE_HEIGHT)
+            / SUMMARY_LINE_HEIGHT) as usize)
+            .max(1);
+
+        let groups = [
+            (PageChangeKind::Inserted, "Inserted"),
+            (PageChangeKind::Removed, "Removed"),
+            (PageChangeKind::Modified, "Modified"),
+        ];
+        let mut entries = Vec::new();
+        groups.iter().for_each(|(kind, heading)| {
+            let group = changes
+                .iter()
+                .filter(|v| v.kind == *kind)
+                .collect::<Vec<&PageChange>>();
+            if !group.is_empty() {
+                entries.push((String::from(*heading), title_font, None));
+                group
+                    .into_iter()
+                    .for_each(|v| entries.push((v.outline_title(), font, v.output_page)));
+            }
+        });
+        if entries.is_empty() {
+            entries.push((String::from("No changed pages."), font, None));
+        }
+        let summary_pages = entries.len().div_ceil(lines_per_page);
+
+        entries
+            .chunks(lines_per_page)
+            .enumerate()
+            .try_for_each(|(index, chunk)| {
+                let summary_index = PdfPageIndex::try_from(index).map_err(|_| {
+                    PDFEditorError::PageIndexOutOfRange {
+                        document: DocumentRef::new(DocumentSide::A),
+                        page: index,
+                    }
+                })?;
+                let modifying = || {
+                    PDFEditorError::modifying(DocumentSide::A, None, EditOperation::WritingSummary)
+                };
+                let mut page = pdf
+                    .pages_mut()
+                    .create_page_at_index(size, summary_index)
+                    .map_err(modifying())?;
+                let mut y = height - SUMMARY_MARGIN;
+                page.objects_mut()
+                    .create_text_object(
+                        PdfPoints::new(SUMMARY_MARGIN),
+                        PdfPoints::new(y),
+                        match new_document {
+                            true => "New document, no previous version",
+                            false => "Changes",
+                        },
+                        title_font,
+                        PdfPoints::new(16.),
+                    )
+                    .map_err(modifying())?;
+                y -= 2. * SUMMARY_LINE_HEIGHT;
+                if let (0, Some(identity)) = (index, identity) {
+                    page.objects_mut()
+                        .create_text_object(
+                            PdfPoints::new(SUMMARY_MARGIN),
+                            PdfPoints::new(SUMMARY_MARGIN - SUMMARY_LINE_HEIGHT),
+                            identity.to_string(),
+                            font,
+                            PdfPoints::new(7.),
+                        )
+                        .map_err(modifying())?;
+                }
+                chunk.iter().try_for_each(|(title, font, output_page)| {
+                    let (x, line) = match output_page {
+                        Some(output_page) => (
+                            SUMMARY_MARGIN + SUMMARY_INDENT,
+                            format!("{} (diff p. {})", title, output_page + summary_pages + 1),
+                        ),
+                        None if *font == title_font => (SUMMARY_MARGIN, title.clone()),
+                        None => (SUMMARY_MARGIN + SUMMARY_INDENT, title.clone()),
+                    };
+                    page.objects_mut()
+                        .create_text_object(
+                            PdfPoints::new(x),
+                            PdfPoints::new(y),
+                            line,
+                            *font,
+                            PdfPoints::new(11.),
+                        )
+                        .map_err(modifying())?;
+                    y -= SUMMARY_LINE_HEIGHT;
+                    Ok::<(), PDFEditorError>(())
+                })
+            })
+    }
+
+    /// Every page of the document must have exactly one comparison, otherwise the document
+    /// has likely changed since it was compared.
+    fn check_page_count(pdf: &PdfDocument, got: usize) -> Result<(), PDFEditorError> {
+        let expected = pdf.pages().len() as usize;
+        if expected != got {
+            return Err(PDFEditorError::PageCountMismatch {
+                document: DocumentRef::new(DocumentSide::A),
+                expected,
+                got,
+            });
+        }
+        Ok(())
+    }
+
+    fn label_text(template: &str, date: &str, segments: &DifferenceSegments) -> String {
+        template
+            .replace("{date}", date)
+            .replace("{regions}", &segments.segments.len().to_string())
+            .replace(
+                "{percent}",
+                &format!("{:.1}", segments.changed_fraction() * 100.),
+            )
+            .replace(
+                "{matched_page}",
+                &segments
+                    .matched_page
+                    .map_or(String::from("-"), |v| (v as usize + 1).to_string()),
+            )
+    }
+
+    /// Places `text` in `color` in a corner of `page`. Labels that would be wider than the page are
+    /// first shrunk down to `LABEL_MIN_FONT_SIZE` and then truncated.
+    fn stamp_label(
+        page: &mut PdfPage,
+        text: &str,
+        corner: LabelCorner,
+        (font, color): (PdfFontToken, PdfColor),
+    ) -> Result<(), PdfiumError> {
+        let width = page.width().value;
+        let height = page.height().value;
+        let available = (width - 2. * LABEL_MARGIN).max(0.);
+        let estimate =
+            |text: &str, size: f32| text.chars().count() as f32 * size * LABEL_GLYPH_WIDTH;
+
+        let size = (available / estimate(text, 1.).max(f32::EPSILON))
+            .clamp(LABEL_MIN_FONT_SIZE, LABEL_FONT_SIZE);
+        let max_chars = (available / (size * LABEL_GLYPH_WIDTH)) as usize;
+        if max_chars < 4 {
+            return Ok(());
+        }
+        let text = if text.chars().count() > max_chars {
+            let mut truncated = text
+                .chars()
+                .take(max_chars.saturating_sub(3))
+                .collect::<String>();
+            truncated.push_str("...");
+            truncated
+        } else {
+            text.to_string()
+        };
+
+        let text_width = estimate(&text, size);
+        let x = match corner {
+            LabelCorner::TopLeft | LabelCorner::BottomLeft => LABEL_MARGIN,
+            LabelCorner::TopRight | LabelCorner::BottomRight => width - LABEL_MARGIN - text_width,
+        };
+        let y = match corner {
+            LabelCorner::TopLeft | LabelCorner::TopRight => height - LABEL_MARGIN - size,
+            LabelCorner::BottomLeft | LabelCorner::BottomRight => LABEL_MARGIN,
+        };
+        let mut object = page.objects_mut().create_text_object(
+            PdfPoints::new(x.max(0.)),
+            PdfPoints::new(y.max(0.)),
+            text,
+            font,
+            PdfPoints::new(size),
+        )?;
+        object.set_fill_color(color)?;
+        Ok(())
+    }
+
+    /// Draws a bar in [`PDFEditorConfig::new_document_color`] across the top of `page`,
+    /// reading [`NEW_DOCUMENT_BANNER`].
+    fn stamp_new_document_banner(
+        &self,
+        page: &mut PdfPage,
+        font: PdfFontToken,
+    ) -> Result<(), PdfiumError> {
+        let width = page.width().value;
+        let height = page.height().value;
+        let banner_height = BANNER_HEIGHT.min(height);
+        let [r, g, b] = self.config.new_document_color;
+        page.objects_mut().create_path_object_rect(
+            PdfRect::new_from_values(height - banner_height, 0., height, width),
+            None,
+            None,
+            Some(PdfColor::new(r, g, b, 230)),
+        )?;
+        let available = (width - 2. * LABEL_MARGIN).max(0.);
+        let natural_width = NEW_DOCUMENT_BANNER.chars().count() as f32 * LABEL_GLYPH_WIDTH;
+        let size = (available / natural_width).clamp(LABEL_MIN_FONT_SIZE, BANNER_FONT_SIZE);
+        let mut object = page.objects_mut().create_text_object(
+            PdfPoints::new(LABEL_MARGIN),
+            PdfPoints::new((height - (banner_height + size * 0.7) / 2.).max(0.)),
+            NEW_DOCUMENT_BANNER,
+            font,
+            PdfPoints::new(size),
+        )?;
+        object.set_fill_color(PdfColor::new(255, 255, 255, 255))?;
+        Ok(())
+    }
+
+    /// Maps the index of a comparison to the index of its page in the partially edited
+    /// document, in which `deleted_pages` preceding pages have already been removed.
+    fn shifted_page_index(
+        index: usize,
+        deleted_pages: usize,
+    ) -> Result<PdfPageIndex, PDFEditorError> {
+        let out_of_range = || PDFEditorError::PageIndexOutOfRange {
+            document: DocumentRef::new(DocumentSide::A),
+            page: index,
+        };
+        let shifted = index.checked_sub(deleted_pages).ok_or_else(out_of_range)?;
+        PdfPageIndex::try_from(shifted).map_err(|_| out_of_range())
+    }
+
+    /// Draws the content of `old_page` tinted and semi-transparent over `page`, scaled to the
+    /// dimensions of `page`. Areas of the old page in the background color stay fully
+    /// transparent.
+    fn overlay_page<'a>(
+        &self,
+        doc: &PdfDocument<'a>,
+        page: &mut PdfPage<'a>,
+        old_page: &PdfPage,
+        overlay: &OverlayConfig,
+    ) -> Result<(), PdfiumError> {
+        let geometry = self.render_geometry((page.width().value, page.height().value));
+        let render_config = PdfRenderConfig::new()
+            .set_target_width(geometry.width as i32)
+            .set_target_height(geometry.height as i32)
+            .set_clear_color(self.background_color());
+        let background = Rgb(self.config.background).to_luma().0[0];
+        let old_image = old_page
+            .render_with_config(&render_config)?
+            .as_image()
+            .into_luma8();
+
+        let [r, g, b] = overlay.color;
+        let opacity = overlay.opacity.clamp(0., 1.);
+        let buffer = RgbaImage::from_fn(old_image.width(), old_image.height(), |x, y| {
+            let ink = background.abs_diff(old_image.get_pixel(x, y).0[0]);
+            Rgba([r, g, b, (ink as f32 * opacity).round() as u8])
+        });
+
+        let object =
+            PdfPageImageObject::new_with_size(doc, &buffer.into(), page.width(), page.height())?;
+        page.objects_mut().add_image_object(object)?;
+        Ok(())
+    }
+
+    fn background_color(&self) -> PdfColor {
+        let [r, g, b] = self.config.background;
+        PdfColor::new(r, g, b, 255)
+    }
+
+    /// Size of the renders of a page of `size` points for overlays and side by side output.
+    fn render_geometry(&self, size: (f32, f32)) -> OverlayGeometry {
+        OverlayResolution::PixelsPerPoint(OVERLAY_RENDER_PIXELS_PER_POINT)
+            .geometry(size, self.config.max_overlay_pixels)
+    }
+
+    fn mark_page_differences(
+        &self,
+        page: &mut PdfPage,
+        segments: &DifferenceSegments,
+    ) -> Result<(), PdfiumError> {
+        match self.config.marker_mode {
+            MarkerMode::Raster => self.mark_page_differences_raster(page, segments),
+            MarkerMode::Annotation => self.mark_page_differences_annotations(page, segments),
+        }
+    }
+
+    fn mark_page_differences_annotations(
+        &self,
+        page: &mut PdfPage,
+        segments: &DifferenceSegments,
+    ) -> Result<(), PdfiumError> {
+        let page_width = page.width().value;
+        let page_height = page.height().value;
+
+        PDFEditor::colored_segments(&self.config, segments).try_for_each(
+            |((start, end), [r, g, b])| {
+                let (top, bottom) = PDFEditor::segment_extent(start, end, page_height);
+                // Segments are measured from the top of the page, pdf coordinates from the bottom.
+                let bounds = PdfRect::new_from_values(
+                    page_height - bottom,
+                    0.,
+                    page_height - top,
+                    page_width,
+                );
+                let mut annotation = page.annotations_mut().create_square_annotation()?;
+                annotation
+                    .set_bounds(bounds)
+                    .and_then(|_| annotation.set_stroke_color(PdfColor::new(r, g, b, 255)))
+                    .and_then(|_| annotation.set_fill_color(PdfColor::new(r, g, b, 64)))
+                    .and_then(|_| annotation.set_creator(&self.config.annotation_author))
+                    .and_then(|_| annotation.set_contents(&self.config.annotation_comment))
+            },
+        )
+    }
+
+    /// Converts a normalized segment into its top and bottom offset in page points measured
+    /// from the top of the page, widened to `MIN_MARKER_HEIGHT` if necessary.
+    fn segment_extent(start: f64, end: f64, page_height: f32) -> (f32, f32) {
+        let min_height = MIN_MARKER_HEIGHT.min(page_height);
+        let top = page_height * start as f32;
+        let bottom = (page_height * end as f32).max(top + min_height);
+        if bottom > page_height {
+            (page_height - min_height, page_height)
+        } else {
+            (top, bottom)
+        }
+    }
+
+    fn mark_page_differences_raster(
+        &self,
+        page: &mut PdfPage,
+        segments: &DifferenceSegments,
+    ) -> Result<(), PdfiumError> {
+        let [r, g, b] = self.config.marker_color;
+        let page_height = page.height().value;
+        let geometry = self.config.overlay_resolution.geometry(
+            (page.width().value, page_height),
+            self.config.max_overlay_pixels,
+        );
+        let (image_width, image_height) = (geometry.width, geometry.height);
+
+        // Bars on both margins must not overlap on very narrow pages.
+        let max_bar_width = match self.config.marker_position {
+            MarkerPosition::Both => image_width / 2,
+            MarkerPosition::Left | MarkerPosition::Right => image_width,
+        };
+        let bar_width = ((self.config.marker_width * geometry.pixels_per_point).round() as u32)
+            .clamp(1, max_bar_width.max(1))
+            .min(image_width);
+        if bar_width == 0 || image_height == 0 {
+            return Ok(());
+        }
+
+        // Only the strip itself is embedded and then moved to the margin(s), a mostly
+        // transparent image of the whole page would bloat the output considerably.
+        let mut strip = RgbaImage::new(bar_width, image_height);
+        let period = geometry.pattern_period();
+
+        let [red, green, blue] = self.config.redaction_color;
+        let redaction_rows = segments
+            .redaction_rows(&geometry)
+            .into_iter()
+            .map(|rows| (rows, Rgba([red, green, blue, 255])));
+        let marker_rows = segments
+            .marker_rows(&geometry)
+            .into_iter()
+            .map(|rows| (rows, Rgba([r, g, b, 255])));
+        redaction_rows.chain(marker_rows).for_each(|(rows, color)| {
+            let top = rows.start;
+            rows.for_each(|row| {
+                (0..bar_width)
+                    .filter(|column| {
+                        self.config
+                            .marker_style
+                            .paints((*column, row - top), period)
+                    })
+                    .for_each(|column| {
+                        strip.put_pixel(column, row, color);
+                    });
+            });
+        });
+
+        let strip = image::DynamicImage::from(strip);
+        let strip_width = bar_width as f32 / geometry.pixels_per_point;
+        let offsets = match self.config.marker_position {
+            MarkerPosition::Left => vec![0.],
+            MarkerPosition::Right => vec![page.width().value - strip_width],
+            MarkerPosition::Both => vec![0., page.width().value - strip_width],
+        };
+
+        offsets.into_iter().try_for_each(|x| {
+            page.objects_mut()
+                .create_image_object(
+                    PdfPoints::new(x),
+                    PdfPoints::zero(),
+                    &strip,
+                    Some(PdfPoints::new(strip_width)),
+                    Some(PdfPoints::new(page_height)),
+                )
+                .map(|_| ())
+        })
+    }
+}
+
+#[cfg(test)]
+mod page_index_tests {
+    use super::{PDFEditor, PDFEditorError};
+
+    /// Marks the changed pages of a stand-in document the way `mark` does, deleting
+    /// identical pages unless they are in `undeletable`. Returns the original page each
+    /// changed page was marked on.
+    fn marked_pages(changed: &[bool], undeletable: &[usize]) -> Vec<usize> {
+        let mut document = (0..changed.len()).collect::<Vec<usize>>();
+        let mut deleted_pages = 0;
+        let mut marked = Vec::new();
+        for (index, changed) in changed.iter().enumerate() {
+            let page_index = PDFEditor::shifted_page_index(index, deleted_pages).unwrap() as usize;
+            match changed {
+                true => marked.push(document[page_index]),
+                false if undeletable.contains(&index) => {}
+                false => {
+                    document.remove(page_index);
+                    deleted_pages += 1;
+                }
+            }
+        }
+        marked
+    }
+
+    #[test]
+    fn pages_after_a_failed_deletion_are_marked_in_place() {
+        let changed = [false, true, false, false, true, true, false, true];
+        assert_eq!(marked_pages(&changed, &[]), vec![1, 4, 5, 7]);
+        for undeletable in [0, 2, 3, 6] {
+            assert_eq!(marked_pages(&changed, &[undeletable]), vec![1, 4, 5, 7]);
+        }
+        assert_eq!(marked_pages(&changed, &[0, 2, 3, 6]), vec![1, 4, 5, 7]);
+    }
+
+    #[test]
+    fn indices_beyond_the_old_16_bit_shift_are_mapped() {
+        for (index, deleted_pages, expected) in [
+            (32_767, 0, 32_767),
+            (32_768, 0, 32_768),
+            (32_768, 1, 32_767),
+            (40_000, 32_768, 7_232),
+            (65_535, 0, 65_535),
+            (65_536, 1, 65_535),
+            (100_000, 99_999, 1),
+        ] {
+            assert_eq!(
+                PDFEditor::shifted_page_index(index, deleted_pages).ok(),
+                Some(expected),
+                "{} with {} deleted pages",
+                index,
+                deleted_pages
+            );
+        }
+        // Pages pdfium can't address fail instead of wrapping around.
+        assert!(matches!(
+            PDFEditor::shifted_page_index(65_536, 0),
+            Err(PDFEditorError::PageIndexOutOfRange { page: 65_536, .. })
+        ));
+    }
+
+    #[test]
+    fn more_deleted_pages_than_preceding_ones_are_out_of_range() {
+        assert!(matches!(
+            PDFEditor::shifted_page_index(2, 3),
+            Err(PDFEditorError::PageIndexOutOfRange { page: 2, .. })
+        ));
     }
 }
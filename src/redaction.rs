@@ -0,0 +1,167 @@
+use image::RgbImage;
+
+/// Highest value of every color channel of a pixel for it to count as black.
+const BLACK_LEVEL: u8 = 48;
+
+/// Pixels a redaction must at least be wide and high, so rules and strokes of text never
+/// count as one.
+const MIN_SIDE: u32 = 4;
+
+/// Fraction of a redaction the previous version must have covered by ink, so boxes drawn
+/// over empty paper count as ordinary changes.
+const MIN_PREVIOUS_INK: f64 = 0.02;
+
+/// A rectangle of a page blacked out in the current version where the previous version had
+/// content, as fractions of the page width and height measured from the top left. See
+/// [`ComparisonConfig::detect_redactions`](crate::ComparisonConfig::detect_redactions).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Redaction {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl Redaction {
+    /// Fraction of the page height the redaction covers.
+    pub fn height(&self) -> f64 {
+        self.bottom - self.top
+    }
+}
+
+/// Rectangle of a render in pixels, `right` and `bottom` excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PixelArea {
+    pub left: u32,
+    pub top: u32,
+    pub right: u32,
+    pub bottom: u32,
+}
+
+impl PixelArea {
+    fn pixels(&self) -> f64 {
+        (self.right - self.left) as f64 * (self.bottom - self.top) as f64
+    }
+
+    /// The area grown by `margin` pixels on every side, within `(width, height)`.
+    pub fn grown(&self, margin: u32, (width, height): (u32, u32)) -> PixelArea {
+        PixelArea {
+            left: self.left.saturating_sub(margin),
+            top: self.top.saturating_sub(margin),
+            right: (self.right + margin).min(width),
+            bottom: (self.bottom + margin).min(height),
+        }
+    }
+
+    /// The area as fractions of a page rendered at `(width, height)`.
+    pub fn redaction(&self, (width, height): (u32, u32)) -> Redaction {
+        let fraction = |v: u32, of: u32| (v as f64 / of.max(1) as f64).min(1.);
+        Redaction {
+            left: fraction(self.left, width),
+            top: fraction(self.top, height),
+            right: fraction(self.right, width),
+            bottom: fraction(self.bottom, height),
+        }
+    }
+}
+
+fn is_black(image: &RgbImage, (x, y): (u32, u32)) -> bool {
+    image.get_pixel(x, y).0.iter().all(|v| *v <= BLACK_LEVEL)
+}
+
+/// Fraction of the pixels of `area` of `image` that are black.
+fn black_fraction(image: &RgbImage, area: &PixelArea) -> f64 {
+    let black = (area.top..area.bottom)
+        .flat_map(|y| (area.left..area.right).map(move |x| (x, y)))
+        .filter(|pixel| is_black(image, *pixel))
+        .count();
+    black as f64 / area.pixels()
+}
+
+/// The redactions of `current`, rectangles of connected black pixels filling at least
+/// `solid_ratio` of their bounding box and at least `min_area` of the page, where
+/// `previous`, rendered at the same size on `background`, had ink but wasn't already black.
+pub(crate) fn find_redactions(
+    current: &RgbImage,
+    previous: &RgbImage,
+    min_area: f64,
+    solid_ratio: f64,
+    background: [u8; 3],
+) -> Vec<PixelArea> {
+    let (width, height) = current.dimensions();
+    let min_pixels = min_area * width as f64 * height as f64;
+    let mut visited = vec![false; width as usize * height as usize];
+    let mut redactions = Vec::new();
+    let mut stack = Vec::new();
+    let index = |(x, y): (u32, u32)| y as usize * width as usize + x as usize;
+    for start in (0..height).flat_map(|y| (0..width).map(move |x| (x, y))) {
+        if visited[index(start)] || !is_black(current, start) {
+            continue;
+        }
+        // Flood fills the black pixels connected to `start`, keeping their bounding box.
+        visited[index(start)] = true;
+        stack.push(start);
+        let mut area = PixelArea {
+            left: start.0,
+            top: start.1,
+            right: start.0 + 1,
+            bottom: start.1 + 1,
+        };
+        let mut pixels = 0usize;
+        while let Some((x, y)) = stack.pop() {
+            pixels += 1;
+            area.left = area.left.min(x);
+            area.top = area.top.min(y);
+            area.right = area.right.max(x + 1);
+            area.bottom = area.bottom.max(y + 1);
+            let neighbours = [
+                x.checked_sub(1).map(|x| (x, y)),
+                (x + 1 < width).then_some((x + 1, y)),
+                y.checked_sub(1).map(|y| (x, y)),
+                (y + 1 < height).then_some((x, y + 1)),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if !visited[index(neighbour)] && is_black(current, neighbour) {
+                    visited[index(neighbour)] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+        if area.right - area.left < MIN_SIDE
+            || area.bottom - area.top < MIN_SIDE
+            || area.pixels() < min_pixels
+            || (pixels as f64) < solid_ratio * area.pixels()
+        {
+            continue;
+        }
+        let covered = image::imageops::crop_imm(
+            previous,
+            area.left,
+            area.top,
+            area.right - area.left,
+            area.bottom - area.top,
+        )
+        .to_image();
+        if crate::duplex::ink_coverage(&covered, background) >= MIN_PREVIOUS_INK
+            && black_fraction(previous, &area) < solid_ratio
+        {
+            redactions.push(area);
+        }
+    }
+    redactions
+}
+
+/// A copy of `current` with the `areas` taken from `previous`, so what remains of the
+/// differences are those outside of the redactions.
+pub(crate) fn unredacted(current: &RgbImage, previous: &RgbImage, areas: &[PixelArea]) -> RgbImage {
+    let mut image = current.clone();
+    for area in areas {
+        for y in area.top..area.bottom {
+            for x in area.left..area.right {
+                image.put_pixel(x, y, *previous.get_pixel(x, y));
+            }
+        }
+    }
+    image
+}
@@ -0,0 +1,136 @@
+use {
+    base64::{engine::general_purpose::STANDARD, Engine},
+    image::{ImageFormat, Rgb, RgbImage},
+    std::{fmt::Write, io::Cursor, path::PathBuf},
+};
+
+use crate::pdf::PageStatistics;
+
+/// A changed document as it appears in the html report.
+pub struct HtmlDocument {
+    /// Path of the document relative to `current_path`.
+    pub path: PathBuf,
+    /// Artifact written for the document, linked from the report if there is one.
+    pub output: Option<PathBuf>,
+    pub pages: Vec<PageStatistics>,
+    /// Thumbnails of the current version, one per page.
+    pub thumbnails: Vec<RgbImage>,
+    /// Pages of the previous version no page of the current version was matched against.
+    pub removed_pages: Vec<usize>,
+}
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+section { margin-bottom: 3em; }
+.pages { display: flex; flex-wrap: wrap; gap: 1em; }
+figure { margin: 0; padding: 0.5em; border: 3px solid #d00; }
+figure.inserted { border-color: #090; }
+figcaption { font-size: 0.9em; margin-top: 0.3em; }
+li.removed { color: #777; text-decoration: line-through; }";
+
+/// Renders a self-contained html page showing thumbnails of all changed pages of `documents`.
+/// The thumbnails are embedded as base64 encoded pngs.
+pub fn render_html_report(title: &str, documents: &[HtmlDocument]) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        escape(title),
+        STYLE,
+        escape(title)
+    );
+    if documents.is_empty() {
+        html.push_str("<p>No documents changed.</p>\n");
+    }
+    documents.iter().for_each(|document| {
+        let _ = writeln!(
+            html,
+            "<section>\n<h2>{}</h2>",
+            escape(&document.path.to_string_lossy())
+        );
+        if let Some(output) = &document.output {
+            let _ = writeln!(
+                html,
+                "<p><a href=\"file://{}\">Open diff</a></p>",
+                escape(&output.to_string_lossy())
+            );
+        }
+        html.push_str("<div class=\"pages\">\n");
+        document
+            .pages
+            .iter()
+            .filter(|page| page.changed() || page.size_change.is_some())
+            .for_each(|page| {
+                let class = match page.matched_page {
+                    Some(_) => "modified",
+                    None => "inserted",
+                };
+                let caption = page.caption();
+                let image = document
+                    .thumbnails
+                    .get(page.page)
+                    .and_then(|thumbnail| encode_thumbnail(thumbnail, &page.segments));
+                let _ = writeln!(html, "<figure class=\"{}\">", class);
+                if let Some(image) = image {
+                    let _ = writeln!(
+                        html,
+                        "<img src=\"data:image/png;base64,{}\" alt=\"{}\">",
+                        image,
+                        escape(&caption)
+                    );
+                }
+                let _ = write!(
+                    html,
+                    "<figcaption>{}</figcaption>\n</figure>\n",
+                    escape(&caption)
+                );
+            });
+        html.push_str("</div>\n");
+        if !document.removed_pages.is_empty() {
+            html.push_str("<ul>\n");
+            document.removed_pages.iter().for_each(|page| {
+                let _ = writeln!(
+                    html,
+                    "<li class=\"removed\">previous p. {} - removed</li>",
+                    page + 1
+                );
+            });
+            html.push_str("</ul>\n");
+        }
+        html.push_str("</section>\n");
+    });
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Outlines every segment on a copy of `thumbnail` and returns it as a base64 encoded png.
+fn encode_thumbnail(thumbnail: &RgbImage, segments: &[(f64, f64)]) -> Option<String> {
+    let mut image = thumbnail.clone();
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let outline = Rgb([221, 0, 0]);
+    segments.iter().for_each(|(start, end)| {
+        let top = ((height - 1) as f64 * start).floor() as u32;
+        let bottom = (((height - 1) as f64 * end).ceil() as u32).min(height - 1);
+        (0..width).for_each(|x| {
+            image.put_pixel(x, top, outline);
+            image.put_pixel(x, bottom, outline);
+        });
+        (top..=bottom).for_each(|y| {
+            image.put_pixel(0, y, outline);
+            image.put_pixel(width - 1, y, outline);
+        });
+    });
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageFormat::Png).ok()?;
+    Some(STANDARD.encode(png.into_inner()))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,211 @@
+use {
+    chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike},
+    std::{error::Error, str::FromStr},
+};
+
+/// Years a schedule can fire in, as many as fit into the bit set of a field.
+const FIRST_YEAR: i32 = 1970;
+const LAST_YEAR: i32 = FIRST_YEAR + 127;
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Errors of parsing a [`CronSchedule`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CronError {
+    /// The expression doesn't have 6 or 7 fields.
+    FieldCount(usize),
+    /// A field isn't a list of values, ranges and steps within its bounds.
+    InvalidField { field: &'static str, value: String },
+}
+
+impl Error for CronError {}
+
+impl std::fmt::Display for CronError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FieldCount(count) => write!(
+                f,
+                "expected 6 or 7 fields (seconds minutes hours day-of-month month day-of-week [year]), got {}",
+                count
+            ),
+            Self::InvalidField { field, value } => write!(f, "invalid {} field {:?}", field, value),
+        }
+    }
+}
+
+/// A field of the expression with the values it matches as a bit set, bit `i` standing for
+/// `min + i`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    bits: u128,
+    min: u32,
+}
+
+impl Field {
+    fn parse(
+        field: &'static str,
+        value: &str,
+        min: u32,
+        max: u32,
+        names: &[&str],
+        names_start: u32,
+    ) -> Result<Field, CronError> {
+        let invalid = || CronError::InvalidField {
+            field,
+            value: value.to_string(),
+        };
+        let number = |v: &str| -> Result<u32, CronError> {
+            let lower = v.to_ascii_lowercase();
+            let number = match names.iter().position(|name| *name == lower) {
+                Some(position) => position as u32 + names_start,
+                None => v.parse::<u32>().map_err(|_| invalid())?,
+            };
+            match (min..=max).contains(&number) {
+                true => Ok(number),
+                false => Err(invalid()),
+            }
+        };
+        let mut bits = 0;
+        for item in value.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+                None => (item, 1),
+            };
+            if step == 0 {
+                return Err(invalid());
+            }
+            let (start, end) = match range {
+                "*" | "?" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((start, end)) => (number(start)?, number(end)?),
+                    // `5/15` runs from 5 to the end of the field, every 15.
+                    None if item.contains('/') => (number(range)?, max),
+                    None => (number(range)?, number(range)?),
+                },
+            };
+            if start > end {
+                return Err(invalid());
+            }
+            for v in (start..=end).step_by(step as usize) {
+                bits |= 1u128 << (v - min);
+            }
+        }
+        Ok(Field { bits, min })
+    }
+
+    fn matches(&self, value: u32) -> bool {
+        value >= self.min
+            && value - self.min < 128
+            && self.bits & (1u128 << (value - self.min)) != 0
+    }
+}
+
+/// A cron expression with seconds, e.g. `0 */10 8-18 * * MON-FRI` for every ten minutes
+/// during business hours.
+///
+/// The fields are seconds, minutes, hours, day of month, month, day of week and an optional
+/// year. Every field is a comma separated list of values, `a-b` ranges and `*`, each
+/// optionally followed by a `/step`. Months and days of the week can be given by their
+/// three letter English names, numeric days of the week count from 1 for Sunday. A day has
+/// to match both the day of month and the day of week field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+    years: Field,
+}
+
+impl FromStr for CronSchedule {
+    type Err = CronError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<&str>>();
+        if !(6..=7).contains(&fields.len()) {
+            return Err(CronError::FieldCount(fields.len()));
+        }
+        Ok(CronSchedule {
+            expression: fields.join(" "),
+            seconds: Field::parse("seconds", fields[0], 0, 59, &[], 0)?,
+            minutes: Field::parse("minutes", fields[1], 0, 59, &[], 0)?,
+            hours: Field::parse("hours", fields[2], 0, 23, &[], 0)?,
+            days: Field::parse("day of month", fields[3], 1, 31, &[], 0)?,
+            months: Field::parse("month", fields[4], 1, 12, &MONTH_NAMES, 1)?,
+            weekdays: Field::parse("day of week", fields[5], 1, 7, &WEEKDAY_NAMES, 1)?,
+            years: Field::parse(
+                "year",
+                fields.get(6).copied().unwrap_or("*"),
+                FIRST_YEAR as u32,
+                LAST_YEAR as u32,
+                &[],
+                0,
+            )?,
+        })
+    }
+}
+
+impl std::fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl CronSchedule {
+    /// The first time after `after` the schedule fires, in the time zone of `after`, or
+    /// `None` if it never fires again. Local times skipped by a clock change don't fire,
+    /// repeated ones fire the first time they occur.
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let mut time = after.naive_local().with_nanosecond(0)? + Duration::seconds(1);
+        loop {
+            time = self.next_matching(time)?;
+            // Ambiguous times after a clock change can lie before `after` on their first
+            // occurrence and after it on the second one.
+            let fired = timezone.from_local_datetime(&time);
+            let candidates = [fired.clone().earliest(), fired.latest()];
+            if let Some(fired) = candidates.into_iter().flatten().find(|v| v > after) {
+                return Some(fired);
+            }
+            time += Duration::seconds(1);
+        }
+    }
+
+    /// The first local time from `time` on matching every field.
+    fn next_matching(&self, mut time: NaiveDateTime) -> Option<NaiveDateTime> {
+        loop {
+            if time.year() > LAST_YEAR {
+                return None;
+            }
+            if !self.years.matches(time.year() as u32) {
+                time = NaiveDate::from_ymd_opt(time.year() + 1, 1, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.months.matches(time.month()) {
+                let (year, month) = match time.month() {
+                    12 => (time.year() + 1, 1),
+                    month => (time.year(), month + 1),
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.days.matches(time.day())
+                || !self
+                    .weekdays
+                    .matches(time.weekday().num_days_from_sunday() + 1)
+            {
+                time = (time.date() + Duration::days(1)).and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.matches(time.hour()) {
+                time = time.with_minute(0)?.with_second(0)? + Duration::hours(1);
+            } else if !self.minutes.matches(time.minute()) {
+                time = time.with_second(0)? + Duration::minutes(1);
+            } else if !self.seconds.matches(time.second()) {
+                time += Duration::seconds(1);
+            } else {
+                return Some(time);
+            }
+        }
+    }
+}
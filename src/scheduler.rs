@@ -0,0 +1,329 @@
+use {
+    crate::schedule::CronSchedule,
+    chrono::{DateTime, Local, TimeZone},
+    std::{
+        collections::{hash_map::RandomState, VecDeque},
+        hash::{BuildHasher, Hasher},
+        time::Duration,
+    },
+};
+
+/// How often the wall clock is checked while waiting for a cycle, so clock changes are
+/// followed instead of sleeping for a fixed duration.
+const WALL_CLOCK_CHECK: Duration = Duration::from_secs(60);
+
+/// When the cycles of a watch loop run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cadence {
+    /// A fixed time between the end of a cycle and the start of the next one.
+    Interval(Duration),
+    /// The fire times of a cron expression.
+    Schedule(Box<CronSchedule>),
+}
+
+/// What happens to the ticks of a cadence that pass while a cycle is still running. The
+/// ticks of an interval are the start of the first cycle and every interval after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum MissedTicks {
+    /// Missed ticks are dropped. An interval is measured from the end of the previous cycle,
+    /// a schedule continues at its next fire time.
+    #[default]
+    Skip,
+    /// All ticks missed during a cycle are run as a single cycle right after it.
+    Coalesce,
+    /// Every missed tick runs a cycle of its own, one after the other until the cycles are
+    /// back on their ticks. Cycles that keep taking longer than the cadence fall further
+    /// behind.
+    Queue,
+}
+
+/// The next cycle of a watch loop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tick<Tz: TimeZone> {
+    /// The time of the cadence the cycle runs for, passed to [`Scheduler::next_tick`] once
+    /// it finished.
+    pub scheduled: DateTime<Tz>,
+    /// When the cycle starts, with jitter and the least gap.
+    pub due: DateTime<Tz>,
+}
+
+/// Computes when the next cycle of a watch loop is due. By default the next cycle is always
+/// measured from the end of the previous cycle, so a long cycle never causes the next one to
+/// start right away, see [`MissedTicks`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduler {
+    cadence: Cadence,
+    jitter: Duration,
+    min_gap: Duration,
+    missed_ticks: MissedTicks,
+}
+
+impl Scheduler {
+    pub fn new(cadence: Cadence) -> Self {
+        Scheduler {
+            cadence,
+            jitter: Duration::ZERO,
+            min_gap: Duration::ZERO,
+            missed_ticks: MissedTicks::Skip,
+        }
+    }
+
+    /// Moves every due time by a random offset of up to `jitter` in either direction, so
+    /// instances started at the same time don't keep waking up at the same second.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// The least time between the end of a cycle and the start of the next one, regardless
+    /// of the cadence and the jitter.
+    pub fn with_min_gap(mut self, min_gap: Duration) -> Self {
+        self.min_gap = min_gap;
+        self
+    }
+
+    pub fn with_missed_ticks(mut self, missed_ticks: MissedTicks) -> Self {
+        self.missed_ticks = missed_ticks;
+        self
+    }
+
+    pub fn cadence(&self) -> &Cadence {
+        &self.cadence
+    }
+
+    /// Time between the ticks of the cadence following `now`, `None` if the schedule doesn't
+    /// fire twice anymore.
+    pub fn period<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> Option<Duration> {
+        match &self.cadence {
+            Cadence::Interval(interval) => Some(*interval),
+            Cadence::Schedule(schedule) => {
+                let first = schedule.next_after(now)?;
+                let second = schedule.next_after(&first)?;
+                (second - first).to_std().ok()
+            }
+        }
+    }
+
+    /// When the next cycle is due after a cycle finished at `finished`, with a random
+    /// jitter. `None` if the schedule doesn't fire anymore.
+    pub fn next<Tz: TimeZone>(&self, finished: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        self.next_with_jitter(finished, random_unit())
+    }
+
+    /// Like [`Scheduler::next`] with the jitter given as a factor in `-1..=1` of the
+    /// configured jitter, for reproducible schedules.
+    pub fn next_with_jitter<Tz: TimeZone>(
+        &self,
+        finished: &DateTime<Tz>,
+        factor: f64,
+    ) -> Option<DateTime<Tz>> {
+        let scheduled = self.skipping(finished)?;
+        self.due(scheduled, finished, factor)
+    }
+
+    /// The next cycle after the cycle for the tick `scheduled` finished at `finished`,
+    /// handling the ticks missed in between as configured with
+    /// [`Scheduler::with_missed_ticks`]. `None` if the schedule doesn't fire anymore.
+    pub fn next_tick<Tz: TimeZone>(
+        &self,
+        scheduled: &DateTime<Tz>,
+        finished: &DateTime<Tz>,
+    ) -> Option<Tick<Tz>> {
+        self.next_tick_with_jitter(scheduled, finished, random_unit())
+    }
+
+    /// Like [`Scheduler::next_tick`] with the jitter given as a factor in `-1..=1` of the
+    /// configured jitter, for reproducible schedules.
+    pub fn next_tick_with_jitter<Tz: TimeZone>(
+        &self,
+        scheduled: &DateTime<Tz>,
+        finished: &DateTime<Tz>,
+        factor: f64,
+    ) -> Option<Tick<Tz>> {
+        let following = self.following(scheduled)?;
+        let scheduled = match self.missed_ticks {
+            MissedTicks::Skip => self.skipping(finished)?,
+            MissedTicks::Queue => following,
+            MissedTicks::Coalesce => {
+                // The latest of the missed ticks stands in for all of them.
+                let mut latest = following;
+                while let Some(next) = self.following(&latest) {
+                    if next > *finished || next <= latest {
+                        break;
+                    }
+                    latest = next;
+                }
+                latest
+            }
+        };
+        Some(Tick {
+            due: self.due(scheduled.clone(), finished, factor)?,
+            scheduled,
+        })
+    }
+
+    /// The tick after `tick`.
+    fn following<Tz: TimeZone>(&self, tick: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match &self.cadence {
+            Cadence::Interval(interval) => {
+                Some(tick.clone() + chrono::Duration::from_std(*interval).ok()?)
+            }
+            Cadence::Schedule(schedule) => schedule.next_after(tick),
+        }
+    }
+
+    /// The next tick after a cycle finished at `finished` if missed ticks are skipped.
+    fn skipping<Tz: TimeZone>(&self, finished: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match &self.cadence {
+            Cadence::Interval(_) => self.following(finished),
+            // Fire times missed during a long cycle or within the gap are skipped, not
+            // caught up on.
+            Cadence::Schedule(schedule) => {
+                schedule.next_after(&(finished.clone() + self.min_gap_duration()?))
+            }
+        }
+    }
+
+    fn min_gap_duration(&self) -> Option<chrono::Duration> {
+        chrono::Duration::from_std(self.min_gap).ok()
+    }
+
+    /// When the cycle for the tick `scheduled` starts after the previous cycle finished at
+    /// `finished`, moved by the jitter and not before the least gap.
+    fn due<Tz: TimeZone>(
+        &self,
+        scheduled: DateTime<Tz>,
+        finished: &DateTime<Tz>,
+        factor: f64,
+    ) -> Option<DateTime<Tz>> {
+        let earliest = finished.clone() + self.min_gap_duration()?;
+        let due = scheduled;
+        let jitter = self.jitter.mul_f64(factor.clamp(-1., 1.).abs());
+        let jitter = chrono::Duration::from_std(jitter).ok()?;
+        let due = match factor < 0. {
+            true => due - jitter,
+            false => due + jitter,
+        };
+        Some(due.max(earliest))
+    }
+}
+
+/// Number of cycles [`CycleDurations`] averages over by default.
+pub const CYCLE_DURATION_WINDOW: usize = 5;
+
+/// Rolling average of the durations of the last cycles of a watch loop, to tell when the
+/// cadence is set shorter than the cycles take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleDurations {
+    window: usize,
+    durations: VecDeque<Duration>,
+}
+
+impl Default for CycleDurations {
+    fn default() -> Self {
+        CycleDurations::new(CYCLE_DURATION_WINDOW)
+    }
+}
+
+impl CycleDurations {
+    /// Averages over the last `window` cycles, at least one.
+    pub fn new(window: usize) -> Self {
+        CycleDurations {
+            window: window.max(1),
+            durations: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        if self.durations.len() == self.window {
+            self.durations.pop_front();
+        }
+        self.durations.push_back(duration);
+    }
+
+    /// Average of the recorded cycles, `None` before the first one.
+    pub fn average(&self) -> Option<Duration> {
+        let count = u32::try_from(self.durations.len())
+            .ok()
+            .filter(|v| *v > 0)?;
+        Some(self.durations.iter().sum::<Duration>() / count)
+    }
+
+    /// How the cycles overrun the cadence of `scheduler` at `now`, if the window is full and
+    /// the cycles take longer on average than the time between two ticks. A single slow
+    /// cycle doesn't count as overrunning.
+    pub fn overrun<Tz: TimeZone>(
+        &self,
+        scheduler: &Scheduler,
+        now: &DateTime<Tz>,
+    ) -> Option<Overrun> {
+        if self.durations.len() < self.window {
+            return None;
+        }
+        let average = self.average()?;
+        let period = scheduler.period(now)?;
+        if average <= period {
+            return None;
+        }
+        // Rounded up to whole seconds so every recent cycle fits.
+        let longest = self.durations.iter().max().copied().unwrap_or(average);
+        let suggested = Duration::from_secs_f64(longest.as_secs_f64().ceil());
+        Some(Overrun {
+            average,
+            period,
+            suggested,
+            missed_ticks: scheduler.missed_ticks,
+        })
+    }
+}
+
+/// Cycles taking longer on average than the time between the ticks of their cadence, see
+/// [`CycleDurations::overrun`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overrun {
+    pub average: Duration,
+    /// Time between two ticks of the cadence.
+    pub period: Duration,
+    /// The least interval the recent cycles fit in.
+    pub suggested: Duration,
+    pub missed_ticks: MissedTicks,
+}
+
+impl std::fmt::Display for Overrun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = |duration: Duration| Duration::from_secs(duration.as_secs());
+        write!(
+            f,
+            "cycles take {} on average, longer than the {} between them, ",
+            humantime::format_duration(seconds(self.average)),
+            humantime::format_duration(self.period)
+        )?;
+        match self.missed_ticks {
+            MissedTicks::Skip => write!(f, "missed cycles are skipped")?,
+            MissedTicks::Coalesce => write!(f, "missed cycles run as one right after")?,
+            MissedTicks::Queue => write!(f, "missed cycles run one after another, falling behind")?,
+        }
+        write!(
+            f,
+            ". Use an interval of at least {}",
+            humantime::format_duration(self.suggested)
+        )
+    }
+}
+
+/// A random number in `-1..=1`, good enough to spread out wake ups.
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() as f64 / u64::MAX as f64) * 2. - 1.
+}
+
+/// Sleeps until the wall clock reaches `due`, checking it at least every minute.
+pub async fn sleep_until(due: DateTime<Local>) {
+    while let Ok(remaining) = (due - Local::now()).to_std() {
+        if remaining.is_zero() {
+            return;
+        }
+        tokio::time::sleep(remaining.min(WALL_CLOCK_CHECK)).await;
+    }
+}
@@ -0,0 +1,263 @@
+use {
+    crate::{
+        pdf::{get_pdfium, Comparison, PDFComparison, PDFEditor, PDFEditorConfig},
+        version::{PdfiumVersion, MIN_PDFIUM_BUILD, VERSION_FILE_NAME},
+    },
+    pdfium_render::prelude::*,
+    std::{path::Path, sync::Arc},
+};
+
+/// Width the generated document is rendered at, in pixels.
+const RENDER_WIDTH: i32 = 200;
+/// Size of the pages of the generated document, A6 in page points.
+const PAGE_SIZE: (f32, f32) = (298., 420.);
+
+/// Outcome of one step of [`run_self_test`], with what was checked or how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestStep {
+    pub name: &'static str,
+    pub result: Result<String, String>,
+}
+
+impl std::fmt::Display for SelfTestStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.result {
+            Ok(message) => write!(f, "PASS {}: {}", self.name, message),
+            Err(message) => write!(f, "FAIL {}: {}", self.name, message),
+        }
+    }
+}
+
+/// Checks that the pdfium library in `pdfium_dir` can do everything a comparison needs:
+/// binding it, checking its version, creating and rendering a document, comparing it and
+/// writing a marked diff. Builds older than [`MIN_PDFIUM_BUILD`] fail unless `allow_old` is
+/// set. The steps build on each other, so the first failing step is the last one returned.
+pub fn run_self_test(pdfium_dir: &Path, allow_old: bool) -> Vec<SelfTestStep> {
+    let mut steps = Vec::new();
+    let _ = run_steps(pdfium_dir, allow_old, &mut steps);
+    steps
+}
+
+/// Records the outcome of step `name` in `steps`, returning its value if it passed.
+fn record<T>(
+    steps: &mut Vec<SelfTestStep>,
+    name: &'static str,
+    result: Result<(T, String), String>,
+) -> Option<T> {
+    match result {
+        Ok((value, message)) => {
+            steps.push(SelfTestStep {
+                name,
+                result: Ok(message),
+            });
+            Some(value)
+        }
+        Err(message) => {
+            steps.push(SelfTestStep {
+                name,
+                result: Err(message),
+            });
+            None
+        }
+    }
+}
+
+fn run_steps(pdfium_dir: &Path, allow_old: bool, steps: &mut Vec<SelfTestStep>) -> Option<()> {
+    let library = Pdfium::pdfium_platform_library_name_at_path(pdfium_dir);
+    let pdfium = record(
+        steps,
+        "load pdfium",
+        get_pdfium(pdfium_dir)
+            .map(|pdfium| {
+                (
+                    Arc::new(pdfium),
+                    format!(
+                        "bound {}, it provides every function used",
+                        library.to_string_lossy()
+                    ),
+                )
+            })
+            .map_err(|e| load_hint(&library, &e)),
+    )?;
+
+    record(
+        steps,
+        "pdfium version",
+        version_check(pdfium_dir, allow_old).map(|message| ((), message)),
+    )?;
+
+    let (previous, current) = record(
+        steps,
+        "generate document",
+        generate_documents(&pdfium)
+            .map(|(previous, current)| {
+                let message = format!(
+                    "created two versions of {} and {} bytes",
+                    previous.len(),
+                    current.len()
+                );
+                ((previous, current), message)
+            })
+            .map_err(|e| {
+                format!(
+                    "unable to create a document: {}, the library may be incomplete or corrupted",
+                    e
+                )
+            }),
+    )?;
+
+    record(
+        steps,
+        "render",
+        render_check(&pdfium, &previous).map(|message| ((), message)),
+    )?;
+
+    let comparison = PDFComparison::new(pdfium.clone());
+    let differences = record(steps, "compare", {
+        let identical = comparison
+            .compare_pdf_bytes(&previous, &previous)
+            .map_err(|e| format!("unable to compare the document with itself: {}", e));
+        let changed = comparison
+            .compare_pdf_bytes(&current, &previous)
+            .map_err(|e| format!("unable to compare the two versions: {}", e));
+        match (identical, changed) {
+            (Ok(identical), Ok(changed)) => match (identical.as_slice(), changed.as_slice()) {
+                ([Comparison::Identical(0)], [Comparison::Different(_)]) => Ok((
+                    changed,
+                    String::from("the document matches itself, the changed version differs"),
+                )),
+                _ => Err(format!(
+                    "unexpected results {:?} and {:?}, renders of this library are unstable",
+                    identical, changed
+                )),
+            },
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        }
+    })?;
+
+    let marked = PDFEditor::new(pdfium.clone(), PDFEditorConfig::default())
+        .mark_differences_bytes(&current, Some(&previous), &differences)
+        .map_err(|e| format!("unable to mark the differences: {}", e))
+        .and_then(|(diff, outcome)| {
+            if let Some((page, e)) = outcome.failed_pages().first() {
+                return Err(format!("unable to mark page {}: {}", page + 1, e));
+            }
+            let pages = pdfium
+                .load_pdf_from_byte_vec(diff, None)
+                .map_err(|e| format!("the written diff can't be loaded again: {}", e))?
+                .pages()
+                .len();
+            match pages {
+                1 => Ok(((), String::from("wrote a diff with the changed page"))),
+                pages => Err(format!("the diff has {} pages instead of 1", pages)),
+            }
+        });
+    record(steps, "mark differences", marked)
+}
+
+/// Explains why binding the library at `library` failed.
+pub(crate) fn load_hint(library: &Path, error: &PdfiumError) -> String {
+    let library = library.to_string_lossy();
+    // The library errors read better than their debug output pdfium-render displays.
+    let message = match error {
+        PdfiumError::LoadLibraryError(e) => e.to_string(),
+        e => e.to_string(),
+    };
+    if message.contains("undefined symbol") || message.contains("could not be found") {
+        format!(
+            "{} lacks functions the bindings need, it is likely an older pdfium build: {}",
+            library, message
+        )
+    } else {
+        format!(
+            "unable to load {}, make sure it exists and is built for {} on {}: {}",
+            library,
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            message
+        )
+    }
+}
+
+/// Checks the version of the library in `pdfium_dir` against [`MIN_PDFIUM_BUILD`]. pdfium
+/// has no call reporting it, so an unknown version passes.
+fn version_check(pdfium_dir: &Path, allow_old: bool) -> Result<String, String> {
+    match PdfiumVersion::read(pdfium_dir) {
+        Ok(Some((version, _))) if version.is_supported() => Ok(format!(
+            "pdfium {}, at least the supported build {}",
+            version, MIN_PDFIUM_BUILD
+        )),
+        Ok(Some((version, path))) => {
+            let message = format!(
+                "pdfium {} read from {} is older than build {} the bindings are written for, \
+                 its renders may differ",
+                version,
+                path.to_string_lossy(),
+                MIN_PDFIUM_BUILD
+            );
+            match allow_old {
+                true => Ok(format!("{}, allowed anyway", message)),
+                false => Err(format!("{}, update the library", message)),
+            }
+        }
+        Ok(None) => Ok(format!(
+            "unknown, pdfium doesn't report it and there is no {} file next to the library",
+            VERSION_FILE_NAME
+        )),
+        Err(e) => Ok(format!(
+            "unknown, the {} file is unreadable: {}",
+            VERSION_FILE_NAME, e
+        )),
+    }
+}
+
+/// A single page document and a version of it with another line of text.
+fn generate_documents(pdfium: &Pdfium) -> Result<(Vec<u8>, Vec<u8>), PdfiumError> {
+    let document = |lines: &[&str]| {
+        let mut pdf = pdfium.create_new_pdf()?;
+        let font = pdf.fonts_mut().helvetica();
+        let size =
+            PdfPagePaperSize::from_points(PdfPoints::new(PAGE_SIZE.0), PdfPoints::new(PAGE_SIZE.1));
+        let mut page = pdf.pages_mut().create_page_at_end(size)?;
+        for (index, line) in lines.iter().enumerate() {
+            page.objects_mut().create_text_object(
+                PdfPoints::new(30.),
+                PdfPoints::new(size.height().value - 40. - 30. * index as f32),
+                *line,
+                font,
+                PdfPoints::new(18.),
+            )?;
+        }
+        pdf.save_to_bytes()
+    };
+    Ok((
+        document(&["pdf_diff_gen self-test"])?,
+        document(&["pdf_diff_gen self-test", "A changed line"])?,
+    ))
+}
+
+/// Renders the first page of `document` and checks that the text shows up.
+fn render_check(pdfium: &Pdfium, document: &[u8]) -> Result<String, String> {
+    let pdf = pdfium
+        .load_pdf_from_byte_slice(document, None)
+        .map_err(|e| format!("unable to load the generated document: {}", e))?;
+    let page = pdf
+        .pages()
+        .first()
+        .map_err(|e| format!("the generated document has no page: {}", e))?;
+    let image = page
+        .render_with_config(&PdfRenderConfig::new().set_target_width(RENDER_WIDTH))
+        .map_err(|e| format!("unable to render: {}", e))?
+        .as_image()
+        .into_rgb8();
+    match image.pixels().any(|pixel| pixel.0 != [255, 255, 255]) {
+        true => Ok(format!(
+            "rendered a page of {}x{} pixels",
+            image.width(),
+            image.height()
+        )),
+        false => Err(String::from(
+            "the rendered page is blank, this build of pdfium can't draw text",
+        )),
+    }
+}
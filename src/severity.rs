@@ -0,0 +1,220 @@
+use {
+    crate::{
+        files::FileOutcome,
+        pages::{PageSelection, PageSelectionError},
+        pdf::PageStatistics,
+    },
+    globset::{Glob, GlobMatcher},
+    serde::{Deserialize, Serialize},
+    std::{error::Error, path::Path},
+};
+
+/// How urgently a changed file needs attention, ordered from least to most urgent.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warn,
+    Critical,
+}
+
+impl Severity {
+    /// The name the severity is serialized as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warn => "warn",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Invalid [`SeverityRule`] of a config file.
+#[derive(Debug)]
+pub enum SeverityRuleError {
+    InvalidPath(globset::Error),
+    InvalidPages(PageSelectionError),
+    /// Change fractions range from 0 to 1.
+    InvalidFraction(f64),
+}
+
+impl Error for SeverityRuleError {}
+
+impl std::fmt::Display for SeverityRuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPath(e) => write!(f, "Invalid path pattern: {}", e),
+            Self::InvalidPages(e) => write!(f, "Invalid pages: {}", e),
+            Self::InvalidFraction(v) => {
+                write!(f, "The change fraction {} isn't between 0 and 1", v)
+            }
+        }
+    }
+}
+
+impl From<globset::Error> for SeverityRuleError {
+    fn from(value: globset::Error) -> Self {
+        Self::InvalidPath(value)
+    }
+}
+
+impl From<PageSelectionError> for SeverityRuleError {
+    fn from(value: PageSelectionError) -> Self {
+        Self::InvalidPages(value)
+    }
+}
+
+/// Assigns `severity` to changed files matching every condition that is set. Without any
+/// condition the rule matches every changed file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RuleConfig")]
+pub struct SeverityRule {
+    severity: Severity,
+    path: Option<GlobMatcher>,
+    pages: Option<PageSelection>,
+    min_change_fraction: Option<f64>,
+}
+
+/// A rule as it is written in a config file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    severity: Severity,
+    path: Option<String>,
+    pages: Option<String>,
+    min_change_fraction: Option<f64>,
+}
+
+impl TryFrom<RuleConfig> for SeverityRule {
+    type Error = SeverityRuleError;
+
+    fn try_from(value: RuleConfig) -> Result<Self, Self::Error> {
+        let mut rule = SeverityRule::new(value.severity);
+        if let Some(path) = value.path {
+            rule = rule.path(Glob::new(&path)?);
+        }
+        if let Some(pages) = value.pages {
+            rule = rule.pages(pages.parse()?);
+        }
+        if let Some(fraction) = value.min_change_fraction {
+            if !(0. ..=1.).contains(&fraction) {
+                return Err(SeverityRuleError::InvalidFraction(fraction));
+            }
+            rule = rule.min_change_fraction(fraction);
+        }
+        Ok(rule)
+    }
+}
+
+impl SeverityRule {
+    pub fn new(severity: Severity) -> Self {
+        SeverityRule {
+            severity,
+            path: None,
+            pages: None,
+            min_change_fraction: None,
+        }
+    }
+
+    /// Only matches files matching `path`, relative to `current_path`.
+    pub fn path(mut self, path: Glob) -> Self {
+        self.path = Some(path.compile_matcher());
+        self
+    }
+
+    /// Only matches files with a changed page among `pages` of the current version.
+    pub fn pages(mut self, pages: PageSelection) -> Self {
+        self.pages = Some(pages);
+        self
+    }
+
+    /// Only matches files with a page of which at least this fraction changed, combined with
+    /// [`Self::pages`] it has to be one of those pages.
+    pub fn min_change_fraction(mut self, fraction: f64) -> Self {
+        self.min_change_fraction = Some(fraction);
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    /// Whether the rule matches the changed file at `path`. `pages` are the statistics of
+    /// its pages, `None` for a removed file, which counts as changed entirely but has no
+    /// pages a page range could match.
+    fn matches(&self, path: &Path, pages: Option<&[PageStatistics]>) -> bool {
+        if self.path.as_ref().is_some_and(|glob| !glob.is_match(path)) {
+            return false;
+        }
+        if self.pages.is_none() && self.min_change_fraction.is_none() {
+            return true;
+        }
+        let Some(pages) = pages else {
+            return self.pages.is_none();
+        };
+        pages.iter().any(|page| {
+            page.changed()
+                && self
+                    .pages
+                    .as_ref()
+                    .is_none_or(|pages| pages.contains(page.page))
+                && self
+                    .min_change_fraction
+                    .is_none_or(|fraction| page.changed_fraction >= fraction)
+        })
+    }
+}
+
+/// Rules turning the outcomes of changed files into a [`Severity`], deserialized from a list
+/// of rules with a `severity` and optionally a `path` glob, `pages` like `1-3,7` and a
+/// `min_change_fraction`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct SeverityRules {
+    rules: Vec<SeverityRule>,
+}
+
+impl SeverityRules {
+    pub fn new(rules: Vec<SeverityRule>) -> Self {
+        SeverityRules { rules }
+    }
+
+    pub fn rules(&self) -> &[SeverityRule] {
+        &self.rules
+    }
+
+    /// Severity of `outcome` of the file at `path`, relative to `current_path`: the highest
+    /// severity of all matching rules, [`Severity::Info`] if none matches. Only changed and
+    /// removed files have a severity, every other outcome gets `None`.
+    pub fn evaluate(&self, path: &Path, outcome: &FileOutcome) -> Option<Severity> {
+        let pages = match outcome {
+            FileOutcome::DiffWritten { stats, .. } => Some(stats.as_slice()),
+            FileOutcome::Removed => None,
+            _ => return None,
+        };
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(path, pages))
+            .map(|rule| rule.severity)
+            .max()
+            .or(Some(Severity::Info))
+    }
+}
@@ -0,0 +1,177 @@
+use {
+    crate::pdf::PageStatistics,
+    image::{Rgb, RgbImage},
+};
+
+/// Space between the cells of a sheet and around them, in pixels.
+const PADDING: u32 = 12;
+const BORDER: u32 = 3;
+/// Glyphs of the captions are drawn with square dots of this size.
+const DOT: u32 = 2;
+const CAPTION_HEIGHT: u32 = GLYPH_HEIGHT * DOT + 8;
+/// Height of cells if no page of the document was rendered, relative to the width.
+const PLACEHOLDER_ASPECT: f64 = 1.414;
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+
+const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const PLACEHOLDER: Rgb<u8> = Rgb([235, 235, 235]);
+const TEXT: Rgb<u8> = Rgb([40, 40, 40]);
+const MODIFIED: Rgb<u8> = Rgb([221, 0, 0]);
+const INSERTED: Rgb<u8> = Rgb([0, 153, 0]);
+const UNCHANGED: Rgb<u8> = Rgb([190, 190, 190]);
+
+/// Layout of the contact sheets of [`render_contact_sheets`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContactSheetConfig {
+    pub columns: u32,
+    /// Rows per sheet, documents with more pages get several sheets.
+    pub rows: u32,
+}
+
+impl Default for ContactSheetConfig {
+    fn default() -> Self {
+        ContactSheetConfig {
+            columns: 6,
+            rows: 5,
+        }
+    }
+}
+
+/// Lays out `thumbnails`, one per page, in a grid of `config.columns` by `config.rows`
+/// cells per sheet. Changed pages get a red border, inserted ones a green border, both
+/// captioned with the page number and the changed percentage. Unchanged pages only show
+/// their page number, those without a thumbnail are drawn as a gray area.
+pub fn render_contact_sheets(
+    thumbnails: &[RgbImage],
+    pages: &[PageStatistics],
+    config: &ContactSheetConfig,
+) -> Vec<RgbImage> {
+    let (columns, rows) = (config.columns.max(1), config.rows.max(1));
+    let cell_width = thumbnails
+        .iter()
+        .map(|thumbnail| thumbnail.width())
+        .max()
+        .filter(|width| *width > 0)
+        .unwrap_or(160);
+    let cell_height = thumbnails
+        .iter()
+        .map(|thumbnail| thumbnail.height())
+        .max()
+        .filter(|height| *height > 0)
+        .unwrap_or((cell_width as f64 * PLACEHOLDER_ASPECT) as u32);
+    let count = thumbnails.len().max(pages.len());
+    let per_sheet = (columns * rows) as usize;
+
+    (0..count)
+        .step_by(per_sheet)
+        .map(|first| {
+            let last = (first + per_sheet).min(count);
+            let used_rows = (last - first).div_ceil(columns as usize) as u32;
+            let used_columns = (last - first).min(columns as usize) as u32;
+            let mut sheet = RgbImage::from_pixel(
+                PADDING + used_columns * (cell_width + 2 * BORDER + PADDING),
+                PADDING + used_rows * (cell_height + 2 * BORDER + CAPTION_HEIGHT + PADDING),
+                BACKGROUND,
+            );
+            for page in first..last {
+                let slot = (page - first) as u32;
+                let x = PADDING + (slot % columns) * (cell_width + 2 * BORDER + PADDING);
+                let y = PADDING
+                    + (slot / columns) * (cell_height + 2 * BORDER + CAPTION_HEIGHT + PADDING);
+                draw_cell(
+                    &mut sheet,
+                    (x, y),
+                    (cell_width, cell_height),
+                    thumbnails.get(page),
+                    page,
+                    pages.get(page),
+                );
+            }
+            sheet
+        })
+        .collect()
+}
+
+fn draw_cell(
+    sheet: &mut RgbImage,
+    (x, y): (u32, u32),
+    (width, height): (u32, u32),
+    thumbnail: Option<&RgbImage>,
+    page: usize,
+    statistics: Option<&PageStatistics>,
+) {
+    let changed = statistics.filter(|v| v.changed());
+    let border = match changed {
+        Some(v) if v.matched_page.is_some() => MODIFIED,
+        Some(_) => INSERTED,
+        None => UNCHANGED,
+    };
+    let (border_width, border_height) = (width + 2 * BORDER, height + 2 * BORDER);
+    fill(sheet, (x, y), (border_width, border_height), border);
+    fill(
+        sheet,
+        (x + BORDER, y + BORDER),
+        (width, height),
+        PLACEHOLDER,
+    );
+    if let Some(thumbnail) = thumbnail.filter(|v| v.width() > 0 && v.height() > 0) {
+        // Thumbnails smaller than the cell are centered in it.
+        let left = x + BORDER + (width - thumbnail.width().min(width)) / 2;
+        let top = y + BORDER + (height - thumbnail.height().min(height)) / 2;
+        image::imageops::replace(sheet, thumbnail, left as i64, top as i64);
+    }
+    let caption = match changed {
+        Some(v) => format!("{}  {:.1}%", page + 1, v.changed_fraction * 100.),
+        None => (page + 1).to_string(),
+    };
+    draw_text(sheet, (x, y + border_height + 4), &caption);
+}
+
+fn fill(sheet: &mut RgbImage, (x, y): (u32, u32), (width, height): (u32, u32), color: Rgb<u8>) {
+    for py in y..(y + height).min(sheet.height()) {
+        for px in x..(x + width).min(sheet.width()) {
+            sheet.put_pixel(px, py, color);
+        }
+    }
+}
+
+/// Draws `text` with its top left corner at `(x, y)`. Only digits, `.`, `%` and spaces are
+/// supported, other characters are left blank.
+fn draw_text(sheet: &mut RgbImage, (x, y): (u32, u32), text: &str) {
+    for (index, c) in text.chars().enumerate() {
+        let left = x + index as u32 * (GLYPH_WIDTH + 1) * DOT;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                    fill(
+                        sheet,
+                        (left + column * DOT, y + row as u32 * DOT),
+                        (DOT, DOT),
+                        TEXT,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Rows of a 3 by 5 glyph, the highest of the three bits is the leftmost dot.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT as usize] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        _ => [0; GLYPH_HEIGHT as usize],
+    }
+}
@@ -0,0 +1,796 @@
+use {
+    pdfium_render::prelude::PdfPageIndex,
+    std::{
+        error::Error,
+        ffi::OsStr,
+        io,
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+use crate::{
+    cancel::CancellationToken,
+    clock::{Clock, SystemClock},
+    pdf::{Comparison, LoadedDocument, MarkOutcome, MergeSource, PDFEditor, PDFEditorError},
+    timestamp::Timestamp,
+    timings::Timings,
+};
+
+/// Errors of a [`DiffSink`].
+#[derive(Debug)]
+pub enum DiffSinkError {
+    /// A page was reported or the sink was finished without a call to `begin_document`.
+    NoDocument,
+    PDFEditorError(PDFEditorError),
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// `outputs` artifacts of the document at `path` were written within `window`, so no
+    /// further one is, see [`OutputLimitAction::Skip`].
+    Flapping {
+        path: PathBuf,
+        outputs: usize,
+        window: Duration,
+    },
+    /// Errors of sinks outside of this crate.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl Error for DiffSinkError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::NoDocument | Self::Flapping { .. } => None,
+            Self::PDFEditorError(e) => e.source(),
+            Self::Io { source, .. } => Some(source),
+            Self::Other(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl std::fmt::Display for DiffSinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoDocument => write!(f, "no document was started"),
+            Self::PDFEditorError(e) => write!(f, "{}", e),
+            Self::Io { path, source } => {
+                write!(f, "failed to write {}: {}", path.to_string_lossy(), source)
+            }
+            Self::Flapping {
+                path,
+                outputs,
+                window,
+            } => write!(
+                f,
+                "{} already got {} diffs within {}",
+                path.to_string_lossy(),
+                outputs,
+                humantime::format_duration(*window)
+            ),
+            Self::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<PDFEditorError> for DiffSinkError {
+    fn from(value: PDFEditorError) -> Self {
+        Self::PDFEditorError(value)
+    }
+}
+
+/// Turns the comparison of a document into some artifact, e.g. a diff pdf.
+///
+/// For every changed document [`FileManager`](crate::FileManager) calls `begin_document`,
+/// then `page_changed` or `page_identical` once for every page in page order and finally
+/// `finish_loaded`. A sink is reused for all documents.
+pub trait DiffSink {
+    /// Starts a new document. `previous` is the previous version, if there is one.
+    fn begin_document(&mut self, path: &Path, previous: Option<&Path>)
+        -> Result<(), DiffSinkError>;
+
+    /// Reports a page of the current version differing from the previous version.
+    fn page_changed(&mut self, index: usize, comparison: &Comparison) -> Result<(), DiffSinkError>;
+
+    /// Reports a page identical to `matched_page` of the previous version.
+    fn page_identical(
+        &mut self,
+        _index: usize,
+        _matched_page: PdfPageIndex,
+    ) -> Result<(), DiffSinkError> {
+        Ok(())
+    }
+
+    /// Completes the current document, returning the path of the written artifact if there
+    /// is one.
+    fn finish(&mut self) -> Result<Option<PathBuf>, DiffSinkError>;
+
+    /// Like `finish`, with the documents the comparison loaded, for sinks that would
+    /// otherwise load them again. `current` may be modified. Calls `finish` by default.
+    fn finish_loaded(
+        &mut self,
+        _current: &mut LoadedDocument,
+        _previous: Option<&LoadedDocument>,
+    ) -> Result<Option<PathBuf>, DiffSinkError> {
+        self.finish()
+    }
+
+    /// Time spent writing the document finished last, for sinks that record it.
+    fn take_timings(&mut self) -> Timings {
+        Timings::default()
+    }
+
+    /// Pages of the document finished last that couldn't be marked, for sinks that mark
+    /// pages.
+    fn take_mark_outcome(&mut self) -> MarkOutcome {
+        MarkOutcome::Complete
+    }
+
+    /// The artifact marking the changes of the document finished last on its previous
+    /// version, for sinks that write one.
+    fn take_previous_version_output(&mut self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Called once at the end of every cycle with every document handed to the sink in it,
+    /// for sinks that combine them into a single artifact. Returns that artifact, if there
+    /// is one.
+    fn end_cycle(
+        &mut self,
+        _documents: &[CycleDocument],
+    ) -> Result<Option<CombinedOutput>, DiffSinkError> {
+        Ok(None)
+    }
+
+    /// Replaces the clock the names of written artifacts are based on, for sinks whose names
+    /// contain a timestamp. [`FileManager`](crate::FileManager) passes its own clock.
+    fn set_clock(&mut self, _clock: Arc<dyn Clock>) {}
+
+    /// Called at the start of every cycle with its timestamp, which sinks whose names
+    /// contain one name all artifacts after until the next cycle instead of asking their
+    /// clock, so the artifacts of a cycle share the timestamp of its reports.
+    fn begin_cycle(&mut self, _timestamp: Timestamp) {}
+
+    /// Sets the time by which `finish` has to be done, for sinks that can abandon a document
+    /// mid-way. [`FileManager`](crate::FileManager) sets it before every document with a
+    /// `file_timeout`.
+    fn set_deadline(&mut self, _deadline: Option<Instant>) {}
+
+    /// Sets the token that stops `finish` early, for sinks that can abandon a document
+    /// mid-way. [`FileManager`](crate::FileManager) passes the token of the current cycle.
+    fn set_cancellation(&mut self, _cancel: Option<CancellationToken>) {}
+}
+
+/// Timestamp the artifacts of a sink are named after, that of the current `cycle` if one
+/// began, see [`DiffSink::begin_cycle`], otherwise the current time of `clock` in UTC.
+fn artifact_timestamp(cycle: Option<Timestamp>, clock: &dyn Clock) -> Timestamp {
+    cycle.unwrap_or_else(|| Timestamp::from(clock.now()))
+}
+
+/// A document of a cycle, see [`DiffSink::end_cycle`].
+#[derive(Debug, Clone)]
+pub struct CycleDocument {
+    pub path: PathBuf,
+    /// The artifact returned by `finish`, `None` if there is none or the document failed.
+    pub output: Option<PathBuf>,
+    /// Why the document failed, `None` if it didn't.
+    pub error: Option<String>,
+}
+
+/// An artifact combining the documents of a cycle.
+#[derive(Debug, Clone)]
+pub struct CombinedOutput {
+    pub path: PathBuf,
+    /// Whether the artifacts of the single documents were removed in favour of this one.
+    pub replaces_documents: bool,
+}
+
+/// Whether [`MarkedPdfSink`] merges the diffs of a cycle into a single pdf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CombinedDiff {
+    /// Only the diffs of the single documents are written.
+    #[default]
+    Off,
+    /// A combined diff is written next to the diffs of the single documents.
+    Alongside,
+    /// The diffs of the single documents are removed once they are merged.
+    Only,
+}
+
+/// Caps the artifacts a sink writes for a single document, e.g. for a report regenerated
+/// with a new timestamp in every cycle. Only the artifacts still in the directory count, so
+/// diffs removed by [`CombinedDiff::Only`] or by hand make room for new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Artifacts of a document named within `window` before now, once reached `action`
+    /// applies.
+    pub max_outputs: usize,
+    pub window: Duration,
+    pub action: OutputLimitAction,
+}
+
+/// What a sink does with a document that reached its [`OutputLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputLimitAction {
+    /// Writes the artifact over the newest one of the window.
+    #[default]
+    Overwrite,
+    /// Writes nothing and fails with [`DiffSinkError::Flapping`].
+    /// [`FileManager`](crate::FileManager) reports it as skipped and keeps the copy, so the
+    /// next diff after the window shows every change since the last one.
+    Skip,
+}
+
+/// A document between `begin_document` and `finish`, with the comparisons of its pages.
+struct PendingDocument {
+    path: PathBuf,
+    previous: Option<PathBuf>,
+    pages: Vec<(usize, Comparison)>,
+}
+
+impl PendingDocument {
+    fn new(path: &Path, previous: Option<&Path>) -> Self {
+        PendingDocument {
+            path: path.to_path_buf(),
+            previous: previous.map(|v| v.to_path_buf()),
+            pages: Vec::new(),
+        }
+    }
+
+    /// The comparisons of all reported pages in page order.
+    fn comparisons(&mut self) -> Vec<Comparison> {
+        self.pages.sort_by_key(|(index, _)| *index);
+        self.pages
+            .iter()
+            .map(|(_, comparison)| comparison.clone())
+            .collect()
+    }
+
+    /// Path of the diff of this document in `dir`, `<filename>.<kind>.<timestamp>.<extension>`
+    /// named after the [`Timestamp::file_stamp`] of `timestamp`. If the name is taken
+    /// anyway, a counter is appended to the timestamp. The file name is kept as is, even if
+    /// it isn't valid UTF-8.
+    fn output_path(
+        &self,
+        dir: &Path,
+        kind: &str,
+        extension: &str,
+        timestamp: &Timestamp,
+    ) -> PathBuf {
+        let name = self
+            .path
+            .file_name()
+            .unwrap_or(OsStr::new("unknown_filename"));
+        let timestamp = timestamp.file_stamp();
+        let mut counter = 0;
+        loop {
+            let mut filename = name.to_os_string();
+            match counter {
+                0 => filename.push(format!(".{}.{}.{}", kind, timestamp, extension)),
+                _ => filename.push(format!(".{}.{}-{}.{}", kind, timestamp, counter, extension)),
+            }
+            let path = dir.join(filename);
+            if !path.exists() {
+                return path;
+            }
+            counter += 1;
+        }
+    }
+
+    /// Artifacts of this document in `dir` named by `output_path`, with the timestamps of
+    /// their names in milliseconds, oldest first. Names with the milliseconds since the
+    /// epoch of older versions count as well.
+    fn existing_outputs(&self, dir: &Path, kind: &str, extension: &str) -> Vec<(i64, PathBuf)> {
+        let name = self
+            .path
+            .file_name()
+            .unwrap_or(OsStr::new("unknown_filename"));
+        let mut prefix = name.as_encoded_bytes().to_vec();
+        prefix.extend_from_slice(format!(".{}.", kind).as_bytes());
+        let suffix = format!(".{}", extension);
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut outputs = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let filename = entry.file_name();
+                let stamp = filename
+                    .as_encoded_bytes()
+                    .strip_prefix(prefix.as_slice())?
+                    .strip_suffix(suffix.as_bytes())?;
+                let stamp = std::str::from_utf8(stamp).ok()?;
+                let (timestamp, counter) = match Timestamp::parse_file_stamp(stamp) {
+                    Some((timestamp, counter)) => (timestamp.utc().timestamp_millis(), counter),
+                    None => {
+                        let (timestamp, counter) =
+                            stamp.split_at(stamp.find('-').unwrap_or(stamp.len()));
+                        (timestamp.parse().ok()?, counter)
+                    }
+                };
+                let counter = match counter.strip_prefix('-') {
+                    Some(counter) => counter.parse::<u64>().ok()?,
+                    None if counter.is_empty() => 0,
+                    None => return None,
+                };
+                Some((timestamp, counter, entry.path()))
+            })
+            .collect::<Vec<_>>();
+        outputs.sort();
+        outputs
+            .into_iter()
+            .map(|(timestamp, _, path)| (timestamp, path))
+            .collect()
+    }
+
+    /// Like `output_path`, but once `limit` is reached the newest artifact of its window is
+    /// returned instead with [`OutputLimitAction::Overwrite`], with
+    /// [`OutputLimitAction::Skip`] it fails with [`DiffSinkError::Flapping`].
+    fn limited_output_path(
+        &self,
+        dir: &Path,
+        (kind, extension): (&str, &str),
+        timestamp: &Timestamp,
+        limit: Option<&OutputLimit>,
+    ) -> Result<PathBuf, DiffSinkError> {
+        let Some(limit) = limit else {
+            return Ok(self.output_path(dir, kind, extension, timestamp));
+        };
+        let window = chrono::Duration::from_std(limit.window).unwrap_or(chrono::Duration::MAX);
+        let since = timestamp
+            .utc()
+            .checked_sub_signed(window)
+            .map_or(i64::MIN, |v| v.timestamp_millis());
+        let mut recent = self
+            .existing_outputs(dir, kind, extension)
+            .into_iter()
+            .filter(|(timestamp, _)| *timestamp > since)
+            .map(|(_, path)| path)
+            .collect::<Vec<PathBuf>>();
+        let outputs = recent.len();
+        if outputs < limit.max_outputs {
+            return Ok(self.output_path(dir, kind, extension, timestamp));
+        }
+        match (limit.action, recent.pop()) {
+            (OutputLimitAction::Overwrite, Some(newest)) => {
+                log::info!(
+                    "{} reached its limit of {} diffs, overwriting {}",
+                    self.path.to_string_lossy(),
+                    limit.max_outputs,
+                    newest.to_string_lossy()
+                );
+                Ok(newest)
+            }
+            (OutputLimitAction::Overwrite, None) => {
+                Ok(self.output_path(dir, kind, extension, timestamp))
+            }
+            (OutputLimitAction::Skip, _) => Err(DiffSinkError::Flapping {
+                path: self.path.clone(),
+                outputs,
+                window: limit.window,
+            }),
+        }
+    }
+}
+
+/// Writes a `<filename>.diff.<timestamp>.pdf` for every document into a directory using
+/// [`PDFEditor::generate_diff`], see [`Timestamp::file_stamp`].
+///
+/// With [`CombinedDiff`] enabled, the diffs of every cycle are additionally merged into a
+/// `combined-<timestamp>.pdf` with a cover page per document.
+pub struct MarkedPdfSink {
+    editor: PDFEditor,
+    diff_path: PathBuf,
+    document: Option<PendingDocument>,
+    timings: Timings,
+    outcome: MarkOutcome,
+    clock: Arc<dyn Clock>,
+    cycle: Option<Timestamp>,
+    combined: CombinedDiff,
+    previous_version_diff: bool,
+    previous_output: Option<PathBuf>,
+    output_limit: Option<OutputLimit>,
+    cancel: Option<CancellationToken>,
+}
+
+impl MarkedPdfSink {
+    pub fn new(editor: PDFEditor, diff_path: PathBuf) -> Self {
+        MarkedPdfSink {
+            editor,
+            diff_path,
+            document: None,
+            timings: Timings::default(),
+            outcome: MarkOutcome::Complete,
+            clock: Arc::new(SystemClock),
+            cycle: None,
+            combined: CombinedDiff::Off,
+            previous_version_diff: false,
+            previous_output: None,
+            output_limit: None,
+            cancel: None,
+        }
+    }
+
+    pub fn with_combined_diff(mut self, combined: CombinedDiff) -> Self {
+        self.combined = combined;
+        self
+    }
+
+    /// Also writes a `<filename>.diff-old.<timestamp>.pdf` for every document with a
+    /// previous version, marking the changed and removed pages on that version with
+    /// [`PDFEditor::mark_previous_version`]. The comparisons should be made with
+    /// [`ComparisonConfig::locate_previous_differences`](crate::ComparisonConfig).
+    pub fn with_previous_version_diff(mut self, previous_version_diff: bool) -> Self {
+        self.previous_version_diff = previous_version_diff;
+        self
+    }
+
+    /// Caps the diffs written per document with `limit`. The diffs of the previous version
+    /// are capped by the same limit, but always overwritten.
+    pub fn with_output_limit(mut self, limit: OutputLimit) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Path of the next diff of `document` of `kind`, see [`OutputLimit`].
+    fn diff_output_path(
+        &self,
+        document: &PendingDocument,
+        kind: &str,
+        limit: Option<&OutputLimit>,
+    ) -> Result<PathBuf, DiffSinkError> {
+        let timestamp = artifact_timestamp(self.cycle, self.clock.as_ref());
+        document.limited_output_path(&self.diff_path, (kind, "pdf"), &timestamp, limit)
+    }
+
+    /// Writes the diff of the previous version of `document` if enabled and there is one.
+    fn write_previous_version_diff(
+        &mut self,
+        document: &PendingDocument,
+        comparisons: &[Comparison],
+    ) -> Result<(), DiffSinkError> {
+        let Some(previous) = document
+            .previous
+            .as_deref()
+            .filter(|_| self.previous_version_diff)
+        else {
+            return Ok(());
+        };
+        let limit = self.output_limit.map(|limit| OutputLimit {
+            action: OutputLimitAction::Overwrite,
+            ..limit
+        });
+        let out_path = self.diff_output_path(document, "diff-old", limit.as_ref())?;
+        if let Some(diff) = self.editor.mark_previous_version(
+            previous,
+            &document.path,
+            comparisons,
+            &out_path,
+            self.cancel.as_ref(),
+        )? {
+            // Only the diff of the current version is reported, this one is an addition.
+            for (page, e) in diff.outcome.failed_pages() {
+                log::warn!(
+                    "Unable to mark page {} of the previous version {}. Error: {}",
+                    page + 1,
+                    previous.to_string_lossy(),
+                    e
+                );
+            }
+            self.timings += diff.timings;
+            self.previous_output = Some(out_path);
+        }
+        Ok(())
+    }
+
+    /// Path of the combined diff in `diff_path`, with a counter appended if the name is
+    /// taken.
+    fn combined_path(&self) -> PathBuf {
+        let timestamp = artifact_timestamp(self.cycle, self.clock.as_ref()).file_stamp();
+        let mut counter = 0;
+        loop {
+            let path = match counter {
+                0 => self.diff_path.join(format!("combined-{}.pdf", timestamp)),
+                _ => self
+                    .diff_path
+                    .join(format!("combined-{}-{}.pdf", timestamp, counter)),
+            };
+            if !path.exists() {
+                return path;
+            }
+            counter += 1;
+        }
+    }
+}
+
+impl DiffSink for MarkedPdfSink {
+    fn begin_document(
+        &mut self,
+        path: &Path,
+        previous: Option<&Path>,
+    ) -> Result<(), DiffSinkError> {
+        self.document = Some(PendingDocument::new(path, previous));
+        Ok(())
+    }
+
+    fn page_changed(&mut self, index: usize, comparison: &Comparison) -> Result<(), DiffSinkError> {
+        let document = self.document.as_mut().ok_or(DiffSinkError::NoDocument)?;
+        document.pages.push((index, comparison.clone()));
+        Ok(())
+    }
+
+    fn page_identical(
+        &mut self,
+        index: usize,
+        matched_page: PdfPageIndex,
+    ) -> Result<(), DiffSinkError> {
+        let document = self.document.as_mut().ok_or(DiffSinkError::NoDocument)?;
+        document
+            .pages
+            .push((index, Comparison::Identical(matched_page)));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<PathBuf>, DiffSinkError> {
+        let mut document = self.document.take().ok_or(DiffSinkError::NoDocument)?;
+        let out_path = self.diff_output_path(&document, "diff", self.output_limit.as_ref())?;
+        let comparisons = document.comparisons();
+        let diff = self.editor.generate_diff(
+            &document.path,
+            document.previous.as_deref(),
+            &comparisons,
+            &out_path,
+            self.cancel.as_ref(),
+        )?;
+        (self.timings, self.outcome) = (diff.timings, diff.outcome);
+        self.write_previous_version_diff(&document, &comparisons)?;
+        Ok(Some(out_path))
+    }
+
+    fn finish_loaded(
+        &mut self,
+        current: &mut LoadedDocument,
+        previous: Option<&LoadedDocument>,
+    ) -> Result<Option<PathBuf>, DiffSinkError> {
+        let mut document = self.document.take().ok_or(DiffSinkError::NoDocument)?;
+        let out_path = self.diff_output_path(&document, "diff", self.output_limit.as_ref())?;
+        let comparisons = document.comparisons();
+        let diff = self.editor.generate_diff_in_document(
+            current,
+            previous,
+            &comparisons,
+            &out_path,
+            self.cancel.as_ref(),
+        )?;
+        (self.timings, self.outcome) = (diff.timings, diff.outcome);
+        self.write_previous_version_diff(&document, &comparisons)?;
+        Ok(Some(out_path))
+    }
+
+    fn take_timings(&mut self) -> Timings {
+        std::mem::take(&mut self.timings)
+    }
+
+    fn take_mark_outcome(&mut self) -> MarkOutcome {
+        std::mem::take(&mut self.outcome)
+    }
+
+    fn take_previous_version_output(&mut self) -> Option<PathBuf> {
+        self.previous_output.take()
+    }
+
+    fn end_cycle(
+        &mut self,
+        documents: &[CycleDocument],
+    ) -> Result<Option<CombinedOutput>, DiffSinkError> {
+        // Failed documents are only listed next to actual diffs, not merged on their own.
+        if self.combined == CombinedDiff::Off || documents.iter().all(|v| v.output.is_none()) {
+            return Ok(None);
+        }
+        let sources = documents
+            .iter()
+            .map(|document| MergeSource {
+                title: document.path.to_string_lossy().to_string(),
+                diff: document.output.clone(),
+                note: document
+                    .error
+                    .as_ref()
+                    .map(|e| format!("The document couldn't be compared: {}", e)),
+            })
+            .collect::<Vec<MergeSource>>();
+        let path = self.combined_path();
+        self.editor.merge_documents(&sources, &path)?;
+        let replaces_documents = self.combined == CombinedDiff::Only;
+        if replaces_documents {
+            for output in documents.iter().filter_map(|v| v.output.as_ref()) {
+                if let Err(e) = std::fs::remove_file(output) {
+                    log::warn!(
+                        "Unable to remove the merged diff {}. Error: {}",
+                        output.to_string_lossy(),
+                        e
+                    );
+                }
+            }
+        }
+        Ok(Some(CombinedOutput {
+            path,
+            replaces_documents,
+        }))
+    }
+
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn begin_cycle(&mut self, timestamp: Timestamp) {
+        self.cycle = Some(timestamp);
+    }
+
+    fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.editor.set_deadline(deadline);
+    }
+
+    fn set_cancellation(&mut self, cancel: Option<CancellationToken>) {
+        self.cancel = cancel;
+    }
+}
+
+/// Writes the page statistics of every document as `<filename>.diff.<timestamp>.json` into
+/// a directory, see [`Timestamp::file_stamp`].
+#[cfg(feature = "serde")]
+pub struct JsonSink {
+    diff_path: PathBuf,
+    document: Option<PendingDocument>,
+    clock: Arc<dyn Clock>,
+    cycle: Option<Timestamp>,
+    output_limit: Option<OutputLimit>,
+}
+
+#[cfg(feature = "serde")]
+impl JsonSink {
+    pub fn new(diff_path: PathBuf) -> Self {
+        JsonSink {
+            diff_path,
+            document: None,
+            clock: Arc::new(SystemClock),
+            cycle: None,
+            output_limit: None,
+        }
+    }
+
+    /// Caps the files written per document with `limit`.
+    pub fn with_output_limit(mut self, limit: OutputLimit) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+}
+
+#[cfg(feature = "serde")]
+impl DiffSink for JsonSink {
+    fn begin_document(
+        &mut self,
+        path: &Path,
+        previous: Option<&Path>,
+    ) -> Result<(), DiffSinkError> {
+        self.document = Some(PendingDocument::new(path, previous));
+        Ok(())
+    }
+
+    fn page_changed(&mut self, index: usize, comparison: &Comparison) -> Result<(), DiffSinkError> {
+        let document = self.document.as_mut().ok_or(DiffSinkError::NoDocument)?;
+        document.pages.push((index, comparison.clone()));
+        Ok(())
+    }
+
+    fn page_identical(
+        &mut self,
+        index: usize,
+        matched_page: PdfPageIndex,
+    ) -> Result<(), DiffSinkError> {
+        let document = self.document.as_mut().ok_or(DiffSinkError::NoDocument)?;
+        document
+            .pages
+            .push((index, Comparison::Identical(matched_page)));
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<PathBuf>, DiffSinkError> {
+        let mut document = self.document.take().ok_or(DiffSinkError::NoDocument)?;
+        let out_path = document.limited_output_path(
+            &self.diff_path,
+            ("diff", "json"),
+            &artifact_timestamp(self.cycle, self.clock.as_ref()),
+            self.output_limit.as_ref(),
+        )?;
+        let pages = crate::pdf::PageStatistics::from_comparisons(&document.comparisons());
+        let io_error = |source| DiffSinkError::Io {
+            path: out_path.clone(),
+            source,
+        };
+        let content = serde_json::to_vec_pretty(&pages).map_err(|e| io_error(e.into()))?;
+        std::fs::create_dir_all(&self.diff_path).map_err(|source| DiffSinkError::Io {
+            path: self.diff_path.clone(),
+            source,
+        })?;
+        std::fs::write(&out_path, content).map_err(io_error)?;
+        Ok(Some(out_path))
+    }
+
+    fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    fn begin_cycle(&mut self, timestamp: Timestamp) {
+        self.cycle = Some(timestamp);
+    }
+}
+
+/// Discards all results. Useful when only the reports are of interest.
+pub struct NoSink;
+
+impl DiffSink for NoSink {
+    fn begin_document(
+        &mut self,
+        _path: &Path,
+        _previous: Option<&Path>,
+    ) -> Result<(), DiffSinkError> {
+        Ok(())
+    }
+
+    fn page_changed(
+        &mut self,
+        _index: usize,
+        _comparison: &Comparison,
+    ) -> Result<(), DiffSinkError> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<Option<PathBuf>, DiffSinkError> {
+        Ok(None)
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use {
+        super::*,
+        crate::timestamp::Timezone,
+        std::{ffi::OsString, os::unix::ffi::OsStringExt},
+    };
+
+    fn non_utf8(name: &[u8]) -> PathBuf {
+        PathBuf::from(OsString::from_vec(name.to_vec()))
+    }
+
+    #[test]
+    fn non_utf8_names_get_distinct_outputs() {
+        let dir = std::env::temp_dir().join(format!("pdf_diff_gen-sink-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let timestamp = Timezone::Utc.at(chrono::DateTime::UNIX_EPOCH);
+        let documents = [b"report-\xff.pdf", b"report-\xfe.pdf"]
+            .map(|name| PendingDocument::new(&non_utf8(name), None));
+        let outputs = documents
+            .each_ref()
+            .map(|document| document.output_path(&dir, "diff", "json", &timestamp));
+        assert_eq!(
+            outputs[0],
+            dir.join(non_utf8(b"report-\xff.pdf.diff.19700101T000000.000Z.json"))
+        );
+        assert_eq!(
+            outputs[1],
+            dir.join(non_utf8(b"report-\xfe.pdf.diff.19700101T000000.000Z.json"))
+        );
+        for output in &outputs {
+            std::fs::write(output, "[]").unwrap();
+        }
+        for (document, output) in documents.iter().zip(&outputs) {
+            assert_eq!(
+                document.existing_outputs(&dir, "diff", "json"),
+                vec![(0, output.clone())]
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
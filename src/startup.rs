@@ -0,0 +1,267 @@
+use {
+    crate::{
+        files::{FileManager, FileManagerError},
+        pdf::get_pdfium,
+        selftest::load_hint,
+        version::{PdfiumVersion, MIN_PDFIUM_BUILD},
+    },
+    pdfium_render::prelude::*,
+    std::{
+        error::Error,
+        io,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Name of the file written to and removed from `last_path` and `diff_path` to check that
+/// they are writable.
+const PROBE_FILE_NAME: &str = ".pdf_diff_gen-probe";
+
+/// Errors of [`StartupCheck`] and [`check_pdfium`]. `name` is the name of the checked
+/// directory, e.g. `last_path`.
+#[derive(Debug)]
+pub enum StartupError {
+    /// The directory doesn't exist and isn't created.
+    Missing {
+        name: &'static str,
+        path: PathBuf,
+    },
+    NotADirectory {
+        name: &'static str,
+        path: PathBuf,
+    },
+    Unreadable {
+        name: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    UnableToCreate {
+        name: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Writing the probe file into the directory failed.
+    NotWritable {
+        name: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The directories overlap, see [`FileManager::check_paths`].
+    Paths(FileManagerError),
+    /// The library at `library` can't be bound, `hint` explains why.
+    Pdfium {
+        library: PathBuf,
+        hint: String,
+    },
+    /// The library is older than [`MIN_PDFIUM_BUILD`] and old builds aren't allowed.
+    OldPdfium {
+        library: PathBuf,
+        version: PdfiumVersion,
+    },
+}
+
+impl Error for StartupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Unreadable { source, .. }
+            | Self::UnableToCreate { source, .. }
+            | Self::NotWritable { source, .. } => Some(source),
+            Self::Paths(e) => e.source(),
+            Self::Missing { .. }
+            | Self::NotADirectory { .. }
+            | Self::Pdfium { .. }
+            | Self::OldPdfium { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StartupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing {
+                name: "current_path",
+                path,
+            } => write!(
+                f,
+                "current_path {} doesn't exist, check it for typos",
+                path.to_string_lossy()
+            ),
+            Self::Missing { name, path } => write!(
+                f,
+                "{} {} doesn't exist and isn't created",
+                name,
+                path.to_string_lossy()
+            ),
+            Self::NotADirectory { name, path } => {
+                write!(f, "{} {} isn't a directory", name, path.to_string_lossy())
+            }
+            Self::Unreadable { name, path, source } => write!(
+                f,
+                "unable to read the metadata of {} {}: {}",
+                name,
+                path.to_string_lossy(),
+                source
+            ),
+            Self::UnableToCreate { name, path, source } => write!(
+                f,
+                "unable to create {} {}: {}",
+                name,
+                path.to_string_lossy(),
+                source
+            ),
+            Self::NotWritable { name, path, source } => write!(
+                f,
+                "{} {} isn't writable, creating a file in it failed: {}",
+                name,
+                path.to_string_lossy(),
+                source
+            ),
+            Self::Paths(e) => write!(f, "{}", e),
+            Self::Pdfium { hint, .. } => write!(f, "{}", hint),
+            Self::OldPdfium { library, version } => write!(
+                f,
+                "{} is pdfium {}, older than build {} the bindings are written for, its \
+                 renders may differ",
+                library.to_string_lossy(),
+                version,
+                MIN_PDFIUM_BUILD
+            ),
+        }
+    }
+}
+
+impl From<FileManagerError> for StartupError {
+    fn from(value: FileManagerError) -> Self {
+        Self::Paths(value)
+    }
+}
+
+/// Checks the directories of a [`FileManager`] before its first cycle, so a wrong path fails
+/// right away instead of in the middle of a cycle.
+#[derive(Debug, Clone)]
+pub struct StartupCheck {
+    current_path: PathBuf,
+    last_path: PathBuf,
+    diff_path: PathBuf,
+    create_missing: bool,
+}
+
+impl StartupCheck {
+    pub fn new(current_path: PathBuf, last_path: PathBuf, diff_path: PathBuf) -> Self {
+        StartupCheck {
+            current_path,
+            last_path,
+            diff_path,
+            create_missing: true,
+        }
+    }
+
+    /// Whether `last_path` and `diff_path` are created if they don't exist, the default, or
+    /// fail the check with [`StartupError::Missing`].
+    pub fn create_missing(mut self, create_missing: bool) -> Self {
+        self.create_missing = create_missing;
+        self
+    }
+
+    /// Fails unless `current_path` is an existing directory, the directories don't overlap
+    /// and `last_path` and `diff_path` are writable directories, creating them if enabled.
+    pub fn run(&self) -> Result<(), StartupError> {
+        if !self.directory_exists("current_path", &self.current_path)? {
+            return Err(StartupError::Missing {
+                name: "current_path",
+                path: self.current_path.clone(),
+            });
+        }
+        // Checked before creating anything, so no directory is created inside another one.
+        FileManager::check_paths(&self.current_path, &self.last_path, &self.diff_path)?;
+        for (name, path) in [
+            ("last_path", &self.last_path),
+            ("diff_path", &self.diff_path),
+        ] {
+            if !self.directory_exists(name, path)? {
+                if !self.create_missing {
+                    return Err(StartupError::Missing {
+                        name,
+                        path: path.clone(),
+                    });
+                }
+                std::fs::create_dir_all(path).map_err(|source| StartupError::UnableToCreate {
+                    name,
+                    path: path.clone(),
+                    source,
+                })?;
+                log::info!("Created {} {}", name, path.to_string_lossy());
+            }
+            StartupCheck::probe(name, path)?;
+        }
+        Ok(())
+    }
+
+    /// Whether `path` exists, failing if it is something other than a directory.
+    fn directory_exists(&self, name: &'static str, path: &Path) -> Result<bool, StartupError> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if metadata.is_dir() => Ok(true),
+            Ok(_) => Err(StartupError::NotADirectory {
+                name,
+                path: path.to_path_buf(),
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(source) => Err(StartupError::Unreadable {
+                name,
+                path: path.to_path_buf(),
+                source,
+            }),
+        }
+    }
+
+    /// Creates and removes a file in the directory `path`.
+    fn probe(name: &'static str, path: &Path) -> Result<(), StartupError> {
+        let probe = path.join(PROBE_FILE_NAME);
+        std::fs::write(&probe, b"").map_err(|source| StartupError::NotWritable {
+            name,
+            path: path.to_path_buf(),
+            source,
+        })?;
+        if let Err(e) = std::fs::remove_file(&probe) {
+            log::warn!(
+                "Unable to remove the probe file {}. Error: {}",
+                probe.to_string_lossy(),
+                e
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Binds the pdfium library in `pdfium_dir` and detects its version, see
+/// [`PdfiumVersion::detect`]. Binding every function of the API version the bindings are
+/// built for rules out builds lacking functions, builds older than [`MIN_PDFIUM_BUILD`] fail
+/// unless `allow_old` is set. Libraries of unknown version are accepted.
+pub fn check_pdfium(
+    pdfium_dir: &Path,
+    allow_old: bool,
+) -> Result<(Pdfium, Option<PdfiumVersion>), StartupError> {
+    let library = Pdfium::pdfium_platform_library_name_at_path(pdfium_dir);
+    let pdfium = get_pdfium(pdfium_dir).map_err(|e| StartupError::Pdfium {
+        hint: load_hint(&library, &e),
+        library: library.clone(),
+    })?;
+    let version = PdfiumVersion::detect(pdfium_dir);
+    log::info!(
+        "Bound {} for pdfium API {:?}, version {}",
+        library.to_string_lossy(),
+        pdfium.bindings().version(),
+        version.map_or_else(|| String::from("unknown"), |v| v.to_string())
+    );
+    if let Some(version) = version.filter(|v| !v.is_supported()) {
+        if !allow_old {
+            return Err(StartupError::OldPdfium { library, version });
+        }
+        log::warn!(
+            "Using pdfium {}, which is older than the supported build {}",
+            version,
+            MIN_PDFIUM_BUILD
+        );
+    }
+    Ok((pdfium, version))
+}
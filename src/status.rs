@@ -0,0 +1,121 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::{io, path::Path},
+    tokio::fs::{read, rename, write},
+};
+
+use crate::{
+    files::{FileOutcome, UpdateReport},
+    pdf::RenderSettings,
+    timestamp::Timestamp,
+};
+
+/// Name of the status file in `diff_path` unless another path is configured.
+pub const STATUS_FILE_NAME: &str = "status.json";
+
+/// Health of a watching [`FileManager`](crate::FileManager), rewritten after every cycle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CycleStatus {
+    /// When the status was written, at the end of the last cycle.
+    pub updated: Timestamp,
+    /// Name of the tree, see [`FileManagerConfig::entry`](crate::FileManagerConfig::entry).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entry: Option<String>,
+    /// Start of the last cycle that finished without an error, as in its reports, `None` if
+    /// there was none yet.
+    pub last_successful_cycle: Option<Timestamp>,
+    /// Counts of the last successful cycle.
+    pub counts: Option<CycleCounts>,
+    /// Error of the last cycle, `None` if it succeeded.
+    pub last_error: Option<String>,
+    /// Render settings of the last cycle, `None` in status files of older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub render_settings: Option<RenderSettings>,
+}
+
+/// What happened to the files of a cycle, see [`UpdateReport`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CycleCounts {
+    pub duration_seconds: f64,
+    pub scanned: usize,
+    pub ignored: usize,
+    pub unmodified: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+    pub renamed: usize,
+    pub removed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+impl From<&UpdateReport> for CycleCounts {
+    fn from(report: &UpdateReport) -> Self {
+        CycleCounts {
+            duration_seconds: report.duration.as_secs_f64(),
+            scanned: report.scanned_files,
+            ignored: report.ignored,
+            unmodified: report.skipped,
+            changed: report.changed(),
+            unchanged: report.count(|v| matches!(v, FileOutcome::Unchanged { .. })),
+            renamed: report.count(|v| matches!(v, FileOutcome::Renamed { .. })),
+            removed: report.removed(),
+            skipped: report.count(|v| matches!(v, FileOutcome::Skipped { .. })),
+            failed: report.failed(),
+        }
+    }
+}
+
+/// A few lines for humans, e.g. for `pdf_diff_gen status`.
+impl std::fmt::Display for CycleStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(entry) = &self.entry {
+            writeln!(f, "Entry:                 {}", entry)?;
+        }
+        writeln!(f, "Updated:               {}", self.updated)?;
+        match &self.last_successful_cycle {
+            Some(v) => writeln!(f, "Last successful cycle: {}", v)?,
+            None => writeln!(f, "Last successful cycle: none")?,
+        }
+        if let Some(counts) = &self.counts {
+            writeln!(
+                f,
+                "Counts:                {} scanned, {} ignored, {} unmodified, {} changed, {} unchanged, {} renamed, {} removed, {} skipped, {} failed in {:.1}s",
+                counts.scanned,
+                counts.ignored,
+                counts.unmodified,
+                counts.changed,
+                counts.unchanged,
+                counts.renamed,
+                counts.removed,
+                counts.skipped,
+                counts.failed,
+                counts.duration_seconds
+            )?;
+        }
+        if let Some(settings) = &self.render_settings {
+            writeln!(f, "Render settings:       {}", settings)?;
+        }
+        match &self.last_error {
+            Some(e) => write!(f, "Last cycle failed:     {}", e),
+            None => write!(f, "Last cycle:            ok"),
+        }
+    }
+}
+
+impl CycleStatus {
+    pub async fn read(path: &Path) -> io::Result<CycleStatus> {
+        serde_json::from_slice(&read(path).await?).map_err(io::Error::other)
+    }
+
+    /// Writes the status next to `path` and renames it over `path`, so readers never see a
+    /// partially written file.
+    pub(crate) async fn write(&self, path: &Path) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        let partial = path.with_file_name(format!(
+            ".{}.partial",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        write(&partial, content).await?;
+        rename(&partial, path).await
+    }
+}
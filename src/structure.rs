@@ -0,0 +1,207 @@
+use {
+    pdfium_render::prelude::*,
+    sha2::{Digest, Sha256},
+    std::collections::HashMap,
+};
+
+/// Digest of everything pdfium exposes about how a page is drawn: its size and rotation and
+/// the type, position, colors, clip path and content of every page object, including the data
+/// of embedded fonts.
+///
+/// Pdfium doesn't give access to the raw content streams, so the digest is built from the
+/// parsed page objects instead. Two pages with the same digest draw the same objects at the
+/// same positions, clipped the same way. Pages with anything the digest can't describe get no
+/// digest at all and are always compared visually: objects that can't be described completely
+/// (form XObjects, shadings, unsupported objects), annotations, transparency and blend modes,
+/// soft masks, masked images, text that clips and clip paths that [`hash_clip_path`] rejects.
+/// Producers that rewrite a page without changing its looks produce a different digest, which
+/// only means the page is rendered and compared as usual.
+fn page_digest(document: &PdfDocument, page: &PdfPage) -> Option<[u8; 32]> {
+    if !page.annotations().is_empty() || page.has_transparency() {
+        return None;
+    }
+    let bindings = page.bindings();
+    let mut hasher = Sha256::new();
+    hash_points(&mut hasher, page.width());
+    hash_points(&mut hasher, page.height());
+    hasher.update([page.rotation().ok()? as u8]);
+
+    for object in page.objects().iter() {
+        if object.has_transparency() {
+            return None;
+        }
+        let handle = bindings.get_handle_from_object(&object);
+        hasher.update([object.object_type() as u8]);
+        hash_clip_path(&mut hasher, bindings, handle)?;
+        let bounds = object.bounds().ok()?;
+        for value in [
+            bounds.x1, bounds.y1, bounds.x2, bounds.y2, bounds.x3, bounds.y3, bounds.x4, bounds.y4,
+        ] {
+            hash_points(&mut hasher, value);
+        }
+        for color in [object.fill_color().ok(), object.stroke_color().ok()] {
+            match color {
+                Some(color) => {
+                    hasher.update([1, color.red(), color.green(), color.blue(), color.alpha()])
+                }
+                None => hasher.update([0]),
+            }
+        }
+        if let Ok(width) = object.stroke_width() {
+            hash_points(&mut hasher, width);
+        }
+
+        match object.object_type() {
+            PdfPageObjectType::Text => {
+                let text = object.as_text_object()?;
+                let render_mode = text.render_mode();
+                if matches!(
+                    render_mode,
+                    PdfPageTextRenderMode::FilledUnstrokedClipping
+                        | PdfPageTextRenderMode::StrokedUnfilledClipping
+                        | PdfPageTextRenderMode::FilledThenStrokedClipping
+                        | PdfPageTextRenderMode::InvisibleClipping
+                ) {
+                    return None;
+                }
+                hasher.update([render_mode as u8]);
+                hash_points(&mut hasher, text.scaled_font_size());
+                let font = text.font();
+                hasher.update(font.name().as_bytes());
+                hasher.update([0]);
+                match font.is_embedded().ok()? {
+                    true => hasher.update(Sha256::digest(font.data().ok()?)),
+                    false => hasher.update([0]),
+                }
+                hasher.update(text.text().as_bytes());
+                hasher.update([0]);
+            }
+            PdfPageObjectType::Path => {
+                let path = object.as_path_object()?;
+                hasher.update([path.fill_mode().ok()? as u8, path.is_stroked().ok()? as u8]);
+                for segment in path.segments().iter() {
+                    hasher.update([segment.segment_type() as u8, segment.is_close() as u8]);
+                    hash_points(&mut hasher, segment.x());
+                    hash_points(&mut hasher, segment.y());
+                }
+            }
+            PdfPageObjectType::Image => {
+                let (document, page) = (
+                    bindings.get_handle_from_document(document),
+                    bindings.get_handle_from_page(page),
+                );
+                if !is_opaque(
+                    bindings,
+                    bindings.FPDFImageObj_GetRenderedBitmap(document, page, handle),
+                ) {
+                    return None;
+                }
+                let image = object.as_image_object()?.get_raw_image().ok()?;
+                hasher.update(image.width().to_le_bytes());
+                hasher.update(image.height().to_le_bytes());
+                hasher.update(image.as_bytes());
+            }
+            _ => return None,
+        }
+    }
+    Some(hasher.finalize().into())
+}
+
+/// Hashes the clip path of `object`, `None` if it can't be described.
+///
+/// Pdfium doesn't tell whether a clip path uses the nonzero or the even-odd rule. Both rules
+/// agree on paths of straight lines through at most four corners, so only clip paths made of
+/// such paths are described. Clipping by text isn't part of the clip path, the caller rejects
+/// the text objects that clip instead.
+fn hash_clip_path(
+    hasher: &mut Sha256,
+    bindings: &dyn PdfiumLibraryBindings,
+    object: FPDF_PAGEOBJECT,
+) -> Option<()> {
+    let clip = bindings.FPDFPageObj_GetClipPath(object);
+    if clip.is_null() {
+        hasher.update([0]);
+        return Some(());
+    }
+    let paths = bindings.FPDFClipPath_CountPaths(clip);
+    if paths < 0 {
+        return None;
+    }
+    hasher.update([1]);
+    hasher.update(paths.to_le_bytes());
+    for path in 0..paths {
+        let mut corners = Vec::new();
+        for index in 0..bindings.FPDFClipPath_CountPathSegments(clip, path) {
+            let segment = bindings.FPDFClipPath_GetPathSegment(clip, path, index);
+            let expected = match corners.is_empty() {
+                true => PdfPathSegmentType::MoveTo,
+                false => PdfPathSegmentType::LineTo,
+            };
+            let (mut x, mut y) = (0.0, 0.0);
+            if segment.is_null()
+                || bindings.FPDFPathSegment_GetType(segment) != expected as i32
+                || !bindings.is_true(bindings.FPDFPathSegment_GetPoint(segment, &mut x, &mut y))
+            {
+                return None;
+            }
+            corners.push((x, y));
+        }
+        if corners.len() > 1 && corners.first() == corners.last() {
+            corners.pop();
+        }
+        if corners.len() > 4 {
+            return None;
+        }
+        hasher.update([corners.len() as u8]);
+        for (x, y) in corners {
+            hasher.update(x.to_le_bytes());
+            hasher.update(y.to_le_bytes());
+        }
+    }
+    Some(())
+}
+
+/// Whether `bitmap`, an image object rendered by pdfium, is opaque everywhere, and destroys it.
+/// Images with a mask or a soft mask leave pixels transparent, as do images pdfium can't render
+/// on their own (`bitmap` is null then) or rotated images, which don't fill their bitmap.
+fn is_opaque(bindings: &dyn PdfiumLibraryBindings, bitmap: FPDF_BITMAP) -> bool {
+    if bitmap.is_null() {
+        return false;
+    }
+    let width = bindings.FPDFBitmap_GetWidth(bitmap) as usize;
+    let stride = bindings.FPDFBitmap_GetStride(bitmap) as usize;
+    let opaque = bindings.FPDFBitmap_GetFormat(bitmap) == PdfBitmapFormat::BGRA as i32
+        && stride >= width * 4
+        && bindings
+            .FPDFBitmap_GetBuffer_as_slice(bitmap)
+            .chunks(stride.max(1))
+            .all(|row| {
+                row[..width * 4]
+                    .chunks_exact(4)
+                    .all(|pixel| pixel[3] == u8::MAX)
+            });
+    bindings.FPDFBitmap_Destroy(bitmap);
+    opaque
+}
+
+fn hash_points(hasher: &mut Sha256, value: PdfPoints) {
+    hasher.update(value.value.to_le_bytes());
+}
+
+/// For every page of `a`, the pages of `b` with the same [`page_digest`], in page order.
+pub(crate) fn structural_matches(a: &PdfDocument, b: &PdfDocument) -> Vec<Vec<PdfPageIndex>> {
+    let mut digests_b: HashMap<[u8; 32], Vec<PdfPageIndex>> = HashMap::new();
+    for (index, page) in (0..b.pages().len()).zip(b.pages().iter()) {
+        if let Some(digest) = page_digest(b, &page) {
+            digests_b.entry(digest).or_default().push(index);
+        }
+    }
+    a.pages()
+        .iter()
+        .map(|page| {
+            page_digest(a, &page)
+                .and_then(|digest| digests_b.get(&digest).cloned())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,182 @@
+use {
+    chrono::{DateTime, FixedOffset, Local, Offset, SecondsFormat, Utc},
+    std::{error::Error, str::FromStr},
+};
+
+/// Format of the timestamps in the names of written artifacts, ISO 8601 in its basic form
+/// so names carry no colons, followed by `Z` or the offset like `+0200`.
+const FILE_STAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3f";
+
+/// Length of a timestamp in `FILE_STAMP_FORMAT`, without the offset.
+const FILE_STAMP_LENGTH: usize = 19;
+
+/// Zone [`Timestamp`]s are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timezone {
+    #[default]
+    Utc,
+    /// The zone of the system, at the time of every timestamp.
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Timezone {
+    /// `time` in this zone.
+    pub fn at(&self, time: DateTime<Utc>) -> Timestamp {
+        let offset = match self {
+            Timezone::Utc => Utc.fix(),
+            Timezone::Local => time.with_timezone(&Local).offset().fix(),
+            Timezone::Fixed(offset) => *offset,
+        };
+        Timestamp(time.with_timezone(&offset))
+    }
+}
+
+/// Invalid zone passed to [`Timezone::from_str`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimezoneError(pub String);
+
+impl Error for TimezoneError {}
+
+impl std::fmt::Display for TimezoneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Expected utc, local or an offset like +02:00, got {}",
+            self.0
+        )
+    }
+}
+
+/// `utc`, `local` or an offset from UTC like `+02:00` or `-0530`.
+impl FromStr for Timezone {
+    type Err = TimezoneError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "utc" | "z" => Ok(Timezone::Utc),
+            "local" => Ok(Timezone::Local),
+            value => {
+                let (sign, digits) = match value.split_at_checked(1) {
+                    Some(("+", digits)) => (1, digits),
+                    Some(("-", digits)) => (-1, digits),
+                    _ => return Err(TimezoneError(s.to_string())),
+                };
+                let digits = digits.replace(':', "");
+                if !digits.bytes().all(|v| v.is_ascii_digit()) {
+                    return Err(TimezoneError(s.to_string()));
+                }
+                let offset = match (digits.len(), digits.parse::<i32>()) {
+                    (2, Ok(hours)) => hours * 3600,
+                    (4, Ok(v)) if v % 100 < 60 => v / 100 * 3600 + v % 100 * 60,
+                    _ => return Err(TimezoneError(s.to_string())),
+                };
+                FixedOffset::east_opt(sign * offset)
+                    .map(Timezone::Fixed)
+                    .ok_or_else(|| TimezoneError(s.to_string()))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Timezone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Timezone::Utc => write!(f, "utc"),
+            Timezone::Local => write!(f, "local"),
+            Timezone::Fixed(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+/// A point in time in the zone it is written in, the same in every report, status file and
+/// artifact name of a cycle. Shown and serialized as RFC 3339 with milliseconds, e.g.
+/// `2024-03-01T14:00:00.000+02:00`, UTC as `Z`. Timestamps are equal if they are the same
+/// point in time, whatever their zones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(DateTime<FixedOffset>);
+
+impl Timestamp {
+    pub fn utc(&self) -> DateTime<Utc> {
+        self.0.with_timezone(&Utc)
+    }
+
+    /// The time in its zone.
+    pub fn local(&self) -> DateTime<FixedOffset> {
+        self.0
+    }
+
+    pub fn rfc3339(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::Millis, true)
+    }
+
+    /// The form used in the names of artifacts, e.g. `20240301T140000.000+0200`, without
+    /// characters file systems reject.
+    pub fn file_stamp(&self) -> String {
+        match self.0.offset().local_minus_utc() {
+            0 => format!("{}Z", self.0.format(FILE_STAMP_FORMAT)),
+            _ => self
+                .0
+                .format(&format!("{}%z", FILE_STAMP_FORMAT))
+                .to_string(),
+        }
+    }
+
+    /// Reads a [`Self::file_stamp`] from the start of `value`, returning it with the rest.
+    pub fn parse_file_stamp(value: &str) -> Option<(Timestamp, &str)> {
+        let offset_length = match value.as_bytes().get(FILE_STAMP_LENGTH)? {
+            b'Z' => 1,
+            b'+' | b'-' => 5,
+            _ => return None,
+        };
+        let (stamp, rest) = value.split_at_checked(FILE_STAMP_LENGTH + offset_length)?;
+        let stamp = match stamp.strip_suffix('Z') {
+            Some(stamp) => format!("{}+0000", stamp),
+            None => stamp.to_string(),
+        };
+        let time = DateTime::parse_from_str(&stamp, &format!("{}%z", FILE_STAMP_FORMAT)).ok()?;
+        Some((Timestamp(time), rest))
+    }
+
+    /// The day in its zone, e.g. `2024-03-01`.
+    pub fn date(&self) -> String {
+        self.0.format("%Y-%m-%d").to_string()
+    }
+}
+
+/// In UTC.
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(value: DateTime<Utc>) -> Self {
+        Timezone::Utc.at(value)
+    }
+}
+
+impl std::fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.rfc3339())
+    }
+}
+
+/// Any RFC 3339 time, keeping its offset.
+impl FromStr for Timestamp {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DateTime::parse_from_rfc3339(s).map(Timestamp)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.rfc3339())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+/// Stage of processing a file recorded in [`Timings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingStage {
+    /// Reading and parsing the documents.
+    Loading,
+    /// Rendering pages, not counting renders taken from the render cache.
+    Rendering,
+    /// Matching the pages of the current version to the previous version, without rendering.
+    Matching,
+    /// Locating the changed rows of matched pages.
+    Segments,
+    /// Marking the changes in the diff document.
+    Marking,
+    /// Writing the diff document.
+    Saving,
+}
+
+impl TimingStage {
+    pub const ALL: [TimingStage; 6] = [
+        TimingStage::Loading,
+        TimingStage::Rendering,
+        TimingStage::Matching,
+        TimingStage::Segments,
+        TimingStage::Marking,
+        TimingStage::Saving,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Loading => "loading",
+            Self::Rendering => "rendering",
+            Self::Matching => "matching",
+            Self::Segments => "segments",
+            Self::Marking => "marking",
+            Self::Saving => "saving",
+        }
+    }
+}
+
+/// Time spent in every [`TimingStage`] while processing a file or a whole cycle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings {
+    stages: [Duration; TimingStage::ALL.len()],
+}
+
+impl Timings {
+    pub fn get(&self, stage: TimingStage) -> Duration {
+        self.stages[stage as usize]
+    }
+
+    pub fn add(&mut self, stage: TimingStage, duration: Duration) {
+        self.stages[stage as usize] += duration;
+    }
+
+    /// Runs `f` and adds the time it took to `stage`.
+    pub fn time<T>(&mut self, stage: TimingStage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.add(stage, started.elapsed());
+        result
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().sum()
+    }
+
+    /// Every stage with the time spent in it.
+    pub fn stages(&self) -> impl Iterator<Item = (TimingStage, Duration)> + '_ {
+        TimingStage::ALL
+            .into_iter()
+            .map(|stage| (stage, self.get(stage)))
+    }
+}
+
+impl AddAssign for Timings {
+    fn add_assign(&mut self, other: Self) {
+        other
+            .stages()
+            .for_each(|(stage, duration)| self.add(stage, duration));
+    }
+}
+
+/// Every stage in seconds, e.g. `loading 0.12s, rendering 1.50s, ...`.
+impl std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (stage, duration)) in self.stages().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} {:.2}s", stage.name(), duration.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// Serialized as a map of the stage names to seconds.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timings {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.stages.len()))?;
+        for (stage, duration) in self.stages() {
+            map.serialize_entry(stage.name(), &duration.as_secs_f64())?;
+        }
+        map.end()
+    }
+}
+
+/// Read from a map of the stage names to seconds, unknown stages are ignored.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timings {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let seconds = std::collections::HashMap::<String, f64>::deserialize(deserializer)?;
+        let mut timings = Timings::default();
+        for stage in TimingStage::ALL {
+            if let Some(seconds) = seconds.get(stage.name()) {
+                let duration =
+                    Duration::try_from_secs_f64(*seconds).map_err(serde::de::Error::custom)?;
+                timings.add(stage, duration);
+            }
+        }
+        Ok(timings)
+    }
+}
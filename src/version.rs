@@ -0,0 +1,148 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Oldest pdfium build comparisons are supported with, the build the bindings are written
+/// for. Older builds may provide every function but render differently.
+pub const MIN_PDFIUM_BUILD: u32 = 7215;
+
+/// Name of the file pdfium builds like the ones of pdfium-binaries ship their version in,
+/// next to the directory of the library.
+pub const VERSION_FILE_NAME: &str = "VERSION";
+
+/// Chromium version of a pdfium build, e.g. `134.0.7215.0`. pdfium has no call reporting
+/// it, it is read from the [`VERSION_FILE_NAME`] shipped with the library.
+///
+/// With the `serde` feature, serialized as the dotted version string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct PdfiumVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub build: u32,
+    pub patch: u32,
+}
+
+/// The version text isn't a dotted version or a version file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidPdfiumVersion(pub String);
+
+impl std::error::Error for InvalidPdfiumVersion {}
+
+impl std::fmt::Display for InvalidPdfiumVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid pdfium version {:?}", self.0)
+    }
+}
+
+impl PdfiumVersion {
+    /// Reads the version of the library in `pdfium_dir` from the version file in it or in
+    /// its parent directory, `None` if neither exists.
+    pub fn read(pdfium_dir: &Path) -> io::Result<Option<(PdfiumVersion, PathBuf)>> {
+        let candidates = [Some(pdfium_dir), pdfium_dir.parent()];
+        for path in candidates
+            .into_iter()
+            .flatten()
+            .map(|v| v.join(VERSION_FILE_NAME))
+        {
+            match std::fs::read_to_string(&path) {
+                Ok(content) => {
+                    let version = content
+                        .parse()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    return Ok(Some((version, path)));
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Like [`PdfiumVersion::read`], logging why the version is unknown instead of failing.
+    pub fn detect(pdfium_dir: &Path) -> Option<PdfiumVersion> {
+        match PdfiumVersion::read(pdfium_dir) {
+            Ok(Some((version, _))) => Some(version),
+            Ok(None) => {
+                log::warn!(
+                    "The version of pdfium in {} is unknown, it ships no {} file",
+                    pdfium_dir.to_string_lossy(),
+                    VERSION_FILE_NAME
+                );
+                None
+            }
+            Err(e) => {
+                log::warn!(
+                    "Unable to read the version of pdfium in {}. Error: {}",
+                    pdfium_dir.to_string_lossy(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Whether the build is at least [`MIN_PDFIUM_BUILD`].
+    pub fn is_supported(&self) -> bool {
+        self.build >= MIN_PDFIUM_BUILD
+    }
+}
+
+/// Parses either the dotted version or the `KEY=value` lines of a version file, with the
+/// keys `MAJOR`, `MINOR`, `BUILD` and `PATCH`.
+impl FromStr for PdfiumVersion {
+    type Err = InvalidPdfiumVersion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = match s.contains('=') {
+            true => ["MAJOR", "MINOR", "BUILD", "PATCH"]
+                .map(|key| {
+                    s.lines()
+                        .filter_map(|line| line.split_once('='))
+                        .find(|(k, _)| k.trim() == key)
+                        .and_then(|(_, value)| value.trim().parse().ok())
+                })
+                .to_vec(),
+            false => s.trim().split('.').map(|v| v.parse().ok()).collect(),
+        };
+        match parts[..] {
+            [Some(major), Some(minor), Some(build), Some(patch)] => Ok(PdfiumVersion {
+                major,
+                minor,
+                build,
+                patch,
+            }),
+            _ => Err(InvalidPdfiumVersion(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for PdfiumVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major, self.minor, self.build, self.patch
+        )
+    }
+}
+
+impl From<PdfiumVersion> for String {
+    fn from(value: PdfiumVersion) -> Self {
+        value.to_string()
+    }
+}
+
+impl TryFrom<String> for PdfiumVersion {
+    type Error = InvalidPdfiumVersion;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
@@ -0,0 +1,67 @@
+//! Parsing and matching of the baseline overrides, without pdfium.
+
+use {
+    pdf_diff_gen::BaselineOverrides,
+    std::path::{Path, PathBuf},
+};
+
+fn overrides(json: &str) -> BaselineOverrides {
+    serde_json::from_str(json).expect("Invalid overrides")
+}
+
+#[test]
+fn the_first_matching_override_counts() {
+    let overrides = overrides(
+        r#"[
+            {"path": "invoices/2024/*.pdf", "baseline": "/templates/invoice_2024.pdf"},
+            {"path": "invoices/**/*.pdf", "baseline": "/templates/invoice_master.pdf"}
+        ]"#,
+    );
+    assert_eq!(
+        overrides.baseline_for(Path::new("invoices/2024/a.pdf")),
+        Some(Path::new("/templates/invoice_2024.pdf"))
+    );
+    assert_eq!(
+        overrides.baseline_for(Path::new("invoices/2023/b.pdf")),
+        Some(Path::new("/templates/invoice_master.pdf"))
+    );
+    assert_eq!(overrides.baseline_for(Path::new("letters/c.pdf")), None);
+}
+
+#[test]
+fn relative_baselines_are_resolved_against_the_directory() {
+    let overrides = overrides(
+        r#"[
+            {"path": "a/*.pdf", "baseline": "templates/a.pdf"},
+            {"path": "b/*.pdf", "baseline": "/templates/b.pdf"}
+        ]"#,
+    )
+    .relative_to(Path::new("/config"));
+    let baselines = overrides
+        .overrides()
+        .iter()
+        .map(|v| v.baseline().to_path_buf())
+        .collect::<Vec<PathBuf>>();
+    assert_eq!(
+        baselines,
+        [
+            PathBuf::from("/config/templates/a.pdf"),
+            PathBuf::from("/templates/b.pdf")
+        ]
+    );
+}
+
+#[test]
+fn invalid_overrides_are_rejected() {
+    for json in [
+        r#"[{"path": "a/[.pdf", "baseline": "a.pdf"}]"#,
+        r#"[{"path": "a/*.pdf"}]"#,
+        r#"[{"path": "a/*.pdf", "baseline": "a.pdf", "copy": true}]"#,
+    ] {
+        assert!(
+            serde_json::from_str::<BaselineOverrides>(json).is_err(),
+            "{} was accepted",
+            json
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! When the cycles of a watch loop are due and when they overrun their cadence.
+
+use {
+    chrono::{DateTime, TimeZone, Utc},
+    pdf_diff_gen::{Cadence, CycleDurations, MissedTicks, Scheduler},
+    std::time::Duration,
+};
+
+fn at(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(1_700_000_000 + seconds, 0).unwrap()
+}
+
+fn every_30s(missed_ticks: MissedTicks) -> Scheduler {
+    Scheduler::new(Cadence::Interval(Duration::from_secs(30))).with_missed_ticks(missed_ticks)
+}
+
+#[test]
+fn skipped_ticks_measure_the_interval_from_the_end() {
+    let scheduler = every_30s(MissedTicks::Skip);
+    let next = scheduler
+        .next_tick_with_jitter(&at(0), &at(310), 0.)
+        .unwrap();
+    assert_eq!(next.scheduled, at(340));
+    assert_eq!(next.due, at(340));
+    assert_eq!(scheduler.next_with_jitter(&at(310), 0.), Some(next.due));
+}
+
+#[test]
+fn cycles_on_time_keep_their_ticks() {
+    for missed_ticks in [MissedTicks::Coalesce, MissedTicks::Queue] {
+        let next = every_30s(missed_ticks)
+            .next_tick_with_jitter(&at(0), &at(10), 0.)
+            .unwrap();
+        assert_eq!(next.scheduled, at(30));
+        assert_eq!(next.due, at(30));
+    }
+}
+
+#[test]
+fn coalesced_ticks_run_once_right_away() {
+    let scheduler = every_30s(MissedTicks::Coalesce);
+    let next = scheduler
+        .next_tick_with_jitter(&at(0), &at(310), 0.)
+        .unwrap();
+    assert_eq!(next.scheduled, at(300));
+    assert_eq!(next.due, at(310));
+    // The cycle after it is back on the ticks.
+    let next = scheduler
+        .next_tick_with_jitter(&next.scheduled, &at(315), 0.)
+        .unwrap();
+    assert_eq!(next.due, at(330));
+}
+
+#[test]
+fn queued_ticks_run_one_after_another() {
+    let scheduler = every_30s(MissedTicks::Queue);
+    let next = scheduler
+        .next_tick_with_jitter(&at(0), &at(310), 0.)
+        .unwrap();
+    assert_eq!(next.scheduled, at(30));
+    assert_eq!(next.due, at(310));
+    let next = scheduler
+        .next_tick_with_jitter(&next.scheduled, &at(315), 0.)
+        .unwrap();
+    assert_eq!(next.scheduled, at(60));
+    assert_eq!(next.due, at(315));
+}
+
+#[test]
+fn missed_ticks_keep_the_least_gap() {
+    let scheduler = every_30s(MissedTicks::Coalesce).with_min_gap(Duration::from_secs(5));
+    let next = scheduler
+        .next_tick_with_jitter(&at(0), &at(310), 0.)
+        .unwrap();
+    assert_eq!(next.due, at(315));
+}
+
+#[test]
+fn jitter_moves_the_due_time_either_way() {
+    let scheduler = every_30s(MissedTicks::Skip).with_jitter(Duration::from_secs(10));
+    for (factor, due) in [
+        (1., 350),
+        (-1., 330),
+        (0.5, 345),
+        (0., 340),
+        (3., 350),
+        (-3., 330),
+    ] {
+        assert_eq!(
+            scheduler.next_with_jitter(&at(310), factor),
+            Some(at(due)),
+            "{}",
+            factor
+        );
+    }
+    for _ in 0..100 {
+        let due = scheduler.next(&at(310)).unwrap();
+        assert!(at(330) <= due && due <= at(350), "{}", due);
+    }
+}
+
+#[test]
+fn jitter_keeps_the_least_gap() {
+    let scheduler = every_30s(MissedTicks::Skip)
+        .with_jitter(Duration::from_secs(20))
+        .with_min_gap(Duration::from_secs(25));
+    assert_eq!(scheduler.next_with_jitter(&at(310), -1.), Some(at(335)));
+    assert_eq!(scheduler.next_with_jitter(&at(310), 1.), Some(at(360)));
+    let next = scheduler
+        .next_tick_with_jitter(&at(0), &at(310), -1.)
+        .unwrap();
+    assert_eq!(next.scheduled, at(340));
+    assert_eq!(next.due, at(335));
+    for _ in 0..100 {
+        assert!(scheduler.next(&at(310)).unwrap() >= at(335));
+    }
+}
+
+#[test]
+fn jitter_moves_the_fire_times_of_a_schedule() {
+    let schedule = "0 */5 * * * *".parse().unwrap();
+    let scheduler =
+        Scheduler::new(Cadence::Schedule(Box::new(schedule))).with_jitter(Duration::from_secs(30));
+    let time = |minute, second| {
+        Utc.with_ymd_and_hms(2024, 1, 1, 10, minute, second)
+            .unwrap()
+    };
+    assert_eq!(
+        scheduler.next_with_jitter(&time(2, 0), 1.),
+        Some(time(5, 30))
+    );
+    assert_eq!(
+        scheduler.next_with_jitter(&time(2, 0), -1.),
+        Some(time(4, 30))
+    );
+    // Fire times within the gap are skipped, the jitter can't move the next one into it.
+    let scheduler = scheduler.with_min_gap(Duration::from_secs(170));
+    assert_eq!(
+        scheduler.next_with_jitter(&time(2, 0), -1.),
+        Some(time(4, 50))
+    );
+    let scheduler = scheduler.with_min_gap(Duration::from_secs(240));
+    assert_eq!(
+        scheduler.next_with_jitter(&time(2, 0), -1.),
+        Some(time(9, 30))
+    );
+}
+
+#[test]
+fn consistently_long_cycles_overrun_the_interval() {
+    let scheduler = every_30s(MissedTicks::Skip);
+    let mut durations = CycleDurations::new(3);
+    durations.record(Duration::from_secs(290));
+    durations.record(Duration::from_millis(300_500));
+    assert_eq!(durations.overrun(&scheduler, &at(0)), None);
+
+    durations.record(Duration::from_secs(310));
+    let overrun = durations.overrun(&scheduler, &at(0)).unwrap();
+    assert_eq!(overrun.average.as_millis(), 300_166);
+    assert_eq!(overrun.period, Duration::from_secs(30));
+    assert_eq!(overrun.suggested, Duration::from_secs(310));
+    assert_eq!(
+        overrun.to_string(),
+        "cycles take 5m on average, longer than the 30s between them, missed cycles are \
+         skipped. Use an interval of at least 5m 10s"
+    );
+}
+
+#[test]
+fn a_single_slow_cycle_doesnt_overrun() {
+    let scheduler = every_30s(MissedTicks::Skip);
+    let mut durations = CycleDurations::new(3);
+    [10, 10, 70]
+        .into_iter()
+        .for_each(|v| durations.record(Duration::from_secs(v)));
+    assert_eq!(durations.average(), Some(Duration::from_secs(30)));
+    assert_eq!(durations.overrun(&scheduler, &at(0)), None);
+    // Only the last cycles of the window count.
+    [70, 70]
+        .into_iter()
+        .for_each(|v| durations.record(Duration::from_secs(v)));
+    assert!(durations.overrun(&scheduler, &at(0)).is_some());
+}
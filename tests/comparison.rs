@@ -0,0 +1,288 @@
+//! Tests of building comparisons from renders, which don't need pdfium.
+
+#[allow(dead_code)]
+mod factories;
+
+use {
+    factories::white_page,
+    image::Rgb,
+    pdf_diff_gen::{
+        pdf::{PageChange, PageChangeKind, PageSimilarity},
+        ChangeScore, Comparison, ComparisonBuildError, ComparisonConfig, DifferenceSegments,
+        DocumentSide,
+    },
+};
+
+#[test]
+fn unchanged_pair_needs_no_renders() {
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(3, 0),
+        None,
+        None,
+        &ComparisonConfig::default(),
+    );
+    assert_eq!(comparison, Ok(Comparison::Identical(3)));
+}
+
+#[test]
+fn missing_render_is_an_error() {
+    let config = ComparisonConfig::default();
+    let similarity = PageSimilarity::Similar(0, 10);
+    assert_eq!(
+        Comparison::from_similarity(&similarity, None, Some(white_page(4, 4)), &config),
+        Err(ComparisonBuildError::MissingRender(DocumentSide::A))
+    );
+    assert_eq!(
+        Comparison::from_similarity(&similarity, Some(white_page(4, 4)), None, &config),
+        Err(ComparisonBuildError::MissingRender(DocumentSide::B))
+    );
+}
+
+#[test]
+fn mismatched_dimensions_are_an_error() {
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(0, 10),
+        Some(white_page(4, 4)),
+        Some(white_page(4, 5)),
+        &ComparisonConfig::default(),
+    );
+    assert_eq!(
+        comparison,
+        Err(ComparisonBuildError::DimensionMismatch {
+            a: (4, 4),
+            b: (4, 5)
+        })
+    );
+}
+
+#[test]
+fn resized_renders_are_compared_where_they_overlap() {
+    // The previous render is twice as tall, the changed last row of the overlap is the
+    // last quarter of the current page and the fourth eighth of the previous one.
+    let mut changed = white_page(4, 4);
+    changed.put_pixel(1, 3, Rgb([0, 0, 0]));
+    let config = ComparisonConfig {
+        compare_resized_pages: true,
+        locate_previous_differences: true,
+        ..ComparisonConfig::default()
+    };
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(0, 1),
+        Some(changed),
+        Some(white_page(4, 8)),
+        &config,
+    );
+    assert_eq!(
+        comparison,
+        Ok(Comparison::Different(DifferenceSegments {
+            segments: vec![(0.75, 1.)],
+            matched_page: Some(0),
+            previous_segments: vec![(0.375, 0.5)],
+            redactions: Vec::new(),
+        }))
+    );
+}
+
+#[test]
+fn changed_row_spans_a_whole_row() {
+    let mut changed = white_page(4, 4);
+    changed.put_pixel(1, 2, Rgb([0, 0, 0]));
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(1, 1),
+        Some(changed),
+        Some(white_page(4, 4)),
+        &ComparisonConfig::default(),
+    );
+    assert_eq!(
+        comparison,
+        Ok(Comparison::Different(DifferenceSegments {
+            segments: vec![(0.5, 0.75)],
+            matched_page: Some(1),
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+fn single_row_render_has_a_segment() {
+    let mut changed = white_page(4, 1);
+    changed.put_pixel(0, 0, Rgb([0, 0, 0]));
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(0, 1),
+        Some(changed),
+        Some(white_page(4, 1)),
+        &ComparisonConfig::default(),
+    );
+    assert_eq!(
+        comparison,
+        Ok(Comparison::Different(DifferenceSegments {
+            segments: vec![(0., 1.)],
+            matched_page: Some(0),
+            ..Default::default()
+        }))
+    );
+}
+
+#[test]
+fn moved_content_is_located_on_both_versions() {
+    // A line inserted above another one, which moved down by a row.
+    let (mut current, mut previous) = (white_page(4, 4), white_page(4, 4));
+    for x in 0..4 {
+        current.put_pixel(x, 0, Rgb([0, 0, 0]));
+        current.put_pixel(x, 1, Rgb([0, 0, 255]));
+        previous.put_pixel(x, 0, Rgb([0, 0, 255]));
+    }
+    let config = ComparisonConfig {
+        locate_previous_differences: true,
+        ..Default::default()
+    };
+    let comparison = Comparison::from_similarity(
+        &PageSimilarity::Similar(2, 8),
+        Some(current),
+        Some(previous),
+        &config,
+    );
+    assert_eq!(
+        comparison,
+        Ok(Comparison::Different(DifferenceSegments {
+            segments: vec![(0., 0.25), (0.5, 0.75)],
+            matched_page: Some(2),
+            previous_segments: vec![(0., 0.5)],
+            redactions: Vec::new(),
+        }))
+    );
+}
+
+#[test]
+fn previous_version_comparisons_are_turned_around() {
+    let current = [
+        Comparison::Different(DifferenceSegments {
+            segments: vec![(0.25, 0.5)],
+            matched_page: Some(1),
+            previous_segments: vec![(0.75, 1.)],
+            redactions: Vec::new(),
+        }),
+        Comparison::Identical(0),
+    ];
+    assert_eq!(
+        Comparison::for_previous_version(&current, 3),
+        vec![
+            Comparison::Identical(1),
+            Comparison::Different(DifferenceSegments {
+                segments: vec![(0.75, 1.)],
+                matched_page: Some(0),
+                previous_segments: vec![(0.25, 0.5)],
+                redactions: Vec::new(),
+            }),
+            // No page of the current version was matched to it, it was removed.
+            Comparison::Different(DifferenceSegments {
+                segments: vec![(0., 1.)],
+                matched_page: None,
+                ..Default::default()
+            }),
+        ]
+    );
+}
+
+fn changed(segments: Vec<(f64, f64)>, matched_page: Option<u16>) -> Comparison {
+    Comparison::Different(DifferenceSegments {
+        segments,
+        matched_page,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn inserted_and_removed_pages_count_as_fully_changed() {
+    // Previous pages 0 and 1 are kept, 0 with a quarter changed, 2 and 3 were removed.
+    let current = [
+        changed(vec![(0., 0.25)], Some(0)),
+        Comparison::Identical(1),
+        changed(vec![(0., 1.)], None),
+        changed(vec![(0.5, 0.5)], None),
+    ];
+    let score = ChangeScore::from_comparisons(&current, Some(4));
+    // Six pages: four current ones and the two removed ones.
+    assert_eq!(score.changed_pages, 5. / 6.);
+    assert_eq!(score.changed_fraction, (0.25 + 1. + 1. + 2.) / 6.);
+}
+
+#[test]
+fn new_documents_change_entirely_and_identical_ones_not_at_all() {
+    let current = [changed(vec![(0., 1.)], None), changed(vec![(0., 1.)], None)];
+    assert_eq!(
+        ChangeScore::from_comparisons(&current, None),
+        ChangeScore::ENTIRE
+    );
+    let identical = [Comparison::Identical(1), Comparison::Identical(0)];
+    assert_eq!(
+        ChangeScore::from_comparisons(&identical, Some(2)),
+        ChangeScore::default()
+    );
+    assert_eq!(
+        ChangeScore::from_comparisons(&[], Some(0)),
+        ChangeScore::default()
+    );
+}
+
+#[test]
+fn replaced_page_scores_like_a_changed_one() {
+    // The only page matched nothing, so the previous page counts as removed.
+    let score = ChangeScore::from_comparisons(&[changed(vec![(0., 1.)], None)], Some(1));
+    assert_eq!(score, ChangeScore::ENTIRE);
+    let modified = ChangeScore::from_comparisons(&[changed(vec![(0., 0.1)], Some(0))], Some(1));
+    assert_eq!(modified.changed_pages, 1.);
+    assert_eq!(modified.changed_fraction, 0.1);
+}
+
+#[test]
+fn blank_inserted_and_removed_pages_have_no_changed_area() {
+    // Page 1 is an inserted blank page, previous page 1 a removed blank one.
+    let current = [Comparison::Identical(0), changed(Vec::new(), None)];
+    let mut changes = PageChange::from_comparisons(&current, Some(3));
+    PageChange::mark_blank(&mut changes, &[1]);
+    let kinds = changes
+        .iter()
+        .map(|v| (v.page, v.kind, v.blank))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            (1, PageChangeKind::Inserted, true),
+            (1, PageChangeKind::Removed, true),
+            (2, PageChangeKind::Removed, false),
+        ]
+    );
+    let score = ChangeScore::from_changes(&changes, current.len());
+    assert_eq!(score.changed_pages, 3. / 4.);
+    assert_eq!(score.changed_fraction, 1. / 4.);
+}
+
+#[test]
+fn settings_digest_only_depends_on_the_outcome() {
+    let config = ComparisonConfig::default();
+    let threaded = ComparisonConfig {
+        threads: Some(2),
+        nice: Some(10),
+        render_cache_bytes: 0,
+        keep_renders: true,
+        ..ComparisonConfig::default()
+    };
+    assert_eq!(config.settings_digest(), threaded.settings_digest());
+    for changed in [
+        ComparisonConfig {
+            target_width: 600,
+            ..ComparisonConfig::default()
+        },
+        ComparisonConfig {
+            pixel_tolerance: 8,
+            ..ComparisonConfig::default()
+        },
+        ComparisonConfig {
+            detect_blank_pages: true,
+            ..ComparisonConfig::default()
+        },
+    ] {
+        assert_ne!(config.settings_digest(), changed.settings_digest());
+    }
+}
@@ -0,0 +1,57 @@
+//! Tests of the contact sheet layout.
+
+mod factories;
+
+use {
+    factories::{page_statistics, white_page},
+    image::Rgb,
+    pdf_diff_gen::{pdf::PageStatistics, sheet::render_contact_sheets, ContactSheetConfig},
+};
+
+#[test]
+fn large_documents_are_split_into_sheets() {
+    let thumbnails = vec![white_page(20, 30); 5];
+    let pages = (0..5)
+        .map(|page| page_statistics(page, Some(page as u16), 0.))
+        .collect::<Vec<PageStatistics>>();
+    let config = ContactSheetConfig {
+        columns: 2,
+        rows: 2,
+    };
+    let sheets = render_contact_sheets(&thumbnails, &pages, &config);
+    assert_eq!(sheets.len(), 2);
+    // The last sheet only has as many cells as it needs.
+    assert!(sheets[1].height() < sheets[0].height());
+    assert!(sheets[1].width() < sheets[0].width());
+}
+
+#[test]
+fn changed_pages_get_a_border_color() {
+    let thumbnails = vec![white_page(20, 30); 3];
+    let pages = vec![
+        page_statistics(0, Some(0), 0.),
+        page_statistics(1, Some(1), 0.5),
+        page_statistics(2, None, 1.),
+    ];
+    let config = ContactSheetConfig {
+        columns: 3,
+        rows: 1,
+    };
+    let sheets = render_contact_sheets(&thumbnails, &pages, &config);
+    let [sheet] = sheets.as_slice() else {
+        panic!("Expected a single sheet, got {}", sheets.len());
+    };
+    // The top left pixel of the border of every cell, cells are evenly spaced.
+    let step = sheet.width() / 3;
+    let border = |cell: u32| *sheet.get_pixel(12 + cell * step, 12);
+    let (unchanged, modified, inserted) = (border(0), border(1), border(2));
+    assert_eq!(modified, Rgb([221, 0, 0]));
+    assert_eq!(inserted, Rgb([0, 153, 0]));
+    assert_ne!(unchanged, modified);
+    assert_ne!(unchanged, inserted);
+}
+
+#[test]
+fn empty_document_has_no_sheet() {
+    assert!(render_contact_sheets(&[], &[], &ContactSheetConfig::default()).is_empty());
+}
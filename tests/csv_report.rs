@@ -0,0 +1,153 @@
+//! Tests of the csv report, parsing the written rows back.
+
+#[allow(dead_code)]
+mod factories;
+
+use {
+    factories::page_statistics,
+    pdf_diff_gen::{
+        csv::{append_csv_report, csv_rows, CSV_HEADER},
+        files::{FileReport, FileResult},
+        RunReport, Timings,
+    },
+    std::path::PathBuf,
+};
+
+/// Splits csv `content` into rows of unquoted fields.
+fn parse(content: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let (mut row, mut field) = (Vec::new(), String::new());
+    let mut chars = content.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    assert!(
+        !quoted && field.is_empty() && row.is_empty(),
+        "Unterminated row"
+    );
+    rows
+}
+
+fn file(path: &str, result: FileResult, output: Option<&str>) -> FileReport {
+    FileReport {
+        path: PathBuf::from(path),
+        result,
+        severity: None,
+        score: None,
+        alert: false,
+        baseline: None,
+        copy_updated: false,
+        forced: false,
+        cached: false,
+        output: output.map(PathBuf::from),
+        previous_output: None,
+        renamed_from: None,
+        error: None,
+        pages: Vec::new(),
+        removed_pages: Vec::new(),
+        blank_pages: Vec::new(),
+        removed_blank_pages: Vec::new(),
+        failed_pages: Vec::new(),
+        flip_gifs: Vec::new(),
+        comparison_seconds: 0.,
+        hook: None,
+        timings: Timings::default(),
+        render_memory: Default::default(),
+        warnings: Vec::new(),
+        changes: Default::default(),
+    }
+}
+
+fn report() -> RunReport {
+    let mut changed = file(
+        "manuals/a \"quoted\", name.pdf",
+        FileResult::Changed,
+        Some("diffs/manuals/a \"quoted\", name.pdf"),
+    );
+    changed.pages = vec![
+        page_statistics(0, Some(0), 0.),
+        page_statistics(1, Some(1), 0.25),
+        page_statistics(2, None, 1.),
+    ];
+    changed.removed_pages = vec![3, 4];
+    RunReport {
+        timestamp: "2024-03-01T12:00:00Z".parse().unwrap(),
+        entry: None,
+        pdfium_version: None,
+        duration_seconds: 1.,
+        files: vec![changed, file("plain.pdf", FileResult::Identical, None)],
+    }
+}
+
+#[test]
+fn rows_parse_back_into_their_fields() {
+    let rows = parse(&format!("{}{}", CSV_HEADER, csv_rows(&report())));
+    assert_eq!(
+        rows,
+        vec![
+            vec![
+                "timestamp",
+                "path",
+                "outcome",
+                "pages_total",
+                "pages_changed",
+                "pages_inserted",
+                "pages_removed",
+                "max_change_fraction",
+                "output"
+            ],
+            vec![
+                "2024-03-01T12:00:00.000Z",
+                "manuals/a \"quoted\", name.pdf",
+                "changed",
+                "3",
+                "1",
+                "1",
+                "2",
+                "1",
+                "diffs/manuals/a \"quoted\", name.pdf"
+            ],
+            vec![
+                "2024-03-01T12:00:00.000Z",
+                "plain.pdf",
+                "identical",
+                "0",
+                "0",
+                "0",
+                "0",
+                "0",
+                ""
+            ],
+        ]
+    );
+}
+
+#[test]
+fn header_is_only_written_once() {
+    let path = std::env::temp_dir().join(format!("pdf_diff_gen-csv-{}.csv", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    append_csv_report(&path, &report()).unwrap();
+    append_csv_report(&path, &report()).unwrap();
+    let content = std::fs::read_to_string(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    let rows = parse(&content);
+    assert_eq!(rows.len(), 5);
+    assert_eq!(rows[0][0], "timestamp");
+    assert!(rows[1..]
+        .iter()
+        .all(|row| row.len() == 9 && row[0] != "timestamp"));
+}
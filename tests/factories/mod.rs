@@ -0,0 +1,30 @@
+//! Hand-built renders and statistics shared by the tests that don't need pdfium.
+
+use {
+    image::{Rgb, RgbImage},
+    pdf_diff_gen::pdf::PageStatistics,
+};
+
+/// A white render of `width` by `height` pixels.
+pub fn white_page(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_pixel(width, height, Rgb([255, 255, 255]))
+}
+
+/// Statistics of page `page` (zero based) matched against `matched_page`, of which the top
+/// `changed_fraction` changed.
+pub fn page_statistics(
+    page: usize,
+    matched_page: Option<u16>,
+    changed_fraction: f64,
+) -> PageStatistics {
+    PageStatistics {
+        page,
+        matched_page,
+        changed_fraction,
+        segments: match changed_fraction > 0. {
+            true => vec![(0., changed_fraction)],
+            false => Vec::new(),
+        },
+        ..Default::default()
+    }
+}
@@ -0,0 +1,229 @@
+//! Small pdfs generated with pdfium for the integration tests.
+//!
+//! The tests need a pdfium library and are ignored by default. Set
+//! `PDF_DIFF_GEN_TEST_PDFIUM` to the directory holding it and run them with
+//! `cargo test -- --ignored`, without it they fail instead of passing unchecked.
+
+use {
+    pdf_diff_gen::get_pdfium,
+    pdfium_render::prelude::*,
+    std::{
+        path::{Path, PathBuf},
+        sync::{Arc, OnceLock},
+    },
+};
+
+/// Directory of the pdfium library the tests run against.
+pub const PDFIUM_ENV: &str = "PDF_DIFF_GEN_TEST_PDFIUM";
+
+const FONT_SIZE: f32 = 14.;
+/// Baseline of the first line, in points from the bottom of the page.
+const FIRST_BASELINE: f32 = 760.;
+const LINE_HEIGHT: f32 = 40.;
+const MARGIN: f32 = 72.;
+/// Edge length of a speck of scanner noise, in points.
+const SPECK_SIZE: f32 = 1.5;
+
+/// The pdfium library shared by all tests of the process. Panics if `PDFIUM_ENV` isn't set.
+pub fn pdfium() -> Arc<Pdfium> {
+    static PDFIUM: OnceLock<Arc<Pdfium>> = OnceLock::new();
+    PDFIUM
+        .get_or_init(|| {
+            let path = std::env::var_os(PDFIUM_ENV).unwrap_or_else(|| {
+                panic!(
+                    "{} isn't set, it must name the directory of the pdfium library",
+                    PDFIUM_ENV
+                )
+            });
+            let pdfium = get_pdfium(Path::new(&path)).unwrap_or_else(|e| {
+                panic!(
+                    "Unable to load pdfium from {}: {}",
+                    Path::new(&path).to_string_lossy(),
+                    e
+                )
+            });
+            Arc::new(pdfium)
+        })
+        .clone()
+}
+
+/// A page of a generated document: an A4 page, unless `paper` says otherwise, with one line
+/// of text per entry.
+#[derive(Debug, Clone)]
+pub struct PageSpec {
+    pub lines: Vec<String>,
+    pub rotation: PdfPageRenderRotation,
+    /// Shift of all lines to the right and up, in points.
+    pub offset: (f32, f32),
+    /// Dark squares of `SPECK_SIZE` at these positions, in points from the bottom left.
+    pub specks: Vec<(f32, f32)>,
+    /// Color of a box behind the text covering the upper half of the page, usually
+    /// translucent. Pages have no background of their own, so the rest is transparent.
+    pub tint: Option<PdfColor>,
+    pub paper: PdfPagePaperSize,
+}
+
+impl PageSpec {
+    /// `count` lines of filler text, different on every `page`.
+    pub fn text(page: usize, count: usize) -> Self {
+        PageSpec {
+            lines: (0..count)
+                .map(|line| {
+                    format!(
+                        "Page {}, paragraph {}: the quick brown fox jumps over the lazy dog.",
+                        page + 1,
+                        line + 1
+                    )
+                })
+                .collect(),
+            rotation: PdfPageRenderRotation::None,
+            offset: (0., 0.),
+            specks: Vec::new(),
+            tint: None,
+            paper: PdfPagePaperSize::a4(),
+        }
+    }
+
+    /// A blank page with `count` specks of scanner noise, scattered differently for every
+    /// `seed`.
+    pub fn noise(seed: u64, count: usize) -> Self {
+        let size = PdfPagePaperSize::a4();
+        let mut state = seed;
+        let mut next = |range: f32| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as f32 / (1u64 << 31) as f32 * range
+        };
+        PageSpec {
+            specks: (0..count)
+                .map(|_| (next(size.width().value), next(size.height().value)))
+                .collect(),
+            ..PageSpec::text(0, 0)
+        }
+    }
+
+    pub fn with_line(mut self, line: usize, text: &str) -> Self {
+        self.lines[line] = text.to_string();
+        self
+    }
+
+    /// Moves every line by `offset`, e.g. by a fraction of a pixel of the render.
+    pub fn offset(mut self, offset: (f32, f32)) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Puts the page on `paper`, with the lines as far from the top as on A4.
+    pub fn on_paper(mut self, paper: PdfPagePaperSize) -> Self {
+        self.offset.1 += paper.height().value - PdfPagePaperSize::a4().height().value;
+        self.paper = paper;
+        self
+    }
+
+    pub fn tinted(mut self, tint: PdfColor) -> Self {
+        self.tint = Some(tint);
+        self
+    }
+
+    pub fn rotated(mut self, rotation: PdfPageRenderRotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Vertical range `line` covers on an unrotated page, as fractions of the page height
+    /// measured from the top like [`DifferenceSegments`](pdf_diff_gen::DifferenceSegments).
+    pub fn line_range(line: usize) -> (f64, f64) {
+        let height = PdfPagePaperSize::a4().height().value;
+        let baseline = FIRST_BASELINE - line as f32 * LINE_HEIGHT;
+        // Generous enough for ascenders and descenders of Helvetica.
+        let top = baseline + FONT_SIZE;
+        let bottom = baseline - FONT_SIZE * 0.3;
+        (
+            ((height - top) / height) as f64,
+            ((height - bottom) / height) as f64,
+        )
+    }
+}
+
+/// Serializes a document with one page per spec.
+pub fn document(pdfium: &Pdfium, pages: &[PageSpec]) -> Vec<u8> {
+    let mut document = pdfium
+        .create_new_pdf()
+        .expect("Unable to create a document");
+    let font = document.fonts_mut().helvetica();
+    for spec in pages {
+        let mut page = document
+            .pages_mut()
+            .create_page_at_end(spec.paper)
+            .expect("Unable to create a page");
+        if let Some(tint) = spec.tint {
+            let (width, height) = (spec.paper.width().value, spec.paper.height().value);
+            page.objects_mut()
+                .create_path_object_rect(
+                    PdfRect::new_from_values(height / 2., 0., height, width),
+                    None,
+                    None,
+                    Some(tint),
+                )
+                .expect("Unable to add the tint");
+        }
+        for (index, line) in spec.lines.iter().enumerate() {
+            page.objects_mut()
+                .create_text_object(
+                    PdfPoints::new(MARGIN + spec.offset.0),
+                    PdfPoints::new(FIRST_BASELINE - index as f32 * LINE_HEIGHT + spec.offset.1),
+                    line,
+                    font,
+                    PdfPoints::new(FONT_SIZE),
+                )
+                .expect("Unable to add a line");
+        }
+        for (x, y) in &spec.specks {
+            page.objects_mut()
+                .create_path_object_rect(
+                    PdfRect::new_from_values(*y, *x, y + SPECK_SIZE, x + SPECK_SIZE),
+                    None,
+                    None,
+                    Some(PdfColor::new(40, 40, 40, 255)),
+                )
+                .expect("Unable to add a speck");
+        }
+        page.set_rotation(spec.rotation);
+    }
+    document
+        .save_to_bytes()
+        .expect("Unable to serialize the document")
+}
+
+/// A directory in the system temp directory, removed again when dropped.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("pdf_diff_gen-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Unable to create the test directory");
+        TestDir { path }
+    }
+
+    /// Writes `content` to `name` in the directory and returns its path.
+    pub fn write(&self, name: &str, content: &[u8]) -> PathBuf {
+        let path = self.path.join(name);
+        std::fs::write(&path, content).expect("Unable to write a fixture");
+        path
+    }
+
+    pub fn join(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
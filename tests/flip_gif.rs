@@ -0,0 +1,26 @@
+//! Tests of encoding flip gifs, which don't need pdfium.
+
+use {
+    image::{codecs::gif::GifDecoder, AnimationDecoder, Rgb, RgbImage},
+    pdf_diff_gen::flip::encode_flip_gif,
+    std::time::Duration,
+};
+
+#[test]
+fn flip_gif_alternates_between_both_versions() {
+    let previous = RgbImage::from_pixel(8, 6, Rgb([255, 255, 255]));
+    let current = RgbImage::from_pixel(8, 6, Rgb([0, 0, 0]));
+    let gif = encode_flip_gif(&previous, &current, Duration::from_millis(500)).unwrap();
+
+    let frames = GifDecoder::new(std::io::Cursor::new(gif))
+        .unwrap()
+        .into_frames()
+        .collect_frames()
+        .unwrap();
+    assert_eq!(frames.len(), 2);
+    for (frame, expected) in frames.iter().zip([255, 0]) {
+        assert_eq!(frame.buffer().dimensions(), (8, 6));
+        assert_eq!(frame.buffer().get_pixel(3, 3).0[..3], [expected; 3]);
+        assert_eq!(Duration::from(frame.delay()), Duration::from_millis(500));
+    }
+}
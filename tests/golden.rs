@@ -0,0 +1,1622 @@
+//! Golden tests of the comparison pipeline against generated documents. They are ignored
+//! unless run with `--ignored` and `PDF_DIFF_GEN_TEST_PDFIUM` set, see [`fixtures`].
+
+mod fixtures;
+
+use {
+    fixtures::{document, pdfium, PageSpec, TestDir},
+    pdf_diff_gen::{
+        cancel::CancellationToken,
+        pdf::{OutputStyle, OverlayConfig, IDENTITY_ATTACHMENT_NAME, NEW_DOCUMENT_BANNER},
+        Comparison, DifferenceSegments, DocumentSide, EditOperation, IdentitySettings,
+        PDFComparison, PDFComparisonError, PDFEditor, PDFEditorConfig, PDFEditorError,
+        PageRotation,
+    },
+    pdfium_render::prelude::*,
+};
+
+/// How far the segments may reach beyond the line that changed, as a fraction of the page
+/// height.
+const SEGMENT_TOLERANCE: f64 = 0.01;
+
+/// Compares the documents built from `current` and `previous` with `comparison`.
+fn compare(
+    dir: &TestDir,
+    pdfium: &Pdfium,
+    comparison: &PDFComparison,
+    current: &[PageSpec],
+    previous: &[PageSpec],
+) -> Vec<Comparison> {
+    let a = dir.write("current.pdf", &document(pdfium, current));
+    let b = dir.write("previous.pdf", &document(pdfium, previous));
+    comparison
+        .compare_pdfs(&a, &b, None)
+        .expect("Unable to compare the fixtures")
+}
+
+/// Asserts that `comparison` is a change of the previous page `matched_page` lying within
+/// `line` of the fixture text.
+fn assert_changed_line(comparison: &Comparison, matched_page: PdfPageIndex, line: usize) {
+    let Comparison::Different(segments) = comparison else {
+        panic!("Expected a changed page, got {:?}", comparison);
+    };
+    assert_eq!(segments.matched_page, Some(matched_page));
+    assert!(!segments.segments.is_empty());
+    let (top, bottom) = PageSpec::line_range(line);
+    for (start, end) in &segments.segments {
+        assert!(
+            *start >= top - SEGMENT_TOLERANCE && *end <= bottom + SEGMENT_TOLERANCE,
+            "Segment {}..{} lies outside of line {} at {}..{}",
+            start,
+            end,
+            line,
+            top,
+            bottom
+        );
+    }
+}
+
+/// Page count of the diff `mark_differences` writes for `comparisons`.
+fn marked_page_count(
+    dir: &TestDir,
+    pdfium: &std::sync::Arc<Pdfium>,
+    comparisons: &[Comparison],
+) -> u16 {
+    let out = dir.join("diff.pdf");
+    PDFEditor::new(pdfium.clone(), PDFEditorConfig::default())
+        .mark_differences(
+            &dir.join("current.pdf"),
+            Some(&dir.join("previous.pdf")),
+            comparisons,
+            &out,
+            None,
+        )
+        .expect("Unable to mark the differences");
+    pdfium
+        .load_pdf_from_file(&out, None)
+        .expect("The diff isn't a loadable pdf")
+        .pages()
+        .len()
+}
+
+fn pages(count: usize) -> Vec<PageSpec> {
+    (0..count).map(|page| PageSpec::text(page, 5)).collect()
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn identical_documents_match_every_page() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("identical");
+    // Once matched by structure and once by rendering every page.
+    for structural_precheck in [true, false] {
+        let comparison = PDFComparison::builder(pdfium.clone())
+            .structural_precheck(structural_precheck)
+            .build()
+            .unwrap();
+        let comparisons = compare(&dir, &pdfium, &comparison, &pages(3), &pages(3));
+        assert_eq!(
+            comparisons,
+            vec![
+                Comparison::Identical(0),
+                Comparison::Identical(1),
+                Comparison::Identical(2)
+            ]
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn blend_modes_are_compared_visually_with_the_structural_precheck() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("blend_mode");
+    let spec = PageSpec::text(0, 5).tinted(PdfColor::new(200, 40, 40, 255));
+    let b = dir.write(
+        "previous.pdf",
+        &document(&pdfium, std::slice::from_ref(&spec)),
+    );
+    // Only the blend mode of the tint, the first object of the page, differs.
+    let current = pdfium
+        .load_pdf_from_byte_vec(document(&pdfium, &[spec]), None)
+        .unwrap();
+    let mut page = current.pages().first().unwrap();
+    page.objects()
+        .first()
+        .unwrap()
+        .set_blend_mode(PdfPageObjectBlendMode::Difference)
+        .unwrap();
+    page.regenerate_content().unwrap();
+    let a = dir.write("current.pdf", &current.save_to_bytes().unwrap());
+
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .structural_precheck(true)
+        .build()
+        .unwrap();
+    let comparisons = comparison.compare_pdfs(&a, &b, None).unwrap();
+    assert!(matches!(comparisons[..], [Comparison::Different(_)]));
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn changed_paragraph_is_located() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("changed_paragraph");
+    let mut current = pages(2);
+    current[1] = current[1].clone().with_line(
+        2,
+        "Page 2, paragraph 3: a paragraph that was rewritten entirely.",
+    );
+    let comparison = PDFComparison::new(pdfium.clone());
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &pages(2));
+
+    assert_eq!(comparisons.len(), 2);
+    assert_eq!(comparisons[0], Comparison::Identical(0));
+    assert_changed_line(&comparisons[1], 1, 2);
+    assert_eq!(marked_page_count(&dir, &pdfium, &comparisons), 1);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn inserted_page_keeps_the_other_pages_matched() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("inserted_page");
+    let previous = pages(2);
+    let current = vec![
+        previous[0].clone(),
+        PageSpec::text(10, 12),
+        previous[1].clone(),
+    ];
+    let comparison = PDFComparison::new(pdfium.clone());
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &previous);
+
+    assert_eq!(comparisons.len(), 3);
+    assert_eq!(comparisons[0], Comparison::Identical(0));
+    assert!(matches!(comparisons[1], Comparison::Different(_)));
+    assert_eq!(comparisons[2], Comparison::Identical(1));
+    assert_eq!(marked_page_count(&dir, &pdfium, &comparisons), 1);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn rotated_page_depends_on_the_rotation_setting() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("rotated_page");
+    let previous = pages(1);
+    let current = vec![previous[0]
+        .clone()
+        .rotated(PdfPageRenderRotation::Degrees90)];
+
+    let normalized = PDFComparison::builder(pdfium.clone())
+        .rotation(PageRotation::Normalize)
+        .build()
+        .unwrap();
+    assert_eq!(
+        compare(&dir, &pdfium, &normalized, &current, &previous),
+        vec![Comparison::Identical(0)]
+    );
+    // Whichever version carries the flag, and whatever its angle.
+    for rotation in [
+        PdfPageRenderRotation::Degrees90,
+        PdfPageRenderRotation::Degrees180,
+        PdfPageRenderRotation::Degrees270,
+    ] {
+        let rotated = vec![previous[0].clone().rotated(rotation)];
+        assert_eq!(
+            compare(&dir, &pdfium, &normalized, &previous, &rotated),
+            vec![Comparison::Identical(0)],
+            "{:?}",
+            rotation
+        );
+        assert_eq!(
+            compare(&dir, &pdfium, &normalized, &rotated, &current),
+            vec![Comparison::Identical(0)],
+            "{:?}",
+            rotation
+        );
+    }
+
+    // Displayed as is, the rotated page has other dimensions and can't match at all.
+    let displayed = PDFComparison::builder(pdfium.clone())
+        .rotation(PageRotation::Off)
+        .build()
+        .unwrap();
+    let comparisons = compare(&dir, &pdfium, &displayed, &current, &previous);
+    let [Comparison::Different(segments)] = comparisons.as_slice() else {
+        panic!("Expected a single changed page, got {:?}", comparisons);
+    };
+    assert_eq!(segments.matched_page, None);
+    assert_eq!(segments.segments, vec![(0., 1.)]);
+    assert_eq!(marked_page_count(&dir, &pdfium, &comparisons), 1);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn transparent_pages_are_identical_on_any_background() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("transparent_page");
+    let tinted = vec![pages(1)[0].clone().tinted(PdfColor::new(200, 30, 30, 96))];
+    for background in [[255, 255, 255], [0, 0, 0], [128, 128, 128]] {
+        let comparison = PDFComparison::builder(pdfium.clone())
+            .background(background)
+            .build()
+            .unwrap();
+        assert_eq!(
+            compare(&dir, &pdfium, &comparison, &tinted, &tinted),
+            vec![Comparison::Identical(0)],
+            "{:?}",
+            background
+        );
+        // The translucent box is still rendered over the background.
+        let comparisons = compare(&dir, &pdfium, &comparison, &tinted, &pages(1));
+        assert!(
+            matches!(comparisons.as_slice(), [Comparison::Different(_)]),
+            "{:?}: {:?}",
+            background,
+            comparisons
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn missing_baseline_is_new_but_a_broken_one_fails() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("baseline");
+    let comparison = PDFComparison::new(pdfium.clone());
+    let a = dir.write("current.pdf", &document(&pdfium, &pages(2)));
+
+    let new = comparison
+        .compare_pdfs_timed(&a, &dir.join("missing.pdf"), None, None, None)
+        .0
+        .expect("A missing baseline isn't an error");
+    assert!(new.new_file);
+    assert!(new
+        .pages
+        .iter()
+        .all(|v| matches!(v, Comparison::Different(seg) if seg.matched_page.is_none())));
+
+    let broken = dir.write("previous.pdf", b"%PDF-1.7 truncated");
+    match comparison.compare_pdfs(&a, &broken, None) {
+        Err(PDFComparisonError::UnableToLoadPDF { document, .. }) => {
+            assert_eq!(document.side, DocumentSide::B)
+        }
+        other => panic!("Expected the baseline to fail loading, got {:?}", other),
+    }
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn new_documents_get_a_banner_instead_of_markers() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("new_document");
+    let current = document(&pdfium, &pages(2));
+    let a = dir.write("current.pdf", &current);
+    let comparisons = PDFComparison::new(pdfium.clone())
+        .compare_pdfs(&a, &dir.join("missing.pdf"), None)
+        .unwrap();
+    let editor = PDFEditor::new(
+        pdfium.clone(),
+        PDFEditorConfig {
+            summary_page: true,
+            ..Default::default()
+        },
+    );
+    let (diff, outcome) = editor
+        .mark_differences_bytes(&current, None, &comparisons)
+        .unwrap();
+    assert!(outcome.is_complete());
+    let diff = pdfium.load_pdf_from_byte_vec(diff, None).unwrap();
+    let texts = diff
+        .pages()
+        .iter()
+        .map(|page| page.text().unwrap().all())
+        .collect::<Vec<String>>();
+    assert_eq!(texts.len(), 3);
+    assert!(texts[0].contains("New document"));
+    for text in &texts[1..] {
+        assert!(
+            text.contains(NEW_DOCUMENT_BANNER),
+            "No banner in {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn removed_pages_are_marked_on_the_previous_version() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("previous_version");
+    let previous = pages(3);
+    let current = vec![previous[0].clone(), previous[2].clone()];
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .locate_previous_differences(true)
+        .build()
+        .unwrap();
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &previous);
+    assert_eq!(
+        comparisons,
+        vec![Comparison::Identical(0), Comparison::Identical(2)]
+    );
+
+    let out = dir.join("diff-old.pdf");
+    let timings = PDFEditor::new(pdfium.clone(), PDFEditorConfig::default())
+        .mark_previous_version(
+            &dir.join("previous.pdf"),
+            &dir.join("current.pdf"),
+            &comparisons,
+            &out,
+            None,
+        )
+        .unwrap();
+    assert!(timings.is_some());
+    let marked = pdfium.load_pdf_from_file(&out, None).unwrap();
+    assert_eq!(marked.pages().len(), 1);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn cancelled_marking_of_the_previous_version_names_it() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("previous_version_cancelled");
+    let previous = pages(3);
+    let current = vec![previous[0].clone(), previous[2].clone()];
+    let comparison = PDFComparison::builder(pdfium.clone()).build().unwrap();
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &previous);
+
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let result = PDFEditor::new(pdfium.clone(), PDFEditorConfig::default()).mark_previous_version(
+        &dir.join("previous.pdf"),
+        &dir.join("current.pdf"),
+        &comparisons,
+        &dir.join("diff-old.pdf"),
+        Some(&cancel),
+    );
+    let Err(PDFEditorError::Cancelled { document }) = result else {
+        panic!("Expected the marking to be cancelled, got {:?}", result);
+    };
+    assert_eq!(document.side, DocumentSide::B);
+    assert_eq!(document.path, Some(dir.join("previous.pdf")));
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn repeated_blank_pages_keep_their_positions() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("blank_pages");
+    // A page without any lines.
+    let blank = PageSpec::text(0, 0);
+    let previous = (0..3)
+        .flat_map(|page| [PageSpec::text(page, 5), blank.clone()])
+        .collect::<Vec<PageSpec>>();
+    let mut current = vec![PageSpec::text(10, 12)];
+    current.extend(previous.iter().cloned());
+
+    // Once matched by structure and once by rendering every page.
+    for structural_precheck in [true, false] {
+        let comparison = PDFComparison::builder(pdfium.clone())
+            .structural_precheck(structural_precheck)
+            .build()
+            .unwrap();
+        let comparisons = compare(&dir, &pdfium, &comparison, &current, &previous);
+        assert_eq!(comparisons.len(), 7);
+        assert!(matches!(comparisons[0], Comparison::Different(_)));
+        assert_eq!(
+            comparisons[1..],
+            (0..6)
+                .map(Comparison::Identical)
+                .collect::<Vec<Comparison>>()
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn noisy_blank_pages_are_identical_when_detected() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("noisy_blank_pages");
+    let detecting = PDFComparison::builder(pdfium.clone())
+        .detect_blank_pages(true)
+        .blank_ink_threshold(0.001)
+        .build()
+        .unwrap();
+    let compare_detecting = |current: &[PageSpec], previous: &[PageSpec]| {
+        let a = dir.write("current.pdf", &document(&pdfium, current));
+        let b = dir.write("previous.pdf", &document(&pdfium, previous));
+        let (result, _) = detecting.compare_pdfs_timed(&a, &b, None, None, None);
+        result.unwrap()
+    };
+
+    let previous = [PageSpec::text(0, 5), PageSpec::noise(1, 8)];
+    let current = [PageSpec::text(0, 5), PageSpec::noise(2, 8)];
+    let result = compare_detecting(&current, &previous);
+    assert_eq!(
+        result.pages,
+        vec![Comparison::Identical(0), Comparison::Identical(1)]
+    );
+    assert_eq!(result.blank_pages, vec![1]);
+    assert_eq!(result.previous_blank_pages, vec![1]);
+    assert!(result.ink_coverage[1].is_some_and(|v| v > 0. && v <= 0.001));
+    // Without detecting blank pages the specks differ.
+    let comparisons = compare(
+        &dir,
+        &pdfium,
+        &PDFComparison::new(pdfium.clone()),
+        &current,
+        &previous,
+    );
+    assert!(matches!(comparisons[1], Comparison::Different(_)));
+
+    // A separator page the previous version lacks has nothing to mark.
+    let previous = pages(2);
+    let current = [
+        pages(1),
+        vec![PageSpec::noise(3, 8)],
+        pages(2)[1..].to_vec(),
+    ]
+    .concat();
+    let result = compare_detecting(&current, &previous);
+    assert_eq!(
+        result.pages,
+        vec![
+            Comparison::Identical(0),
+            Comparison::Different(DifferenceSegments {
+                segments: Vec::new(),
+                matched_page: None,
+                ..Default::default()
+            }),
+            Comparison::Identical(1),
+        ]
+    );
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn resized_page_is_identical_and_reports_the_size_change() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("resized_page");
+    let taller = PdfPagePaperSize::new_custom(PdfPoints::new(595.28), PdfPoints::new(1000.));
+    let previous = pages(1)
+        .into_iter()
+        .map(|v| v.on_paper(taller))
+        .collect::<Vec<_>>();
+    let a = dir.write("current.pdf", &document(&pdfium, &pages(1)));
+    let b = dir.write("previous.pdf", &document(&pdfium, &previous));
+
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .compare_resized_pages(true)
+        .build()
+        .unwrap();
+    let (result, _) = comparison.compare_pdfs_timed(&a, &b, None, None, None);
+    let result = result.expect("Unable to compare the fixtures");
+    assert_eq!(result.pages, vec![Comparison::Identical(0)]);
+    let [Some(size_change)] = result.size_changes[..] else {
+        panic!("Unexpected size changes {:?}", result.size_changes);
+    };
+    assert_eq!(
+        size_change.to_string(),
+        "page size changed from 210 x 353 mm to A4"
+    );
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn windowed_matching_finds_moved_pages() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("match_window");
+    let previous = pages(4);
+    let mut current = previous.clone();
+    current.rotate_right(1);
+    // The moved page lies outside of the window and is found by matching all pages.
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .structural_precheck(false)
+        .match_window(1)
+        .build()
+        .unwrap();
+    assert_eq!(
+        compare(&dir, &pdfium, &comparison, &current, &previous),
+        [3, 0, 1, 2].map(Comparison::Identical)
+    );
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn dedicated_thread_pool_compares_like_the_global_one() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("thread_pool");
+    let mut current = pages(2);
+    current[1] = current[1]
+        .clone()
+        .with_line(1, "Page 2, paragraph 2: compared on two threads.");
+    let global = compare(
+        &dir,
+        &pdfium,
+        &PDFComparison::builder(pdfium.clone())
+            .structural_precheck(false)
+            .build()
+            .unwrap(),
+        &current,
+        &pages(2),
+    );
+    let dedicated = PDFComparison::builder(pdfium.clone())
+        .structural_precheck(false)
+        .threads(2)
+        .nice(10)
+        .build()
+        .unwrap();
+    assert_eq!(
+        compare(&dir, &pdfium, &dedicated, &current, &pages(2)),
+        global
+    );
+    assert_changed_line(&global[1], 1, 1);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn blur_absorbs_text_shifted_by_a_fraction_of_a_pixel() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("blur");
+    // A4 pages are 595 points wide, rendered at 500 pixels this is a third of a pixel.
+    let shifted = vec![pages(1)[0].clone().offset((0.4, 0.4))];
+    let comparison = |blur_radius| {
+        PDFComparison::builder(pdfium.clone())
+            .pixel_tolerance(48)
+            .blur_radius(blur_radius)
+            .build()
+            .unwrap()
+    };
+    assert!(matches!(
+        compare(&dir, &pdfium, &comparison(0), &shifted, &pages(1)).as_slice(),
+        [Comparison::Different(_)]
+    ));
+    assert_eq!(
+        compare(&dir, &pdfium, &comparison(2), &shifted, &pages(1)),
+        vec![Comparison::Identical(0)]
+    );
+
+    // Changes larger than the radius still register.
+    let changed = pages(1)[0].clone().with_line(
+        3,
+        "Page 1, paragraph 4: a paragraph that was rewritten entirely.",
+    );
+    let comparisons = compare(&dir, &pdfium, &comparison(2), &[changed], &pages(1));
+    assert_changed_line(&comparisons[0], 0, 3);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn pages_within_the_threshold_are_not_rendered_again() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("render_counts");
+    let mut current = pages(2);
+    current[1] = current[1]
+        .clone()
+        .with_line(4, "Page 2, paragraph 5: a slightly different last line.");
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &pages(2)));
+    // Without a cache every page is rendered whenever it is needed.
+    let compare = |page_threshold| {
+        let comparison = PDFComparison::builder(pdfium.clone())
+            .structural_precheck(false)
+            .prepass_width(0)
+            .render_cache_bytes(0)
+            .page_threshold(page_threshold)
+            .build()
+            .unwrap();
+        let (result, _) = comparison.compare_pdfs_timed(&a, &b, None, None, None);
+        result.expect("Unable to compare the fixtures")
+    };
+
+    // Matching renders both pages of the current version and both previous pages for each.
+    let within = compare(0.5);
+    assert_eq!(
+        within.pages,
+        vec![Comparison::Identical(0), Comparison::Identical(1)]
+    );
+    assert_eq!(within.pages_rendered, 6);
+    // Only the changed page is rendered again to locate its differences.
+    let changed = compare(0.);
+    assert_eq!(changed.pages[0], Comparison::Identical(0));
+    assert_changed_line(&changed.pages[1], 1, 4);
+    assert_eq!(changed.pages_rendered, 8);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn streamed_comparison_matches_the_collected_one() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("streamed");
+    let mut current = pages(3);
+    current[2] = current[2]
+        .clone()
+        .with_line(0, "A rewritten first paragraph.");
+    let comparison = PDFComparison::new(pdfium.clone());
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &pages(3));
+
+    let (a, b) = (dir.join("current.pdf"), dir.join("previous.pdf"));
+    let streamed = comparison
+        .compare_pdfs_iter(&a, &b, None)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("Unable to stream the comparison");
+    assert_eq!(
+        streamed,
+        comparisons
+            .iter()
+            .cloned()
+            .enumerate()
+            .map(|(page, v)| (page as u16, v))
+            .collect::<Vec<_>>()
+    );
+
+    let out = dir.join("streamed.pdf");
+    PDFEditor::new(pdfium.clone(), PDFEditorConfig::default())
+        .mark_differences_iter(
+            &a,
+            Some(&b),
+            comparison.compare_pdfs_iter(&a, &b, None),
+            &out,
+            None,
+        )
+        .expect("Unable to mark the streamed differences");
+    let marked = pdfium
+        .load_pdf_from_file(&out, None)
+        .expect("The diff isn't a loadable pdf");
+    assert_eq!(
+        marked.pages().len(),
+        marked_page_count(&dir, &pdfium, &comparisons)
+    );
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn marked_diff_records_the_compared_versions() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("identity");
+    let mut current = pages(2);
+    current[0] = current[0]
+        .clone()
+        .with_line(1, "A rewritten second paragraph.");
+    let comparison = PDFComparison::new(pdfium.clone());
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &pages(2));
+
+    let out = dir.join("diff.pdf");
+    let config = PDFEditorConfig {
+        identity: Some(IdentitySettings::from(comparison.config())),
+        summary_page: true,
+        ..Default::default()
+    };
+    PDFEditor::new(pdfium.clone(), config)
+        .mark_differences(
+            &dir.join("current.pdf"),
+            Some(&dir.join("previous.pdf")),
+            &comparisons,
+            &out,
+            None,
+        )
+        .expect("Unable to mark the differences");
+    let marked = pdfium
+        .load_pdf_from_file(&out, None)
+        .expect("The diff isn't a loadable pdf");
+    let attachment = marked
+        .attachments()
+        .iter()
+        .find(|v| v.name() == IDENTITY_ATTACHMENT_NAME)
+        .expect("The diff has no identity");
+    let text = String::from_utf8(attachment.save_to_bytes().unwrap()).unwrap();
+    let value = |key: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(key)?.strip_prefix(": "))
+            .map(String::from)
+    };
+    assert!(value("source").unwrap().ends_with("current.pdf"));
+    assert!(value("baseline").unwrap().ends_with("previous.pdf"));
+    assert_eq!(value("source_sha256").unwrap().len(), 64);
+    assert_ne!(value("source_sha256"), value("baseline_sha256"));
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn memory_limit_reduces_the_resolution_instead_of_failing() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("memory_limit");
+    let mut current = pages(2);
+    current[1] = current[1]
+        .clone()
+        .with_line(3, "A rewritten fourth paragraph.");
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &pages(2)));
+    // Far less than two renders of a page at the default width take.
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .structural_precheck(false)
+        .render_memory_limit(200 * 1024)
+        .build()
+        .unwrap();
+    let (result, _) = comparison.compare_pdfs_timed(&a, &b, None, None, None);
+    let result = result.expect("Exceeding the limit isn't an error");
+
+    let reduced = result
+        .memory
+        .reduced_width
+        .expect("The resolution wasn't reduced");
+    assert!(reduced < comparison.config().target_width);
+    assert!(result.memory.warning().is_some());
+    assert!(result.memory.peak_bytes > 0);
+    assert_eq!(result.pages[0], Comparison::Identical(0));
+    assert_changed_line(&result.pages[1], 1, 3);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn shared_documents_are_marked_like_loaded_files() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("shared_documents");
+    let mut current = pages(3);
+    current[2] = current[2]
+        .clone()
+        .with_line(0, "A rewritten first paragraph.");
+    let comparison = PDFComparison::new(pdfium.clone());
+    let comparisons = compare(&dir, &pdfium, &comparison, &current, &pages(3));
+
+    let (a, b) = (dir.join("current.pdf"), dir.join("previous.pdf"));
+    let (loaded, _) = comparison.compare_pdfs_loaded(&a, &b, None, None, None);
+    let mut loaded = loaded.expect("Unable to compare the fixtures");
+    assert_eq!(loaded.comparison.pages, comparisons);
+    assert!(loaded.previous.is_some());
+
+    let out = dir.join("shared.pdf");
+    let config = PDFEditorConfig {
+        summary_page: true,
+        ..Default::default()
+    };
+    PDFEditor::new(pdfium.clone(), config)
+        .mark_differences_in_document(
+            &mut loaded.current,
+            loaded.previous.as_ref(),
+            &comparisons,
+            &out,
+            None,
+        )
+        .expect("Unable to mark the loaded document");
+    let shared = pdfium
+        .load_pdf_from_file(&out, None)
+        .expect("The diff isn't a loadable pdf")
+        .pages()
+        .len();
+    // The summary page precedes the changed page.
+    assert_eq!(shared, 2);
+    assert_eq!(marked_page_count(&dir, &pdfium, &comparisons), 1);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn touched_unchanged_file_is_compared_once() {
+    use pdf_diff_gen::{FileManager, FileManagerConfig, FileOutcome, NoSink};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("touched");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let content = document(&pdfium, &pages(2));
+    std::fs::write(current.join("doc.pdf"), &content).unwrap();
+    std::fs::write(last.join("doc.pdf"), &content).unwrap();
+    // Only the modification time of the current version is newer.
+    std::fs::File::options()
+        .write(true)
+        .open(last.join("doc.pdf"))
+        .unwrap()
+        .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000))
+        .unwrap();
+
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(NoSink),
+        current,
+        last,
+        diffs,
+        FileManagerConfig::default(),
+    );
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let first = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            first.processed.as_slice(),
+            [(
+                _,
+                FileOutcome::Unchanged {
+                    copy_updated: true,
+                    ..
+                }
+            )]
+        ),
+        "Unexpected outcome {:?}",
+        first.processed
+    );
+    let second = runtime.block_on(manager.update(None)).unwrap();
+    assert!(second.processed.is_empty(), "The file was compared again");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn forced_files_are_processed_in_the_next_cycle_only() {
+    use pdf_diff_gen::{FileManager, FileManagerConfig, ForceSelection, NoSink, RunReport};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("forced");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let content = document(&pdfium, &pages(2));
+    std::fs::write(current.join("doc.pdf"), &content).unwrap();
+    std::fs::write(last.join("doc.pdf"), &content).unwrap();
+
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(NoSink),
+        current,
+        last,
+        diffs.clone(),
+        FileManagerConfig::default(),
+    );
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let unmodified = runtime.block_on(manager.update(None)).unwrap();
+    assert!(unmodified.processed.is_empty());
+
+    manager.force_next_cycle(ForceSelection::All);
+    let forced = runtime.block_on(manager.update(None)).unwrap();
+    assert_eq!(forced.processed.len(), 1);
+    let report = std::fs::read_dir(&diffs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("report-") && name.ends_with(".json")
+        })
+        .unwrap();
+    let report: RunReport = serde_json::from_slice(&std::fs::read(report).unwrap()).unwrap();
+    assert!(report.files[0].forced);
+
+    let after = runtime.block_on(manager.update(None)).unwrap();
+    assert!(after.processed.is_empty(), "The file was forced again");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn files_mapped_to_a_shared_baseline_leave_it_untouched() {
+    use pdf_diff_gen::{
+        BaselineOverride, BaselineOverrides, FileManager, FileManagerConfig, FileOutcome, NoSink,
+        RunReport,
+    };
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("shared-baseline");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current.join("invoices"), &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let template = pages(2);
+    let template_content = document(&pdfium, &template);
+    let template_path = dir.write("invoice_master.pdf", &template_content);
+    let mut invoice = template.clone();
+    invoice[1] = invoice[1].clone().with_line(2, "Total: 1,250.00 EUR");
+    let invoice = document(&pdfium, &invoice);
+    std::fs::write(current.join("invoices/a.pdf"), &invoice).unwrap();
+
+    let config = FileManagerConfig {
+        baselines: BaselineOverrides::new(vec![BaselineOverride::new(
+            globset::Glob::new("invoices/*.pdf").unwrap(),
+            template_path.clone(),
+        )]),
+        ..Default::default()
+    };
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(NoSink),
+        current,
+        last.clone(),
+        diffs.clone(),
+        config,
+    );
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let first = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            first.processed.as_slice(),
+            [(
+                _,
+                FileOutcome::DiffWritten {
+                    new_file: false,
+                    ..
+                }
+            )]
+        ),
+        "Unexpected outcome {:?}",
+        first.processed
+    );
+    assert_eq!(std::fs::read(&template_path).unwrap(), template_content);
+    assert!(!last.join("invoices/a.pdf").exists(), "The file was copied");
+    let report = std::fs::read_dir(&diffs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            path.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("report-")
+        })
+        .unwrap();
+    let report: RunReport = serde_json::from_slice(&std::fs::read(report).unwrap()).unwrap();
+    assert_eq!(report.files[0].baseline.as_ref(), Some(&template_path));
+
+    let second = runtime.block_on(manager.update(None)).unwrap();
+    assert!(second.processed.is_empty(), "The file was compared again");
+
+    std::fs::write(&template_path, &invoice).unwrap();
+    let third = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            third.processed.as_slice(),
+            [(_, FileOutcome::Unchanged { .. })]
+        ),
+        "The changed baseline wasn't compared: {:?}",
+        third.processed
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn render_settings_are_recorded_in_the_status_file() {
+    use pdf_diff_gen::{CycleStatus, FileManager, FileManagerConfig, NoSink, RenderFlags};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("render_settings");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    std::fs::write(current.join("doc.pdf"), document(&pdfium, &pages(1))).unwrap();
+
+    let flags = RenderFlags {
+        text_antialiasing: false,
+        grayscale: true,
+        ..RenderFlags::default()
+    };
+    let comparison = PDFComparison::builder(pdfium.clone())
+        .render_flags(flags)
+        .build()
+        .unwrap();
+    let mut manager = FileManager::new(
+        comparison,
+        Box::new(NoSink),
+        current,
+        last,
+        diffs,
+        FileManagerConfig::default(),
+    );
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(manager.update(None)).unwrap();
+    let status = runtime
+        .block_on(CycleStatus::read(&manager.status_path()))
+        .unwrap();
+    assert_eq!(status.render_settings.map(|v| v.flags), Some(flags));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn unchanged_versions_reuse_the_comparison_until_the_settings_change() {
+    use pdf_diff_gen::{FileManager, FileManagerConfig, FixedClock, MarkedPdfSink, RunReport};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("comparison_cache");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let mut changed = pages(2);
+    changed[1] = changed[1].clone().with_line(2, "A rewritten paragraph.");
+    std::fs::write(last.join("doc.pdf"), document(&pdfium, &pages(2))).unwrap();
+    std::fs::write(current.join("doc.pdf"), document(&pdfium, &changed)).unwrap();
+
+    let clock = std::sync::Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(MarkedPdfSink::new(
+            PDFEditor::new(pdfium.clone(), PDFEditorConfig::default()),
+            diffs.clone(),
+        )),
+        current,
+        last,
+        diffs.clone(),
+        FileManagerConfig::default(),
+    )
+    .with_clock(clock);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let run = |manager: &mut FileManager| {
+        runtime.block_on(manager.compare_trees()).unwrap();
+        let report = std::fs::read_dir(&diffs)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.starts_with("report-") && name.ends_with(".json")
+            })
+            .unwrap();
+        let report: RunReport = serde_json::from_slice(&std::fs::read(report).unwrap()).unwrap();
+        let file = &report.files[0];
+        (
+            file.cached,
+            diffs.join(file.output.clone().expect("No diff was written")),
+        )
+    };
+    let (cached, diff) = run(&mut manager);
+    assert!(!cached, "The first comparison was cached");
+    std::fs::remove_file(&diff).unwrap();
+    let (cached, diff) = run(&mut manager);
+    assert!(cached, "The unchanged versions were compared again");
+    assert!(
+        diff.exists(),
+        "The diff wasn't marked from the cached comparison"
+    );
+
+    let wider = PDFComparison::builder(pdfium.clone())
+        .target_width(600)
+        .build()
+        .unwrap();
+    manager.set_comparison(wider);
+    let (cached, _) = run(&mut manager);
+    assert!(!cached, "The changed settings reused the comparison");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn repeated_runs_report_the_files_in_the_same_order() {
+    use pdf_diff_gen::{FileManager, FileManagerConfig, FixedClock, NoSink};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("report_order");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs, &current.join("nested")] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let mut changed = pages(2);
+    changed[1] = changed[1].clone().with_line(2, "A rewritten paragraph.");
+    for name in ["b.pdf", "a.pdf", "nested/c.pdf", "d.pdf"] {
+        std::fs::write(last.join(name), document(&pdfium, &pages(2))).unwrap();
+        std::fs::write(current.join(name), document(&pdfium, &changed)).unwrap();
+    }
+    std::fs::write(current.join("new.pdf"), document(&pdfium, &pages(1))).unwrap();
+    std::fs::write(last.join("removed.pdf"), document(&pdfium, &pages(1))).unwrap();
+
+    let csv = dir.join("report.csv");
+    let clock = std::sync::Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(NoSink),
+        current,
+        last,
+        diffs,
+        FileManagerConfig {
+            write_report: false,
+            csv_report: Some(csv.clone()),
+            ..Default::default()
+        },
+    )
+    .with_clock(clock);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut orders = Vec::new();
+    for _ in 0..2 {
+        let report = runtime.block_on(manager.compare_trees()).unwrap();
+        orders.push(
+            report
+                .processed
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect::<Vec<_>>(),
+        );
+    }
+    assert_eq!(orders[0], orders[1]);
+    let mut sorted = orders[0].clone();
+    sorted.sort();
+    assert_eq!(orders[0], sorted);
+
+    let content = std::fs::read_to_string(&csv).unwrap();
+    let rows = content.lines().skip(1).collect::<Vec<&str>>();
+    let (first, second) = rows.split_at(rows.len() / 2);
+    assert_eq!(first.len(), 6);
+    assert_eq!(first, second);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn a_page_failing_to_be_marked_is_kept_and_reported() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("partial_marking");
+    dir.write("current.pdf", &document(&pdfium, &pages(3)));
+    dir.write("previous.pdf", &document(&pdfium, &pages(3)));
+    let changed = |matched_page| {
+        Comparison::Different(DifferenceSegments {
+            segments: vec![(0.1, 0.2)],
+            matched_page: Some(matched_page),
+            ..Default::default()
+        })
+    };
+    // The previous version has no page 8 to overlay, so only the first page fails.
+    let comparisons = [changed(7), Comparison::Identical(1), changed(2)];
+
+    let out = dir.join("diff.pdf");
+    let config = PDFEditorConfig {
+        overlay: Some(OverlayConfig {
+            opacity: 0.5,
+            color: [0, 0, 255],
+        }),
+        ..Default::default()
+    };
+    let diff = PDFEditor::new(pdfium.clone(), config)
+        .mark_differences(
+            &dir.join("current.pdf"),
+            Some(&dir.join("previous.pdf")),
+            &comparisons,
+            &out,
+            None,
+        )
+        .expect("A failing page shouldn't fail the diff");
+    match diff.outcome.failed_pages() {
+        [(
+            0,
+            PDFEditorError::UnableToModifyPDF {
+                page: Some(0),
+                operation: EditOperation::OverlayingPreviousVersion,
+                ..
+            },
+        )] => {}
+        failed => panic!("Unexpected failed pages {:?}", failed),
+    }
+    let marked = pdfium
+        .load_pdf_from_file(&out, None)
+        .expect("The diff isn't a loadable pdf");
+    assert_eq!(marked.pages().len(), 2);
+}
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn excerpt_copies_the_changed_pages_and_leaves_the_source_untouched() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("excerpt");
+    let previous = pages(3);
+    let mut current = previous.clone();
+    current[1] = current[1].clone().with_line(2, "A rewritten paragraph.");
+    let comparisons = compare(
+        &dir,
+        &pdfium,
+        &PDFComparison::new(pdfium.clone()),
+        &current,
+        &previous,
+    );
+    let source = std::fs::read(dir.join("current.pdf")).unwrap();
+
+    let out = dir.join("diff.pdf");
+    let editor = PDFEditor::new(
+        pdfium.clone(),
+        PDFEditorConfig {
+            output_style: OutputStyle::Excerpt,
+            ..Default::default()
+        },
+    );
+    let diff = editor
+        .generate_diff(
+            &dir.join("current.pdf"),
+            Some(&dir.join("previous.pdf")),
+            &comparisons,
+            &out,
+            None,
+        )
+        .unwrap();
+    assert!(diff.outcome.is_complete());
+    assert_eq!(std::fs::read(dir.join("current.pdf")).unwrap(), source);
+    let excerpt = pdfium.load_pdf_from_file(&out, None).unwrap();
+    assert_eq!(excerpt.pages().len(), 1);
+    let text = excerpt.pages().first().unwrap().text().unwrap().all();
+    assert!(text.contains("Page 2"), "No page number in {:?}", text);
+    assert!(text.contains("A rewritten paragraph."), "{:?}", text);
+}
+
+#[cfg(all(feature = "serde", unix))]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn isolated_comparisons_match_and_crashing_files_are_quarantined() {
+    use pdf_diff_gen::{
+        isolation::IsolationConfig, FileManager, FileManagerConfig, FileOutcome, NoSink, SkipReason,
+    };
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("isolation");
+    let mut changed = pages(2);
+    changed[1] = changed[1].clone().with_line(2, "A rewritten paragraph.");
+    // A tree of its own for every manager, the first one updates the copy.
+    let isolated = |tree: &str, isolation: IsolationConfig| {
+        let (current, last, diffs) = (
+            dir.join(tree).join("current"),
+            dir.join(tree).join("last"),
+            dir.join(tree).join("diffs"),
+        );
+        for path in [&current, &last, &diffs] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        std::fs::write(last.join("doc.pdf"), document(&pdfium, &pages(2))).unwrap();
+        std::fs::write(current.join("doc.pdf"), document(&pdfium, &changed)).unwrap();
+        let manager = FileManager::new(
+            PDFComparison::new(pdfium.clone()),
+            Box::new(NoSink),
+            current.clone(),
+            last,
+            diffs,
+            FileManagerConfig {
+                isolation: Some(isolation),
+                ..FileManagerConfig::default()
+            },
+        );
+        (manager, current)
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let pdfium_dir = std::env::var_os(fixtures::PDFIUM_ENV).unwrap();
+    let (mut manager, _) = isolated(
+        "compared",
+        IsolationConfig {
+            program: env!("CARGO_BIN_EXE_pdf_diff_gen").into(),
+            args: vec!["compare-internal".into(), "--pdfium".into(), pdfium_dir],
+            timeout: None,
+        },
+    );
+    let report = runtime.block_on(manager.update(None)).unwrap();
+    match report.processed.as_slice() {
+        [(_, FileOutcome::DiffWritten { score, .. })] => {
+            assert!(score.changed_fraction > 0., "No page changed")
+        }
+        other => panic!("Unexpected outcome {:?}", other),
+    }
+
+    let (mut manager, current) = isolated(
+        "crashed",
+        IsolationConfig {
+            program: "sh".into(),
+            args: vec!["-c".into(), "kill -SEGV $$".into()],
+            timeout: None,
+        },
+    );
+    let crashed = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            crashed.processed.as_slice(),
+            [(_, FileOutcome::Failed { .. })]
+        ),
+        "Unexpected outcome {:?}",
+        crashed.processed
+    );
+    let quarantined = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            quarantined.processed.as_slice(),
+            [(
+                _,
+                FileOutcome::Skipped {
+                    reason: SkipReason::Quarantined
+                }
+            )]
+        ),
+        "Unexpected outcome {:?}",
+        quarantined.processed
+    );
+    // Modifying the file lifts the quarantine.
+    std::fs::write(current.join("doc.pdf"), document(&pdfium, &pages(3))).unwrap();
+    let modified = runtime.block_on(manager.update(None)).unwrap();
+    assert!(
+        matches!(
+            modified.processed.as_slice(),
+            [(_, FileOutcome::Failed { .. })]
+        ),
+        "Unexpected outcome {:?}",
+        modified.processed
+    );
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn artifacts_of_a_cycle_share_its_timestamp() {
+    use {
+        chrono::{DateTime, Utc},
+        pdf_diff_gen::{Clock, CycleStatus, FileManager, FileManagerConfig, MarkedPdfSink},
+        std::sync::Mutex,
+    };
+
+    /// A clock a second later at every reading, as if every file took that long.
+    struct TickingClock(Mutex<DateTime<Utc>>);
+
+    impl Clock for TickingClock {
+        fn now(&self) -> DateTime<Utc> {
+            let mut now = self.0.lock().unwrap();
+            *now += chrono::Duration::seconds(1);
+            *now
+        }
+    }
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("cycle_timestamp");
+    let (current, last, diffs) = (dir.join("current"), dir.join("last"), dir.join("diffs"));
+    for path in [&current, &last, &diffs] {
+        std::fs::create_dir_all(path).unwrap();
+    }
+    let mut changed = pages(2);
+    changed[1] = changed[1].clone().with_line(2, "A rewritten paragraph.");
+    for name in ["a.pdf", "b.pdf"] {
+        std::fs::write(last.join(name), document(&pdfium, &pages(2))).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(last.join(name))
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000))
+            .unwrap();
+        std::fs::write(current.join(name), document(&pdfium, &changed)).unwrap();
+    }
+
+    let csv = dir.join("report.csv");
+    let clock = std::sync::Arc::new(TickingClock(Mutex::new(DateTime::UNIX_EPOCH)));
+    let mut manager = FileManager::new(
+        PDFComparison::new(pdfium.clone()),
+        Box::new(MarkedPdfSink::new(
+            PDFEditor::new(pdfium.clone(), PDFEditorConfig::default()),
+            diffs.clone(),
+        )),
+        current,
+        last,
+        diffs.clone(),
+        FileManagerConfig {
+            timezone: "+02:00".parse().unwrap(),
+            csv_report: Some(csv.clone()),
+            ..Default::default()
+        },
+    )
+    .with_clock(clock);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(manager.update(None)).unwrap();
+
+    let names = std::fs::read_dir(&diffs)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<String>>();
+    let report = names
+        .iter()
+        .find(|name| name.starts_with("report-") && name.ends_with(".json"))
+        .expect("No report was written");
+    let stamp = &report["report-".len()..report.len() - ".json".len()];
+    assert!(stamp.ends_with("+0200"), "{} isn't in the zone", stamp);
+    for name in ["a.pdf", "b.pdf"] {
+        let diff = format!("{}.diff.{}.pdf", name, stamp);
+        assert!(
+            names.contains(&diff),
+            "{} wasn't written: {:?}",
+            diff,
+            names
+        );
+    }
+
+    let report: pdf_diff_gen::RunReport =
+        serde_json::from_slice(&std::fs::read(diffs.join(report)).unwrap()).unwrap();
+    assert_eq!(&report.timestamp.file_stamp(), stamp);
+    let content = std::fs::read_to_string(&csv).unwrap();
+    let rows = content.lines().skip(1).collect::<Vec<&str>>();
+    assert_eq!(rows.len(), 2);
+    for row in rows {
+        assert!(row.starts_with(&format!("{},", report.timestamp.rfc3339())));
+    }
+    let status = runtime
+        .block_on(CycleStatus::read(&manager.status_path()))
+        .unwrap();
+    assert_eq!(status.last_successful_cycle, Some(report.timestamp));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn pages_matched_in_child_processes_match_the_serial_comparison() {
+    use pdf_diff_gen::{isolation::IsolationConfig, PDFComparisonBuilder};
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("compare_threads");
+    // Pages moved across the ranges of the children, a removed, a duplicated, a changed and
+    // an inserted page, and noise pages for the blank page detection.
+    let previous = (0..30)
+        .map(|page| match page % 10 {
+            9 => PageSpec::noise(page as u64, 4),
+            _ => PageSpec::text(page, 5),
+        })
+        .collect::<Vec<PageSpec>>();
+    let mut current = previous.clone();
+    current.swap(2, 25);
+    current.remove(14);
+    current.insert(7, previous[3].clone());
+    current[20] = current[20].clone().with_line(1, "A rewritten paragraph.");
+    current.insert(11, PageSpec::text(40, 5));
+    let a = dir.write("current.pdf", &document(&pdfium, &current));
+    let b = dir.write("previous.pdf", &document(&pdfium, &previous));
+
+    let pdfium_dir = std::env::var_os(fixtures::PDFIUM_ENV).unwrap();
+    let children = || IsolationConfig {
+        program: env!("CARGO_BIN_EXE_pdf_diff_gen").into(),
+        args: vec![
+            "compare-internal".into(),
+            "--pdfium".into(),
+            pdfium_dir.clone(),
+        ],
+        timeout: None,
+    };
+    let settings: [fn(PDFComparisonBuilder) -> PDFComparisonBuilder; 4] = [
+        |builder| builder,
+        |builder| builder.prepass_width(0),
+        |builder| builder.detect_blank_pages(true),
+        |builder| builder.match_window(3).structural_precheck(true),
+    ];
+    for (index, settings) in settings.into_iter().enumerate() {
+        let compare = |comparison: PDFComparison| {
+            let (result, _) = comparison.compare_pdfs_timed(&a, &b, None, None, None);
+            let result = result.expect("Unable to compare the fixtures");
+            (
+                result.pages,
+                result.blank_pages,
+                result.previous_blank_pages,
+                result.ink_coverage,
+            )
+        };
+        let serial = compare(
+            settings(PDFComparison::builder(pdfium.clone()))
+                .build()
+                .unwrap(),
+        );
+        for threads in [2, 4, 7] {
+            let parallel = settings(PDFComparison::builder(pdfium.clone()))
+                .compare_threads(threads, children())
+                .build()
+                .unwrap();
+            assert_eq!(
+                compare(parallel),
+                serial,
+                "Settings {} with {} threads",
+                index,
+                threads
+            );
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn timed_out_files_are_quarantined() {
+    use {
+        pdf_diff_gen::{
+            isolation::IsolationConfig, FileManager, FileManagerConfig, FileManagerError,
+            FileOutcome, NoSink, SkipReason,
+        },
+        std::time::Duration,
+    };
+
+    let pdfium = pdfium();
+    let dir = TestDir::new("timeout_quarantine");
+    let mut changed = pages(2);
+    changed[1] = changed[1].clone().with_line(2, "A rewritten paragraph.");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let configs = [
+        (
+            "in_process",
+            FileManagerConfig {
+                file_timeout: Some(Duration::from_nanos(1)),
+                ..FileManagerConfig::default()
+            },
+        ),
+        (
+            "isolated",
+            FileManagerConfig {
+                isolation: Some(IsolationConfig {
+                    program: "sh".into(),
+                    args: vec!["-c".into(), "exec sleep 30".into()],
+                    timeout: Some(Duration::from_millis(100)),
+                }),
+                ..FileManagerConfig::default()
+            },
+        ),
+    ];
+    for (tree, config) in configs {
+        let (current, last, diffs) = (
+            dir.join(tree).join("current"),
+            dir.join(tree).join("last"),
+            dir.join(tree).join("diffs"),
+        );
+        for path in [&current, &last, &diffs] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        std::fs::write(last.join("doc.pdf"), document(&pdfium, &pages(2))).unwrap();
+        std::fs::write(current.join("doc.pdf"), document(&pdfium, &changed)).unwrap();
+        let mut manager = FileManager::new(
+            PDFComparison::new(pdfium.clone()),
+            Box::new(NoSink),
+            current.clone(),
+            last,
+            diffs,
+            config,
+        );
+        let timed_out = runtime.block_on(manager.update(None)).unwrap();
+        assert!(
+            matches!(
+                timed_out.processed.as_slice(),
+                [(
+                    _,
+                    FileOutcome::Failed {
+                        error: FileManagerError::Timeout { .. }
+                    }
+                )]
+            ),
+            "Unexpected outcome {:?} {}",
+            timed_out.processed,
+            tree
+        );
+        let quarantined = runtime.block_on(manager.update(None)).unwrap();
+        assert!(
+            matches!(
+                quarantined.processed.as_slice(),
+                [(
+                    _,
+                    FileOutcome::Skipped {
+                        reason: SkipReason::Quarantined
+                    }
+                )]
+            ),
+            "Unexpected outcome {:?} {}",
+            quarantined.processed,
+            tree
+        );
+    }
+}
+
+/// Bytes a marked page may add to the document, far less than a marker image covering the
+/// whole page.
+const MARKED_PAGE_BYTES: usize = 16 * 1024;
+
+#[test]
+#[ignore = "needs pdfium, see tests/fixtures"]
+fn markers_add_little_to_the_size_of_the_diff() {
+    let pdfium = pdfium();
+    let dir = TestDir::new("marker_size");
+    let input = document(&pdfium, &pages(50));
+    let current = dir.write("current.pdf", &input);
+    let previous = dir.write("previous.pdf", &input);
+    let comparisons = (0..50)
+        .map(|page| {
+            Comparison::Different(DifferenceSegments {
+                segments: vec![PageSpec::line_range(2), PageSpec::line_range(4)],
+                matched_page: Some(page),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<Comparison>>();
+    let out = dir.join("diff.pdf");
+    PDFEditor::new(pdfium.clone(), PDFEditorConfig::default())
+        .mark_differences(&current, Some(&previous), &comparisons, &out, None)
+        .expect("Unable to mark the differences");
+    let size = std::fs::metadata(&out).unwrap().len() as usize;
+    assert!(
+        size < input.len() + 50 * MARKED_PAGE_BYTES,
+        "The diff of {} bytes has {} bytes",
+        input.len(),
+        size
+    );
+}
@@ -0,0 +1,92 @@
+//! How the parent side of isolated comparisons handles children that crash, hang or answer
+//! nonsense, using shell scripts as children, without pdfium.
+#![cfg(unix)]
+
+use {
+    pdf_diff_gen::{
+        cancel::CancellationToken,
+        isolation::{compare, IsolatedComparison, IsolationConfig, UNAVAILABLE_EXIT_CODE},
+        ComparisonConfig, IsolationError,
+    },
+    std::{
+        path::Path,
+        time::{Duration, Instant},
+    },
+};
+
+fn run_script(
+    script: &str,
+    timeout: Option<Duration>,
+    cancel: Option<&CancellationToken>,
+) -> Result<IsolatedComparison, IsolationError> {
+    let config = IsolationConfig {
+        program: "sh".into(),
+        args: vec!["-c".into(), script.into()],
+        timeout,
+    };
+    compare(
+        &config,
+        (Path::new("current.pdf"), Path::new("last.pdf")),
+        &ComparisonConfig::default(),
+        None,
+        cancel,
+    )
+}
+
+#[test]
+fn crashed_child_keeps_its_stderr() {
+    let result = run_script("cat >/dev/null; echo broken >&2; kill -SEGV $$", None, None);
+    match result {
+        Err(IsolationError::Crashed { status, stderr }) => {
+            assert!(status.code().is_none(), "Exited with {}", status);
+            assert_eq!(stderr, "broken");
+        }
+        other => panic!("Unexpected result {:?}", other.err()),
+    }
+}
+
+#[test]
+fn failing_exit_codes_are_crashes_unless_unavailable() {
+    assert!(matches!(
+        run_script("exit 3", None, None),
+        Err(IsolationError::Crashed { .. })
+    ));
+    let unavailable = run_script(
+        &format!("echo no pdfium >&2; exit {}", UNAVAILABLE_EXIT_CODE),
+        None,
+        None,
+    );
+    match unavailable {
+        Err(IsolationError::Unavailable { stderr }) => assert_eq!(stderr, "no pdfium"),
+        other => panic!("Unexpected result {:?}", other.err()),
+    }
+}
+
+#[test]
+fn invalid_answers_are_reported() {
+    assert!(matches!(
+        run_script("cat >/dev/null; echo nonsense", None, None),
+        Err(IsolationError::InvalidMessage(_))
+    ));
+}
+
+#[test]
+fn hanging_child_is_killed_at_its_deadline() {
+    let started = Instant::now();
+    let result = run_script("exec sleep 30", Some(Duration::from_millis(100)), None);
+    assert!(matches!(result, Err(IsolationError::TimedOut)));
+    assert!(
+        started.elapsed() < Duration::from_secs(10),
+        "The child wasn't killed"
+    );
+}
+
+#[test]
+fn cancelled_comparison_kills_the_child() {
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    let started = Instant::now();
+    let result = run_script("exec sleep 30", None, Some(&cancel));
+    assert!(matches!(result, Err(IsolationError::Cancelled)));
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
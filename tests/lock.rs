@@ -0,0 +1,24 @@
+//! Locking the diff directory against a second instance.
+
+use pdf_diff_gen::lock::{InstanceLock, LockError};
+
+#[test]
+fn held_locks_are_never_broken() {
+    let dir = std::env::temp_dir().join(format!("pdf_diff_gen-lock-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let lock = InstanceLock::acquire(&dir).unwrap();
+    // The recorded holder looks stale, yet its lock is still held.
+    std::fs::write(lock.path(), u32::MAX.to_string()).unwrap();
+    match InstanceLock::acquire(&dir) {
+        Err(LockError::Held { pid, .. }) => assert_eq!(pid, Some(u32::MAX)),
+        v => panic!("expected the lock to be held, got {:?}", v),
+    }
+    drop(lock);
+    let relocked = InstanceLock::acquire(&dir).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(relocked.path()).unwrap(),
+        std::process::id().to_string()
+    );
+    drop(relocked);
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,136 @@
+//! Capping the diffs written per document, with the json sink so no pdfium is needed.
+
+use {
+    pdf_diff_gen::{
+        Clock, Comparison, DiffSink, DiffSinkError, DifferenceSegments, FixedClock, JsonSink,
+        OutputLimit, OutputLimitAction, Timezone,
+    },
+    std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::Duration,
+    },
+};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "pdf_diff_gen-output-limit-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        Dir(path)
+    }
+
+    fn outputs(&self) -> Vec<String> {
+        let mut names = std::fs::read_dir(&self.0)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn sink(dir: &Dir, clock: &Arc<FixedClock>, action: OutputLimitAction) -> JsonSink {
+    let mut sink = JsonSink::new(dir.0.clone()).with_output_limit(OutputLimit {
+        max_outputs: 2,
+        window: 3 * HOUR,
+        action,
+    });
+    sink.set_clock(clock.clone());
+    sink
+}
+
+/// Hands a changed single page document to `sink`, one cycle later than the previous one.
+fn cycle(sink: &mut JsonSink, clock: &FixedClock) -> Result<Option<PathBuf>, DiffSinkError> {
+    clock.advance(chrono::Duration::hours(1));
+    sink.begin_document(Path::new("reports/hourly.pdf"), None)?;
+    sink.page_changed(
+        0,
+        &Comparison::Different(DifferenceSegments {
+            segments: vec![(0.1, 0.2)],
+            matched_page: Some(0),
+            ..Default::default()
+        }),
+    )?;
+    sink.finish()
+}
+
+#[test]
+fn the_newest_diff_is_overwritten_once_the_limit_is_reached() {
+    let dir = Dir::new("overwrite");
+    let clock = Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut sink = sink(&dir, &clock, OutputLimitAction::Overwrite);
+    let first = cycle(&mut sink, &clock).unwrap().unwrap();
+    let second = cycle(&mut sink, &clock).unwrap().unwrap();
+    assert_ne!(first, second);
+    assert_eq!(cycle(&mut sink, &clock).unwrap().unwrap(), second);
+    assert_eq!(
+        dir.outputs(),
+        [
+            "hourly.pdf.diff.19700101T010000.000Z.json",
+            "hourly.pdf.diff.19700101T020000.000Z.json"
+        ]
+    );
+
+    // Four hours in, the first diff left the window.
+    let fourth = cycle(&mut sink, &clock).unwrap().unwrap();
+    assert!(fourth.ends_with("hourly.pdf.diff.19700101T040000.000Z.json"));
+}
+
+#[test]
+fn documents_at_the_limit_are_skipped() {
+    let dir = Dir::new("skip");
+    let clock = Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut sink = sink(&dir, &clock, OutputLimitAction::Skip);
+    cycle(&mut sink, &clock).unwrap();
+    cycle(&mut sink, &clock).unwrap();
+    match cycle(&mut sink, &clock) {
+        Err(DiffSinkError::Flapping {
+            outputs: 2, window, ..
+        }) => assert_eq!(window, 3 * HOUR),
+        result => panic!("Unexpected result {:?}", result.map(|_| ())),
+    }
+    assert_eq!(dir.outputs().len(), 2);
+    assert!(cycle(&mut sink, &clock).unwrap().is_some());
+}
+
+#[test]
+fn diffs_named_in_milliseconds_still_count() {
+    let dir = Dir::new("milliseconds");
+    std::fs::create_dir_all(&dir.0).unwrap();
+    std::fs::write(dir.0.join("hourly.pdf.diff.3600000.json"), "[]").unwrap();
+    std::fs::write(dir.0.join("hourly.pdf.diff.3600000-1.json"), "[]").unwrap();
+    let clock = Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut sink = sink(&dir, &clock, OutputLimitAction::Skip);
+    assert!(matches!(
+        cycle(&mut sink, &clock),
+        Err(DiffSinkError::Flapping { outputs: 2, .. })
+    ));
+}
+
+#[test]
+fn documents_of_a_cycle_are_named_after_its_timestamp() {
+    let dir = Dir::new("cycle");
+    let clock = Arc::new(FixedClock::new(chrono::DateTime::UNIX_EPOCH));
+    let mut sink = JsonSink::new(dir.0.clone());
+    sink.set_clock(clock.clone());
+    let timezone = "+02:00".parse::<Timezone>().unwrap();
+    sink.begin_cycle(timezone.at(clock.now()));
+    let first = cycle(&mut sink, &clock).unwrap().unwrap();
+    let second = cycle(&mut sink, &clock).unwrap().unwrap();
+    assert!(first.ends_with("hourly.pdf.diff.19700101T020000.000+0200.json"));
+    assert!(second.ends_with("hourly.pdf.diff.19700101T020000.000+0200-1.json"));
+}
@@ -0,0 +1,130 @@
+//! Mapping of difference segments onto the rows of the raster markers, without pdfium.
+
+use pdf_diff_gen::{
+    pdf::{MarkerStyle, OverlayGeometry, OverlayResolution},
+    DifferenceSegments,
+};
+
+const MAX_PIXELS: u32 = 16384;
+
+fn geometry(resolution: OverlayResolution, size: (f32, f32)) -> OverlayGeometry {
+    resolution.geometry(size, MAX_PIXELS)
+}
+
+#[test]
+fn segments_map_to_rows_at_several_page_sizes() {
+    // Resolution, page size in points, segment, expected size and rows.
+    let cases = [
+        (
+            OverlayResolution::PixelsPerPoint(5.),
+            (595., 842.),
+            (0.5, 0.625),
+            (2975, 4210),
+            2105..2632,
+        ),
+        (
+            OverlayResolution::PixelsPerPoint(2.),
+            (612., 792.),
+            (0., 0.25),
+            (1224, 1584),
+            0..396,
+        ),
+        (
+            OverlayResolution::RenderWidth(500),
+            (1000., 2000.),
+            (0.1, 0.2),
+            (500, 1000),
+            100..200,
+        ),
+        (
+            OverlayResolution::PixelsPerPoint(1.),
+            (100., 50.),
+            (0.75, 1.),
+            (100, 50),
+            37..50,
+        ),
+    ];
+    for (resolution, size, segment, (width, height), rows) in cases {
+        let geometry = geometry(resolution, size);
+        assert_eq!(
+            (geometry.width, geometry.height),
+            (width, height),
+            "Size of {:?} at {:?}",
+            size,
+            resolution
+        );
+        assert_eq!(
+            geometry.marker_rows(segment),
+            rows,
+            "Rows of {:?} on {:?} at {:?}",
+            segment,
+            size,
+            resolution
+        );
+    }
+}
+
+#[test]
+fn fractional_page_sizes_are_rounded() {
+    let geometry = geometry(OverlayResolution::PixelsPerPoint(5.), (100.5, 200.25));
+    assert_eq!((geometry.width, geometry.height), (503, 1001));
+    assert_eq!(geometry.marker_rows((0.25, 0.5)), 250..501);
+}
+
+#[test]
+fn large_pages_are_scaled_down_proportionally() {
+    let geometry = OverlayResolution::PixelsPerPoint(1.).geometry((40000., 20000.), 10000);
+    assert_eq!(geometry.pixels_per_point, 0.25);
+    assert_eq!((geometry.width, geometry.height), (10000, 5000));
+    assert_eq!(geometry.marker_rows((0.5, 0.75)), 2500..3750);
+}
+
+#[test]
+fn short_segments_get_the_minimum_height() {
+    let geometry = geometry(OverlayResolution::PixelsPerPoint(5.), (595., 842.));
+    let segments = DifferenceSegments {
+        segments: vec![(0.5, 0.5), (1., 1.)],
+        matched_page: Some(0),
+        ..Default::default()
+    };
+    assert_eq!(segments.marker_rows(&geometry), [2105..2115, 4200..4210]);
+}
+
+#[test]
+fn pages_without_a_size_get_no_rows() {
+    let geometry = geometry(OverlayResolution::RenderWidth(500), (0., 0.));
+    assert_eq!((geometry.width, geometry.height), (0, 0));
+    assert!(geometry.marker_rows((0., 1.)).is_empty());
+}
+
+#[test]
+fn pattern_period_is_the_same_length_on_the_page() {
+    let size = (595., 842.);
+    let fine = geometry(OverlayResolution::PixelsPerPoint(5.), size).pattern_period();
+    let coarse = geometry(OverlayResolution::PixelsPerPoint(1.), size).pattern_period();
+    assert_eq!(fine, 30);
+    assert_eq!(coarse, 6);
+    // Very coarse overlays still alternate.
+    let tiny = geometry(OverlayResolution::PixelsPerPoint(0.1), size).pattern_period();
+    assert_eq!(tiny, 2);
+}
+
+#[test]
+fn patterns_leave_gaps_that_solid_markers_fill() {
+    let period = 12;
+    let painted = |style: MarkerStyle| {
+        (0..period)
+            .flat_map(|column| (0..period).map(move |row| (column, row)))
+            .filter(|pixel| style.paints(*pixel, period))
+            .count()
+    };
+    assert_eq!(painted(MarkerStyle::Color), 144);
+    assert_eq!(painted(MarkerStyle::Hatch), 72);
+    assert_eq!(painted(MarkerStyle::Dashed), 96);
+    // Stripes shift along the rows, dashes span the whole bar.
+    assert!(MarkerStyle::Hatch.paints((0, 0), period));
+    assert!(!MarkerStyle::Hatch.paints((6, 0), period));
+    assert!(MarkerStyle::Hatch.paints((6, 6), period));
+    assert!((0..4).all(|column| MarkerStyle::Dashed.paints((column, 0), period)));
+    assert!((0..4).all(|column| !MarkerStyle::Dashed.paints((column, 8), period)));
+}
@@ -0,0 +1,52 @@
+//! Tests of naming the page sizes of size changes.
+
+use pdf_diff_gen::{pdf::PageStatistics, PageSize, PageSizeChange};
+
+const A4: PageSize = PageSize {
+    width: 595.28,
+    height: 841.89,
+};
+
+#[test]
+fn common_sizes_are_named_in_either_orientation() {
+    assert_eq!(A4.to_string(), "A4");
+    // Exporters round the size to whole points.
+    let rounded = PageSize {
+        width: 842.,
+        height: 595.,
+    };
+    assert_eq!(rounded.to_string(), "A4 landscape");
+    let letter = PageSize {
+        width: 612.,
+        height: 792.,
+    };
+    assert_eq!(letter.name(), Some("Letter"));
+    let other = PageSize {
+        width: 283.46,
+        height: 425.2,
+    };
+    assert_eq!(other.name(), None);
+    assert_eq!(other.to_string(), "100 x 150 mm");
+}
+
+#[test]
+fn caption_names_the_sizes_and_the_changed_content() {
+    let statistics = PageStatistics {
+        page: 0,
+        matched_page: Some(0),
+        changed_fraction: 0.004,
+        segments: vec![(0.5, 0.504)],
+        size_change: Some(PageSizeChange {
+            previous: A4,
+            current: PageSize {
+                width: 841.89,
+                height: 1190.55,
+            },
+        }),
+        ..Default::default()
+    };
+    assert_eq!(
+        statistics.caption(),
+        "p. 1 - page size changed from A4 to A3, content 0.4% different"
+    );
+}
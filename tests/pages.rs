@@ -0,0 +1,64 @@
+//! Parsing page selections like `1-25,40,50-`.
+
+use pdf_diff_gen::{PageSelection, PageSelectionError};
+
+fn selection(value: &str) -> PageSelection {
+    value.parse().expect("Invalid selection")
+}
+
+/// The zero based pages of the first `pages` that `selection` contains.
+fn selected(selection: &PageSelection, pages: usize) -> Vec<usize> {
+    (0..pages)
+        .filter(|page| selection.contains(*page))
+        .collect()
+}
+
+#[test]
+fn pages_and_ranges_are_combined() {
+    let selection = selection("1-25,40,50-");
+    let mut expected = (0..25).collect::<Vec<usize>>();
+    expected.push(39);
+    expected.extend(49..60);
+    assert_eq!(selected(&selection, 60), expected);
+    assert_eq!(selection.to_string(), "1-25,40,50-");
+}
+
+#[test]
+fn ranges_may_be_open_and_overlap() {
+    assert_eq!(selected(&selection("-3"), 10), vec![0, 1, 2]);
+    assert_eq!(selected(&selection("8-"), 10), vec![7, 8, 9]);
+    assert_eq!(selected(&selection("2-5, 4-6,5"), 10), vec![1, 2, 3, 4, 5]);
+    assert_eq!(selection(" 3 ,").to_string(), "3");
+}
+
+#[test]
+fn pages_past_the_end_of_the_document_are_ignored() {
+    let selection = selection("3,1000,7-");
+    assert!(selection.contains(999));
+    assert!(selection.contains(1_000_000));
+    assert_eq!(selected(&selection, 4), vec![2]);
+}
+
+#[test]
+fn invalid_selections_are_rejected() {
+    assert!(matches!(
+        "5-2".parse::<PageSelection>(),
+        Err(PageSelectionError::ReversedRange { start: 5, end: 2 })
+    ));
+    for zero in ["0", "0-3", "2-0"] {
+        assert!(matches!(
+            zero.parse::<PageSelection>(),
+            Err(PageSelectionError::ZeroPage)
+        ));
+    }
+    for empty in ["", " , ,"] {
+        assert!(matches!(
+            empty.parse::<PageSelection>(),
+            Err(PageSelectionError::Empty)
+        ));
+    }
+    assert!(matches!(
+        "1,two".parse::<PageSelection>(),
+        Err(PageSelectionError::InvalidPage(page)) if page == "two"
+    ));
+}
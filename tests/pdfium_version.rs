@@ -0,0 +1,68 @@
+//! Tests of reading the version of pdfium builds, which don't need pdfium.
+
+use {
+    pdf_diff_gen::{version::VERSION_FILE_NAME, PdfiumVersion, MIN_PDFIUM_BUILD},
+    std::path::PathBuf,
+};
+
+fn version(build: u32) -> PdfiumVersion {
+    PdfiumVersion {
+        major: 134,
+        minor: 0,
+        build,
+        patch: 0,
+    }
+}
+
+/// An empty directory below the temp dir.
+fn dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "pdf_diff_gen-version-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(path.join("lib")).unwrap();
+    path
+}
+
+#[test]
+fn version_files_and_dotted_versions_parse() {
+    let file = "MAJOR=134\nMINOR=0\nBUILD=7215\nPATCH=0\n";
+    assert_eq!(file.parse(), Ok(version(7215)));
+    assert_eq!("134.0.7215.0".parse(), Ok(version(7215)));
+    assert_eq!(version(7215).to_string(), "134.0.7215.0");
+    for invalid in [
+        "",
+        "134.0.7215",
+        "MAJOR=134\nMINOR=0\nBUILD=7215\n",
+        "a.b.c.d",
+    ] {
+        assert!(invalid.parse::<PdfiumVersion>().is_err(), "{:?}", invalid);
+    }
+}
+
+#[test]
+fn older_builds_are_unsupported() {
+    assert!(version(MIN_PDFIUM_BUILD).is_supported());
+    assert!(!version(MIN_PDFIUM_BUILD - 1).is_supported());
+    assert!(version(MIN_PDFIUM_BUILD - 1) < version(MIN_PDFIUM_BUILD));
+}
+
+#[test]
+fn version_file_is_read_next_to_the_library_directory() {
+    let root = dir("parent");
+    let lib = root.join("lib");
+    assert_eq!(PdfiumVersion::read(&lib).unwrap(), None);
+    assert_eq!(PdfiumVersion::detect(&lib), None);
+
+    std::fs::write(root.join(VERSION_FILE_NAME), "134.0.6996.0").unwrap();
+    assert_eq!(
+        PdfiumVersion::read(&lib).unwrap(),
+        Some((version(6996), root.join(VERSION_FILE_NAME)))
+    );
+    std::fs::write(lib.join(VERSION_FILE_NAME), "garbage").unwrap();
+    assert!(PdfiumVersion::read(&lib).is_err());
+    assert_eq!(PdfiumVersion::detect(&lib), None);
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,134 @@
+//! Tests of detecting redactions in renders, which don't need pdfium.
+
+#[allow(dead_code)]
+mod factories;
+
+use {
+    factories::white_page,
+    image::{Rgb, RgbImage},
+    pdf_diff_gen::{
+        pdf::PageSimilarity, ChangeScore, Comparison, ComparisonConfig, DifferenceSegments,
+        Redaction,
+    },
+};
+
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+
+/// A page with a line of "text", vertical strokes on every other column of rows 20 to 23.
+fn text_page() -> RgbImage {
+    let mut page = white_page(100, 100);
+    for y in 20..24 {
+        for x in (10..90).step_by(2) {
+            page.put_pixel(x, y, BLACK);
+        }
+    }
+    page
+}
+
+fn fill(page: &mut RgbImage, (left, top): (u32, u32), (right, bottom): (u32, u32)) {
+    for y in top..bottom {
+        for x in left..right {
+            page.put_pixel(x, y, BLACK);
+        }
+    }
+}
+
+fn config() -> ComparisonConfig {
+    ComparisonConfig {
+        detect_redactions: true,
+        ..ComparisonConfig::default()
+    }
+}
+
+fn compare(current: RgbImage, previous: RgbImage, config: &ComparisonConfig) -> DifferenceSegments {
+    match Comparison::from_similarity(
+        &PageSimilarity::Similar(0, 1),
+        Some(current),
+        Some(previous),
+        config,
+    ) {
+        Ok(Comparison::Different(segments)) => segments,
+        v => panic!("expected a difference, got {:?}", v),
+    }
+}
+
+#[test]
+fn box_over_text_is_a_redaction() {
+    let mut current = text_page();
+    fill(&mut current, (10, 18), (90, 26));
+    let segments = compare(current, text_page(), &config());
+    assert_eq!(segments.segments, Vec::new());
+    assert_eq!(
+        segments.redactions,
+        vec![Redaction {
+            left: 0.1,
+            top: 0.18,
+            right: 0.9,
+            bottom: 0.26,
+        }]
+    );
+    assert!((segments.changed_fraction() - 0.08).abs() < 1e-9);
+    assert!((segments.redacted_fraction() - 0.08).abs() < 1e-9);
+}
+
+#[test]
+fn other_changes_are_located_beside_redactions() {
+    let mut current = text_page();
+    fill(&mut current, (10, 18), (90, 26));
+    current.put_pixel(50, 60, BLACK);
+    let segments = compare(current, text_page(), &config());
+    assert_eq!(segments.segments, vec![(0.6, 0.61)]);
+    assert_eq!(segments.redactions.len(), 1);
+    assert!((segments.changed_fraction() - 0.09).abs() < 1e-9);
+    assert!((segments.redacted_fraction() - 0.08).abs() < 1e-9);
+}
+
+#[test]
+fn boxes_over_empty_paper_rules_and_text_are_ordinary_changes() {
+    let cases = [
+        // A box where the previous version had nothing.
+        (white_page(100, 100), (10, 50), (90, 58)),
+        // A rule too thin to be a redaction.
+        (text_page(), (10, 18), (90, 20)),
+        // A box too small to be a redaction.
+        (text_page(), (10, 20), (12, 24)),
+    ];
+    for (previous, top_left, bottom_right) in cases {
+        let mut current = previous.clone();
+        fill(&mut current, top_left, bottom_right);
+        let segments = compare(current, previous, &config());
+        assert_eq!(segments.redactions, Vec::new());
+        assert!(!segments.segments.is_empty());
+    }
+}
+
+#[test]
+fn boxes_with_gaps_are_ordinary_changes() {
+    let mut current = text_page();
+    fill(&mut current, (10, 18), (90, 26));
+    for x in (10..90).step_by(2) {
+        current.put_pixel(x, 22, Rgb([255, 255, 255]));
+    }
+    let segments = compare(current, text_page(), &config());
+    assert_eq!(segments.redactions, Vec::new());
+}
+
+#[test]
+fn redactions_are_only_detected_if_enabled() {
+    let mut current = text_page();
+    fill(&mut current, (10, 18), (90, 26));
+    let segments = compare(current, text_page(), &ComparisonConfig::default());
+    assert_eq!(segments.redactions, Vec::new());
+    assert_eq!(segments.segments, vec![(0.18, 0.26)]);
+}
+
+#[test]
+fn substantive_fraction_leaves_redactions_out() {
+    let mut current = text_page();
+    fill(&mut current, (10, 18), (90, 26));
+    let redacted = Comparison::Different(compare(current, text_page(), &config()));
+    let score = ChangeScore::from_comparisons(&[redacted, Comparison::Identical(1)], Some(2));
+    assert!((score.changed_fraction - 0.04).abs() < 1e-9);
+    assert!((score.redacted_fraction - 0.04).abs() < 1e-9);
+    assert_eq!(score.substantive_fraction(), 0.);
+}
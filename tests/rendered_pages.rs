@@ -0,0 +1,231 @@
+//! Tests of comparing pages rendered elsewhere, which don't need pdfium.
+
+#[allow(dead_code)]
+mod factories;
+
+use {
+    factories::white_page,
+    image::{Rgb, RgbImage},
+    pdf_diff_gen::{
+        compare_rendered_pages, pdf::PageSimilarity, Comparison, ComparisonConfig,
+        DifferenceSegments, PDFComparisonError,
+    },
+};
+
+/// A white page with black rows `rows`.
+fn page_with_rows(width: u32, height: u32, rows: std::ops::Range<u32>) -> RgbImage {
+    let mut image = white_page(width, height);
+    for y in rows {
+        for x in 0..width {
+            image.put_pixel(x, y, Rgb([0, 0, 0]));
+        }
+    }
+    image
+}
+
+#[test]
+fn identical_pages_are_identical() {
+    let a = [page_with_rows(20, 40, 4..8)];
+    let result = compare_rendered_pages(&a, &a, &ComparisonConfig::default()).unwrap();
+    assert_eq!(result.pages, vec![Comparison::Identical(0)]);
+    assert!(matches!(
+        result.matches[..],
+        [PageSimilarity::Similar(0, 0)]
+    ));
+}
+
+#[test]
+fn changed_rows_are_located() {
+    let a = [page_with_rows(20, 40, 10..20)];
+    let b = [white_page(20, 40)];
+    let result = compare_rendered_pages(&a, &b, &ComparisonConfig::default()).unwrap();
+    let [Comparison::Different(segments)] = &result.pages[..] else {
+        panic!("unexpected comparisons {:?}", result.pages);
+    };
+    assert_eq!(segments.matched_page, Some(0));
+    assert_eq!(segments.segments, vec![(0.25, 0.5)]);
+}
+
+#[test]
+fn reordered_pages_are_matched() {
+    let a = [
+        page_with_rows(20, 40, 0..10),
+        page_with_rows(20, 40, 30..40),
+    ];
+    let b = [a[1].clone(), a[0].clone()];
+    let result = compare_rendered_pages(&a, &b, &ComparisonConfig::default()).unwrap();
+    assert_eq!(
+        result.pages,
+        vec![Comparison::Identical(1), Comparison::Identical(0)]
+    );
+}
+
+#[test]
+fn pages_of_other_dimensions_and_new_documents_differ() {
+    let a = [white_page(20, 40)];
+    let config = ComparisonConfig::default();
+    for b in [&[white_page(40, 20)][..], &[]] {
+        let result = compare_rendered_pages(&a, b, &config).unwrap();
+        assert!(matches!(result.pages[..], [Comparison::Different(_)]));
+        assert!(matches!(result.matches[..], [PageSimilarity::Different]));
+    }
+}
+
+#[test]
+fn resized_pages_match_when_enabled() {
+    // The previous page is taller, its content keeps the distance to the top.
+    let a = [page_with_rows(20, 40, 4..8)];
+    let b = [page_with_rows(20, 50, 4..8)];
+    let unmatched = compare_rendered_pages(&a, &b, &ComparisonConfig::default()).unwrap();
+    assert!(matches!(unmatched.matches[..], [PageSimilarity::Different]));
+
+    let config = ComparisonConfig {
+        compare_resized_pages: true,
+        ..ComparisonConfig::default()
+    };
+    let result = compare_rendered_pages(&a, &b, &config).unwrap();
+    assert_eq!(result.pages, vec![Comparison::Identical(0)]);
+}
+
+#[test]
+fn page_limit_applies_to_the_current_version() {
+    let a = [white_page(4, 4), white_page(4, 4)];
+    let config = ComparisonConfig {
+        max_pages: Some(1),
+        ..ComparisonConfig::default()
+    };
+    assert!(matches!(
+        compare_rendered_pages(&a, &a[..1], &config),
+        Err(PDFComparisonError::TooManyPages {
+            pages: 2,
+            limit: 1,
+            ..
+        })
+    ));
+}
+
+/// Pages of a duplex scan, with the blank back of the second sheet at index 3.
+fn duplex_pages() -> Vec<RgbImage> {
+    vec![
+        page_with_rows(20, 40, 0..5),
+        page_with_rows(20, 40, 5..10),
+        page_with_rows(20, 40, 10..15),
+        white_page(20, 40),
+        page_with_rows(20, 40, 15..20),
+        page_with_rows(20, 40, 20..25),
+    ]
+}
+
+fn sheets() -> ComparisonConfig {
+    ComparisonConfig {
+        sheet_pages: Some(2),
+        ..ComparisonConfig::default()
+    }
+}
+
+#[test]
+fn dropped_blank_back_pages_are_tolerated() {
+    let b = duplex_pages();
+    let mut a = b.clone();
+    a.remove(3);
+    let result = compare_rendered_pages(&a, &b, &sheets()).unwrap();
+    assert_eq!(
+        result.pages,
+        [0, 1, 2, 4, 5].map(Comparison::Identical).to_vec()
+    );
+    assert!(result.blank_pages.is_empty());
+}
+
+#[test]
+fn inserted_blank_back_pages_are_identical() {
+    let a = duplex_pages();
+    let mut b = a.clone();
+    b.remove(3);
+    let result = compare_rendered_pages(&a, &b, &sheets()).unwrap();
+    assert!(result
+        .pages
+        .iter()
+        .all(|v| matches!(v, Comparison::Identical(_))));
+    assert_eq!(
+        [0, 1, 2, 4, 5].map(|page| result.pages[page].clone()),
+        [0, 1, 2, 3, 4].map(Comparison::Identical)
+    );
+    assert_eq!(result.blank_pages, vec![3]);
+}
+
+#[test]
+fn pages_with_little_ink_count_as_blank() {
+    let mut speckled = white_page(20, 40);
+    speckled.put_pixel(3, 3, Rgb([0, 0, 0]));
+    let a = [speckled, page_with_rows(20, 40, 0..1)];
+    let config = ComparisonConfig {
+        blank_ink_threshold: 0.01,
+        ..sheets()
+    };
+    let result = compare_rendered_pages(&a, &a, &config).unwrap();
+    assert_eq!(result.blank_pages, vec![0]);
+    assert_eq!(
+        result.pages,
+        vec![Comparison::Identical(0), Comparison::Identical(1)]
+    );
+}
+
+/// A blank page with scanner noise: single dark pixels at `specks`.
+fn noisy_page(specks: &[(u32, u32)]) -> RgbImage {
+    let mut image = white_page(20, 40);
+    for (x, y) in specks {
+        image.put_pixel(*x, *y, Rgb([40, 40, 40]));
+    }
+    image
+}
+
+fn blank_detection() -> ComparisonConfig {
+    ComparisonConfig {
+        detect_blank_pages: true,
+        blank_ink_threshold: 0.01,
+        ..ComparisonConfig::default()
+    }
+}
+
+#[test]
+fn noisy_blank_pages_are_identical() {
+    let a = [page_with_rows(20, 40, 0..5), noisy_page(&[(1, 1), (7, 30)])];
+    let b = [page_with_rows(20, 40, 0..5), noisy_page(&[(15, 12)])];
+    let result = compare_rendered_pages(&a, &b, &blank_detection()).unwrap();
+    assert_eq!(
+        result.pages,
+        vec![Comparison::Identical(0), Comparison::Identical(1)]
+    );
+    assert_eq!(result.blank_pages, vec![1]);
+    assert_eq!(result.previous_blank_pages, vec![1]);
+    assert_eq!(result.ink_coverage, vec![Some(0.125), Some(2. / 800.)]);
+
+    // Without detecting them, the noise differs.
+    let result = compare_rendered_pages(&a, &b, &ComparisonConfig::default()).unwrap();
+    assert!(matches!(result.pages[1], Comparison::Different(_)));
+    assert!(result.ink_coverage.iter().all(Option::is_none));
+}
+
+#[test]
+fn blank_pages_of_one_version_are_inserted_or_removed_blank_pages() {
+    let content = page_with_rows(20, 40, 0..5);
+    let a = [content.clone(), noisy_page(&[(3, 3)])];
+    let b = [content.clone(), page_with_rows(20, 40, 0..1)];
+    let result = compare_rendered_pages(&a, &b, &blank_detection()).unwrap();
+    // The blank page doesn't match the nearly blank one and has nothing to mark.
+    assert_eq!(
+        result.pages,
+        vec![
+            Comparison::Identical(0),
+            Comparison::Different(DifferenceSegments {
+                segments: Vec::new(),
+                matched_page: None,
+                ..Default::default()
+            }),
+        ]
+    );
+
+    let result = compare_rendered_pages(&b[..1], &a, &blank_detection()).unwrap();
+    assert_eq!(result.pages, vec![Comparison::Identical(0)]);
+    assert_eq!(result.previous_blank_pages, vec![1]);
+}
@@ -0,0 +1,219 @@
+//! Parsing cron expressions and finding the times they fire at.
+
+use {
+    chrono::{DateTime, FixedOffset, MappedLocalTime, NaiveDate, NaiveDateTime, TimeZone, Utc},
+    pdf_diff_gen::{CronError, CronSchedule},
+};
+
+fn schedule(expression: &str) -> CronSchedule {
+    expression.parse().expect("Invalid expression")
+}
+
+fn utc(year: i32, month: u32, day: u32, (hour, minute, second): (u32, u32, u32)) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, hour, minute, second)
+        .unwrap()
+}
+
+/// The next `count` times `expression` fires after `after`.
+fn fires<Tz: TimeZone>(expression: &str, after: DateTime<Tz>, count: usize) -> Vec<DateTime<Tz>> {
+    let schedule = schedule(expression);
+    std::iter::successors(schedule.next_after(&after), |v| schedule.next_after(v))
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn lists_ranges_and_steps_are_combined() {
+    assert_eq!(
+        fires("0 0,30 8-11/2 * * *", utc(2024, 1, 1, (7, 59, 0)), 5),
+        vec![
+            utc(2024, 1, 1, (8, 0, 0)),
+            utc(2024, 1, 1, (8, 30, 0)),
+            utc(2024, 1, 1, (10, 0, 0)),
+            utc(2024, 1, 1, (10, 30, 0)),
+            utc(2024, 1, 2, (8, 0, 0)),
+        ]
+    );
+}
+
+#[test]
+fn months_and_days_of_the_week_can_be_named() {
+    // The 1st of February 2024 is a Thursday.
+    assert_eq!(
+        fires("0 0 12 * feb sat,SUN", utc(2024, 1, 1, (0, 0, 0)), 2),
+        vec![utc(2024, 2, 3, (12, 0, 0)), utc(2024, 2, 4, (12, 0, 0))]
+    );
+    // Numeric days of the week count from 1 for Sunday, the 1st of January 2024 is a Monday.
+    assert_eq!(
+        fires("0 0 12 * * 1", utc(2024, 1, 1, (0, 0, 0)), 1),
+        vec![utc(2024, 1, 7, (12, 0, 0))]
+    );
+    let after = utc(2024, 3, 20, (0, 0, 0));
+    assert_eq!(
+        fires("0 0 12 * JAN-MAR MON", after, 2),
+        fires("0 0 12 * 1-3 2", after, 2)
+    );
+    assert_eq!(
+        fires("0 0 12 * JAN-MAR MON", after, 2),
+        vec![utc(2024, 3, 25, (12, 0, 0)), utc(2025, 1, 6, (12, 0, 0))]
+    );
+}
+
+#[test]
+fn a_start_with_a_step_runs_to_the_end_of_the_field() {
+    assert_eq!(
+        fires("5/15 * * * * *", utc(2024, 1, 1, (0, 0, 0)), 5),
+        vec![
+            utc(2024, 1, 1, (0, 0, 5)),
+            utc(2024, 1, 1, (0, 0, 20)),
+            utc(2024, 1, 1, (0, 0, 35)),
+            utc(2024, 1, 1, (0, 0, 50)),
+            utc(2024, 1, 1, (0, 1, 5)),
+        ]
+    );
+}
+
+#[test]
+fn invalid_expressions_are_rejected() {
+    for (expression, field, value) in [
+        ("*/0 * * * * *", "seconds", "*/0"),
+        ("0 5-2 * * * *", "minutes", "5-2"),
+        ("0 0 24 * * *", "hours", "24"),
+        ("0 0 0 0 * *", "day of month", "0"),
+        ("0 0 0 * foo *", "month", "foo"),
+        ("0 0 0 * * 8", "day of week", "8"),
+        ("0 0 0 * * * 1969", "year", "1969"),
+    ] {
+        assert_eq!(
+            expression.parse::<CronSchedule>(),
+            Err(CronError::InvalidField {
+                field,
+                value: value.to_string(),
+            }),
+            "{}",
+            expression
+        );
+    }
+    assert_eq!(
+        "* * * * *".parse::<CronSchedule>(),
+        Err(CronError::FieldCount(5))
+    );
+}
+
+#[test]
+fn impossible_dates_never_fire() {
+    assert_eq!(
+        schedule("0 0 0 30 2 *").next_after(&utc(2024, 1, 1, (0, 0, 0))),
+        None
+    );
+    assert_eq!(
+        schedule("0 0 0 * * * 2020").next_after(&utc(2024, 1, 1, (0, 0, 0))),
+        None
+    );
+}
+
+/// Central European time with the clock changes of 2024 only: 02:00 became 03:00 on the 31st
+/// of March and 03:00 became 02:00 on the 27th of October.
+#[derive(Debug, Clone, Copy)]
+struct Berlin2024;
+
+const WINTER: i32 = 3600;
+const SUMMER: i32 = 7200;
+
+fn berlin_offset(seconds: i32) -> FixedOffset {
+    FixedOffset::east_opt(seconds).unwrap()
+}
+
+fn naive(month: u32, day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2024, month, day)
+        .unwrap()
+        .and_hms_opt(hour, 0, 0)
+        .unwrap()
+}
+
+impl TimeZone for Berlin2024 {
+    type Offset = FixedOffset;
+
+    fn from_offset(_offset: &FixedOffset) -> Self {
+        Berlin2024
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+        self.offset_from_local_datetime(&local.and_hms_opt(12, 0, 0).unwrap())
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<FixedOffset> {
+        if (naive(3, 31, 2)..naive(3, 31, 3)).contains(local) {
+            MappedLocalTime::None
+        } else if (naive(10, 27, 2)..naive(10, 27, 3)).contains(local) {
+            MappedLocalTime::Ambiguous(berlin_offset(SUMMER), berlin_offset(WINTER))
+        } else if (naive(3, 31, 3)..naive(10, 27, 3)).contains(local) {
+            MappedLocalTime::Single(berlin_offset(SUMMER))
+        } else {
+            MappedLocalTime::Single(berlin_offset(WINTER))
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+        self.offset_from_utc_datetime(&utc.and_hms_opt(12, 0, 0).unwrap())
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+        match (naive(3, 31, 1)..naive(10, 27, 1)).contains(utc) {
+            true => berlin_offset(SUMMER),
+            false => berlin_offset(WINTER),
+        }
+    }
+}
+
+fn berlin(
+    month: u32,
+    day: u32,
+    (hour, minute): (u32, u32),
+) -> MappedLocalTime<DateTime<Berlin2024>> {
+    Berlin2024.with_ymd_and_hms(2024, month, day, hour, minute, 0)
+}
+
+#[test]
+fn times_skipped_by_the_clock_change_in_spring_dont_fire() {
+    assert_eq!(
+        fires("0 30 2 * * *", berlin(3, 30, (3, 0)).unwrap(), 2),
+        vec![
+            berlin(4, 1, (2, 30)).unwrap(),
+            berlin(4, 2, (2, 30)).unwrap()
+        ]
+    );
+    // Hourly schedules go on right after the skipped hour.
+    assert_eq!(
+        fires("0 0 * * * *", berlin(3, 31, (1, 30)).unwrap(), 2),
+        vec![
+            berlin(3, 31, (3, 0)).unwrap(),
+            berlin(3, 31, (4, 0)).unwrap()
+        ]
+    );
+}
+
+#[test]
+fn times_repeated_by_the_clock_change_in_autumn_fire_once() {
+    let fired = fires("0 30 2 * * *", berlin(10, 26, (3, 0)).unwrap(), 2);
+    assert_eq!(
+        fired,
+        vec![
+            berlin(10, 27, (2, 30)).earliest().unwrap(),
+            berlin(10, 28, (2, 30)).unwrap()
+        ]
+    );
+    assert_eq!(fired[0].offset(), &berlin_offset(SUMMER));
+    assert_eq!(
+        fires("0 0 * * * *", berlin(10, 27, (1, 30)).unwrap(), 2),
+        vec![
+            berlin(10, 27, (2, 0)).earliest().unwrap(),
+            berlin(10, 27, (3, 0)).unwrap()
+        ]
+    );
+    // From within the repeated hour, its remaining times fire on their second occurrence.
+    let after = berlin(10, 27, (2, 45)).latest().unwrap();
+    let fired = fires("0 50 2 * * *", after, 1);
+    assert_eq!(fired, vec![berlin(10, 27, (2, 50)).latest().unwrap()]);
+    assert_eq!(fired[0].offset(), &berlin_offset(WINTER));
+}
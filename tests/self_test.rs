@@ -0,0 +1,14 @@
+//! Tests of the self-test that don't need pdfium.
+
+use pdf_diff_gen::selftest::run_self_test;
+
+#[test]
+fn missing_library_fails_the_first_step() {
+    let dir = std::env::temp_dir().join("pdf_diff_gen-self-test-missing");
+    let steps = run_self_test(&dir, false);
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].name, "load pdfium");
+    let message = steps[0].result.as_ref().unwrap_err();
+    assert!(message.contains(std::env::consts::ARCH), "{}", message);
+    assert!(steps[0].to_string().starts_with("FAIL load pdfium: "));
+}
@@ -0,0 +1,75 @@
+//! The serialized form of comparison results, which state files and reports rely on.
+
+use pdf_diff_gen::{pdf::PageSimilarity, Comparison, DifferenceSegments, Redaction};
+
+fn round_trip(comparison: &Comparison) -> Comparison {
+    let json = serde_json::to_string(comparison).unwrap();
+    serde_json::from_str(&json).unwrap()
+}
+
+#[test]
+fn comparisons_are_tagged_by_variant_name() {
+    assert_eq!(
+        serde_json::to_string(&Comparison::Identical(3)).unwrap(),
+        r#"{"Identical":3}"#
+    );
+    let different = Comparison::Different(DifferenceSegments {
+        segments: vec![(0.1, 0.25), (0.5, 1.)],
+        matched_page: Some(3),
+        ..Default::default()
+    });
+    assert_eq!(
+        serde_json::to_string(&different).unwrap(),
+        r#"{"Different":{"segments":[[0.1,0.25],[0.5,1.0]],"matched_page":3}}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<Comparison>(
+            r#"{"Different":{"segments":[[0.1,0.25],[0.5,1]],"matched_page":3}}"#
+        )
+        .unwrap(),
+        different
+    );
+}
+
+#[test]
+fn comparisons_survive_a_round_trip() {
+    let comparisons = [
+        Comparison::Identical(0),
+        Comparison::Different(DifferenceSegments {
+            segments: vec![(0., 1.)],
+            matched_page: None,
+            ..Default::default()
+        }),
+        Comparison::Different(DifferenceSegments {
+            segments: vec![(0.125, 0.2), (0.3, 0.3)],
+            matched_page: Some(u16::MAX),
+            previous_segments: vec![(0.1, 0.175)],
+            redactions: vec![Redaction {
+                left: 0.1,
+                top: 0.5,
+                right: 0.9,
+                bottom: 2. / 3.,
+            }],
+        }),
+    ];
+    for comparison in &comparisons {
+        assert_eq!(&round_trip(comparison), comparison);
+    }
+}
+
+#[test]
+fn page_similarities_are_tagged_by_variant_name() {
+    assert_eq!(
+        serde_json::to_string(&PageSimilarity::Different).unwrap(),
+        r#""Different""#
+    );
+    assert_eq!(
+        serde_json::to_string(&PageSimilarity::Similar(2, 40)).unwrap(),
+        r#"{"Similar":[2,40]}"#
+    );
+    assert!(matches!(
+        serde_json::from_str(r#"{"Similar":[2,40]}"#),
+        Ok(PageSimilarity::Similar(2, 40))
+    ));
+    assert!(serde_json::from_str::<PageSimilarity>(r#"{"Similar":[-1,40]}"#).is_err());
+}
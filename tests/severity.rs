@@ -0,0 +1,146 @@
+//! Table-driven tests of the severity rules, evaluated against hand-built outcomes.
+
+#[allow(dead_code)]
+mod factories;
+
+use {
+    factories::page_statistics,
+    pdf_diff_gen::{
+        files::{FileManagerError, FileOutcome},
+        pdf::PageStatistics,
+        Severity, SeverityRules,
+    },
+    std::path::Path,
+};
+
+/// Page `page` (zero based) of which `changed_fraction` changed.
+fn page(page: usize, changed_fraction: f64) -> PageStatistics {
+    page_statistics(page, Some(page as u16), changed_fraction)
+}
+
+fn changed(pages: Vec<PageStatistics>) -> FileOutcome {
+    FileOutcome::DiffWritten {
+        new_file: false,
+        out: None,
+        previous_out: None,
+        failed_pages: Vec::new(),
+        stats: pages,
+        score: Default::default(),
+        timings: Default::default(),
+        memory: Default::default(),
+        changes: Default::default(),
+    }
+}
+
+fn rules(json: &str) -> SeverityRules {
+    serde_json::from_str(json).expect("Invalid rules")
+}
+
+const RULES: &str = r#"[
+    {"severity": "critical", "path": "invoices/**", "pages": "1"},
+    {"severity": "warn", "min_change_fraction": 0.25},
+    {"severity": "info", "path": "**/appendix-*.pdf"}
+]"#;
+
+#[test]
+fn outcomes_get_the_highest_matching_severity() {
+    let rules = rules(RULES);
+    let cases = [
+        (
+            "invoices/2024/a.pdf",
+            changed(vec![page(0, 0.01), page(1, 0.)]),
+            Some(Severity::Critical),
+        ),
+        // Only the second page changed, outside the range of the critical rule.
+        (
+            "invoices/2024/a.pdf",
+            changed(vec![page(0, 0.), page(1, 0.01)]),
+            Some(Severity::Info),
+        ),
+        (
+            "invoices/2024/a.pdf",
+            changed(vec![page(0, 0.), page(1, 0.5)]),
+            Some(Severity::Warn),
+        ),
+        (
+            "reports/appendix-b.pdf",
+            changed(vec![page(0, 0.25)]),
+            Some(Severity::Warn),
+        ),
+        (
+            "reports/summary.pdf",
+            changed(vec![page(0, 0.1)]),
+            Some(Severity::Info),
+        ),
+        // Removed files changed entirely, but have no pages a page range could match.
+        ("invoices/b.pdf", FileOutcome::Removed, Some(Severity::Warn)),
+        (
+            "invoices/b.pdf",
+            FileOutcome::Unchanged {
+                timings: Default::default(),
+                memory: Default::default(),
+                changes: Default::default(),
+                copy_updated: false,
+            },
+            None,
+        ),
+        (
+            "invoices/b.pdf",
+            FileOutcome::Failed {
+                error: FileManagerError::Cancelled("invoices/b.pdf".into()),
+            },
+            None,
+        ),
+    ];
+    for (path, outcome, expected) in cases {
+        assert_eq!(
+            rules.evaluate(Path::new(path), &outcome),
+            expected,
+            "{} with {:?}",
+            path,
+            outcome
+        );
+    }
+}
+
+#[test]
+fn rules_without_conditions_match_every_changed_file() {
+    let cases = [
+        (r#"[]"#, Some(Severity::Info)),
+        (r#"[{"severity": "critical"}]"#, Some(Severity::Critical)),
+        (
+            r#"[{"severity": "warn", "path": "other/*"}]"#,
+            Some(Severity::Info),
+        ),
+        (
+            r#"[{"severity": "warn", "min_change_fraction": 0.5}]"#,
+            Some(Severity::Info),
+        ),
+    ];
+    for (json, expected) in cases {
+        assert_eq!(
+            rules(json).evaluate(Path::new("a.pdf"), &changed(vec![page(0, 0.1)])),
+            expected,
+            "{}",
+            json
+        );
+    }
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    let cases = [
+        r#"[{"severity": "urgent"}]"#,
+        r#"[{"severity": "warn", "path": "a/[b"}]"#,
+        r#"[{"severity": "warn", "pages": "3-1"}]"#,
+        r#"[{"severity": "warn", "min_change_fraction": 1.5}]"#,
+        r#"[{"severity": "warn", "page": "1"}]"#,
+    ];
+    for json in cases {
+        assert!(
+            serde_json::from_str::<SeverityRules>(json).is_err(),
+            "{} was accepted",
+            json
+        );
+    }
+}
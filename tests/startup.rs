@@ -0,0 +1,107 @@
+//! Checks of the directories before the first cycle, without pdfium.
+
+use {
+    pdf_diff_gen::{StartupCheck, StartupError},
+    std::path::{Path, PathBuf},
+};
+
+/// A directory below the temp dir that is removed again when dropped.
+struct Root(PathBuf);
+
+impl Root {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "pdf_diff_gen-startup-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("current")).unwrap();
+        Root(path)
+    }
+
+    fn check(&self) -> StartupCheck {
+        StartupCheck::new(
+            self.0.join("current"),
+            self.0.join("last"),
+            self.0.join("diffs"),
+        )
+    }
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn is_dir(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|v| v.is_dir())
+}
+
+#[test]
+fn missing_directories_are_created() {
+    let root = Root::new("create");
+    root.check().run().unwrap();
+    assert!(is_dir(&root.0.join("last")));
+    assert!(is_dir(&root.0.join("diffs")));
+    assert_eq!(std::fs::read_dir(root.0.join("diffs")).unwrap().count(), 0);
+}
+
+#[test]
+fn missing_directories_fail_without_creating_them() {
+    let root = Root::new("no-create");
+    std::fs::create_dir(root.0.join("last")).unwrap();
+    match root.check().create_missing(false).run() {
+        Err(StartupError::Missing { name, path }) => {
+            assert_eq!(name, "diff_path");
+            assert_eq!(path, root.0.join("diffs"));
+        }
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(!root.0.join("diffs").exists());
+}
+
+#[test]
+fn missing_current_path_is_reported_as_such() {
+    let root = Root::new("typo");
+    let check = StartupCheck::new(
+        root.0.join("curent"),
+        root.0.join("last"),
+        root.0.join("diffs"),
+    );
+    let error = check.run().unwrap_err();
+    assert!(matches!(
+        error,
+        StartupError::Missing {
+            name: "current_path",
+            ..
+        }
+    ));
+    assert!(error.to_string().contains("curent"));
+    assert!(!root.0.join("last").exists());
+}
+
+#[test]
+fn files_in_place_of_directories_are_rejected() {
+    let root = Root::new("file");
+    std::fs::write(root.0.join("last"), b"").unwrap();
+    match root.check().run() {
+        Err(StartupError::NotADirectory {
+            name: "last_path", ..
+        }) => {}
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn overlapping_directories_are_rejected_before_creating_them() {
+    let root = Root::new("overlap");
+    let check = StartupCheck::new(
+        root.0.join("current"),
+        root.0.join("current").join("last"),
+        root.0.join("diffs"),
+    );
+    assert!(matches!(check.run(), Err(StartupError::Paths(_))));
+    assert!(!root.0.join("current").join("last").exists());
+}
@@ -0,0 +1,78 @@
+//! Tests of the timestamps written to reports, status files and artifact names.
+
+use {
+    chrono::{DateTime, FixedOffset, TimeZone, Utc},
+    pdf_diff_gen::{Timestamp, Timezone},
+};
+
+fn noon() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap()
+}
+
+fn plus_two() -> Timezone {
+    Timezone::Fixed(FixedOffset::east_opt(2 * 3600).unwrap())
+}
+
+#[test]
+fn zones_are_parsed_from_names_and_offsets() {
+    assert_eq!("utc".parse(), Ok(Timezone::Utc));
+    assert_eq!("Z".parse(), Ok(Timezone::Utc));
+    assert_eq!("LOCAL".parse(), Ok(Timezone::Local));
+    for offset in ["+02", "+0200", "+02:00"] {
+        assert_eq!(offset.parse(), Ok(plus_two()));
+    }
+    assert_eq!(
+        "-05:30".parse(),
+        Ok(Timezone::Fixed(
+            FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap()
+        ))
+    );
+    for invalid in ["", "cet", "02:00", "+2", "+02:60", "+25:00", "+0a00"] {
+        assert!(
+            invalid.parse::<Timezone>().is_err(),
+            "{:?} was accepted",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn timestamps_are_written_in_their_zone() {
+    let utc = Timezone::Utc.at(noon());
+    assert_eq!(utc.rfc3339(), "2024-03-01T12:00:00.000Z");
+    assert_eq!(utc.file_stamp(), "20240301T120000.000Z");
+    let shifted = plus_two().at(noon());
+    assert_eq!(shifted.rfc3339(), "2024-03-01T14:00:00.000+02:00");
+    assert_eq!(shifted.file_stamp(), "20240301T140000.000+0200");
+    assert_eq!(shifted.utc(), noon());
+    assert_eq!(shifted, utc);
+}
+
+#[test]
+fn dates_are_the_day_in_the_zone() {
+    let late = Utc.with_ymd_and_hms(2024, 3, 1, 23, 30, 0).unwrap();
+    assert_eq!(Timezone::Utc.at(late).date(), "2024-03-01");
+    assert_eq!(plus_two().at(late).date(), "2024-03-02");
+}
+
+#[test]
+fn file_stamps_are_read_back_with_the_rest_of_the_name() {
+    for timestamp in [Timezone::Utc.at(noon()), plus_two().at(noon())] {
+        let name = format!("{}-1.pdf", timestamp.file_stamp());
+        let (parsed, rest) = Timestamp::parse_file_stamp(&name).unwrap();
+        assert_eq!(parsed.local(), timestamp.local());
+        assert_eq!(rest, "-1.pdf");
+    }
+    assert_eq!(Timestamp::parse_file_stamp("1709294400000.pdf"), None);
+    assert_eq!(Timestamp::parse_file_stamp("20240301T120000.000"), None);
+}
+
+#[test]
+fn timestamps_are_serialized_as_rfc3339() {
+    let timestamp = plus_two().at(noon());
+    let json = serde_json::to_string(&timestamp).unwrap();
+    assert_eq!(json, "\"2024-03-01T14:00:00.000+02:00\"");
+    let parsed: Timestamp = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.local(), timestamp.local());
+    assert!(serde_json::from_str::<Timestamp>("\"yesterday\"").is_err());
+}